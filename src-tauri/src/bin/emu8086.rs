@@ -0,0 +1,5 @@
+// Runs a program from the command line, see `emu8086 --help`
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    std::process::exit(tauri_app::cli::main(&args));
+}
@@ -0,0 +1,683 @@
+use std::io::{Read, Write};
+
+use unicase::UniCase;
+
+use crate::{
+    compiler::{
+        compile_program_with_options,
+        includes::SourceFiles,
+        types_structs::{CompileOptions, Encoding, VariableAddressMap},
+    },
+    consts::{Byte, Word},
+    cpu::{
+        keyboard,
+        registers::{Flag, Register},
+        CPU,
+    },
+    debugger::{tracer::TraceFormat, Debugger, StopReason, DEFAULT_INSTRUCTION_BUDGET},
+    grader::{grade_with_options, parse_spec},
+    memory::Memory,
+};
+
+// the exit codes of the runner, the program's own return code is used when it exits
+pub const EXIT_GRADING_FAILED: i32 = 1;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_ASSEMBLY_ERROR: i32 = 65;
+pub const EXIT_NO_INPUT: i32 = 66;
+pub const EXIT_FAULT: i32 = 70;
+pub const EXIT_IO_ERROR: i32 = 74;
+pub const EXIT_STEP_LIMIT: i32 = 75;
+
+// the console output is written out after every slice of instructions
+const SLICE_SIZE: usize = 1000;
+const DUMP_BYTES_PER_ROW: usize = 16;
+// DOS marks the end of the input with Ctrl-Z
+const END_OF_INPUT: Byte = 0x1A;
+
+pub const USAGE: &str = "Usage: emu8086 [options] <file.asm | file.com | file.exe>
+
+Assembles and runs a program without the editor. The output of the program goes
+to stdout, stdin is typed on the keyboard and the final state goes to stderr.
+
+Options:
+    --steps <n>          stop after n instructions (default 100000)
+    --dump <range>       print memory after the run, either SEG:OFF:LEN in hex
+                         or a variable as NAME or NAME:LEN, can be repeated
+    --tail <text>        the command tail of .COM and .EXE files
+    -D <name[=value]>    define a constant for IF and IFDEF, the value is 1 when
+                         it isn't given, can be repeated
+    --long-jumps         compile a Jcc too far for a short jump as the inverted
+                         Jcc over a JMP instead of an error
+    --smallest           use the shortest encoding of the numbers instead of the
+                         bytes of emu8086 and print the bytes it saves
+    --quiet              don't print the registers, flags and memory
+    --trace <file>       write a trace of every executed instruction, as CSV for
+                         a .csv file and as JSON Lines otherwise
+    --grade <spec.json>  run the program against the scenarios of a grading spec
+                         and print a pass/fail report instead
+    --help               print this message
+
+Exit codes:
+    0-255  the return code of INT 21h/4Ch, 0 after HLT or INT 20h
+    0, 1   whether all the scenarios passed when grading
+    64     wrong arguments
+    65     the program has assembly errors
+    66     the program waits for input after the end of stdin
+    70     the cpu faulted
+    74     the file couldn't be read
+    75     the step limit was reached";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpRange {
+    Address {
+        segment: Word,
+        offset: Word,
+        length: Word,
+    },
+    // a variable of the data segment
+    Variable {
+        name: String,
+        length: Option<Word>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub file: String,
+    pub max_steps: usize,
+    pub dumps: Vec<DumpRange>,
+    pub command_tail: String,
+    pub quiet: bool,
+    // the path of a grading spec
+    pub grade: Option<String>,
+    pub trace: Option<String>,
+    // the constants of -D NAME=VALUE
+    pub defines: Vec<(String, String)>,
+    pub long_conditional_jumps: bool,
+    pub encoding: Encoding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Program {
+    Source(String),
+    // a .COM or .EXE file
+    Binary(Vec<Byte>),
+}
+
+pub(crate) fn parse_hex(value: &str) -> Result<Word, String> {
+    let digits = value.trim_end_matches(['h', 'H']);
+    let digits = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+        .unwrap_or(digits);
+    Word::from_str_radix(digits, 16).map_err(|_| format!("{} isn't a hex word", value))
+}
+
+fn parse_dump_range(value: &str) -> Result<DumpRange, String> {
+    let parts = value.split(':').collect::<Vec<&str>>();
+    let is_name = |part: &str| part.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    match parts.as_slice() {
+        [name] if is_name(name) => Ok(DumpRange::Variable {
+            name: name.to_string(),
+            length: None,
+        }),
+        [name, length] if is_name(name) => Ok(DumpRange::Variable {
+            name: name.to_string(),
+            length: Some(parse_hex(length)?),
+        }),
+        [segment, offset, length] => Ok(DumpRange::Address {
+            segment: parse_hex(segment)?,
+            offset: parse_hex(offset)?,
+            length: parse_hex(length)?,
+        }),
+        _ => Err(format!(
+            "{} isn't a memory range, use SEG:OFF:LEN or NAME:LEN",
+            value
+        )),
+    }
+}
+
+// NAME=VALUE (or) NAME, the compiler takes a define without a value as 1
+fn parse_define(value: &str) -> (String, String) {
+    match value.split_once('=') {
+        Some((name, value)) => (name.to_string(), value.to_string()),
+        None => (value.to_string(), String::new()),
+    }
+}
+
+// Ok(None) when only the usage was asked for
+pub fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut file = None;
+    let mut options = Options {
+        file: String::new(),
+        max_steps: DEFAULT_INSTRUCTION_BUDGET,
+        dumps: Vec::new(),
+        command_tail: String::new(),
+        quiet: false,
+        grade: None,
+        trace: None,
+        defines: Vec::new(),
+        long_conditional_jumps: false,
+        encoding: Encoding::Emu8086,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut get_value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--help" | "-h" => return Ok(None),
+            "--steps" => {
+                let value = get_value()?;
+                options.max_steps = value
+                    .parse()
+                    .map_err(|_| format!("{} isn't a number of steps", value))?;
+            }
+            "--dump" => options.dumps.push(parse_dump_range(get_value()?)?),
+            "--tail" => options.command_tail = get_value()?.to_string(),
+            "--quiet" => options.quiet = true,
+            "--long-jumps" => options.long_conditional_jumps = true,
+            "--smallest" => options.encoding = Encoding::Smallest,
+            "--grade" => options.grade = Some(get_value()?.to_string()),
+            "--trace" => options.trace = Some(get_value()?.to_string()),
+            "-D" => options.defines.push(parse_define(get_value()?)),
+            _ if arg.starts_with("-D") => options.defines.push(parse_define(&arg[2..])),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if file.is_some() => return Err(format!("Only one file can be run, got {}", arg)),
+            _ => file = Some(arg.to_string()),
+        }
+    }
+
+    options.file = file.ok_or("No file to run was given")?;
+    Ok(Some(options))
+}
+
+pub fn read_program(file: &str) -> std::io::Result<Program> {
+    let is_binary = [".com", ".exe"]
+        .iter()
+        .any(|extension| file.to_lowercase().ends_with(extension));
+    if is_binary {
+        Ok(Program::Binary(std::fs::read(file)?))
+    } else {
+        Ok(Program::Source(std::fs::read_to_string(file)?))
+    }
+}
+
+// the next key from the input, new lines are typed as the enter key
+fn read_key(input: &mut impl Read) -> std::io::Result<Option<Byte>> {
+    let mut buffer = [0x00];
+    loop {
+        if input.read(&mut buffer)? == 0 {
+            return Ok(None);
+        }
+        match buffer[0] {
+            b'\r' => continue,
+            b'\n' => return Ok(Some(0x0D)),
+            key => return Ok(Some(key)),
+        }
+    }
+}
+
+fn write_state(report: &mut impl Write, cpu: &CPU) -> std::io::Result<()> {
+    let registers = |registers: &[(&str, Register)]| {
+        registers
+            .iter()
+            .map(|(name, register)| format!("{}={:04X}", name, cpu.get_register(*register)))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+    writeln!(
+        report,
+        "{}",
+        registers(&[
+            ("AX", Register::AX),
+            ("BX", Register::BX),
+            ("CX", Register::CX),
+            ("DX", Register::DX),
+            ("SP", Register::SP),
+            ("BP", Register::BP),
+            ("SI", Register::SI),
+            ("DI", Register::DI),
+        ])
+    )?;
+    writeln!(
+        report,
+        "{}",
+        registers(&[
+            ("CS", Register::CS),
+            ("DS", Register::DS),
+            ("ES", Register::ES),
+            ("SS", Register::SS),
+            ("IP", Register::IP),
+        ])
+    )?;
+    let flags = [
+        ("CF", Flag::CF),
+        ("ZF", Flag::ZF),
+        ("SF", Flag::SF),
+        ("OF", Flag::OF),
+        ("PF", Flag::PF),
+        ("AF", Flag::AF),
+        ("IF", Flag::IF),
+        ("DF", Flag::DF),
+    ]
+    .iter()
+    .map(|(name, flag)| format!("{}={}", name, cpu.get_flag(*flag) as u8))
+    .collect::<Vec<String>>()
+    .join(" ");
+    writeln!(report, "{}", flags)
+}
+
+fn write_memory(
+    report: &mut impl Write,
+    mem: &Memory,
+    segment: Word,
+    offset: Word,
+    length: Word,
+) -> std::io::Result<()> {
+    let bytes = (0..length)
+        .map(|i| mem.peek_byte(segment, offset.wrapping_add(i)))
+        .collect::<Vec<Byte>>();
+    for (i, row) in bytes.chunks(DUMP_BYTES_PER_ROW).enumerate() {
+        let row_offset = offset.wrapping_add((i * DUMP_BYTES_PER_ROW) as Word);
+        let hex = row
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let text = row
+            .iter()
+            .map(|byte| match byte {
+                0x20..=0x7E => *byte as char,
+                _ => '.',
+            })
+            .collect::<String>();
+        writeln!(
+            report,
+            "{:04X}:{:04X}  {:<47}  {}",
+            segment, row_offset, hex, text
+        )?;
+    }
+    Ok(())
+}
+
+fn write_dump(
+    report: &mut impl Write,
+    cpu: &CPU,
+    mem: &Memory,
+    variables: &VariableAddressMap,
+    dump: &DumpRange,
+) -> std::io::Result<()> {
+    match dump {
+        DumpRange::Address {
+            segment,
+            offset,
+            length,
+        } => write_memory(report, mem, *segment, *offset, *length),
+        DumpRange::Variable { name, length } => match variables.get(&UniCase::new(name.clone())) {
+            // the whole variable, i.e every element of an array
+            Some((_, offset, variable_size)) => {
+                let length = length.unwrap_or(variable_size.size);
+                writeln!(report, "{}:", name)?;
+                write_memory(
+                    report,
+                    mem,
+                    cpu.get_register(Register::DS),
+                    *offset as Word,
+                    length,
+                )
+            }
+            None => writeln!(report, "{}: no such variable", name),
+        },
+    }
+}
+
+fn get_compile_options(options: &Options) -> CompileOptions {
+    // the INCLUDE files are next to the program
+    let root = std::path::Path::new(&options.file)
+        .parent()
+        .unwrap_or(std::path::Path::new(""));
+    CompileOptions {
+        source_files: SourceFiles::from_root(root),
+        defines: options.defines.clone(),
+        long_conditional_jumps: options.long_conditional_jumps,
+        encoding: options.encoding,
+    }
+}
+
+// Runs the program to the end, streaming its console output and typing the input on
+// the keyboard when it waits for a key, then reports the state, returns the exit code
+pub fn execute(
+    program: &Program,
+    options: &Options,
+    input: &mut impl Read,
+    console: &mut impl Write,
+    report: &mut impl Write,
+) -> std::io::Result<i32> {
+    let mut cpu = CPU::new();
+    let mut mem = Memory::new();
+    let mut debugger = Debugger::new();
+    let mut variables = VariableAddressMap::new();
+
+    match program {
+        Program::Source(code) => {
+            let compile_options = get_compile_options(options);
+            let source_files = &compile_options.source_files;
+            match compile_program_with_options(code, &compile_options, false) {
+                Ok(program) => {
+                    for warning in &program.warnings {
+                        writeln!(report, "Warning: {}", warning.to_short_string())?;
+                    }
+                    for message in &program.messages {
+                        writeln!(report, "{}", message)?;
+                    }
+                    if options.encoding == Encoding::Smallest {
+                        writeln!(
+                            report,
+                            "The smallest encoding saved {} bytes",
+                            program.bytes_saved
+                        )?;
+                    }
+                    debugger.load_program(&mut cpu, &mut mem, &program);
+                    variables = program.variable_address_map;
+                }
+                Err(errors) => {
+                    for error in &errors {
+                        let included_code = error
+                            .get_file_name()
+                            .and_then(|file_name| source_files.read(file_name));
+                        error.print_compilation_error(included_code.as_deref().unwrap_or(code));
+                    }
+                    writeln!(report, "{} assembly errors", errors.len())?;
+                    return Ok(EXIT_ASSEMBLY_ERROR);
+                }
+            }
+        }
+        Program::Binary(bytes) => {
+            if let Err(error) =
+                debugger.load_binary(&mut cpu, &mut mem, bytes, &options.command_tail)
+            {
+                writeln!(report, "{}", error)?;
+                return Ok(EXIT_USAGE);
+            }
+        }
+    }
+
+    // only as much history as stepping back a single instruction would need
+    debugger.set_history_depth(&mut mem, 1);
+    debugger.set_tracing(options.trace.is_some());
+    let start_time = mem.get_time();
+    let mut is_input_done = false;
+    let reason = loop {
+        let executed = mem.get_time() - start_time;
+        if executed >= options.max_steps {
+            break StopReason::BudgetExhausted;
+        }
+        let slice = SLICE_SIZE.min(options.max_steps - executed);
+        let (reason, output) = debugger.continue_execution(&mut cpu, &mut mem, slice);
+        console.write_all(output.as_bytes())?;
+        console.flush()?;
+        match reason {
+            StopReason::BudgetExhausted => continue,
+            StopReason::WaitingForInput if !is_input_done => match read_key(input)? {
+                Some(key) => {
+                    keyboard::push_key(&mut mem, key);
+                }
+                None => {
+                    is_input_done = true;
+                    keyboard::push_key(&mut mem, END_OF_INPUT);
+                }
+            },
+            reason => break reason,
+        }
+    };
+
+    if let Some(file) = &options.trace {
+        let format = match file.to_lowercase().ends_with(".csv") {
+            true => TraceFormat::Csv,
+            false => TraceFormat::JsonLines,
+        };
+        std::fs::write(file, debugger.get_trace(format).unwrap_or_default())?;
+    }
+
+    let exit_code = match reason {
+        StopReason::Halted => 0,
+        StopReason::Exited(code) => code as i32,
+        StopReason::WaitingForInput => EXIT_NO_INPUT,
+        StopReason::BudgetExhausted => EXIT_STEP_LIMIT,
+        _ => EXIT_FAULT,
+    };
+    if !options.quiet {
+        writeln!(
+            report,
+            "Stopped: {:?} after {} instructions",
+            reason,
+            mem.get_time() - start_time
+        )?;
+        write_state(report, &cpu)?;
+        for dump in &options.dumps {
+            write_dump(report, &cpu, &mem, &variables, dump)?;
+        }
+    }
+    Ok(exit_code)
+}
+
+fn grade_file(options: &Options, spec_file: &str) -> i32 {
+    let (code, spec) = match (
+        std::fs::read_to_string(&options.file),
+        std::fs::read_to_string(spec_file),
+    ) {
+        (Ok(code), Ok(spec)) => (code, spec),
+        (Err(error), _) | (_, Err(error)) => {
+            eprintln!("Couldn't read the program or the spec: {}", error);
+            return EXIT_IO_ERROR;
+        }
+    };
+    let spec = match parse_spec(&spec) {
+        Ok(spec) => spec,
+        Err(error) => {
+            eprintln!("{}", error);
+            return EXIT_USAGE;
+        }
+    };
+    let report = grade_with_options(&code, &spec, &get_compile_options(options));
+    print!("{}", report);
+    if report.is_passed() {
+        0
+    } else {
+        EXIT_GRADING_FAILED
+    }
+}
+
+pub fn main(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    if let Some(spec) = &options.grade {
+        return grade_file(&options, spec);
+    }
+    let program = match read_program(&options.file) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("Couldn't read {}: {}", options.file, error);
+            return EXIT_IO_ERROR;
+        }
+    };
+    let result = execute(
+        &program,
+        &options,
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout().lock(),
+        &mut std::io::stderr().lock(),
+    );
+    result.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        EXIT_IO_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        execute, parse_args, DumpRange, Encoding, Options, Program, EXIT_ASSEMBLY_ERROR,
+        EXIT_NO_INPUT, EXIT_STEP_LIMIT,
+    };
+
+    fn options(args: &[&str]) -> Options {
+        let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        parse_args(&args).unwrap().unwrap()
+    }
+
+    fn execute_source(code: &str, options: &Options, input: &str) -> (i32, String, String) {
+        let mut console = Vec::new();
+        let mut report = Vec::new();
+        let exit_code = execute(
+            &Program::Source(code.to_string()),
+            options,
+            &mut input.as_bytes(),
+            &mut console,
+            &mut report,
+        )
+        .unwrap();
+        (
+            exit_code,
+            String::from_utf8(console).unwrap(),
+            String::from_utf8(report).unwrap(),
+        )
+    }
+
+    #[test]
+    fn args_are_parsed() {
+        let options = options(&[
+            "--steps",
+            "50",
+            "--dump",
+            "0700:0100:4",
+            "--dump",
+            "var:3",
+            "-D",
+            "DEBUG",
+            "-DLEVEL=2",
+            "--long-jumps",
+            "--smallest",
+            "prog.asm",
+        ]);
+        assert_eq!(options.file, "prog.asm");
+        assert_eq!(options.max_steps, 50);
+        assert!(options.long_conditional_jumps);
+        assert_eq!(options.encoding, Encoding::Smallest);
+        assert_eq!(
+            options.defines,
+            vec![
+                ("DEBUG".to_string(), String::new()),
+                ("LEVEL".to_string(), "2".to_string())
+            ]
+        );
+        assert_eq!(
+            options.dumps,
+            vec![
+                DumpRange::Address {
+                    segment: 0x0700,
+                    offset: 0x0100,
+                    length: 4
+                },
+                DumpRange::Variable {
+                    name: "var".to_string(),
+                    length: Some(3)
+                }
+            ]
+        );
+
+        let args = ["--steps".to_string()];
+        assert!(parse_args(&args).is_err());
+        assert_eq!(parse_args(&["--help".to_string()]), Ok(None));
+    }
+
+    #[test]
+    fn input_is_echoed_and_the_return_code_is_the_exit_code() {
+        let code = "
+            org 100h
+            .data
+            var dw 0x1234
+            code:
+            mov ah, 0x01
+            int 0x21
+            mov bl, al
+            int 0x21
+            mov ah, 0x4C
+            mov al, bl
+            int 0x21
+        ";
+        let options = options(&["--dump", "var", "prog.asm"]);
+        let (exit_code, console, report) = execute_source(code, &options, "\n");
+        assert_eq!(exit_code, 0x0D);
+        // the end of the input is typed as Ctrl-Z
+        assert_eq!(console, "\r\u{1A}");
+        // the interrupts that waited for a key ran twice
+        let lines = report.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "Stopped: Exited(13) after 15 instructions");
+        assert!(lines[1].starts_with("AX=4C0D BX=000D"));
+        assert_eq!(lines[lines.len() - 2], "var:");
+        assert_eq!(
+            lines[lines.len() - 1],
+            format!("0700:0102  {:<47}  4.", "34 12")
+        );
+
+        // reading after the end of the input can't go on
+        let code = "
+            mov ah, 0x01
+            int 0x21
+            int 0x21
+        ";
+        let (exit_code, _, _) = execute_source(code, &options, "");
+        assert_eq!(exit_code, EXIT_NO_INPUT);
+    }
+
+    #[test]
+    fn dump_of_an_array_is_the_whole_array() {
+        let code = "
+            org 100h
+            .data
+            m1 dw 2 dup(3 dup(7))
+            code:
+            mov ah, 0x4C
+            int 0x21
+        ";
+        let options = options(&["--dump", "m1", "--dump", "m1:2", "prog.asm"]);
+        let (_, _, report) = execute_source(code, &options, "");
+        let lines = report.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[lines.len() - 4], "m1:");
+        assert_eq!(
+            lines[lines.len() - 3],
+            format!(
+                "0700:0102  {:<47}  ............",
+                "07 00 07 00 07 00 07 00 07 00 07 00"
+            )
+        );
+        assert_eq!(lines[lines.len() - 2], "m1:");
+        assert_eq!(
+            lines[lines.len() - 1],
+            format!("0700:0102  {:<47}  ..", "07 00")
+        );
+    }
+
+    #[test]
+    fn errors_and_the_step_limit_have_their_own_exit_codes() {
+        let options = options(&["--steps", "10", "prog.asm"]);
+        let (exit_code, _, _) = execute_source("mov ax, \n hlt", &options, "");
+        assert_eq!(exit_code, EXIT_ASSEMBLY_ERROR);
+
+        let (exit_code, _, report) = execute_source("l1: jmp l1", &options, "");
+        assert_eq!(exit_code, EXIT_STEP_LIMIT);
+        assert!(report.starts_with("Stopped: BudgetExhausted after 10 instructions"));
+    }
+}
@@ -1,6 +1,10 @@
 use serde::Serialize;
 
-use super::suggestions::SuggestionType;
+use super::{
+    includes::{FileId, IncludeSite, SourceMap, MAIN_FILE_ID},
+    macros::{MacroExpansionSite, MacroSourceMap},
+    suggestions::SuggestionType,
+};
 
 #[derive(Debug, Serialize)]
 pub struct CompilationError {
@@ -9,6 +13,11 @@ pub struct CompilationError {
     length: u32,
     message: String,
     suggestions: Option<Vec<SuggestionType>>,
+    // the line is in this file, the include chain leads to it from the main file
+    file_id: FileId,
+    include_chain: Vec<IncludeSite>,
+    // the macro calls the line is expanded from, the inner most first
+    macro_expansions: Vec<MacroExpansionSite>,
 }
 
 impl CompilationError {
@@ -30,6 +39,9 @@ impl CompilationError {
                 None
             },
             // suggestions: None
+            file_id: MAIN_FILE_ID,
+            include_chain: Vec::new(),
+            macro_expansions: Vec::new(),
         }
     }
 
@@ -45,6 +57,9 @@ impl CompilationError {
             length,
             message: message.to_string(),
             suggestions: None,
+            file_id: MAIN_FILE_ID,
+            include_chain: Vec::new(),
+            macro_expansions: Vec::new(),
         }
     }
 
@@ -66,16 +81,72 @@ impl CompilationError {
             } else {
                 None
             },
+            file_id: MAIN_FILE_ID,
+            include_chain: Vec::new(),
+            macro_expansions: Vec::new(),
+        }
+    }
+
+    // moves the error from a line of a macro expansion to the line of the macro
+    pub(super) fn relocate_expansion(&mut self, macro_source_map: &MacroSourceMap) {
+        if let Some(expanded_line) = macro_source_map.get_expanded_line(self.line_number) {
+            self.macro_expansions = expanded_line.expansions.clone();
+            (self.line_number, self.column_number, self.length) =
+                macro_source_map.locate(self.line_number, self.column_number, self.length);
+        }
+    }
+
+    // moves the error from the line of the expanded source to the file it was included from
+    pub(super) fn relocate(&mut self, source_map: &SourceMap) {
+        let (file_id, line_number) = source_map.locate(self.line_number);
+        self.file_id = file_id;
+        self.line_number = line_number;
+        self.include_chain = source_map.include_chain(file_id);
+        if self.macro_expansions.is_empty() {
+            return;
+        }
+        for macro_expansion in &mut self.macro_expansions {
+            macro_expansion.relocate(source_map);
+        }
+        let notes = self
+            .macro_expansions
+            .iter()
+            .map(|macro_expansion| macro_expansion.to_note())
+            .collect::<Vec<String>>();
+        self.message = format!("{} ({})", self.message, notes.join(", "));
+    }
+
+    // the included file the error is in, None for the main file
+    pub fn get_file_name(&self) -> Option<&str> {
+        self.include_chain
+            .first()
+            .map(|include_site| include_site.file_name.as_str())
+    }
+
+    // a single line like `macros.inc:3:5: message`, for the places that can't show the source
+    pub fn to_short_string(&self) -> String {
+        let location = format!("{}:{}", self.line_number + 1, self.column_number + 1);
+        match self.get_file_name() {
+            Some(file_name) => format!("{}:{}: {}", file_name, location, self.message),
+            None => format!("{}: {}", location, self.message),
         }
     }
 
     pub fn print_compilation_error(&self, source: &str) {
+        self.print_with_title("Error", source);
+    }
+
+    pub fn print_compilation_warning(&self, source: &str) {
+        self.print_with_title("Warning", source);
+    }
+
+    fn print_with_title(&self, title: &str, source: &str) {
         let line = source.lines().nth(self.line_number as usize).unwrap();
         let line_number = self.line_number + 1;
         let column_number = self.column_number;
         let length = self.length;
         let message = &self.message;
-        println!("Error: {}", message);
+        println!("{}: {}", title, message);
         println!(" --> {}:{}:{}", line_number, column_number, length);
         println!("  |");
         println!("{} | {}", line_number, line);
@@ -85,5 +156,17 @@ impl CompilationError {
             "^".repeat(length as usize),
             " ".repeat(line.len() - column_number as usize)
         );
+        for (i, include_site) in self.include_chain.iter().enumerate() {
+            let including_file = match self.include_chain.get(i + 1) {
+                Some(parent) => format!("\"{}\"", parent.file_name),
+                None => "the program".to_string(),
+            };
+            println!(
+                "  = \"{}\" is included on line {} of {}",
+                include_site.file_name,
+                include_site.line_number + 1,
+                including_file
+            );
+        }
     }
 }
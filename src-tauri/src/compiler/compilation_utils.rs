@@ -5,6 +5,15 @@ use super::{
     tokens::{assembler_directives, Assembly8086Tokens, Token},
 };
 
+// the segment and offset the debugger loads the program at, see `CPU::set_org_defined`
+pub(crate) fn get_load_address(is_org_defined: bool) -> (u16, u16) {
+    if is_org_defined {
+        (0x0700, 0x0100)
+    } else {
+        (0x0100, 0x0000)
+    }
+}
+
 pub(crate) fn find_data_line_num(lexed_strings: &[Vec<Token>]) -> u32 {
     // find the data directive
     let data_line_num = lexed_strings.iter().position(|line| {
@@ -0,0 +1,218 @@
+use super::{
+    compilation_error::CompilationError,
+    listing::get_text_of_tokens,
+    tokens::{assembler_directives::AssemblerDirectives, Assembly8086Tokens, Token},
+};
+
+// A single IF ... ELSE ... ENDIF, the lines in it are assembled while it's active
+struct ConditionalBlock {
+    if_token: Token,
+    is_active: bool,
+    // the IF (or) the ELSE of the block is already taken
+    is_taken: bool,
+    is_parent_active: bool,
+    has_else: bool,
+}
+
+#[derive(Default)]
+pub(in crate::compiler) struct ConditionalStack {
+    blocks: Vec<ConditionalBlock>,
+}
+
+impl ConditionalStack {
+    pub fn is_active(&self) -> bool {
+        self.blocks.last().is_none_or(|block| block.is_active)
+    }
+
+    // the condition isn't evaluated inside a block that is skipped
+    pub fn push_if(&mut self, if_token: &Token, condition: Option<bool>) {
+        let is_parent_active = self.is_active();
+        let is_active = is_parent_active && condition.unwrap_or(false);
+        self.blocks.push(ConditionalBlock {
+            if_token: if_token.clone(),
+            is_active,
+            is_taken: is_active,
+            is_parent_active,
+            has_else: false,
+        });
+    }
+
+    pub fn push_else(&mut self, else_token: &Token) -> Result<(), CompilationError> {
+        let block = match self.blocks.last_mut() {
+            Some(block) if !block.has_else => block,
+            Some(_) => {
+                return Err(CompilationError::error_with_token(
+                    else_token,
+                    "The IF already has an ELSE, Please close it with ENDIF first",
+                ))
+            }
+            None => {
+                return Err(CompilationError::error_with_token(
+                    else_token,
+                    "This ELSE doesn't have an IF, Please add an IF before it",
+                ))
+            }
+        };
+        block.has_else = true;
+        block.is_active = block.is_parent_active && !block.is_taken;
+        block.is_taken = true;
+        Ok(())
+    }
+
+    pub fn pop_endif(&mut self, endif_token: &Token) -> Result<(), CompilationError> {
+        match self.blocks.pop() {
+            Some(_) => Ok(()),
+            None => Err(CompilationError::error_with_token(
+                endif_token,
+                "This ENDIF doesn't have an IF, Please add an IF before it",
+            )),
+        }
+    }
+
+    pub fn get_unclosed_errors(&self) -> Vec<CompilationError> {
+        self.blocks
+            .iter()
+            .map(|block| {
+                CompilationError::error_with_token(
+                    &block.if_token,
+                    &format!(
+                        "The {} isn't closed, Please close it with ENDIF",
+                        block.if_token.token_type.to_string().to_uppercase()
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+pub(in crate::compiler) fn is_conditional_directive(token_type: &Assembly8086Tokens) -> bool {
+    matches!(
+        token_type,
+        Assembly8086Tokens::AssemblerDirectives(
+            AssemblerDirectives::If
+                | AssemblerDirectives::Ife
+                | AssemblerDirectives::Ifdef
+                | AssemblerDirectives::Ifndef
+                | AssemblerDirectives::Else
+                | AssemblerDirectives::Endif
+        )
+    )
+}
+
+// The text written after a directive, i.e the message of ECHO (or) .ERR,
+// the quotes (or) angle brackets around it are removed
+pub(in crate::compiler) fn get_directive_text(
+    source_lines: &[Vec<char>],
+    line: &[Token],
+    directive_token: &Token,
+) -> String {
+    let tokens = line
+        .iter()
+        .skip_while(|token| !token.is_abs_eq(directive_token))
+        .skip(1)
+        .take_while(|token| token.token_type != Assembly8086Tokens::Comment)
+        .cloned()
+        .collect::<Vec<Token>>();
+    let text = get_text_of_tokens(source_lines, &tokens);
+    let text = text.trim();
+    match (text.chars().next(), text.chars().last()) {
+        (Some('<'), Some('>')) | (Some('\''), Some('\'')) | (Some('"'), Some('"'))
+            if text.len() > 1 =>
+        {
+            text[1..text.len() - 1].to_string()
+        }
+        _ => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{
+        compile_program_with_options, compile_str, types_structs::CompileOptions,
+    };
+
+    fn get_compile_options(defines: &[(&str, &str)]) -> CompileOptions {
+        CompileOptions {
+            defines: defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn if_else_with_constants() {
+        let code = "LEVEL equ 3
+            IF LEVEL GT 2
+                IF LEVEL EQ 3 AND LEVEL NE 4
+                    inc ax
+                ELSE
+                    inc bx
+                ENDIF
+            ELSE
+                X = 10
+                inc cx
+            ENDIF
+            IFE LEVEL - 3
+                inc dx
+            ENDIF";
+        let (bytes, _) = compile_str(code, false).unwrap();
+        assert_eq!(bytes, vec![0x40, 0x42]);
+
+        // the constant in the skipped block isn't defined
+        assert!(compile_str(&format!("{}\nmov ax, X", code), false).is_err());
+    }
+
+    #[test]
+    fn ifdef_with_defines() {
+        let code = "IFDEF DEBUG
+                mov ax, DEBUG
+            ELSE
+                inc ax
+            ENDIF
+            IFNDEF BUF_SIZE
+                BUF_SIZE = 2
+            ENDIF
+            mov bx, BUF_SIZE";
+        let program = compile_program_with_options(code, &get_compile_options(&[]), false).unwrap();
+        assert_eq!(program.bytes, vec![0x40, 0xBB, 0x02, 0x00]);
+
+        let program = compile_program_with_options(
+            code,
+            &get_compile_options(&[("DEBUG", ""), ("BUF_SIZE", "5")]),
+            false,
+        )
+        .unwrap();
+        assert_eq!(program.bytes, vec![0xB8, 0x01, 0x00, 0xBB, 0x05, 0x00]);
+
+        // the labels and the variables are defined too
+        let code = "var db 1\nIFDEF var\ninc ax\nENDIF";
+        assert_eq!(compile_str(code, false).unwrap().0, vec![0x01, 0x40]);
+    }
+
+    #[test]
+    fn err_erre_and_echo() {
+        assert!(compile_str("IF 1\n.err <not supported>\nENDIF", false).is_err());
+        assert!(compile_str("IF 0\n.err <not supported>\nENDIF", false).is_ok());
+        assert!(compile_str("BUF_SIZE = 6\n.erre BUF_SIZE LE 5, <too large>", false).is_err());
+        assert!(compile_str("BUF_SIZE = 5\n.erre BUF_SIZE LE 5, <too large>", false).is_ok());
+
+        let program = compile_program_with_options(
+            "echo 'building'\nIF 0\n%out skipped\nENDIF\n%out done",
+            &get_compile_options(&[]),
+            false,
+        )
+        .unwrap();
+        assert_eq!(program.messages, vec!["building", "done"]);
+    }
+
+    #[test]
+    fn unbalanced_conditionals() {
+        assert!(compile_str("IF 1\ninc ax", false).is_err());
+        assert!(compile_str("inc ax\nELSE", false).is_err());
+        assert!(compile_str("inc ax\nENDIF", false).is_err());
+        assert!(compile_str("IF 1\nELSE\nELSE\nENDIF", false).is_err());
+        assert!(compile_str("IF UNDEFINED_NAME\nENDIF", false).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::utils::TokenPosition;
+
+use super::{
+    compile_lines_perform_var_label_substiution,
+    labels::LabelScopes,
+    lexer::Lexer,
+    macros::MacroSourceMap,
+    program_end::ProgramEnd,
+    segments::Segments,
+    tokens::Token,
+    types_structs::{
+        CompileOptions, LabelAddressMap, LabelRefrenceList, MacroBoundsDefintionMap,
+        MacroReferenceList, ProcDefinitionLineNumberMap, ProcReferenceList,
+        VariableAddressDefinitionMap, VariableReferenceList,
+    },
+    utils::get_label_token_from_line,
+};
+
+pub type DefintionTokenPosition = TokenPosition;
+pub type ReferenceTokenPosition = TokenPosition;
+
+// the tokens of the macro expansions are moved back to the lines of the macros
+fn get_position(macro_source_map: &MacroSourceMap, token: &Token) -> TokenPosition {
+    let (line_number, column_number, length) =
+        macro_source_map.locate(token.line_number, token.column_number, token.token_length);
+    TokenPosition::new(
+        line_number as usize,
+        column_number as usize,
+        length as usize,
+    )
+}
+
+// pairs every reference to a label, variable, proc or macro with its definition
+pub fn get_label_and_var_address_definitions(
+    code: &str,
+) -> Vec<(DefintionTokenPosition, ReferenceTokenPosition)> {
+    let mut lexer = Lexer::new();
+    lexer.tokenize(code);
+    let old_lexer = lexer.clone();
+
+    let mut label_addr_map = LabelAddressMap::new();
+    let mut label_ref = LabelRefrenceList::new();
+
+    let mut var_addr_def_map = VariableAddressDefinitionMap::new();
+    let mut var_ref = VariableReferenceList::new();
+
+    let mut proc_line_num_map = ProcDefinitionLineNumberMap::new();
+    let mut proc_ref = ProcReferenceList::new();
+
+    let mut macro_line_num_map = MacroBoundsDefintionMap::new();
+    let mut macro_ref = MacroReferenceList::new();
+    let mut macro_source_map = MacroSourceMap::default();
+    let mut label_scopes = LabelScopes::default();
+
+    compile_lines_perform_var_label_substiution(
+        &mut lexer,
+        code,
+        &CompileOptions::default(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut label_addr_map,
+        &mut label_ref,
+        &mut var_addr_def_map,
+        &mut var_ref,
+        &mut proc_line_num_map,
+        &mut proc_ref,
+        &mut macro_line_num_map,
+        &mut macro_ref,
+        &mut macro_source_map,
+        &mut label_scopes,
+        &mut Segments::default(),
+        &mut ProgramEnd::default(),
+    );
+
+    let mut label_and_var_address_definitions = Vec::new();
+
+    for (label, ref_token, _, _) in label_ref {
+        if let Some(line_number) = label_addr_map.get(&label) {
+            let label = label_scopes.get_name(&label);
+            let def_token = get_label_token_from_line(&lexer, *line_number, label).unwrap();
+            label_and_var_address_definitions.push((
+                get_position(&macro_source_map, def_token),
+                get_position(&macro_source_map, &ref_token),
+            ));
+        }
+    }
+
+    for (label, _, _, tokenized_line_number) in var_ref {
+        let ref_token = get_label_token_from_line(&lexer, tokenized_line_number, &label).unwrap();
+        if let Some((_, line_number)) = var_addr_def_map.get(&label) {
+            let def_token = get_label_token_from_line(&lexer, *line_number, &label).unwrap();
+            label_and_var_address_definitions.push((
+                get_position(&macro_source_map, def_token),
+                get_position(&macro_source_map, ref_token),
+            ));
+        }
+    }
+
+    for (label, ref_token, _, _) in proc_ref {
+        if let Some((line_number, _)) = proc_line_num_map.get(&label) {
+            let def_token = get_label_token_from_line(&lexer, *line_number, &label).unwrap();
+            label_and_var_address_definitions.push((
+                get_position(&macro_source_map, def_token),
+                get_position(&macro_source_map, &ref_token),
+            ));
+        }
+    }
+
+    for (label, ref_token, _) in macro_ref {
+        if let Some((line_number, _)) = macro_line_num_map.get(&label) {
+            let def_token = get_label_token_from_line(&old_lexer, *line_number, &label).unwrap();
+            label_and_var_address_definitions.push((
+                get_position(&macro_source_map, def_token),
+                get_position(&macro_source_map, &ref_token),
+            ));
+        }
+    }
+
+    label_and_var_address_definitions
+}
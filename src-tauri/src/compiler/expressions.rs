@@ -0,0 +1,1358 @@
+use std::collections::{HashMap, HashSet};
+
+use unicase::UniCase;
+
+use super::{
+    compilation_error::CompilationError,
+    conditionals::{get_directive_text, is_conditional_directive, ConditionalStack},
+    lexer::{parse_wide_number, Lexer},
+    parsers::{
+        pattern_extractors::utils::get_label_address_or_push_into_ref, var::LabelAddressContext,
+    },
+    segments::Segments,
+    strip_space_and_comments_and_iterate_labels,
+    tokens::{
+        assembler_directives::AssemblerDirectives, instructions::Instructions, Assembly8086Tokens,
+        Token,
+    },
+    types_structs::{Label, LineNumber, VariableType},
+};
+use crate::utils::Either;
+
+// the operators that are written as words, they are names when they aren't
+// used as an operator i.e a variable called size
+const PREFIX_OPERATORS: [&str; 7] = ["high", "low", "not", "seg", "size", "length", "type"];
+const BINARY_OPERATORS: [&str; 2] = ["mod", "shr"];
+const RELATIONAL_OPERATORS: [&str; 6] = ["eq", "ne", "lt", "le", "gt", "ge"];
+
+struct Constant {
+    tokens: Vec<Token>,
+    // = can be defined again, EQU can't
+    is_redefinable: bool,
+    // None for the ones defined with -D
+    line_number: Option<LineNumber>,
+}
+
+impl Constant {
+    fn get_definition_place(&self) -> String {
+        match self.line_number {
+            Some(line_number) => format!("in line {}", line_number + 1),
+            None => "with -D".to_string(),
+        }
+    }
+}
+
+fn is_operator_word(name: &str, operators: &[&str]) -> bool {
+    operators
+        .iter()
+        .any(|operator| operator.eq_ignore_ascii_case(name))
+}
+
+fn is_expression_token(token_type: &Assembly8086Tokens) -> bool {
+    matches!(
+        token_type,
+        Assembly8086Tokens::Number8bit(_)
+            | Assembly8086Tokens::Number16bit(_)
+            | Assembly8086Tokens::Plus
+            | Assembly8086Tokens::Minus
+            | Assembly8086Tokens::Multiply
+            | Assembly8086Tokens::Divide
+            | Assembly8086Tokens::OpenParenthesis
+            | Assembly8086Tokens::CloseParenthesis
+            | Assembly8086Tokens::Character(_)
+            | Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Offset)
+            | Assembly8086Tokens::Instruction(
+                Instructions::And | Instructions::Or | Instructions::Xor | Instructions::Shl
+            )
+    )
+}
+
+// a label (or) a variable
+fn is_name(token_type: &Assembly8086Tokens) -> bool {
+    match token_type {
+        Assembly8086Tokens::Character(name) => {
+            !is_operator_word(name, &PREFIX_OPERATORS)
+                && !is_operator_word(name, &BINARY_OPERATORS)
+                && !is_operator_word(name, &RELATIONAL_OPERATORS)
+        }
+        _ => false,
+    }
+}
+
+fn get_token_at(token: &Token, token_type: Assembly8086Tokens) -> Token {
+    Token::new(
+        token_type,
+        token.line_number,
+        token.column_number,
+        token.token_length,
+    )
+}
+
+fn get_number_tokens(token: &Token, number: i64) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    if number < 0 {
+        tokens.push(get_token_at(token, Assembly8086Tokens::Minus));
+    }
+    let number = number.unsigned_abs();
+    tokens.push(get_token_at(
+        token,
+        match number {
+            0..=0xFF => Assembly8086Tokens::Number8bit(number as u8),
+            0x100..=0xFFFF => Assembly8086Tokens::Number16bit(number as u16),
+            // only the data of dd, dq and dt can be this wide, see `parse_wide_number`
+            _ => Assembly8086Tokens::Character(UniCase::new(number.to_string())),
+        },
+    ));
+    tokens
+}
+
+fn get_location_label_tokens(token: &Token, location_label: &str) -> [Token; 2] {
+    [
+        get_token_at(
+            token,
+            Assembly8086Tokens::Character(UniCase::new(location_label.to_string())),
+        ),
+        get_token_at(token, Assembly8086Tokens::Colon),
+    ]
+}
+
+// Replaces the constants with their value and $ with the label of the line,
+// returns the line and if $ was used
+fn expand_line(
+    line: &[&Token],
+    label: &Option<String>,
+    constants: &HashMap<Label, Constant>,
+    location_label: &str,
+) -> Result<(Vec<Token>, bool), CompilationError> {
+    // the names that are defined on this line aren't replaced
+    let is_data = |idx: usize| {
+        line.get(idx)
+            .is_some_and(|token| matches!(token.token_type, Assembly8086Tokens::Data(_)))
+    };
+    let defined_name_idx = match label {
+        Some(_) if is_data(3) => Some(2),
+        Some(_) => Some(0),
+        None if is_data(1) => Some(0),
+        None => None,
+    };
+    if let Some(token) = defined_name_idx.map(|idx| line[idx]) {
+        if let Assembly8086Tokens::Character(name) = &token.token_type {
+            if let Some(constant) = constants.get(name) {
+                return Err(CompilationError::error_with_token(
+                    token,
+                    &format!(
+                        "\"{}\" is already defined as a constant {}, Please use a different name.",
+                        name,
+                        constant.get_definition_place()
+                    ),
+                ));
+            }
+        }
+    }
+
+    let mut is_location_used = false;
+    let mut expanded_line = Vec::new();
+    for (i, token) in line.iter().enumerate() {
+        let name = match &token.token_type {
+            Assembly8086Tokens::Character(name) if Some(i) != defined_name_idx => name,
+            _ => {
+                expanded_line.push((*token).clone());
+                continue;
+            }
+        };
+        if name.as_str() == "$" {
+            is_location_used = true;
+            let location = label.clone().unwrap_or(location_label.to_string());
+            expanded_line.push(get_token_at(
+                token,
+                Assembly8086Tokens::Character(UniCase::new(location)),
+            ));
+            continue;
+        }
+        match constants.get(name) {
+            // the value takes the place of the name, so the errors point to it
+            Some(constant) => {
+                let is_expression = constant.tokens.len() > 1
+                    && constant
+                        .tokens
+                        .iter()
+                        .all(|token| is_expression_token(&token.token_type));
+                if is_expression {
+                    expanded_line.push(get_token_at(token, Assembly8086Tokens::OpenParenthesis));
+                }
+                for constant_token in &constant.tokens {
+                    expanded_line.push(get_token_at(token, constant_token.token_type.clone()));
+                }
+                if is_expression {
+                    expanded_line.push(get_token_at(token, Assembly8086Tokens::CloseParenthesis));
+                }
+            }
+            None => expanded_line.push((*token).clone()),
+        }
+    }
+    Ok((expanded_line, is_location_used))
+}
+
+// NAME EQU value (or) NAME = value, returns the tokens the line is left with
+fn define_constant(
+    line: &[&Token],
+    is_redefinable: bool,
+    constants: &mut HashMap<Label, Constant>,
+    location_label: &str,
+) -> Result<Vec<Token>, CompilationError> {
+    let (name_token, operator_token) = (line[0], line[1]);
+    let name = match &name_token.token_type {
+        Assembly8086Tokens::Character(name) => name.clone(),
+        _ => unreachable!(),
+    };
+    if line.len() == 2 {
+        return Err(CompilationError::error_with_token(
+            operator_token,
+            &format!(
+                "Expected a value after {}, Got nothing!",
+                operator_token.token_type.to_string().to_uppercase()
+            ),
+        ));
+    }
+    if let Some(constant) = constants.get(&name) {
+        if !constant.is_redefinable || !is_redefinable {
+            return Err(CompilationError::error_with_token(
+                name_token,
+                &format!(
+                    "The constant \"{}\" is already defined {}, Please use a different name.",
+                    name,
+                    constant.get_definition_place()
+                ),
+            ));
+        }
+    }
+
+    // the value is expanded first, so `count = count + 1` uses the previous count
+    let (mut tokens, is_location_used) = expand_line(&line[2..], &None, constants, location_label)?;
+    // the values that don't depend on an address are calculated once
+    let is_number = tokens.iter().all(|token| {
+        is_expression_token(&token.token_type)
+            && !is_name(&token.token_type)
+            && token.token_type
+                != Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Offset)
+    });
+    if is_number {
+        let token_refs = tokens.iter().collect::<Vec<&Token>>();
+        let value = Evaluator::new(&token_refs, None).evaluate()?;
+        tokens = get_number_tokens(name_token, value.number);
+    }
+    constants.insert(
+        name,
+        Constant {
+            tokens,
+            is_redefinable,
+            line_number: Some(name_token.line_number as LineNumber),
+        },
+    );
+
+    // $ in the value is the address of this line
+    Ok(match is_location_used {
+        true => get_location_label_tokens(name_token, location_label).to_vec(),
+        false => Vec::new(),
+    })
+}
+
+// Some(is_redefinable) for the lines that define a constant, i.e NAME EQU value (or) NAME = value
+fn get_constant_definition(stripped_line: &[&Token]) -> Option<bool> {
+    match (stripped_line.first(), stripped_line.get(1)) {
+        (Some(name), Some(operator)) if is_name(&name.token_type) => match operator.token_type {
+            Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Equ) => Some(false),
+            Assembly8086Tokens::Equals => Some(true),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// the constants given with -D (or) from the editor, a define without a value is 1
+fn define_constants_from_defines(
+    defines: &[(String, String)],
+    constants: &mut HashMap<Label, Constant>,
+) -> Result<(), CompilationError> {
+    for (name, value) in defines {
+        let mut name_lexer = Lexer::new();
+        name_lexer.tokenize(name);
+        let is_valid_name = matches!(
+            name_lexer.tokens.first().map(Vec::as_slice),
+            Some([token]) if is_name(&token.token_type)
+        );
+        if !is_valid_name {
+            return Err(CompilationError::new_without_suggestions(
+                0,
+                0,
+                0,
+                &format!(
+                    "The define \"{}\" isn't a valid name, Please use a name like DEBUG",
+                    name
+                ),
+            ));
+        }
+        let mut value_lexer = Lexer::new();
+        value_lexer.tokenize(match value.trim() {
+            "" => "1",
+            value => value,
+        });
+        let (tokens, _) = strip_space_and_comments_and_iterate_labels(&value_lexer.tokens[0]);
+        constants.insert(
+            UniCase::new(name.to_string()),
+            Constant {
+                tokens: tokens.into_iter().cloned().collect(),
+                is_redefinable: false,
+                line_number: None,
+            },
+        );
+    }
+    Ok(())
+}
+
+fn evaluate_constant_expression(
+    directive_token: &Token,
+    tokens: &[&Token],
+    constants: &HashMap<Label, Constant>,
+    location_label: &str,
+) -> Result<i64, CompilationError> {
+    if tokens.is_empty() {
+        return Err(CompilationError::error_with_token(
+            directive_token,
+            &format!(
+                "Expected a value after {}, Got nothing!",
+                directive_token.token_type.to_string().to_uppercase()
+            ),
+        ));
+    }
+    let (expanded_tokens, _) = expand_line(tokens, &None, constants, location_label)?;
+    let token_refs = expanded_tokens.iter().collect::<Vec<&Token>>();
+    Ok(Evaluator::new(&token_refs, None).evaluate()?.number)
+}
+
+// The macros are expanded before the constants, so the count of a REPT is calculated
+// with the constants of the lines that are expanded before it
+pub(in crate::compiler) struct ConstantScope {
+    constants: HashMap<Label, Constant>,
+}
+
+impl ConstantScope {
+    // the errors of the defines are shown when the constants are expanded
+    pub fn new(defines: &[(String, String)]) -> Self {
+        let mut constants = HashMap::new();
+        let _ = define_constants_from_defines(defines, &mut constants);
+        Self { constants }
+    }
+
+    pub fn define(&mut self, line: &[Token]) {
+        let (stripped_line, _) = strip_space_and_comments_and_iterate_labels(line);
+        if let Some(is_redefinable) = get_constant_definition(&stripped_line) {
+            let _ = define_constant(&stripped_line, is_redefinable, &mut self.constants, "$");
+        }
+    }
+
+    pub fn evaluate(
+        &self,
+        directive_token: &Token,
+        tokens: &[&Token],
+    ) -> Result<i64, CompilationError> {
+        evaluate_constant_expression(directive_token, tokens, &self.constants, "$")
+    }
+}
+
+// IF, IFE, IFDEF, IFNDEF, ELSE (or) ENDIF
+fn expand_conditional(
+    stripped_line: &[&Token],
+    constants: &HashMap<Label, Constant>,
+    defined_names: &HashSet<Label>,
+    conditionals: &mut ConditionalStack,
+    location_label: &str,
+) -> Result<(), CompilationError> {
+    let directive_token = stripped_line[0];
+    let directive = match &directive_token.token_type {
+        Assembly8086Tokens::AssemblerDirectives(directive) => directive,
+        _ => unreachable!(),
+    };
+    let condition = match directive {
+        AssemblerDirectives::Else => return conditionals.push_else(directive_token),
+        AssemblerDirectives::Endif => return conditionals.pop_endif(directive_token),
+        _ if !conditionals.is_active() => {
+            conditionals.push_if(directive_token, None);
+            return Ok(());
+        }
+        AssemblerDirectives::Ifdef | AssemblerDirectives::Ifndef => {
+            match stripped_line.get(1).map(|token| &token.token_type) {
+                Some(Assembly8086Tokens::Character(name)) => {
+                    let is_defined = constants.contains_key(name) || defined_names.contains(name);
+                    Ok(is_defined == (*directive == AssemblerDirectives::Ifdef))
+                }
+                _ => Err(CompilationError::error_with_token(
+                    directive_token,
+                    &format!(
+                        "Expected a name after {}, Please use it like {} DEBUG",
+                        directive.to_string().to_uppercase(),
+                        directive.to_string().to_uppercase()
+                    ),
+                )),
+            }
+        }
+        _ => evaluate_constant_expression(
+            directive_token,
+            &stripped_line[1..],
+            constants,
+            location_label,
+        )
+        .map(|value| (value != 0) == (*directive == AssemblerDirectives::If)),
+    };
+    // the block is still opened when the condition has an error, so its ENDIF matches it
+    conditionals.push_if(directive_token, Some(*condition.as_ref().unwrap_or(&false)));
+    condition.map(|_| ())
+}
+
+// .ERR, .ERRE, ECHO (or) %OUT, the messages of ECHO are shown after compiling
+fn expand_message(
+    line: &[Token],
+    stripped_line: &[&Token],
+    source_lines: &[Vec<char>],
+    constants: &HashMap<Label, Constant>,
+    location_label: &str,
+    echo_messages: &mut Vec<String>,
+) -> Result<(), CompilationError> {
+    let directive_token = stripped_line[0];
+    match directive_token.token_type {
+        Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Err) => {
+            let text = get_directive_text(source_lines, line, directive_token);
+            Err(CompilationError::error_with_token(
+                directive_token,
+                match text.is_empty() {
+                    true => "The .ERR directive is reached",
+                    false => &text,
+                },
+            ))
+        }
+        // .ERRE condition, message
+        Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::ErrE) => {
+            let comma_idx = stripped_line
+                .iter()
+                .position(|token| token.token_type == Assembly8086Tokens::Comma)
+                .unwrap_or(stripped_line.len());
+            let value = evaluate_constant_expression(
+                directive_token,
+                &stripped_line[1..comma_idx],
+                constants,
+                location_label,
+            )?;
+            if value != 0 {
+                return Ok(());
+            }
+            let text = match stripped_line.get(comma_idx) {
+                Some(comma_token) => get_directive_text(source_lines, line, comma_token),
+                None => String::new(),
+            };
+            Err(CompilationError::error_between_tokens(
+                directive_token,
+                stripped_line[comma_idx - 1],
+                match text.is_empty() {
+                    true => "The condition of .ERRE is false",
+                    false => &text,
+                },
+            ))
+        }
+        _ => {
+            echo_messages.push(get_directive_text(source_lines, line, directive_token));
+            Ok(())
+        }
+    }
+}
+
+fn is_message_directive(token_type: &Assembly8086Tokens) -> bool {
+    matches!(
+        token_type,
+        Assembly8086Tokens::AssemblerDirectives(
+            AssemblerDirectives::Err
+                | AssemblerDirectives::ErrE
+                | AssemblerDirectives::Echo
+                | AssemblerDirectives::Out
+        )
+    )
+}
+
+// Defines the EQU and = constants and replaces them in the lines after them,
+// the blocks of IF that aren't taken are removed as they go, so the constants in them aren't defined
+pub(in crate::compiler) fn expand_constants(
+    lexer: &mut Lexer,
+    code: &str,
+    defines: &[(String, String)],
+    echo_messages: &mut Vec<String>,
+) -> Result<(), Vec<CompilationError>> {
+    let mut constants = HashMap::<Label, Constant>::new();
+    if let Err(err) = define_constants_from_defines(defines, &mut constants) {
+        return Err(vec![err]);
+    }
+    let source_lines = code
+        .lines()
+        .map(|line| line.chars().collect::<Vec<char>>())
+        .collect::<Vec<Vec<char>>>();
+    // the labels and variables defined so far, for IFDEF
+    let mut defined_names = HashSet::<Label>::new();
+    let mut conditionals = ConditionalStack::default();
+    let mut compilation_errors = Vec::new();
+    for (i, line) in lexer.tokens.iter_mut().enumerate() {
+        let (stripped_line, label) = strip_space_and_comments_and_iterate_labels(line);
+        // a label that can't be written in the code
+        let location_label = format!("${}", i);
+        let first_token_type = stripped_line.first().map(|token| &token.token_type);
+        if first_token_type.is_some_and(is_conditional_directive) {
+            if let Err(err) = expand_conditional(
+                &stripped_line,
+                &constants,
+                &defined_names,
+                &mut conditionals,
+                &location_label,
+            ) {
+                compilation_errors.push(err);
+            }
+            *line = Vec::new();
+            continue;
+        }
+        if !conditionals.is_active() {
+            *line = Vec::new();
+            continue;
+        }
+        if first_token_type.is_some_and(is_message_directive) {
+            if let Err(err) = expand_message(
+                line,
+                &stripped_line,
+                &source_lines,
+                &constants,
+                &location_label,
+                echo_messages,
+            ) {
+                compilation_errors.push(err);
+            }
+            *line = Vec::new();
+            continue;
+        }
+
+        if let Some(label) = &label {
+            defined_names.insert(UniCase::new(label.clone()));
+        }
+        if let (Some(name), Some(data)) = (stripped_line.first(), stripped_line.get(1)) {
+            if let (Assembly8086Tokens::Character(name), Assembly8086Tokens::Data(_)) =
+                (&name.token_type, &data.token_type)
+            {
+                defined_names.insert(name.clone());
+            }
+        }
+        let expanded_line = match get_constant_definition(&stripped_line) {
+            Some(is_redefinable) => define_constant(
+                &stripped_line,
+                is_redefinable,
+                &mut constants,
+                &location_label,
+            ),
+            None => expand_line(&stripped_line, &label, &constants, &location_label).map(
+                |(mut expanded_line, is_location_used)| {
+                    if is_location_used && label.is_none() {
+                        let location_label_tokens =
+                            get_location_label_tokens(&expanded_line[0], &location_label);
+                        expanded_line.splice(0..0, location_label_tokens);
+                    }
+                    expanded_line
+                },
+            ),
+        };
+        match expanded_line {
+            // the lines without constants keep their spaces and comments
+            Ok(expanded_line) if expanded_line.iter().eq(stripped_line.iter().copied()) => {}
+            Ok(expanded_line) => *line = expanded_line,
+            Err(err) => compilation_errors.push(err),
+        }
+    }
+    compilation_errors.extend(conditionals.get_unclosed_errors());
+    match compilation_errors.is_empty() {
+        true => Ok(()),
+        false => Err(compilation_errors),
+    }
+}
+
+// the labels that are given to the lines using $
+pub(in crate::compiler) fn is_location_label(label: &str) -> bool {
+    label.starts_with('$')
+}
+
+#[derive(Debug, Clone)]
+struct Value {
+    number: i64,
+    // the number of addresses that are added, the difference of two addresses is a number
+    address_count: i64,
+    // the name and address of the last address that is added
+    base: Option<(Token, i64)>,
+}
+
+impl Value {
+    fn number(number: i64) -> Self {
+        Self {
+            number,
+            address_count: 0,
+            base: None,
+        }
+    }
+}
+
+struct Evaluator<'a, 'b> {
+    tokens: &'a [&'a Token],
+    position: usize,
+    // the labels and variables can only be used while compiling a line
+    context: Option<&'a mut LabelAddressContext<'b>>,
+}
+
+impl<'a, 'b> Evaluator<'a, 'b> {
+    fn new(tokens: &'a [&'a Token], context: Option<&'a mut LabelAddressContext<'b>>) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            context,
+        }
+    }
+
+    fn evaluate(&mut self) -> Result<Value, CompilationError> {
+        let value = self.parse_or()?;
+        match self.tokens.get(self.position) {
+            None => Ok(value),
+            Some(token) => Err(CompilationError::error_with_token(
+                token,
+                &format!("Unexpected {} in the expression", token.token_type),
+            )),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn last_token(&self) -> &'a Token {
+        self.tokens[self.position.min(self.tokens.len()) - 1]
+    }
+
+    fn is_word(&self, words: &[&str]) -> Option<String> {
+        match self.peek().map(|token| &token.token_type) {
+            Some(Assembly8086Tokens::Character(name)) if is_operator_word(name, words) => {
+                Some(name.to_lowercase())
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_number(
+        token: &Token,
+        value: &Value,
+        operator: &str,
+    ) -> Result<i64, CompilationError> {
+        match value.address_count {
+            0 => Ok(value.number),
+            _ => Err(CompilationError::error_with_token(
+                token,
+                &format!("{} can't be used on an address, Only + and - can", operator),
+            )),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Value, CompilationError> {
+        let mut value = self.parse_and()?;
+        while let Some(token) = self.peek() {
+            let operator = match token.token_type {
+                Assembly8086Tokens::Instruction(Instructions::Or) => "OR",
+                Assembly8086Tokens::Instruction(Instructions::Xor) => "XOR",
+                _ => break,
+            };
+            self.position += 1;
+            let rhs = self.parse_and()?;
+            let (lhs, rhs) = (
+                Self::expect_number(token, &value, operator)?,
+                Self::expect_number(token, &rhs, operator)?,
+            );
+            value = Value::number(match operator {
+                "OR" => lhs | rhs,
+                _ => lhs ^ rhs,
+            });
+        }
+        Ok(value)
+    }
+
+    fn parse_and(&mut self) -> Result<Value, CompilationError> {
+        let mut value = self.parse_not()?;
+        while let Some(token) = self.peek() {
+            if token.token_type != Assembly8086Tokens::Instruction(Instructions::And) {
+                break;
+            }
+            self.position += 1;
+            let rhs = self.parse_not()?;
+            value = Value::number(
+                Self::expect_number(token, &value, "AND")?
+                    & Self::expect_number(token, &rhs, "AND")?,
+            );
+        }
+        Ok(value)
+    }
+
+    fn parse_not(&mut self) -> Result<Value, CompilationError> {
+        if self.is_word(&["not"]).is_some() && self.has_operand_after() {
+            let token = self.tokens[self.position];
+            self.position += 1;
+            let value = self.parse_not()?;
+            // the bits of a word are flipped, i.e NOT 0 is 0FFFFh
+            return Ok(Value::number(
+                !Self::expect_number(token, &value, "NOT")? & 0xFFFF,
+            ));
+        }
+        self.parse_relational()
+    }
+
+    // EQ, NE, LT, LE, GT (or) GE, true is 0FFFFh like in MASM
+    fn parse_relational(&mut self) -> Result<Value, CompilationError> {
+        let mut value = self.parse_add()?;
+        while let Some(operator) = self.is_word(&RELATIONAL_OPERATORS) {
+            let token = self.tokens[self.position];
+            self.position += 1;
+            let rhs = self.parse_add()?;
+            let operator_name = operator.to_uppercase();
+            let (lhs, rhs) = match (value.address_count, rhs.address_count) {
+                // two addresses can be compared, i.e label1 LT label2
+                (1, 1) => (value.number, rhs.number),
+                _ => (
+                    Self::expect_number(token, &value, &operator_name)?,
+                    Self::expect_number(token, &rhs, &operator_name)?,
+                ),
+            };
+            let is_true = match operator.as_str() {
+                "eq" => lhs == rhs,
+                "ne" => lhs != rhs,
+                "lt" => lhs < rhs,
+                "le" => lhs <= rhs,
+                "gt" => lhs > rhs,
+                _ => lhs >= rhs,
+            };
+            value = Value::number(match is_true {
+                true => 0xFFFF,
+                false => 0,
+            });
+        }
+        Ok(value)
+    }
+
+    fn parse_add(&mut self) -> Result<Value, CompilationError> {
+        let mut value = self.parse_mul()?;
+        while let Some(token) = self.peek() {
+            let sign = match token.token_type {
+                Assembly8086Tokens::Plus => 1,
+                Assembly8086Tokens::Minus => -1,
+                _ => break,
+            };
+            self.position += 1;
+            let rhs = self.parse_mul()?;
+            value = Value {
+                number: value.number + sign * rhs.number,
+                address_count: value.address_count + sign * rhs.address_count,
+                base: match (sign, rhs.base) {
+                    (1, Some(base)) => Some(base),
+                    _ => value.base,
+                },
+            };
+        }
+        Ok(value)
+    }
+
+    fn parse_mul(&mut self) -> Result<Value, CompilationError> {
+        let mut value = self.parse_unary()?;
+        while let Some(token) = self.peek() {
+            let operator = match &token.token_type {
+                Assembly8086Tokens::Multiply => "*".to_string(),
+                Assembly8086Tokens::Divide => "/".to_string(),
+                Assembly8086Tokens::Instruction(Instructions::Shl) => "shl".to_string(),
+                _ => match self.is_word(&BINARY_OPERATORS) {
+                    Some(operator) => operator,
+                    None => break,
+                },
+            };
+            self.position += 1;
+            let rhs = self.parse_unary()?;
+            let operator_name = operator.to_uppercase();
+            let (lhs, rhs) = (
+                Self::expect_number(token, &value, &operator_name)?,
+                Self::expect_number(token, &rhs, &operator_name)?,
+            );
+            if (operator == "/" || operator == "mod") && rhs == 0 {
+                return Err(CompilationError::error_with_token(
+                    token,
+                    "Can't divide by zero in the expression",
+                ));
+            }
+            value = Value::number(match operator.as_str() {
+                "*" => lhs.wrapping_mul(rhs),
+                "/" => lhs / rhs,
+                "mod" => lhs % rhs,
+                "shl" => lhs.checked_shl(rhs as u32).unwrap_or(0),
+                _ => lhs.checked_shr(rhs as u32).unwrap_or(0),
+            });
+        }
+        Ok(value)
+    }
+
+    // the operators written as words only apply when they are followed by an operand
+    fn has_operand_after(&self) -> bool {
+        self.tokens.get(self.position + 1).is_some_and(|token| {
+            matches!(
+                token.token_type,
+                Assembly8086Tokens::Number8bit(_)
+                    | Assembly8086Tokens::Number16bit(_)
+                    | Assembly8086Tokens::Character(_)
+                    | Assembly8086Tokens::OpenParenthesis
+                    | Assembly8086Tokens::Plus
+                    | Assembly8086Tokens::Minus
+                    | Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Offset)
+            )
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Value, CompilationError> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => {
+                return Err(CompilationError::error_with_token(
+                    self.last_token(),
+                    &format!(
+                        "Expected a value after {}, Got nothing!",
+                        self.last_token().token_type
+                    ),
+                ))
+            }
+        };
+        match &token.token_type {
+            Assembly8086Tokens::Plus => {
+                self.position += 1;
+                self.parse_unary()
+            }
+            Assembly8086Tokens::Minus => {
+                self.position += 1;
+                let value = self.parse_unary()?;
+                Ok(Value {
+                    number: -value.number,
+                    address_count: -value.address_count,
+                    base: None,
+                })
+            }
+            Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Offset) => {
+                self.position += 1;
+                let (name_token, label) = self.expect_name("OFFSET")?;
+                Ok(Value::number(self.get_address(name_token, &label)?))
+            }
+            Assembly8086Tokens::Character(_) if self.has_operand_after() => {
+                match self.is_word(&PREFIX_OPERATORS).as_deref() {
+                    Some("high") | Some("low") => {
+                        let is_high = self.is_word(&["high"]).is_some();
+                        self.position += 1;
+                        let value = self.parse_unary()?;
+                        let number = Self::expect_number(token, &value, "HIGH (or) LOW")?;
+                        Ok(Value::number(match is_high {
+                            true => (number >> 8) & 0xFF,
+                            false => number & 0xFF,
+                        }))
+                    }
+                    Some("not") => self.parse_not(),
+                    Some(operator) => {
+                        let operator = operator.to_uppercase();
+                        self.position += 1;
+                        let (name_token, label) = self.expect_name(&operator)?;
+                        Ok(Value::number(
+                            self.get_attribute(&operator, name_token, &label)?,
+                        ))
+                    }
+                    None => self.parse_primary(),
+                }
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Value, CompilationError> {
+        let token = self.tokens[self.position];
+        self.position += 1;
+        match &token.token_type {
+            Assembly8086Tokens::Number8bit(number) => Ok(Value::number(*number as i64)),
+            Assembly8086Tokens::Number16bit(number) => Ok(Value::number(*number as i64)),
+            Assembly8086Tokens::OpenParenthesis => {
+                let value = self.parse_or()?;
+                match self.peek() {
+                    Some(close) if close.token_type == Assembly8086Tokens::CloseParenthesis => {
+                        self.position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(CompilationError::error_with_token(
+                        token,
+                        "This ( isn't closed, Please close it with )",
+                    )),
+                }
+            }
+            // the numbers wider than a word are lexed as names
+            Assembly8086Tokens::Character(label) if parse_wide_number(label).is_some() => {
+                match i64::try_from(parse_wide_number(label).unwrap_or_default()) {
+                    Ok(number) => Ok(Value::number(number)),
+                    Err(_) => Err(CompilationError::error_with_token(
+                        token,
+                        &format!("The number {} is too large for an expression", label),
+                    )),
+                }
+            }
+            Assembly8086Tokens::Character(label) => {
+                // a segment is a number, i.e mov ax, @data
+                let segment = self
+                    .context
+                    .as_deref()
+                    .and_then(|context| context.find_segment(label));
+                if let Some(segment) = segment {
+                    return Ok(Value::number(segment as i64));
+                }
+                let address = self.get_address(token, label)?;
+                Ok(Value {
+                    number: address,
+                    address_count: 1,
+                    base: Some((token.clone(), address)),
+                })
+            }
+            _ => Err(CompilationError::error_with_token(
+                token,
+                &format!("Expected a number, Got {} insted", token.token_type),
+            )),
+        }
+    }
+
+    fn expect_name(&mut self, operator: &str) -> Result<(&'a Token, Label), CompilationError> {
+        match self.peek() {
+            Some(token) => match &token.token_type {
+                Assembly8086Tokens::Character(label) => {
+                    self.position += 1;
+                    Ok((token, label.clone()))
+                }
+                _ => Err(CompilationError::error_with_token(
+                    token,
+                    &format!(
+                        "Expected a label (or) a variable after {}, Got {} insted",
+                        operator, token.token_type
+                    ),
+                )),
+            },
+            None => Err(CompilationError::error_with_token(
+                self.last_token(),
+                &format!(
+                    "Expected a label (or) a variable after {}, Got nothing!",
+                    operator
+                ),
+            )),
+        }
+    }
+
+    fn get_context(
+        &mut self,
+        token: &Token,
+        label: &Label,
+    ) -> Result<&mut LabelAddressContext<'b>, CompilationError> {
+        match self.context.as_deref_mut() {
+            Some(context) => Ok(context),
+            None => Err(CompilationError::error_with_token(
+                token,
+                &format!("\"{}\" isn't a constant", label),
+            )),
+        }
+    }
+
+    // the address is a placeholder until the labels are known, the line is compiled again then
+    fn get_address(&mut self, token: &Token, label: &Label) -> Result<i64, CompilationError> {
+        let context = self.get_context(token, label)?;
+        let address = get_label_address_or_push_into_ref(
+            0,
+            label,
+            token,
+            context.is_org_defined,
+            true,
+            VariableType::Word,
+            context.maps.variable_abs_address_map,
+            context.maps.variable_ref_map,
+            context.maps.label_idx_map,
+            context.maps.compiled_line_offset_maps,
+        );
+        Ok(match address {
+            Either::Left(address) => u16::from_le_bytes(address) as i64,
+            Either::Right(offset) => offset.get_as_u16() as i64,
+        })
+    }
+
+    // SEG, SIZE, LENGTH (or) TYPE of a variable
+    fn get_attribute(
+        &mut self,
+        operator: &str,
+        token: &Token,
+        label: &Label,
+    ) -> Result<i64, CompilationError> {
+        let address = self.get_address(token, label)?;
+        let context = self.get_context(token, label)?;
+        if operator == "SEG" {
+            return Ok(context.get_segment_of(label) as i64);
+        }
+        match context.maps.variable_abs_address_map.get(label) {
+            Some((variable_type, _, variable_size)) => Ok(match operator {
+                "SIZE" => variable_size.size,
+                "LENGTH" => variable_size.length,
+                _ => variable_type.get_size(),
+            } as i64),
+            // the variables are known when the line is compiled again
+            None if context.maps.compiled_line_offset_maps.is_none() => Ok(address),
+            None => Err(CompilationError::error_with_token(
+                token,
+                &format!(
+                    "The {} of \"{}\" can't be taken as it isn't a variable",
+                    operator, label
+                ),
+            )),
+        }
+    }
+}
+
+// A single operand (or) the ones the parsers take as they are, i.e bx + var - 2
+fn is_simple_operand(tokens: &[&Token], is_data: bool, segments: &Segments) -> bool {
+    // the segments are only known to the expressions
+    let is_segment = |token: &&Token| matches!(&token.token_type, Assembly8086Tokens::Character(name) if segments.is_segment_name(name));
+    if tokens.iter().any(is_segment) {
+        return false;
+    }
+    if is_data {
+        return match tokens {
+            [_] => true,
+            [sign, _] => matches!(
+                sign.token_type,
+                Assembly8086Tokens::Plus | Assembly8086Tokens::Minus
+            ),
+            _ => false,
+        };
+    }
+    let names = tokens
+        .iter()
+        .filter(|token| matches!(token.token_type, Assembly8086Tokens::Character(_)))
+        .count();
+    names <= 1
+        && tokens.iter().all(|token| match &token.token_type {
+            Assembly8086Tokens::Character(_) => is_name(&token.token_type),
+            token_type => matches!(
+                token_type,
+                Assembly8086Tokens::Number8bit(_)
+                    | Assembly8086Tokens::Number16bit(_)
+                    | Assembly8086Tokens::Plus
+                    | Assembly8086Tokens::Minus
+                    | Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Offset)
+            ),
+        })
+}
+
+// Replaces the expressions in the operands of an instruction (or) the data of a variable
+// with their value, returns None when the line doesn't have any
+pub(in crate::compiler) fn fold_expressions(
+    tokens: &[&Token],
+    context: &mut LabelAddressContext,
+) -> Result<Option<Vec<Token>>, CompilationError> {
+    let head = match tokens.iter().position(|token| {
+        matches!(
+            token.token_type,
+            Assembly8086Tokens::Instruction(_) | Assembly8086Tokens::Data(_)
+        )
+    }) {
+        Some(head) => head,
+        None => return Ok(None),
+    };
+    let is_data = matches!(tokens[head].token_type, Assembly8086Tokens::Data(_));
+
+    let mut folded_tokens = tokens[..=head]
+        .iter()
+        .map(|token| (*token).clone())
+        .collect::<Vec<Token>>();
+    let mut is_folded = false;
+    let mut i = head + 1;
+    while i < tokens.len() {
+        // the tokens of an expression, the parentheses of a dup aren't a part of it
+        let start = i;
+        let mut depth = 0;
+        while i < tokens.len() && is_expression_token(&tokens[i].token_type) {
+            match tokens[i].token_type {
+                Assembly8086Tokens::OpenParenthesis
+                    if tokens[i - 1].token_type
+                        == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Dup) =>
+                {
+                    break
+                }
+                Assembly8086Tokens::OpenParenthesis => depth += 1,
+                Assembly8086Tokens::CloseParenthesis if depth == 0 => break,
+                Assembly8086Tokens::CloseParenthesis => depth -= 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        // the sign before a register belongs to it, i.e [2 * 3 + bx]
+        if i > start + 1
+            && i < tokens.len()
+            && matches!(
+                tokens[i - 1].token_type,
+                Assembly8086Tokens::Plus | Assembly8086Tokens::Minus
+            )
+        {
+            i -= 1;
+        }
+        if i == start {
+            folded_tokens.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+
+        let expression = &tokens[start..i];
+        if is_simple_operand(expression, is_data, context.segments) {
+            folded_tokens.extend(expression.iter().map(|token| (*token).clone()));
+            continue;
+        }
+        let value = Evaluator::new(expression, Some(context)).evaluate()?;
+        let (first_token, last_token) = (expression[0], expression[expression.len() - 1]);
+        let token = Token::new(
+            Assembly8086Tokens::Comma,
+            first_token.line_number,
+            first_token.column_number,
+            (last_token.column_number + last_token.token_length)
+                .saturating_sub(first_token.column_number)
+                .max(1),
+        );
+        // the value is added to the register before it
+        if start > head + 1
+            && matches!(
+                first_token.token_type,
+                Assembly8086Tokens::Plus | Assembly8086Tokens::Minus
+            )
+        {
+            folded_tokens.push(get_token_at(&token, Assembly8086Tokens::Plus));
+        }
+
+        let number = match (value.address_count, &value.base) {
+            (0, _) => value.number,
+            // the data holds the address
+            (1, Some(_)) if is_data => value.number,
+            // a variable with an offset, i.e [var + 2 * 3]
+            (1, Some((base_token, address))) => {
+                folded_tokens.push(base_token.clone());
+                folded_tokens.push(get_token_at(&token, Assembly8086Tokens::Plus));
+                value.number - address
+            }
+            _ => {
+                return Err(CompilationError::error_with_token(
+                    &token,
+                    "The expression can't be used as it doesn't give a number (or) an address",
+                ))
+            }
+        };
+        if !is_data && !(-0x8000..=0xFFFF).contains(&number) {
+            return Err(CompilationError::error_with_token(
+                &token,
+                &format!(
+                    "The value {} of the expression doesn't fit in 16 bits",
+                    number
+                ),
+            ));
+        }
+        // a + before a - is dropped, i.e bx - 2
+        if number < 0
+            && folded_tokens
+                .last()
+                .is_some_and(|token| token.token_type == Assembly8086Tokens::Plus)
+        {
+            folded_tokens.pop();
+        }
+        folded_tokens.extend(get_number_tokens(&token, number));
+        is_folded = true;
+    }
+
+    Ok(match is_folded {
+        true => Some(folded_tokens),
+        false => None,
+    })
+}
+
+#[cfg(test)]
+mod test_expressions {
+    use crate::{
+        compile_and_compare_ins,
+        compiler::{compile_program, compile_str},
+        cpu::{instructions::test_macro::execute_code, registers::Register},
+        test_compile,
+    };
+
+    compile_and_compare_ins!(
+        equ_constants_in_instructions_and_data,
+        "
+        COLS equ 80
+        mov ax, COLS
+        mov bx, COLS * 2 + 1
+        mov cl, [bx+COLS]
+        row db COLS, -COLS, 2 dup(COLS / 2)
+        ",
+        vec![0xB8, 0x50, 0x00, 0xBB, 0xA1, 0x00, 0x8A, 0x4F, 0x50, 0x50, 0xB0, 0x28, 0x28]
+    );
+
+    compile_and_compare_ins!(
+        redefinable_constants,
+        "
+        count = 10
+        count = count + 1
+        mov ax, count
+        count = count * 2
+        mov bx, count
+        ",
+        vec![0xB8, 0x0B, 0x00, 0xBB, 0x16, 0x00]
+    );
+
+    compile_and_compare_ins!(
+        operators,
+        "
+        mov ax, (1 SHL 4) OR 3
+        mov ax, 17 MOD 5 + 0F0h AND 30h
+        mov ax, NOT 0 XOR 0FFh
+        mov cl, HIGH 1234h
+        mov ch, LOW (1234h SHR 4)
+        mov dx, -2 * 200
+        mov ax, [bx-2*2]
+        ",
+        vec![
+            0xB8, 0x13, 0x00, 0xB8, 0x30, 0x00, 0xB8, 0x00, 0xFF, 0xB1, 0x12, 0xB5, 0x23, 0xBA,
+            0x70, 0xFE, 0x8B, 0x47, 0xFC
+        ]
+    );
+
+    compile_and_compare_ins!(
+        negative_constants_in_16bit_operands,
+        "
+        K equ -1
+        J = 1 - 2
+        N equ -129
+        mov ax, K
+        mov ax, 2 - 3
+        cmp ax, J
+        add ax, K
+        add bx, K
+        mov cx, N
+        mov al, K
+        mov ax, 200
+        ",
+        vec![
+            0xB8, 0xFF, 0xFF, 0xB8, 0xFF, 0xFF, 0x3D, 0xFF, 0xFF, 0x05, 0xFF, 0xFF, 0x83, 0xC3,
+            0xFF, 0xB9, 0x7F, 0xFF, 0xB0, 0xFF, 0xB8, 0xC8, 0x00
+        ]
+    );
+
+    #[test]
+    fn negative_constants_in_16bit_operands_run() {
+        let (cpu, _) = execute_code(
+            "
+            K equ -1
+            COLS equ 80
+            mov bx, 0x10
+            add bx, K
+            mov cx, 0x100
+            add cx, -COLS
+            mov dx, 5
+            sub dx, K
+            mov si, 0x1234
+            and si, K - 0x0F
+            ",
+        );
+        assert_eq!(cpu.get_register(Register::BX), 0x000F);
+        assert_eq!(cpu.get_register(Register::CX), 0x00B0);
+        assert_eq!(cpu.get_register(Register::DX), 0x0006);
+        assert_eq!(cpu.get_register(Register::SI), 0x1230);
+    }
+
+    #[test]
+    fn negative_constants_in_word_variables_run() {
+        let (cpu, mem) = execute_code(
+            "
+            org 100h
+            jmp code
+            var dw 0x10
+            K equ -1
+            code:
+            add var, K
+            sub var, -4
+            ",
+        );
+        let data_segment = cpu.get_register(Register::DS);
+        assert_eq!(mem.peek_word(data_segment, 0x102), 0x0013);
+    }
+
+    test_compile!(
+        negative_constants_in_word_variables,
+        "
+        org 100h
+        jmp code
+        var dw 0
+        K equ -1
+        code:
+        mov var, K
+        sub var, K
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(
+                instructions[4..],
+                vec![0xC7, 0x06, 0x02, 0x01, 0xFF, 0xFF, 0x81, 0x2E, 0x02, 0x01, 0xFF, 0xFF]
+            );
+        }
+    );
+
+    test_compile!(
+        location_and_variable_operators,
+        "
+        org 100h
+        .data
+        msg db 'hi!'
+        len equ $ - msg
+        arr dw 4 dup(?)
+        code:
+        mov cx, len
+        mov cx, LENGTH arr
+        mov cx, SIZE arr
+        mov cx, TYPE arr
+        mov ax, SEG arr
+        mov ax, arr + 2 * 3
+        jmp $
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(
+                instructions[13..],
+                vec![
+                    0xB9, 0x03, 0x00, 0xB9, 0x04, 0x00, 0xB9, 0x08, 0x00, 0xB9, 0x02, 0x00, 0xB8,
+                    0x00, 0x07, 0xA1, 0x0B, 0x01, 0xEB, 0xFE
+                ]
+            );
+        }
+    );
+
+    #[test]
+    fn constants_are_not_labels() {
+        let program = compile_program("here equ $\n mov ax, 1\n jmp $", false).unwrap();
+        assert_eq!(program.label_offset_map.len(), 0);
+    }
+
+    #[test]
+    fn invalid_constants_are_errors() {
+        assert!(compile_str("x equ 1\n x equ 2", false).is_err());
+        assert!(compile_str("x = 1\n x equ 2", false).is_err());
+        assert!(compile_str("x equ 1\n x db 1", false).is_err());
+        assert!(compile_str("x equ", false).is_err());
+        assert!(compile_str("mov ax, 5 / 0", false).is_err());
+        assert!(compile_str("mov ax, (1 + 2", false).is_err());
+        assert!(compile_str("mov ax, 70000h / 1", false).is_err());
+        assert!(compile_str("var dw 1\n mov ax, var * 2", false).is_err());
+        assert!(compile_str("mov ax, SIZE here\n here: hlt", false).is_err());
+    }
+}
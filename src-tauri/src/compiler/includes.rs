@@ -0,0 +1,333 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Serialize;
+
+use super::{
+    compilation_error::CompilationError,
+    lexer::Lexer,
+    tokens::{assembler_directives::AssemblerDirectives, Assembly8086Tokens, Token},
+};
+
+pub type FileId = u32;
+// the file being compiled, the included files get the ids after it in the order they are included
+pub const MAIN_FILE_ID: FileId = 0;
+
+// Where the files of INCLUDE are read from, the files passed from the editor
+// are looked up first and then the ones in the project folder
+#[derive(Debug, Clone, Default)]
+pub struct SourceFiles {
+    pub root: Option<PathBuf>,
+    pub files: HashMap<String, String>,
+}
+
+impl SourceFiles {
+    pub fn from_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: Some(root.into()),
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn from_files(files: HashMap<String, String>) -> Self {
+        Self { root: None, files }
+    }
+
+    // the names are case insensitive like in DOS, i.e INCLUDE MACROS.INC finds macros.inc
+    pub fn read(&self, name: &str) -> Option<String> {
+        let code = self.files.get(name).or_else(|| {
+            self.files
+                .iter()
+                .find(|(file_name, _)| file_name.eq_ignore_ascii_case(name))
+                .map(|(_, code)| code)
+        });
+        if let Some(code) = code {
+            return Some(code.clone());
+        }
+        let root = self.root.as_ref()?;
+        std::fs::read_to_string(root.join(name.replace('\\', "/"))).ok()
+    }
+}
+
+// The INCLUDE line that brought a file into the program
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IncludeSite {
+    // the included file
+    pub file_name: String,
+    // the file with the INCLUDE and the line of it
+    pub file_id: FileId,
+    pub line_number: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub name: String,
+    pub included_from: Option<(FileId, u32)>,
+}
+
+// The includes are expanded before the lexer runs, so the compiler sees a single source
+// and this maps every line of it back to the file and the line it came from
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    // indexed by the file id, the main file doesn't have a name
+    pub files: Vec<SourceFile>,
+    pub lines: Vec<(FileId, u32)>,
+}
+
+impl SourceMap {
+    pub fn locate(&self, line_number: u32) -> (FileId, u32) {
+        self.lines
+            .get(line_number as usize)
+            .copied()
+            .unwrap_or((MAIN_FILE_ID, line_number))
+    }
+
+    // the include that brought in the file first, up to the one in the main file
+    pub fn include_chain(&self, file_id: FileId) -> Vec<IncludeSite> {
+        let mut chain = Vec::new();
+        let mut file_id = file_id;
+        while let Some(file) = self.files.get(file_id as usize) {
+            let (parent_id, line_number) = match file.included_from {
+                Some(included_from) => included_from,
+                None => break,
+            };
+            chain.push(IncludeSite {
+                file_name: file.name.clone(),
+                file_id: parent_id,
+                line_number,
+            });
+            file_id = parent_id;
+        }
+        chain
+    }
+}
+
+struct IncludeExpander<'a> {
+    source_files: &'a SourceFiles,
+    source: Vec<String>,
+    source_map: SourceMap,
+    compilation_errors: Vec<CompilationError>,
+}
+
+// the name after INCLUDE, it can be quoted like INCLUDE 'macros.inc' (or) <macros.inc>
+fn get_included_file_name<'a>(tokens: &'a [Token], line: &str) -> Option<(&'a Token, String)> {
+    let mut tokens = tokens
+        .iter()
+        .filter(|token| token.token_type != Assembly8086Tokens::Space);
+    let include_token = tokens.next()?;
+    if include_token.token_type
+        != Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Include)
+    {
+        return None;
+    }
+    let comment_column = tokens
+        .find(|token| token.token_type == Assembly8086Tokens::Comment)
+        .map(|token| token.column_number as usize);
+    let name_start = (include_token.column_number + include_token.token_length) as usize;
+    let name = line
+        .chars()
+        .take(comment_column.unwrap_or(usize::MAX))
+        .skip(name_start)
+        .collect::<String>();
+    let name = name
+        .trim()
+        .trim_matches(|c| matches!(c, '\'' | '"' | '<' | '>'))
+        .to_string();
+    Some((include_token, name))
+}
+
+impl<'a> IncludeExpander<'a> {
+    fn push_error(&mut self, token: &Token, line_number: u32, msg: &str) {
+        self.compilation_errors
+            .push(CompilationError::new_without_suggestions(
+                line_number,
+                token.column_number,
+                token.token_length,
+                msg,
+            ));
+    }
+
+    fn expand_file(&mut self, code: &str, file_id: FileId, open_files: &mut Vec<String>) {
+        let mut lexer = Lexer::new();
+        lexer.tokenize(code);
+
+        for (line_number, line) in code.lines().enumerate() {
+            let expanded_line_number = self.source.len() as u32;
+            self.source.push(line.to_string());
+            self.source_map.lines.push((file_id, line_number as u32));
+
+            let tokens = match lexer.tokens.get(line_number) {
+                Some(tokens) => tokens,
+                None => continue,
+            };
+            let (include_token, name) = match get_included_file_name(tokens, line) {
+                Some(include) => include,
+                None => continue,
+            };
+            if name.is_empty() {
+                self.push_error(
+                    include_token,
+                    expanded_line_number,
+                    "The INCLUDE is missing the name of the file, Please use it like INCLUDE 'macros.inc'",
+                );
+                continue;
+            }
+            if open_files
+                .iter()
+                .any(|file| file.eq_ignore_ascii_case(&name))
+            {
+                self.push_error(
+                    include_token,
+                    expanded_line_number,
+                    &format!(
+                        "The file \"{}\" includes itself through {} -> {}, Please remove this INCLUDE",
+                        name,
+                        open_files.join(" -> "),
+                        name
+                    ),
+                );
+                continue;
+            }
+            let included_code = match self.source_files.read(&name) {
+                Some(included_code) => included_code,
+                None => {
+                    self.push_error(
+                        include_token,
+                        expanded_line_number,
+                        &format!(
+                            "Can't find the file \"{}\", Please add it to the project (or) check the name",
+                            name
+                        ),
+                    );
+                    continue;
+                }
+            };
+
+            let included_file_id = self.source_map.files.len() as FileId;
+            self.source_map.files.push(SourceFile {
+                name: name.clone(),
+                included_from: Some((file_id, line_number as u32)),
+            });
+            open_files.push(name);
+            self.expand_file(&included_code, included_file_id, open_files);
+            open_files.pop();
+        }
+    }
+}
+
+// Puts the lines of every included file right after its INCLUDE line,
+// the compiler works on the lines of the expanded source and relocates the errors at the end
+pub(in crate::compiler) fn expand_includes(
+    code: &str,
+    source_files: &SourceFiles,
+) -> Result<(String, SourceMap), Vec<CompilationError>> {
+    let mut expander = IncludeExpander {
+        source_files,
+        source: Vec::new(),
+        source_map: SourceMap {
+            files: vec![SourceFile {
+                name: String::new(),
+                included_from: None,
+            }],
+            lines: Vec::new(),
+        },
+        compilation_errors: Vec::new(),
+    };
+    expander.expand_file(code, MAIN_FILE_ID, &mut Vec::new());
+
+    if !expander.compilation_errors.is_empty() {
+        let source_map = &expander.source_map;
+        for err in &mut expander.compilation_errors {
+            err.relocate(source_map);
+        }
+        return Err(expander.compilation_errors);
+    }
+    Ok((expander.source.join("\n"), expander.source_map))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::compiler::{
+        compile_program_with_options, includes::MAIN_FILE_ID, try_compile_code,
+        types_structs::CompileOptions,
+    };
+
+    use super::SourceFiles;
+
+    fn get_compile_options(files: &[(&str, &str)]) -> CompileOptions {
+        CompileOptions {
+            source_files: SourceFiles::from_files(
+                files
+                    .iter()
+                    .map(|(name, code)| (name.to_string(), code.to_string()))
+                    .collect::<HashMap<String, String>>(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn include_macros_and_data() {
+        let compile_options = get_compile_options(&[
+            (
+                "macros.inc",
+                "inc_twice macro reg\n inc reg\n inc reg\nendm",
+            ),
+            ("DATA.INC", "var db 0x10"),
+        ]);
+        let code =
+            "INCLUDE 'macros.inc'\ninclude data.inc ; the variables\ninc_twice ax\nmov al, var";
+        let program = compile_program_with_options(code, &compile_options, false).unwrap();
+        assert_eq!(program.bytes, vec![0x10, 0x40, 0x40, 0xA0, 0x00, 0x00]);
+
+        // the bytes are on the lines of the files they came from
+        let lines = program
+            .compiled_bytes_ref
+            .iter()
+            .map(|compiled_bytes| (compiled_bytes.file_id, compiled_bytes.line_number))
+            .collect::<Vec<_>>();
+        assert_eq!(lines[0], (2, 0));
+        assert_eq!(lines.last(), Some(&(MAIN_FILE_ID, 3)));
+        assert!(program.listing.to_string().contains("   1C  0100:0000  10"));
+    }
+
+    #[test]
+    fn include_chain_of_nested_errors() {
+        let compile_options =
+            get_compile_options(&[("a.inc", "; a\ninclude b.inc"), ("b.inc", "\n\nmov ax, bl")]);
+        let errors = compile_program_with_options("inc ax\ninclude a.inc", &compile_options, false)
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        let error = serde_json::to_value(&errors[0]).unwrap();
+        assert_eq!(error["file_id"], 2);
+        assert_eq!(error["line_number"], 2);
+        assert_eq!(errors[0].get_file_name(), Some("b.inc"));
+        assert_eq!(
+            error["include_chain"],
+            serde_json::json!([
+                { "file_name": "b.inc", "file_id": 1, "line_number": 1 },
+                { "file_name": "a.inc", "file_id": 0, "line_number": 1 },
+            ])
+        );
+    }
+
+    #[test]
+    fn include_errors() {
+        let compile_options = get_compile_options(&[
+            ("a.inc", "include b.inc"),
+            ("b.inc", "inc ax\ninclude 'A.INC'"),
+        ]);
+        let errors = try_compile_code("include a.inc", &compile_options).unwrap_err();
+        let error = serde_json::to_value(&errors[0]).unwrap();
+        assert!(error["message"]
+            .as_str()
+            .unwrap()
+            .contains("a.inc -> b.inc -> A.INC"));
+        assert_eq!(error["file_id"], 2);
+        assert_eq!(error["line_number"], 1);
+
+        assert!(try_compile_code("include missing.inc", &compile_options).is_err());
+        assert!(try_compile_code("include ; no name", &compile_options).is_err());
+    }
+}
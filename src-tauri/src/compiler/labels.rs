@@ -0,0 +1,367 @@
+use std::collections::{HashMap, HashSet};
+
+use unicase::UniCase;
+
+use super::{
+    compilation_error::CompilationError,
+    compilation_utils::check_is_label,
+    strip_space_and_comments_and_iterate_labels,
+    suggestions::SuggestionType,
+    tokens::{instructions::Instructions, Assembly8086Tokens, Token},
+    types_structs::{Label, LabelAddressMap, LineNumber},
+};
+
+// @@: is an anonymous label, @F jumps to the next one and @B to the one before
+const ANONYMOUS_LABEL: &str = "@@";
+const LOCAL_LABEL_PREFIX: &str = "@@";
+
+fn is_forward_reference(label: &str) -> bool {
+    label.eq_ignore_ascii_case("@F")
+}
+
+fn is_backward_reference(label: &str) -> bool {
+    label.eq_ignore_ascii_case("@B")
+}
+
+fn is_local_label(label: &str) -> bool {
+    label.starts_with(LOCAL_LABEL_PREFIX) && label.len() > LOCAL_LABEL_PREFIX.len()
+}
+
+// the number of characters to insert, remove (or) replace to get from one name to the other
+fn get_edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut distances = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut previous_diagonal = distances[0];
+        distances[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let replaced = previous_diagonal + usize::from(!a_char.eq_ignore_ascii_case(b_char));
+            previous_diagonal = distances[j + 1];
+            distances[j + 1] = replaced.min(distances[j] + 1).min(distances[j + 1] + 1);
+        }
+    }
+    distances[b.len()]
+}
+
+// the label with the name closest to the misspelled one, if it is close enough to be a typo
+fn get_closest_label<'a>(label: &str, labels: &'a [Label]) -> Option<&'a Label> {
+    let max_distance = (label.len() / 3).max(1);
+    labels
+        .iter()
+        .map(|candidate| (get_edit_distance(label, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// PROC name (or) ENDP name
+fn get_proc_line<'a>(stripped_line: &[&'a Token]) -> Option<(&'a Instructions, &'a Label)> {
+    let start = match check_is_label(&stripped_line.to_vec()) {
+        Some(_) => 2,
+        None => 0,
+    };
+    match (
+        stripped_line.get(start).map(|token| &token.token_type),
+        stripped_line.get(start + 1).map(|token| &token.token_type),
+    ) {
+        (
+            Some(Assembly8086Tokens::Instruction(
+                instruction @ (Instructions::Proc | Instructions::EndP),
+            )),
+            Some(Assembly8086Tokens::Character(name)),
+        ) => Some((instruction, name)),
+        _ => None,
+    }
+}
+
+// The labels starting with @@ are only visible in the PROC they are defined in,
+// so every PROC can have its own @@again. They are kept in the maps as proc@@again
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LabelScopes {
+    // the PROC of every line, None outside of the procs
+    line_procs: Vec<Option<Label>>,
+    // the lines of the @@: labels in their order
+    anonymous_label_lines: Vec<LineNumber>,
+    // the name written in the code for every label that is renamed
+    names: HashMap<Label, Label>,
+    defined_labels: HashSet<Label>,
+}
+
+impl LabelScopes {
+    pub fn new(lines: &[Vec<Token>], warnings: &mut Vec<CompilationError>) -> Self {
+        let mut label_scopes = LabelScopes::default();
+        let mut current_proc = None;
+        for (i, line) in lines.iter().enumerate() {
+            let (stripped_line, label) = strip_space_and_comments_and_iterate_labels(line);
+            match get_proc_line(&stripped_line) {
+                Some((Instructions::Proc, name)) => current_proc = Some(name.clone()),
+                Some(_) => {
+                    // the ENDP line is still in the proc
+                    label_scopes.line_procs.push(current_proc.take());
+                    continue;
+                }
+                None => {}
+            }
+            label_scopes.line_procs.push(current_proc.clone());
+            if label.as_deref() == Some(ANONYMOUS_LABEL) {
+                label_scopes.anonymous_label_lines.push(i);
+            }
+        }
+
+        let mut local_labels = Vec::new();
+        let mut global_labels = HashMap::new();
+        for (i, line) in lines.iter().enumerate() {
+            let (stripped_line, label) = strip_space_and_comments_and_iterate_labels(line);
+            let label = match label {
+                Some(label) => label,
+                None => continue,
+            };
+            let key = label_scopes.get_scoped_key(&label, i);
+            label_scopes.defined_labels.insert(key.clone());
+            match key.as_str() == label {
+                true => {
+                    global_labels.entry(key).or_insert(i);
+                }
+                false => {
+                    label_scopes
+                        .names
+                        .insert(key.clone(), UniCase::new(label.clone()));
+                    if is_local_label(&label) {
+                        local_labels.push((UniCase::new(label), stripped_line[0]));
+                    }
+                }
+            }
+        }
+
+        // a @@label in a proc hides the @@label outside of the procs
+        for (label, label_token) in local_labels {
+            if let Some(line_number) = global_labels.get(&label) {
+                warnings.push(CompilationError::error_with_token(
+                    label_token,
+                    &format!(
+                        "The label \"{}\" hides the label \"{}\" on line {}, Please use a different name.",
+                        label,
+                        label,
+                        line_number + 1
+                    ),
+                ));
+            }
+        }
+        label_scopes
+    }
+
+    fn get_proc(&self, line_number: LineNumber) -> Option<&Label> {
+        self.line_procs
+            .get(line_number)
+            .and_then(|proc| proc.as_ref())
+    }
+
+    fn get_anonymous_key(idx: usize) -> Label {
+        UniCase::new(format!("{}:{}", ANONYMOUS_LABEL, idx + 1))
+    }
+
+    fn get_scoped_key(&self, label: &str, line_number: LineNumber) -> Label {
+        let anonymous_labels = &self.anonymous_label_lines;
+        let anonymous_idx = match label {
+            ANONYMOUS_LABEL => anonymous_labels
+                .iter()
+                .position(|line| *line == line_number),
+            _ if is_forward_reference(label) => {
+                anonymous_labels.iter().position(|line| *line > line_number)
+            }
+            // the label on the same line is before the instruction
+            _ if is_backward_reference(label) => anonymous_labels
+                .iter()
+                .rposition(|line| *line <= line_number),
+            _ => None,
+        };
+        if let Some(idx) = anonymous_idx {
+            return Self::get_anonymous_key(idx);
+        }
+        match self.get_proc(line_number) {
+            Some(proc) if is_local_label(label) => UniCase::new(format!("{}{}", proc, label)),
+            _ => UniCase::new(label.to_string()),
+        }
+    }
+
+    // the name the label is kept as in the maps, for the label written on the line.
+    // a @@label that isn't in the proc can still be outside of the procs
+    pub fn get_key(&self, label: &str, line_number: LineNumber) -> Label {
+        let key = self.get_scoped_key(label, line_number);
+        let label = UniCase::new(label.to_string());
+        match !self.defined_labels.contains(&key) && self.defined_labels.contains(&label) {
+            true => label,
+            false => key,
+        }
+    }
+
+    // the name written in the code, to find the token of the label
+    pub fn get_name<'a>(&'a self, key: &'a Label) -> &'a Label {
+        self.names.get(key).unwrap_or(key)
+    }
+
+    // the error of a label that isn't defined, the @@labels could be in a different proc
+    pub fn get_undefined_label_error(
+        &self,
+        token: &Token,
+        line_number: LineNumber,
+        label_addr_map: &LabelAddressMap,
+    ) -> CompilationError {
+        let label = match &token.token_type {
+            Assembly8086Tokens::Character(label) => label.to_string(),
+            token_type => token_type.to_string(),
+        };
+        if is_forward_reference(&label) || is_backward_reference(&label) {
+            let direction = match is_forward_reference(&label) {
+                true => "after",
+                false => "before",
+            };
+            return CompilationError::error_with_token(
+                token,
+                &format!(
+                    "There is no @@: label {} the {}, Please define one.",
+                    direction,
+                    label.to_uppercase()
+                ),
+            );
+        }
+        // the labels that can be used on the line
+        let mut suggestions = label_addr_map
+            .keys()
+            .filter(|key| {
+                let name = self.get_name(key);
+                name.as_str() != ANONYMOUS_LABEL && self.get_key(name, line_number) == **key
+            })
+            .map(|key| self.get_name(key).clone())
+            .collect::<Vec<Label>>();
+        suggestions.sort();
+        let proc = self.get_proc(line_number);
+        let other_proc = label_addr_map
+            .iter()
+            .filter(|(key, _)| {
+                self.get_name(key).eq_ignore_ascii_case(&label) && !key.eq_ignore_ascii_case(&label)
+            })
+            .find_map(|(_, line_number)| self.get_proc(*line_number));
+        let message = match (other_proc, proc) {
+            (Some(other_proc), Some(proc)) => format!(
+                "The label \"{}\" is local to the proc \"{}\", Please define it in the proc \"{}\" (or) remove the @@ to use it everywhere.",
+                label, other_proc, proc
+            ),
+            (Some(other_proc), None) => format!(
+                "The label \"{}\" is local to the proc \"{}\", Please remove the @@ to use it outside of the proc.",
+                label, other_proc
+            ),
+            _ => match get_closest_label(&label, &suggestions) {
+                Some(closest) => format!(
+                    "The label \"{}\" is Undefined, Please define it (or) use the label \"{}\".",
+                    label, closest
+                ),
+                None => format!("The label \"{}\" is Undefined, Please define it.", label),
+            },
+        };
+        CompilationError::new_with_suggestions(
+            token.line_number,
+            token.column_number,
+            token.token_length,
+            &message,
+            vec![suggestions.into_iter().map(SuggestionType::Label).collect()],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unicase::UniCase;
+
+    use crate::compiler::{compile_program, compile_str};
+
+    #[test]
+    fn local_labels_in_every_proc() {
+        let code = "PROC first
+                @@again: dec cx
+                jnz @@again
+                ret
+            ENDP first
+            PROC second
+                @@again: dec dx
+                jnz @@again
+                ret
+            ENDP second";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(
+            program.bytes,
+            vec![0x49, 0x75, 0xFD, 0xC3, 0x4A, 0x75, 0xFD, 0xC3]
+        );
+        assert!(program
+            .label_offset_map
+            .contains_key(&UniCase::new("second@@again".to_string())));
+    }
+
+    #[test]
+    fn anonymous_labels() {
+        let code = "@@: dec cx
+            jz @F
+            jmp @B
+            @@: inc ax
+            jmp @b";
+        let (bytes, _) = compile_str(code, false).unwrap();
+        assert_eq!(bytes, vec![0x49, 0x74, 0x02, 0xEB, 0xFB, 0x40, 0xEB, 0xFD]);
+
+        assert!(compile_str("jmp @F\n@@: inc ax\njmp @F", false).is_err());
+        assert!(compile_str("jmp @B\n@@: inc ax", false).is_err());
+    }
+
+    #[test]
+    fn out_of_scope_and_shadowed_labels() {
+        let code = "PROC first
+                @@done: ret
+            ENDP first
+            PROC second
+                jmp @@done
+            ENDP second";
+        let errors = compile_program(code, false).unwrap_err();
+        assert_eq!(
+            errors[0].to_short_string(),
+            "5:21: The label \"@@done\" is local to the proc \"first\", Please define it in the proc \"second\" (or) remove the @@ to use it everywhere."
+        );
+
+        let code = "@@done: hlt
+            PROC first
+                @@done: ret
+            ENDP first";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(program.warnings.len(), 1);
+    }
+
+    #[test]
+    fn misspelled_labels_name_the_closest_one() {
+        let code = "PROC first
+                @@again: dec cx
+                jnz @@agian
+                jmp @@other
+            ENDP first
+            PROC second
+                @@other: ret
+            ENDP second";
+        let errors = compile_program(code, false).unwrap_err();
+        assert_eq!(
+            errors[0].to_short_string(),
+            "3:21: The label \"@@agian\" is Undefined, Please define it (or) use the label \"@@again\"."
+        );
+        assert_eq!(
+            errors[1].to_short_string(),
+            "4:21: The label \"@@other\" is local to the proc \"second\", Please define it in the proc \"first\" (or) remove the @@ to use it everywhere."
+        );
+
+        let errors = compile_program(
+            "jmp @@xyz
+main: hlt",
+            false,
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors[0].to_short_string(),
+            "1:5: The label \"@@xyz\" is Undefined, Please define it."
+        );
+    }
+}
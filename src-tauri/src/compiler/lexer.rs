@@ -24,7 +24,7 @@ impl Lexer {
             let mut iterating_col_num = 0;
             // convert the string into array of chars don't use line.chars()
             let line_chars: Vec<char> = line.chars().collect();
-            while iterating_col_num < line.len() {
+            while iterating_col_num < line_chars.len() {
                 match line_chars[iterating_col_num] {
                     ' ' => {
                         // count the number of spaces
@@ -57,7 +57,18 @@ impl Lexer {
                             iterating_col_num as u32,
                             1,
                         ));
-                        iterating_col_num = line.len();
+                        iterating_col_num = line_chars.len();
+                    }
+                    quote @ ('\'' | '"') => {
+                        let (token_type, token_length) =
+                            Self::tokenize_string(&line_chars[iterating_col_num..], quote);
+                        temp_vec.push(Token::new(
+                            token_type,
+                            line_number,
+                            iterating_col_num as u32,
+                            token_length as u32,
+                        ));
+                        iterating_col_num += token_length;
                     }
                     ':' => {
                         temp_vec.push(Token::new(
@@ -104,11 +115,56 @@ impl Lexer {
                         ));
                         iterating_col_num += 1;
                     }
+                    '*' => {
+                        temp_vec.push(Token::new(
+                            Assembly8086Tokens::Multiply,
+                            line_number,
+                            iterating_col_num as u32,
+                            1,
+                        ));
+                        iterating_col_num += 1;
+                    }
+                    '/' => {
+                        temp_vec.push(Token::new(
+                            Assembly8086Tokens::Divide,
+                            line_number,
+                            iterating_col_num as u32,
+                            1,
+                        ));
+                        iterating_col_num += 1;
+                    }
+                    '=' => {
+                        temp_vec.push(Token::new(
+                            Assembly8086Tokens::Equals,
+                            line_number,
+                            iterating_col_num as u32,
+                            1,
+                        ));
+                        iterating_col_num += 1;
+                    }
+                    '(' => {
+                        temp_vec.push(Token::new(
+                            Assembly8086Tokens::OpenParenthesis,
+                            line_number,
+                            iterating_col_num as u32,
+                            1,
+                        ));
+                        iterating_col_num += 1;
+                    }
+                    ')' => {
+                        temp_vec.push(Token::new(
+                            Assembly8086Tokens::CloseParenthesis,
+                            line_number,
+                            iterating_col_num as u32,
+                            1,
+                        ));
+                        iterating_col_num += 1;
+                    }
                     _ => {
                         let mut token_length = 0;
                         let mut token_string_buffer = String::new();
                         let mut i = iterating_col_num;
-                        while i < line.len() {
+                        while i < line_chars.len() {
                             let c = line_chars[i];
                             if c.is_whitespace()
                                 || c == ','
@@ -118,6 +174,11 @@ impl Lexer {
                                 || c == ']'
                                 || c == '+'
                                 || c == '-'
+                                || c == '*'
+                                || c == '/'
+                                || c == '='
+                                || c == '('
+                                || c == ')'
                             {
                                 break;
                             }
@@ -141,11 +202,74 @@ impl Lexer {
                     }
                 }
             }
-            self.tokens.push(temp_vec);
+            self.tokens.push(Self::merge_size_operators(temp_vec));
+        }
+    }
+
+    // BYTE PTR and WORD PTR are the same as b. and w.
+    fn merge_size_operators(tokens: Vec<Token>) -> Vec<Token> {
+        let mut merged_tokens = Vec::<Token>::new();
+        for token in tokens {
+            let is_ptr = token.token_type
+                == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Ptr);
+            let size_idx = merged_tokens
+                .iter()
+                .rposition(|token| token.token_type != Assembly8086Tokens::Space);
+            let size = size_idx.and_then(|idx| match &merged_tokens[idx].token_type {
+                Assembly8086Tokens::Character(size) if size.eq_ignore_ascii_case("byte") => {
+                    Some(AssemblerDirectives::AsByte)
+                }
+                Assembly8086Tokens::Character(size) if size.eq_ignore_ascii_case("word") => {
+                    Some(AssemblerDirectives::AsWord)
+                }
+                _ => None,
+            });
+            match (is_ptr, size_idx, size) {
+                (true, Some(idx), Some(size)) => {
+                    let size_token = &merged_tokens[idx];
+                    let merged_token = Token::new(
+                        Assembly8086Tokens::AssemblerDirectives(size),
+                        size_token.line_number,
+                        size_token.column_number,
+                        token.column_number + token.token_length - size_token.column_number,
+                    );
+                    merged_tokens.truncate(idx);
+                    merged_tokens.push(merged_token);
+                }
+                _ => merged_tokens.push(token),
+            }
+        }
+        merged_tokens
+    }
+
+    // A quoted string where the quote is escaped by doubling it, i.e 'it''s',
+    // returns the token and the number of chars it took including the quotes
+    fn tokenize_string(chars: &[char], quote: char) -> (Assembly8086Tokens, usize) {
+        let mut string = String::new();
+        let mut i = 1;
+        while i < chars.len() {
+            if chars[i] != quote {
+                string.push(chars[i]);
+                i += 1;
+            } else if chars.get(i + 1) == Some(&quote) {
+                string.push(quote);
+                i += 2;
+            } else {
+                return (get_string_token(string), i + 1);
+            }
         }
+        // a string that isn't closed is left as a label, so it is reported as an unknown one
+        let text = chars.iter().collect::<String>();
+        (
+            Assembly8086Tokens::Character(UniCase::new(text)),
+            chars.len(),
+        )
     }
 
     fn str_to_token(&self, token_string: &str) -> Option<Assembly8086Tokens> {
+        if token_string == "?" {
+            return Some(Assembly8086Tokens::QuestionMark);
+        }
         if let Ok(directive) = AssemblerDirectives::from_str(token_string) {
             return Some(Assembly8086Tokens::AssemblerDirectives(directive));
         }
@@ -228,8 +352,99 @@ impl Lexer {
     }
 }
 
+// a single character is its code, i.e 'A' is 0x41
+pub(crate) fn get_string_token(string: String) -> Assembly8086Tokens {
+    let mut string_chars = string.chars();
+    match (string_chars.next(), string_chars.next()) {
+        (Some(c), None) if (c as u32) <= u8::MAX as u32 => Assembly8086Tokens::Number8bit(c as u8),
+        _ => Assembly8086Tokens::String(string),
+    }
+}
+
+// A number that doesn't fit in a token, i.e the 32, 64 and 80 bit values of dd, dq and dt
+pub(crate) fn parse_wide_number(token_string: &str) -> Option<u128> {
+    let token_string = token_string.to_lowercase().replace('_', "");
+    if let Some(stripped_token) = token_string.strip_prefix("0x") {
+        u128::from_str_radix(stripped_token, 16).ok()
+    } else if let Some(stripped_token) = token_string.strip_suffix('h') {
+        u128::from_str_radix(stripped_token, 16).ok()
+    } else if let Some(stripped_token) = token_string.strip_suffix('b') {
+        u128::from_str_radix(stripped_token, 2).ok()
+    } else if let Some(stripped_token) = token_string.strip_suffix('o') {
+        u128::from_str_radix(stripped_token, 8).ok()
+    } else {
+        token_string.parse::<u128>().ok()
+    }
+}
+
 impl Default for Lexer {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use unicase::UniCase;
+
+    use crate::compiler::{
+        compile_str,
+        tokens::{data::DefineData, Assembly8086Tokens},
+    };
+
+    use super::Lexer;
+
+    fn get_token_types(line: &str) -> Vec<(Assembly8086Tokens, u32, u32)> {
+        let mut lexer = Lexer::new();
+        lexer.tokenize(line);
+        lexer.tokens[0]
+            .iter()
+            .filter(|token| token.token_type != Assembly8086Tokens::Space)
+            .map(|token| {
+                (
+                    token.token_type.clone(),
+                    token.column_number,
+                    token.token_length,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strings_keep_their_spaces_commas_and_doubled_quotes() {
+        assert_eq!(
+            get_token_types("msg db 'a, ''b'';', \"é\" ; ü"),
+            vec![
+                (
+                    Assembly8086Tokens::Character(UniCase::new("msg".to_string())),
+                    0,
+                    3
+                ),
+                (Assembly8086Tokens::Data(DefineData::Db), 4, 2),
+                (Assembly8086Tokens::String("a, 'b';".to_string()), 7, 11),
+                (Assembly8086Tokens::Comma, 18, 1),
+                (Assembly8086Tokens::Number8bit(0xE9), 20, 3),
+                (Assembly8086Tokens::Comment, 24, 1),
+            ]
+        );
+        // an unclosed string takes the rest of the line
+        assert_eq!(
+            get_token_types("db 'ab"),
+            vec![
+                (Assembly8086Tokens::Data(DefineData::Db), 0, 2),
+                (
+                    Assembly8086Tokens::Character(UniCase::new("'ab".to_string())),
+                    3,
+                    3
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn characters_are_immediates() {
+        let (bytes, _) = compile_str("mov al, 'A'\ncmp bl, ';'\nmov cx, \"'\"", false).unwrap();
+        assert_eq!(bytes, vec![0xB0, 0x41, 0x80, 0xFB, 0x3B, 0xB9, 0x27, 0x00]);
+    }
+}
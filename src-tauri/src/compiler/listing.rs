@@ -0,0 +1,451 @@
+use std::fmt::Write;
+
+use crate::consts::Word;
+
+use super::{
+    expressions::is_location_label,
+    includes::{FileId, SourceMap, MAIN_FILE_ID},
+    macros::MacroSourceMap,
+    tokens::{Assembly8086Tokens, Token},
+    types_structs::{
+        LabelAddressMap, LineNumber, MacroBoundsDefintionMap, ProcDefinitionLineNumberMap,
+        VariableAddressDefinitionMap, VariableAddressMap, VariableType,
+    },
+};
+
+// the number of bytes shown in a single row, the rest continue in the next rows
+const BYTES_PER_ROW: usize = 6;
+const MACRO_INDENT: &str = "    ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    // the line in the source code, None for the lines of a macro expansion
+    pub line_number: Option<LineNumber>,
+    pub segment: Word,
+    pub offset: Word,
+    pub bytes: Vec<u8>,
+    pub source: String,
+    pub expansion_depth: usize,
+    pub file_id: FileId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListingSymbolKind {
+    Label,
+    Variable(VariableType),
+    Proc,
+    Macro,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingSymbol {
+    pub name: String,
+    pub kind: ListingSymbolKind,
+    pub size: Option<usize>,
+    pub segment: Word,
+    // macros don't have an address, they are defined at a line instead
+    pub offset: Option<Word>,
+    pub line_number: Option<LineNumber>,
+    pub file_id: FileId,
+}
+
+// A MASM style listing of a compiled program, with the symbol table at the end
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    // the segment the program is loaded at, the lines of the segments are after it
+    pub segment: Word,
+    pub lines: Vec<ListingLine>,
+    pub symbols: Vec<ListingSymbol>,
+}
+
+impl Listing {
+    // the listing is made from the source with the includes expanded,
+    // this moves the lines back to the lines of the files they are in
+    pub(super) fn relocate(&mut self, source_map: &SourceMap) {
+        for line in &mut self.lines {
+            if let Some(line_number) = line.line_number {
+                let (file_id, line_number) = source_map.locate(line_number as u32);
+                line.file_id = file_id;
+                line.line_number = Some(line_number as LineNumber);
+            }
+        }
+        for symbol in &mut self.symbols {
+            if let Some(line_number) = symbol.line_number {
+                let (file_id, line_number) = source_map.locate(line_number as u32);
+                symbol.file_id = file_id;
+                symbol.line_number = Some(line_number as LineNumber);
+            }
+        }
+    }
+}
+
+// the lines of an included file are marked with a C like in MASM
+fn fmt_line_number(line_number: LineNumber, file_id: FileId) -> String {
+    match file_id {
+        MAIN_FILE_ID => format!("{}", line_number + 1),
+        _ => format!("{}C", line_number + 1),
+    }
+}
+
+impl std::fmt::Display for Listing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            let line_number = match line.line_number {
+                Some(line_number) => fmt_line_number(line_number, line.file_id),
+                None => String::new(),
+            };
+            let source = format!(
+                "{}{}",
+                MACRO_INDENT.repeat(line.expansion_depth),
+                line.source.trim_end()
+            );
+            if line.bytes.is_empty() {
+                writeln!(f, "{:>5}  {:9}  {:18}  {}", line_number, ' ', ' ', source)?;
+                continue;
+            }
+            for (i, row) in line.bytes.chunks(BYTES_PER_ROW).enumerate() {
+                let offset = line.offset.wrapping_add((i * BYTES_PER_ROW) as Word);
+                let address = format!("{:04X}:{:04X}", line.segment, offset);
+                let bytes = fmt_bytes(row);
+                if i == 0 {
+                    writeln!(
+                        f,
+                        "{:>5}  {}  {:18}  {}",
+                        line_number, address, bytes, source
+                    )?;
+                } else {
+                    writeln!(f, "{:>5}  {}  {}", ' ', address, bytes)?;
+                }
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Symbols:")?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<20} {:<10} {:<6} {:>6}  Value",
+            "Name", "Kind", "Type", "Size"
+        )?;
+        for symbol in &self.symbols {
+            let (kind, variable_type) = match symbol.kind {
+                ListingSymbolKind::Label => ("Label", ""),
+                ListingSymbolKind::Variable(VariableType::Byte) => ("Variable", "Byte"),
+                ListingSymbolKind::Variable(VariableType::Word) => ("Variable", "Word"),
+                ListingSymbolKind::Variable(VariableType::DWord) => ("Variable", "DWord"),
+                ListingSymbolKind::Variable(VariableType::QWord) => ("Variable", "QWord"),
+                ListingSymbolKind::Variable(VariableType::TByte) => ("Variable", "TByte"),
+                ListingSymbolKind::Proc => ("Proc", ""),
+                ListingSymbolKind::Macro => ("Macro", ""),
+            };
+            let size = symbol
+                .size
+                .map(|size| format!("{:04X}", size))
+                .unwrap_or_default();
+            let value = match (symbol.offset, symbol.line_number) {
+                (Some(offset), _) => format!("{:04X}:{:04X}", symbol.segment, offset),
+                (None, Some(line_number)) => {
+                    format!("Line {}", fmt_line_number(line_number, symbol.file_id))
+                }
+                (None, None) => String::new(),
+            };
+            writeln!(
+                f,
+                "{:<20} {:<10} {:<6} {:>6}  {}",
+                symbol.name, kind, variable_type, size, value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn fmt_bytes(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut acc, byte| {
+        if !acc.is_empty() {
+            acc.push(' ');
+        }
+        let _ = write!(acc, "{:02X}", byte);
+        acc
+    })
+}
+
+// rebuilds the text of a line from the source code its tokens came from,
+// so the macro parameters show up as the arguments they got replaced with
+pub(super) fn get_text_of_tokens(source_lines: &[Vec<char>], tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        // a comment before the other tokens only marks a line the compiler skips
+        if token.token_type == Assembly8086Tokens::Comment && i + 1 < tokens.len() {
+            continue;
+        }
+        let line = match source_lines.get(token.line_number as usize) {
+            Some(line) => line,
+            None => continue,
+        };
+        let start = (token.column_number as usize).min(line.len());
+        let end = match token.token_type {
+            // the comment token only marks the start of the comment
+            Assembly8086Tokens::Comment => line.len(),
+            _ => (start + token.token_length as usize).min(line.len()),
+        };
+        text.extend(&line[start..end]);
+    }
+    text
+}
+
+// The lexer has the macro expansions inserted right after the line of the macro call,
+// the lines of the code have the depth 0 and the lines of the expansions are deeper
+pub(crate) fn get_listing_lines(
+    code: &str,
+    compiled_bytes: &[Vec<u8>],
+    macro_source_map: &MacroSourceMap,
+    line_addresses: &[(Word, Word)],
+) -> Vec<ListingLine> {
+    let source_lines = code.lines().collect::<Vec<&str>>();
+
+    let mut lines = Vec::new();
+    for (expanded_line_idx, line_number) in macro_source_map.lexer_lines.iter().enumerate() {
+        let expansion_depth = macro_source_map.get_expansion_depth(expanded_line_idx);
+        let source = source_lines
+            .get(*line_number as usize)
+            .copied()
+            .unwrap_or_default();
+        let (listing_line_number, source) = match expansion_depth {
+            0 => (Some(*line_number as LineNumber), source.to_string()),
+            _ => (None, source.trim().to_string()),
+        };
+        let bytes = compiled_bytes
+            .get(expanded_line_idx)
+            .cloned()
+            .unwrap_or_default();
+        let (segment, offset) = line_addresses
+            .get(expanded_line_idx)
+            .copied()
+            .unwrap_or_default();
+        lines.push(ListingLine {
+            line_number: listing_line_number,
+            segment,
+            offset,
+            source,
+            expansion_depth,
+            bytes,
+            file_id: MAIN_FILE_ID,
+        });
+    }
+    lines
+}
+
+// The labels, variables, procs and macros sorted by their name,
+// the addresses are the ones of the lines they are defined at
+pub(crate) fn get_listing_symbols(
+    label_addr_map: &LabelAddressMap,
+    variable_address_map: &VariableAddressMap,
+    var_addr_def_map: &VariableAddressDefinitionMap,
+    proc_line_num_map: &ProcDefinitionLineNumberMap,
+    macro_bounds: &MacroBoundsDefintionMap,
+    compiled_bytes: &[Vec<u8>],
+    line_addresses: &[(Word, Word)],
+) -> Vec<ListingSymbol> {
+    let get_size = |start: usize, end: usize| -> usize {
+        compiled_bytes
+            .iter()
+            .take(end)
+            .skip(start)
+            .map(|bytes| bytes.len())
+            .sum()
+    };
+
+    let mut symbols = Vec::new();
+    let get_address = |line_number: &LineNumber| {
+        line_addresses
+            .get(*line_number)
+            .copied()
+            .unwrap_or_default()
+    };
+    for (label, line_number) in label_addr_map
+        .iter()
+        .filter(|(label, _)| !is_location_label(label))
+    {
+        let (segment, offset) = get_address(line_number);
+        // the procs are in the label map as well
+        let (kind, size) = match proc_line_num_map.get(label) {
+            Some((start, end)) => (
+                ListingSymbolKind::Proc,
+                end.map(|end| get_size(*start, end)),
+            ),
+            None => (ListingSymbolKind::Label, None),
+        };
+        symbols.push(ListingSymbol {
+            name: label.to_string(),
+            kind,
+            size,
+            segment,
+            offset: Some(offset),
+            line_number: None,
+            file_id: MAIN_FILE_ID,
+        });
+    }
+
+    for (variable, (variable_type, offset, _)) in variable_address_map {
+        let line_number = var_addr_def_map
+            .get(variable)
+            .map(|(_, line_number)| line_number);
+        let size = line_number.map(|line_number| get_size(*line_number, line_number + 1));
+        symbols.push(ListingSymbol {
+            name: variable.to_string(),
+            kind: ListingSymbolKind::Variable(*variable_type),
+            size,
+            segment: line_number.map_or(0, |line_number| get_address(line_number).0),
+            offset: Some(*offset),
+            line_number: None,
+            file_id: MAIN_FILE_ID,
+        });
+    }
+
+    for (label, (start, _)) in macro_bounds {
+        symbols.push(ListingSymbol {
+            name: label.to_string(),
+            kind: ListingSymbolKind::Macro,
+            size: None,
+            segment: 0,
+            offset: None,
+            line_number: Some(*start),
+            file_id: MAIN_FILE_ID,
+        });
+    }
+
+    symbols.sort_by_key(|symbol| symbol.name.to_lowercase());
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::compiler::compile_program;
+
+    use super::ListingSymbolKind;
+
+    #[test]
+    fn lines_have_addresses_bytes_and_source() {
+        let program = compile_program(
+            "org 100h
+.data
+var dw 0x1234
+code:
+    mov ax, var ; load
+    hlt",
+            false,
+        )
+        .unwrap();
+        let listing = program.listing;
+        assert_eq!(listing.segment, 0x0700);
+
+        let offsets = listing
+            .lines
+            .iter()
+            .map(|line| (line.offset, line.bytes.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            offsets,
+            vec![
+                (0x100, 0),
+                (0x100, 2),
+                (0x102, 2),
+                (0x104, 0),
+                (0x104, 3),
+                (0x107, 1)
+            ]
+        );
+
+        let text = listing.to_string();
+        let lines = text.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            lines[2],
+            "    3  0700:0102  34 12               var dw 0x1234"
+        );
+        assert_eq!(
+            lines[4],
+            "    5  0700:0104  A1 02 01                mov ax, var ; load"
+        );
+
+        let symbols = listing
+            .symbols
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.name.as_str(),
+                    symbol.kind,
+                    symbol.size,
+                    symbol.offset,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![
+                ("code", ListingSymbolKind::Label, None, Some(0x104)),
+                (
+                    "var",
+                    ListingSymbolKind::Variable(crate::compiler::types_structs::VariableType::Word),
+                    Some(2),
+                    Some(0x102)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_expansions_are_indented_under_the_call() {
+        let program = compile_program(
+            "inc_twice macro reg
+    inc reg
+    inc reg
+endm
+inc_twice bx
+PROC print
+    ret
+ENDP print
+",
+            false,
+        )
+        .unwrap();
+        let listing = program.listing;
+        let lines = listing
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.line_number,
+                    line.expansion_depth,
+                    line.source.as_str(),
+                    line.bytes.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines[4], (Some(4), 0, "inc_twice bx", vec![]));
+        assert_eq!(lines[5], (None, 1, "inc bx", vec![0x43]));
+        assert_eq!(lines[6], (None, 1, "inc bx", vec![0x43]));
+        assert_eq!(lines[8], (Some(6), 0, "    ret", vec![0xC3]));
+
+        let symbols = listing
+            .symbols
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.name.as_str(),
+                    symbol.kind,
+                    symbol.size,
+                    symbol.offset,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![
+                ("inc_twice", ListingSymbolKind::Macro, None, None),
+                ("print", ListingSymbolKind::Proc, Some(1), Some(0x02)),
+            ]
+        );
+    }
+}
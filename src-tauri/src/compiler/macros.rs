@@ -0,0 +1,1445 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use unicase::UniCase;
+
+use super::{
+    compilation_error::CompilationError,
+    expressions::ConstantScope,
+    includes::{expand_includes, FileId, SourceMap, MAIN_FILE_ID},
+    lexer::{get_string_token, Lexer},
+    listing::get_text_of_tokens,
+    relocate_errors, strip_space_and_comments_and_iterate_labels,
+    tokens::{assembler_directives::AssemblerDirectives, Assembly8086Tokens, Token},
+    types_structs::{
+        CompileOptions, Label, LineNumber, MacroBoundsDefintionMap, MacroReferenceList,
+    },
+};
+
+// a macro that calls itself would be expanded forever
+const MAX_EXPANSION_DEPTH: usize = 32;
+const MAX_REPEAT_COUNT: i64 = 0xFFFF;
+
+// The macro call (or) the REPT, IRP and IRPC block that a line was expanded from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MacroExpansionSite {
+    // the name of the macro, (or) REPT, IRP and IRPC for the blocks
+    pub name: String,
+    pub is_macro: bool,
+    pub file_id: FileId,
+    pub line_number: u32,
+}
+
+impl MacroExpansionSite {
+    pub(in crate::compiler) fn relocate(&mut self, source_map: &SourceMap) {
+        let (file_id, line_number) = source_map.locate(self.line_number);
+        self.file_id = file_id;
+        self.line_number = line_number;
+    }
+
+    // i.e in expansion of macro "print" at line 12
+    pub fn to_note(&self) -> String {
+        match self.is_macro {
+            true => format!(
+                "in expansion of macro \"{}\" at line {}",
+                self.name,
+                self.line_number + 1
+            ),
+            false => format!(
+                "in expansion of {} at line {}",
+                self.name,
+                self.line_number + 1
+            ),
+        }
+    }
+}
+
+// A line made by expanding a macro, it is put after the lines of the code
+#[derive(Debug, Clone)]
+pub struct ExpandedLine {
+    pub text: String,
+    // the line of the macro (or) the block the line is a copy of
+    pub definition_line: u32,
+    // the inner most expansion first, the last one is in the code
+    pub expansions: Vec<MacroExpansionSite>,
+    // the column of every token in the text and the position it was copied from
+    token_origins: Vec<(u32, Token)>,
+}
+
+// The tokens of the expanded lines point into lines after the end of the code,
+// so every token has a position of its own and this maps them back to the macros
+#[derive(Debug, Clone, Default)]
+pub struct MacroSourceMap {
+    pub code_line_count: u32,
+    pub expanded_lines: Vec<ExpandedLine>,
+    // the line of the code (or) the expanded line, for every line of the lexer
+    pub lexer_lines: Vec<u32>,
+}
+
+impl MacroSourceMap {
+    pub fn get_expanded_line(&self, line_number: u32) -> Option<&ExpandedLine> {
+        let idx = line_number.checked_sub(self.code_line_count)?;
+        self.expanded_lines.get(idx as usize)
+    }
+
+    pub fn get_expansion_depth(&self, lexer_line: usize) -> usize {
+        self.lexer_lines
+            .get(lexer_line)
+            .and_then(|line_number| self.get_expanded_line(*line_number))
+            .map_or(0, |expanded_line| expanded_line.expansions.len())
+    }
+
+    // the code with the expanded lines after it, the positions of the tokens are in this
+    pub fn get_expanded_code(&self, code: &str) -> String {
+        let mut lines = code.lines().collect::<Vec<&str>>();
+        lines.resize(self.code_line_count as usize, "");
+        lines.extend(
+            self.expanded_lines
+                .iter()
+                .map(|expanded_line| expanded_line.text.as_str()),
+        );
+        lines.join("\n")
+    }
+
+    // the position in the macro that a position in an expanded line came from,
+    // the arguments aren't on the line of the macro so the start of the line is used for them
+    pub fn locate(&self, line_number: u32, column_number: u32, length: u32) -> (u32, u32, u32) {
+        let expanded_line = match self.get_expanded_line(line_number) {
+            Some(expanded_line) => expanded_line,
+            None => return (line_number, column_number, length),
+        };
+        let definition_line = expanded_line.definition_line;
+        let origins = expanded_line
+            .token_origins
+            .iter()
+            .filter(|(_, token)| {
+                token.line_number == definition_line
+                    && token.token_type != Assembly8086Tokens::Space
+            })
+            .collect::<Vec<&(u32, Token)>>();
+        let end_column = column_number + length.max(1);
+        let spanned_origins = origins
+            .iter()
+            .filter(|(column, _)| (column_number..end_column).contains(column))
+            .collect::<Vec<&&(u32, Token)>>();
+        let first = spanned_origins.first().copied().or(origins.first());
+        let last = spanned_origins.last().copied().or(origins.first());
+        match (first, last) {
+            (Some((_, first)), Some((_, last))) => (
+                definition_line,
+                first.column_number,
+                (last.column_number + last.token_length).saturating_sub(first.column_number),
+            ),
+            _ => (definition_line, 0, 0),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct MacroParameter {
+    name: Label,
+    // name:REQ has to be given an argument
+    is_required: bool,
+    // name:=<value> is used when the argument is left out
+    default: Vec<Token>,
+}
+
+#[derive(Clone)]
+struct MacroDefinition {
+    parameters: Vec<MacroParameter>,
+    // the lines of MACRO and ENDM
+    start: usize,
+    end: usize,
+}
+
+enum Replacement {
+    Argument(Vec<Token>),
+    // the labels of LOCAL get a new name in every expansion
+    Name(String),
+}
+
+enum BlockLine<'a> {
+    // name MACRO p1, p2, p3
+    Macro {
+        name_token: Option<&'a Token>,
+        directive_token: &'a Token,
+    },
+    // REPT, IRP (or) IRPC
+    Repeat(&'a Token),
+    // ENDM (or) name ENDM
+    End {
+        name_token: Option<&'a Token>,
+        directive_token: &'a Token,
+    },
+}
+
+fn get_directive<'a>(token: Option<&&'a Token>) -> Option<&'a AssemblerDirectives> {
+    match token.map(|token| &token.token_type) {
+        Some(Assembly8086Tokens::AssemblerDirectives(directive)) => Some(directive),
+        _ => None,
+    }
+}
+
+fn get_block_line<'a>(stripped_line: &[&'a Token]) -> Option<BlockLine<'a>> {
+    match (
+        get_directive(stripped_line.first()),
+        get_directive(stripped_line.get(1)),
+    ) {
+        (
+            Some(AssemblerDirectives::Rept | AssemblerDirectives::Irp | AssemblerDirectives::Irpc),
+            _,
+        ) => Some(BlockLine::Repeat(stripped_line[0])),
+        (Some(AssemblerDirectives::Macro), _) => Some(BlockLine::Macro {
+            name_token: None,
+            directive_token: stripped_line[0],
+        }),
+        (Some(AssemblerDirectives::EndM), _) => Some(BlockLine::End {
+            name_token: None,
+            directive_token: stripped_line[0],
+        }),
+        (_, Some(AssemblerDirectives::Macro)) => Some(BlockLine::Macro {
+            name_token: Some(stripped_line[0]),
+            directive_token: stripped_line[1],
+        }),
+        (_, Some(AssemblerDirectives::EndM)) => Some(BlockLine::End {
+            name_token: Some(stripped_line[0]),
+            directive_token: stripped_line[1],
+        }),
+        _ => None,
+    }
+}
+
+fn is_local_line(stripped_line: &[&Token]) -> bool {
+    get_directive(stripped_line.first()) == Some(&AssemblerDirectives::Local)
+}
+
+// the line is kept for the listing, but the compiler skips it like a comment
+fn get_commented_line(line: &[Token]) -> Vec<Token> {
+    let mut commented_line = line.to_vec();
+    if let Some(first) = line.first() {
+        let mut comment_token = first.clone();
+        comment_token.token_type = Assembly8086Tokens::Comment;
+        commented_line.insert(0, comment_token);
+    }
+    commented_line
+}
+
+fn trim_spaces(mut tokens: Vec<Token>) -> Vec<Token> {
+    while tokens
+        .last()
+        .is_some_and(|token| token.token_type == Assembly8086Tokens::Space)
+    {
+        tokens.pop();
+    }
+    let leading_spaces = tokens
+        .iter()
+        .take_while(|token| token.token_type == Assembly8086Tokens::Space)
+        .count();
+    tokens.drain(..leading_spaces);
+    tokens
+}
+
+fn get_character_text(token: &Token) -> Option<&str> {
+    match &token.token_type {
+        Assembly8086Tokens::Character(text) => Some(text.as_str()),
+        _ => None,
+    }
+}
+
+// the tokens of a part of a token, i.e the `ax` of `<ax`
+fn lex_at(text: &str, at: &Token, column_offset: u32) -> Vec<Token> {
+    let mut lexer = Lexer::new();
+    lexer.tokenize(text);
+    lexer
+        .tokens
+        .into_iter()
+        .next()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|mut token| {
+            token.line_number = at.line_number;
+            token.column_number += at.column_number + column_offset;
+            token
+        })
+        .collect()
+}
+
+// <a, b> is a single argument, the brackets are removed when it's passed
+fn strip_angle_brackets(tokens: Vec<Token>) -> Vec<Token> {
+    let (first, last) = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return tokens,
+    };
+    let first_text = get_character_text(first).filter(|text| text.starts_with('<'));
+    let last_text = get_character_text(last).filter(|text| text.ends_with('>'));
+    let (first_text, last_text) = match (first_text, last_text) {
+        (Some(first_text), Some(last_text)) => (first_text, last_text),
+        _ => return tokens,
+    };
+    if tokens.len() == 1 {
+        if first_text.len() < 2 {
+            return tokens;
+        }
+        return lex_at(&first_text[1..first_text.len() - 1], first, 1);
+    }
+    let mut stripped_tokens = lex_at(&first_text[1..], first, 1);
+    stripped_tokens.extend(tokens[1..tokens.len() - 1].iter().cloned());
+    stripped_tokens.extend(lex_at(&last_text[..last_text.len() - 1], last, 0));
+    trim_spaces(stripped_tokens)
+}
+
+// the arguments of a macro call (or) IRP, the commas inside <> don't separate them
+fn split_arguments(tokens: &[Token]) -> Vec<Vec<Token>> {
+    let mut arguments = vec![Vec::new()];
+    let mut bracket_depth = 0;
+    for token in tokens {
+        match &token.token_type {
+            Assembly8086Tokens::Comment => break,
+            Assembly8086Tokens::Comma if bracket_depth == 0 => {
+                arguments.push(Vec::new());
+                continue;
+            }
+            Assembly8086Tokens::Character(text) => {
+                bracket_depth += text.matches('<').count() as i32;
+                bracket_depth -= text.matches('>').count() as i32;
+            }
+            _ => {}
+        }
+        if let Some(argument) = arguments.last_mut() {
+            argument.push(token.clone());
+        }
+    }
+    let arguments = arguments
+        .into_iter()
+        .map(|argument| strip_angle_brackets(trim_spaces(argument)))
+        .collect::<Vec<Vec<Token>>>();
+    match arguments.as_slice() {
+        [argument] if argument.is_empty() => Vec::new(),
+        _ => arguments,
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '?' | '@')
+}
+
+// in a string only the names next to a & are replaced and the & is removed, i.e '&c&'
+fn substitute_in_string(
+    string: &str,
+    replacements: &HashMap<Label, Replacement>,
+    source_lines: &[Vec<char>],
+) -> String {
+    let chars = string.chars().collect::<Vec<char>>();
+    let mut substituted = String::new();
+    // the & between 2 names is used by both, i.e '&a&b'
+    let mut used_ampersand = None;
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_name_char(chars[i]) {
+            substituted.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && is_name_char(chars[i]) {
+            i += 1;
+        }
+        let name = chars[start..i].iter().collect::<String>();
+        let has_ampersand_before = start > 0 && chars[start - 1] == '&';
+        let has_ampersand_after = chars.get(i) == Some(&'&');
+        let replacement = match replacements.get(&UniCase::new(name.clone())) {
+            Some(replacement) if has_ampersand_before || has_ampersand_after => replacement,
+            _ => {
+                substituted.push_str(&name);
+                continue;
+            }
+        };
+        if has_ampersand_before && used_ampersand != Some(start - 1) {
+            substituted.pop();
+        }
+        match replacement {
+            Replacement::Argument(argument) => {
+                substituted.push_str(&get_text_of_tokens(source_lines, argument))
+            }
+            Replacement::Name(new_name) => substituted.push_str(new_name),
+        }
+        if has_ampersand_after {
+            used_ampersand = Some(i);
+            i += 1;
+        }
+    }
+    substituted
+}
+
+// the names can be in angle brackets too, i.e IRP x, <p1>
+fn substitute(
+    line: &[Token],
+    replacements: &HashMap<Label, Replacement>,
+    source_lines: &[Vec<char>],
+) -> Vec<Token> {
+    let mut substituted_line = Vec::new();
+    for token in line {
+        if let Assembly8086Tokens::String(string) = &token.token_type {
+            let mut token = token.clone();
+            token.token_type =
+                get_string_token(substitute_in_string(string, replacements, source_lines));
+            substituted_line.push(token);
+            continue;
+        }
+        let text = match get_character_text(token) {
+            Some(text) => text,
+            None => {
+                substituted_line.push(token.clone());
+                continue;
+            }
+        };
+        let name = text.trim_start_matches('<').trim_end_matches('>');
+        let replacement = match replacements.get(&UniCase::new(name.to_string())) {
+            Some(replacement) => replacement,
+            None => {
+                substituted_line.push(token.clone());
+                continue;
+            }
+        };
+        let opening_len = text.len() - text.trim_start_matches('<').len();
+        let closing_len = text.len() - opening_len - name.len();
+        let get_part = |start: usize, len: usize, token_type: Assembly8086Tokens| {
+            Token::new(
+                token_type,
+                token.line_number,
+                token.column_number + start as u32,
+                len as u32,
+            )
+        };
+        if opening_len > 0 {
+            substituted_line.push(get_part(
+                0,
+                opening_len,
+                Assembly8086Tokens::Character(UniCase::new(text[..opening_len].to_string())),
+            ));
+        }
+        match replacement {
+            Replacement::Argument(argument) => substituted_line.extend(argument.clone()),
+            Replacement::Name(new_name) => substituted_line.push(get_part(
+                opening_len,
+                name.len(),
+                Assembly8086Tokens::Character(UniCase::new(new_name.clone())),
+            )),
+        }
+        if closing_len > 0 {
+            substituted_line.push(get_part(
+                opening_len + name.len(),
+                closing_len,
+                Assembly8086Tokens::Character(UniCase::new(
+                    text[opening_len + name.len()..].to_string(),
+                )),
+            ));
+        }
+    }
+    substituted_line
+}
+
+// p1, p2:REQ, p3:=<value>
+fn parse_parameters(
+    macro_name: &Label,
+    definition_line: &[Token],
+    directive_token: &Token,
+    warnings: &mut Vec<CompilationError>,
+) -> Result<Vec<MacroParameter>, CompilationError> {
+    let parameter_tokens = definition_line
+        .iter()
+        .skip_while(|token| !token.is_abs_eq(directive_token))
+        .skip(1)
+        .cloned()
+        .collect::<Vec<Token>>();
+    let mut parameters = Vec::<MacroParameter>::new();
+    for parameter in split_arguments(&parameter_tokens) {
+        let stripped_parameter = parameter
+            .iter()
+            .filter(|token| token.token_type != Assembly8086Tokens::Space)
+            .collect::<Vec<&Token>>();
+        let (name_token, name) = match stripped_parameter.first() {
+            Some(token) => match &token.token_type {
+                Assembly8086Tokens::Character(name) => (*token, name.clone()),
+                _ => {
+                    return Err(CompilationError::error_with_token(
+                        token,
+                        &format!(
+                            "The parameter \"{}\" of the macro \"{}\" has to be a name, Please use a different name.",
+                            token.token_type, macro_name
+                        ),
+                    ))
+                }
+            },
+            None => {
+                return Err(CompilationError::error_with_token(
+                    directive_token,
+                    &format!(
+                        "Expected a parameter after the comma in the macro \"{}\", Got nothing!",
+                        macro_name
+                    ),
+                ))
+            }
+        };
+        let (is_required, default) = match stripped_parameter[1..] {
+            [] => (false, Vec::new()),
+            [colon, req]
+                if colon.token_type == Assembly8086Tokens::Colon
+                    && get_character_text(req).is_some_and(|req| req.eq_ignore_ascii_case("req")) =>
+            {
+                (true, Vec::new())
+            }
+            [colon, equals, ..]
+                if colon.token_type == Assembly8086Tokens::Colon
+                    && equals.token_type == Assembly8086Tokens::Equals =>
+            {
+                let default = parameter
+                    .iter()
+                    .skip_while(|token| !token.is_abs_eq(equals))
+                    .skip(1)
+                    .cloned()
+                    .collect::<Vec<Token>>();
+                (false, strip_angle_brackets(trim_spaces(default)))
+            }
+            _ => {
+                return Err(CompilationError::error_between_tokens(
+                    name_token,
+                    stripped_parameter[stripped_parameter.len() - 1],
+                    &format!(
+                        "The parameter \"{}\" isn't valid, Please use it like {} (or) {}:REQ (or) {}:=<1>",
+                        name, name, name, name
+                    ),
+                ))
+            }
+        };
+        if parameters.iter().any(|parameter| parameter.name == name) {
+            warnings.push(CompilationError::error_with_token(
+                name_token,
+                &format!(
+                    "The parameter \"{}\" is defined more than once in the macro \"{}\", The last one is used. Please use a different name.",
+                    name, macro_name
+                ),
+            ));
+        }
+        parameters.push(MacroParameter {
+            name,
+            is_required,
+            default,
+        });
+    }
+    Ok(parameters)
+}
+
+struct OpenMacro<'a> {
+    name: Label,
+    name_token: &'a Token,
+    directive_token: &'a Token,
+    start: usize,
+    // the REPT, IRP and IRPC blocks in the macro that aren't closed yet
+    open_repeats: Vec<&'a Token>,
+}
+
+fn get_unclosed_repeat_error(directive_token: &Token) -> CompilationError {
+    CompilationError::error_with_token(
+        directive_token,
+        &format!(
+            "The {} isn't closed, Please close it with ENDM",
+            directive_token.token_type.to_string().to_uppercase()
+        ),
+    )
+}
+
+// the macros of the code, they are found before expanding so a macro can be used before its definition
+fn find_macros(
+    lines: &[Vec<Token>],
+    macro_bounds: &mut MacroBoundsDefintionMap,
+    warnings: &mut Vec<CompilationError>,
+) -> Result<HashMap<Label, MacroDefinition>, Vec<CompilationError>> {
+    let mut macros = HashMap::new();
+    let mut open_macro: Option<OpenMacro> = None;
+    let mut open_repeats = Vec::<&Token>::new();
+    let mut compilation_errors = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let (stripped_line, _) = strip_space_and_comments_and_iterate_labels(line);
+        match get_block_line(&stripped_line) {
+            Some(BlockLine::Macro {
+                name_token: None,
+                directive_token,
+            }) => {
+                compilation_errors.push(CompilationError::error_with_token(
+                    directive_token,
+                    "Need to define a name for the macro with the folling syntax `Name MACRO p1, p2, p3`",
+                ));
+            }
+            Some(BlockLine::Macro {
+                name_token: Some(name_token),
+                directive_token,
+            }) => {
+                let name = match &name_token.token_type {
+                    Assembly8086Tokens::Character(name) => name.clone(),
+                    _ => {
+                        compilation_errors.push(CompilationError::error_with_token(
+                            name_token,
+                            "Macro name must be a character",
+                        ));
+                        continue;
+                    }
+                };
+                if let Some(open_macro) = &open_macro {
+                    compilation_errors.push(CompilationError::error_with_token(
+                        open_macro.name_token,
+                        &format!(
+                            "You have not closed this MACRO with name {}, Nested macros are not supported",
+                            open_macro.name
+                        ),
+                    ));
+                }
+                open_macro = Some(OpenMacro {
+                    name,
+                    name_token,
+                    directive_token,
+                    start: i,
+                    open_repeats: Vec::new(),
+                });
+            }
+            Some(BlockLine::Repeat(directive_token)) => match &mut open_macro {
+                Some(open_macro) => open_macro.open_repeats.push(directive_token),
+                None => open_repeats.push(directive_token),
+            },
+            Some(BlockLine::End {
+                name_token,
+                directive_token,
+            }) => {
+                let end_name = match name_token.map(|token| &token.token_type) {
+                    Some(Assembly8086Tokens::Character(name)) => Some(name),
+                    Some(_) => {
+                        compilation_errors.push(CompilationError::error_with_token(
+                            name_token.unwrap(),
+                            "Macro name must be a character",
+                        ));
+                        continue;
+                    }
+                    None => None,
+                };
+                // the ENDM of a REPT (or) IRP in the macro
+                if let Some(open_macro) = &mut open_macro {
+                    if end_name.is_none() && open_macro.open_repeats.pop().is_some() {
+                        continue;
+                    }
+                }
+                let closed_macro = match open_macro.take() {
+                    Some(closed_macro) => closed_macro,
+                    None if end_name.is_none() && open_repeats.pop().is_some() => continue,
+                    None => {
+                        compilation_errors.push(CompilationError::error_with_token(
+                            name_token.unwrap_or(directive_token),
+                            &match end_name {
+                                Some(end_name) => format!(
+                                    "The macro \"{}\" is not defined, Please define it before ending it.",
+                                    end_name
+                                ),
+                                None => "No macro defintion found, define a macro using `Name MACRO p1, p2, p3 syntax`, Please define it before ending it.".to_string(),
+                            },
+                        ));
+                        continue;
+                    }
+                };
+                if let Some(end_name) = end_name.filter(|end_name| **end_name != closed_macro.name)
+                {
+                    compilation_errors.push(CompilationError::error_with_token(
+                        name_token.unwrap(),
+                        &format!(
+                            "The macro \"{}\" is not defined, Please define it before ending it. Maybe you are trying to end the macro \"{}\" ?",
+                            end_name, closed_macro.name
+                        ),
+                    ));
+                }
+                compilation_errors.extend(
+                    closed_macro
+                        .open_repeats
+                        .iter()
+                        .map(|directive_token| get_unclosed_repeat_error(directive_token)),
+                );
+                match parse_parameters(
+                    &closed_macro.name,
+                    &lines[closed_macro.start],
+                    closed_macro.directive_token,
+                    warnings,
+                ) {
+                    Ok(parameters) => {
+                        macro_bounds.insert(closed_macro.name.clone(), (closed_macro.start, i));
+                        macros.insert(
+                            closed_macro.name,
+                            MacroDefinition {
+                                parameters,
+                                start: closed_macro.start,
+                                end: i,
+                            },
+                        );
+                    }
+                    Err(err) => compilation_errors.push(err),
+                }
+            }
+            None => {}
+        }
+    }
+
+    if let Some(open_macro) = open_macro {
+        compilation_errors.push(CompilationError::error_with_token(
+            open_macro.name_token,
+            &format!(
+                "The macro \"{}\" is not ended, Please end it with `ENDM`",
+                open_macro.name
+            ),
+        ));
+        compilation_errors.extend(
+            open_macro
+                .open_repeats
+                .iter()
+                .map(|directive_token| get_unclosed_repeat_error(directive_token)),
+        );
+    }
+    compilation_errors.extend(
+        open_repeats
+            .iter()
+            .map(|directive_token| get_unclosed_repeat_error(directive_token)),
+    );
+
+    match compilation_errors.is_empty() {
+        true => Ok(macros),
+        false => Err(compilation_errors),
+    }
+}
+
+// the index of the ENDM that closes the block started at the given line
+fn find_block_end(lines: &[Vec<Token>], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        let (stripped_line, _) = strip_space_and_comments_and_iterate_labels(line);
+        match get_block_line(&stripped_line) {
+            Some(BlockLine::Macro { .. } | BlockLine::Repeat(_)) => depth += 1,
+            Some(BlockLine::End { .. }) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+struct MacroExpander<'a> {
+    source_lines: Vec<Vec<char>>,
+    // the lines of the code, the bodies of the macros are taken from them
+    lines: &'a [Vec<Token>],
+    macros: HashMap<Label, MacroDefinition>,
+    constants: ConstantScope,
+    local_label_count: usize,
+    is_too_deep: bool,
+    expanded_lines: Vec<Vec<Token>>,
+    macro_source_map: MacroSourceMap,
+    macro_ref_list: &'a mut MacroReferenceList,
+    compilation_errors: Vec<CompilationError>,
+}
+
+impl<'a> MacroExpander<'a> {
+    fn push_line(
+        &mut self,
+        line: Vec<Token>,
+        definition_line: LineNumber,
+        expansions: &[MacroExpansionSite],
+    ) {
+        if expansions.is_empty() {
+            self.expanded_lines.push(line);
+            self.macro_source_map
+                .lexer_lines
+                .push(definition_line as u32);
+            return;
+        }
+        // the tokens are moved to a new line after the code, with the text they are copied from
+        let line_number = self.source_lines.len() as u32;
+        let mut text = String::new();
+        let mut token_origins = Vec::new();
+        let mut expanded_line = Vec::new();
+        for (i, token) in line.iter().enumerate() {
+            let is_marker = token.token_type == Assembly8086Tokens::Comment && i + 1 < line.len();
+            let token_text = get_text_of_tokens(&self.source_lines, std::slice::from_ref(token));
+            let token_text = match &token.token_type {
+                _ if is_marker => String::new(),
+                // the LOCAL labels have a new name
+                Assembly8086Tokens::Character(name) if !token_text.eq_ignore_ascii_case(name) => {
+                    name.to_string()
+                }
+                _ => token_text,
+            };
+            let column_number = text.chars().count() as u32;
+            token_origins.push((column_number, token.clone()));
+            let mut expanded_token = token.clone();
+            expanded_token.line_number = line_number;
+            expanded_token.column_number = column_number;
+            if token.token_type != Assembly8086Tokens::Comment {
+                expanded_token.token_length = token_text.chars().count() as u32;
+            }
+            expanded_line.push(expanded_token);
+            text.push_str(&token_text);
+        }
+        self.source_lines.push(text.chars().collect());
+        self.macro_source_map.expanded_lines.push(ExpandedLine {
+            text,
+            definition_line: definition_line as u32,
+            expansions: expansions.to_vec(),
+            token_origins,
+        });
+        self.macro_source_map.lexer_lines.push(line_number);
+        self.expanded_lines.push(expanded_line);
+    }
+
+    // only the first error is shown, the expansions inside it would repeat it
+    fn check_depth(&mut self, token: &Token, depth: usize) -> bool {
+        if self.is_too_deep {
+            return false;
+        }
+        if depth > MAX_EXPANSION_DEPTH {
+            self.is_too_deep = true;
+            let expanded = match &token.token_type {
+                Assembly8086Tokens::Character(name) => format!("macro \"{}\"", name),
+                token_type => token_type.to_string().to_uppercase(),
+            };
+            self.compilation_errors
+                .push(CompilationError::error_with_token(
+                    token,
+                    &format!(
+                    "The {} is nested more than {} levels deep, Please check if it calls itself",
+                    expanded, MAX_EXPANSION_DEPTH
+                ),
+                ));
+            return false;
+        }
+        true
+    }
+
+    // the line calls a macro if it starts with the name of one, after the label
+    fn get_macro_call<'b>(
+        &mut self,
+        stripped_line: &[&'b Token],
+        label: &Option<String>,
+    ) -> Option<&'b Token> {
+        let name_idx = match label {
+            Some(_) => 2,
+            None => 0,
+        };
+        let name_token = stripped_line.get(name_idx)?;
+        match &name_token.token_type {
+            Assembly8086Tokens::Character(name) if self.macros.contains_key(name) => {}
+            _ => return None,
+        }
+        if let Some(Assembly8086Tokens::Data(_)) = stripped_line
+            .get(name_idx + 1)
+            .map(|token| &token.token_type)
+        {
+            self.compilation_errors
+                .push(CompilationError::error_with_token(
+                    name_token,
+                    "The macro name cannot be a variable",
+                ));
+            return None;
+        }
+        Some(name_token)
+    }
+
+    fn expand_lines(
+        &mut self,
+        lines: &[Vec<Token>],
+        definition_lines: &[LineNumber],
+        expansions: &[MacroExpansionSite],
+    ) {
+        let depth = expansions.len();
+        let mut i = 0;
+        while i < lines.len() {
+            let line = &lines[i];
+            let (stripped_line, label) = strip_space_and_comments_and_iterate_labels(line);
+            match get_block_line(&stripped_line) {
+                // the definitions are only in the code, find_macros has checked them
+                Some(BlockLine::Macro { .. }) => {
+                    let end = find_block_end(lines, i).unwrap_or(lines.len() - 1);
+                    for (line, definition_line) in
+                        lines[i..=end].iter().zip(&definition_lines[i..=end])
+                    {
+                        self.push_line(get_commented_line(line), *definition_line, expansions);
+                    }
+                    i = end + 1;
+                    continue;
+                }
+                Some(BlockLine::Repeat(directive_token)) => {
+                    let end = find_block_end(lines, i).unwrap_or(lines.len() - 1);
+                    for (line, definition_line) in
+                        lines[i..=end].iter().zip(&definition_lines[i..=end])
+                    {
+                        self.push_line(get_commented_line(line), *definition_line, expansions);
+                    }
+                    if self.check_depth(directive_token, depth + 1) {
+                        let mut repeat_expansions = vec![MacroExpansionSite {
+                            name: directive_token.token_type.to_string().to_uppercase(),
+                            is_macro: false,
+                            file_id: MAIN_FILE_ID,
+                            line_number: definition_lines[i] as u32,
+                        }];
+                        repeat_expansions.extend_from_slice(expansions);
+                        self.expand_repeat(
+                            line,
+                            &stripped_line,
+                            &lines[i + 1..end],
+                            &definition_lines[i + 1..end],
+                            &repeat_expansions,
+                        );
+                    }
+                    i = end + 1;
+                    continue;
+                }
+                _ => {}
+            }
+            if is_local_line(&stripped_line) {
+                self.compilation_errors
+                    .push(CompilationError::error_with_token(
+                    stripped_line[0],
+                    "The LOCAL labels can only be defined inside a MACRO, Please move it into one",
+                ));
+            }
+
+            match self.get_macro_call(&stripped_line, &label) {
+                Some(name_token) => {
+                    // the label before the macro name stays on the line, the call is commented
+                    let name_idx = line
+                        .iter()
+                        .position(|token| token.is_abs_eq(name_token))
+                        .unwrap_or(line.len());
+                    let mut call_line = line[..name_idx].to_vec();
+                    let mut comment_token = name_token.clone();
+                    comment_token.token_type = Assembly8086Tokens::Comment;
+                    call_line.push(comment_token);
+                    call_line.extend_from_slice(&line[name_idx..]);
+                    self.push_line(call_line, definition_lines[i], expansions);
+                    let argument_tokens = line[name_idx..]
+                        .iter()
+                        .skip(1)
+                        .cloned()
+                        .collect::<Vec<Token>>();
+                    if self.check_depth(name_token, depth + 1) {
+                        let mut macro_expansions = vec![MacroExpansionSite {
+                            name: name_token.token_type.to_string(),
+                            is_macro: true,
+                            file_id: MAIN_FILE_ID,
+                            line_number: definition_lines[i] as u32,
+                        }];
+                        macro_expansions.extend_from_slice(expansions);
+                        self.expand_macro(name_token, &argument_tokens, &macro_expansions);
+                    }
+                }
+                None => {
+                    self.constants.define(line);
+                    self.push_line(line.clone(), definition_lines[i], expansions);
+                }
+            }
+            i += 1;
+        }
+    }
+
+    fn expand_macro(
+        &mut self,
+        name_token: &Token,
+        argument_tokens: &[Token],
+        expansions: &[MacroExpansionSite],
+    ) {
+        let name = match &name_token.token_type {
+            Assembly8086Tokens::Character(name) => name.clone(),
+            _ => return,
+        };
+        let definition = match self.macros.get(&name) {
+            Some(definition) => definition.clone(),
+            None => return,
+        };
+        self.macro_ref_list.push((
+            name.clone(),
+            name_token.clone(),
+            (self.expanded_lines.len() - 1) as LineNumber,
+        ));
+
+        let arguments = split_arguments(argument_tokens);
+        if arguments.len() > definition.parameters.len() {
+            self.compilation_errors
+                .push(CompilationError::error_with_token(
+                    name_token,
+                    &format!(
+                        "The macro \"{}\" is defined with {} arguments, but you have passed {} arguments",
+                        name,
+                        definition.parameters.len(),
+                        arguments.len()
+                    ),
+                ));
+            return;
+        }
+        let mut replacements = HashMap::<Label, Replacement>::new();
+        for (i, parameter) in definition.parameters.iter().enumerate() {
+            let argument = match arguments.get(i) {
+                Some(argument) if !argument.is_empty() => argument.clone(),
+                _ => parameter.default.clone(),
+            };
+            if argument.is_empty() && parameter.is_required {
+                self.compilation_errors
+                    .push(CompilationError::error_with_token(
+                        name_token,
+                        &format!(
+                            "The macro \"{}\" needs an argument for the parameter \"{}\", Please pass it",
+                            name, parameter.name
+                        ),
+                    ));
+                return;
+            }
+            replacements.insert(parameter.name.clone(), Replacement::Argument(argument));
+        }
+
+        let lines = self.lines;
+        let mut body = Vec::new();
+        let mut body_definition_lines = Vec::new();
+        for (i, line) in lines
+            .iter()
+            .enumerate()
+            .take(definition.end)
+            .skip(definition.start + 1)
+        {
+            let (stripped_line, _) = strip_space_and_comments_and_iterate_labels(line);
+            if !is_local_line(&stripped_line) {
+                body.push(line);
+                body_definition_lines.push(i);
+                continue;
+            }
+            for local_label in stripped_line[1..]
+                .iter()
+                .filter(|token| token.token_type != Assembly8086Tokens::Comma)
+            {
+                match &local_label.token_type {
+                    Assembly8086Tokens::Character(local_label) => {
+                        replacements.insert(
+                            local_label.clone(),
+                            Replacement::Name(format!("??{:04X}", self.local_label_count)),
+                        );
+                        self.local_label_count += 1;
+                    }
+                    _ => self
+                        .compilation_errors
+                        .push(CompilationError::error_with_token(
+                            local_label,
+                            &format!(
+                                "Expected a label name after LOCAL, Got {}",
+                                local_label.token_type
+                            ),
+                        )),
+                }
+            }
+        }
+        let body = body
+            .into_iter()
+            .map(|line| substitute(line, &replacements, &self.source_lines))
+            .collect::<Vec<Vec<Token>>>();
+        self.expand_lines(&body, &body_definition_lines, expansions);
+    }
+
+    // REPT count, IRP name, <a, b, c> (or) IRPC name, abc
+    fn expand_repeat(
+        &mut self,
+        line: &[Token],
+        stripped_line: &[&Token],
+        body: &[Vec<Token>],
+        definition_lines: &[LineNumber],
+        expansions: &[MacroExpansionSite],
+    ) {
+        let directive_token = stripped_line[0];
+        if directive_token.token_type
+            == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Rept)
+        {
+            let count = match self
+                .constants
+                .evaluate(directive_token, &stripped_line[1..])
+            {
+                Ok(count) => count,
+                Err(err) => {
+                    self.compilation_errors.push(err);
+                    return;
+                }
+            };
+            if !(0..=MAX_REPEAT_COUNT).contains(&count) {
+                self.compilation_errors
+                    .push(CompilationError::error_between_tokens(
+                        directive_token,
+                        stripped_line[stripped_line.len() - 1],
+                        &format!(
+                            "The count of REPT has to be between 0 and {}, Got {}",
+                            MAX_REPEAT_COUNT, count
+                        ),
+                    ));
+                return;
+            }
+            for _ in 0..count {
+                self.expand_lines(body, definition_lines, expansions);
+            }
+            return;
+        }
+
+        let parameter = match (stripped_line.get(1), stripped_line.get(2)) {
+            (Some(parameter), Some(comma)) if comma.token_type == Assembly8086Tokens::Comma => {
+                match &parameter.token_type {
+                    Assembly8086Tokens::Character(parameter) if !parameter.contains(['<', '>']) => {
+                        Some((parameter.clone(), *comma))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let (parameter, comma_token) = match parameter {
+            Some(parameter) => parameter,
+            None => {
+                self.compilation_errors
+                    .push(CompilationError::error_with_token(
+                        directive_token,
+                        &format!(
+                            "Expected a name and the values after {}, Please use it like IRP x, <1, 2, 3> (or) IRPC x, 123",
+                            directive_token.token_type.to_string().to_uppercase()
+                        ),
+                    ));
+                return;
+            }
+        };
+        let value_tokens = line
+            .iter()
+            .skip_while(|token| !token.is_abs_eq(comma_token))
+            .skip(1)
+            .cloned()
+            .collect::<Vec<Token>>();
+
+        let items = match directive_token.token_type {
+            Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Irp) => {
+                match split_arguments(&value_tokens).as_slice() {
+                    [values] => split_arguments(values),
+                    values => values.to_vec(),
+                }
+            }
+            // every character of the text is a value
+            _ => {
+                let value_tokens = trim_spaces(
+                    value_tokens
+                        .into_iter()
+                        .take_while(|token| token.token_type != Assembly8086Tokens::Comment)
+                        .collect(),
+                );
+                let first_token = match value_tokens.first() {
+                    Some(first_token) => first_token,
+                    None => return,
+                };
+                let text = get_text_of_tokens(&self.source_lines, &value_tokens);
+                let (text, column_offset) =
+                    match (text.chars().next(), text.chars().last(), text.len() > 1) {
+                        (Some('<'), Some('>'), true) => (&text[1..text.len() - 1], 1),
+                        _ => (text.as_str(), 0),
+                    };
+                text.chars()
+                    .enumerate()
+                    .map(|(i, c)| lex_at(&c.to_string(), first_token, column_offset + i as u32))
+                    .collect()
+            }
+        };
+        for item in items {
+            let replacements = HashMap::from([(parameter.clone(), Replacement::Argument(item))]);
+            let substituted_body = body
+                .iter()
+                .map(|line| substitute(line, &replacements, &self.source_lines))
+                .collect::<Vec<Vec<Token>>>();
+            self.expand_lines(&substituted_body, definition_lines, expansions);
+        }
+    }
+}
+
+// Replaces the macro calls and the REPT, IRP and IRPC blocks with the lines they expand to,
+// the lines of the code stay in their order and every expansion comes right after the line of it
+pub(in crate::compiler) fn expand_macros(
+    lexer: &mut Lexer,
+    code: &str,
+    defines: &[(String, String)],
+    macro_bounds: &mut MacroBoundsDefintionMap,
+    macro_ref_list: &mut MacroReferenceList,
+    macro_source_map: &mut MacroSourceMap,
+    warnings: &mut Vec<CompilationError>,
+) -> Result<(), Vec<CompilationError>> {
+    let macros = find_macros(&lexer.tokens, macro_bounds, warnings)?;
+    let lines = std::mem::take(&mut lexer.tokens);
+    let mut source_lines = code
+        .lines()
+        .map(|line| line.chars().collect::<Vec<char>>())
+        .collect::<Vec<Vec<char>>>();
+    source_lines.resize(source_lines.len().max(lines.len()), Vec::new());
+    let mut expander = MacroExpander {
+        macro_source_map: MacroSourceMap {
+            code_line_count: source_lines.len() as u32,
+            ..Default::default()
+        },
+        source_lines,
+        lines: &lines,
+        macros,
+        constants: ConstantScope::new(defines),
+        local_label_count: 0,
+        is_too_deep: false,
+        expanded_lines: Vec::new(),
+        macro_ref_list,
+        compilation_errors: Vec::new(),
+    };
+    let definition_lines = (0..lines.len()).collect::<Vec<LineNumber>>();
+    expander.expand_lines(&lines, &definition_lines, &[]);
+
+    let compilation_errors = expander.compilation_errors;
+    *macro_source_map = expander.macro_source_map;
+    lexer.tokens = expander.expanded_lines;
+    match compilation_errors.is_empty() {
+        true => Ok(()),
+        false => Err(compilation_errors),
+    }
+}
+
+// The lines that the macro call (or) the REPT, IRP and IRPC block on the line of the main file
+// expands to, the nested expansions are indented. None if nothing is expanded on the line
+pub fn get_macro_expansion(
+    code: &str,
+    line_number: u32,
+    compile_options: &CompileOptions,
+) -> Result<Option<String>, Vec<CompilationError>> {
+    let (code, source_map) = expand_includes(code, &compile_options.source_files)?;
+    let mut lexer = Lexer::new();
+    lexer.tokenize(&code);
+    let mut macro_source_map = MacroSourceMap::default();
+    if let Err(err) = expand_macros(
+        &mut lexer,
+        &code,
+        &compile_options.defines,
+        &mut MacroBoundsDefintionMap::new(),
+        &mut MacroReferenceList::new(),
+        &mut macro_source_map,
+        &mut Vec::new(),
+    ) {
+        return Err(relocate_errors(err, &macro_source_map, &source_map));
+    }
+
+    let mut expanded_lines = Vec::new();
+    for (tokens, expanded_line_number) in lexer.tokens.iter().zip(&macro_source_map.lexer_lines) {
+        let expanded_line = match macro_source_map.get_expanded_line(*expanded_line_number) {
+            Some(expanded_line) => expanded_line,
+            None => continue,
+        };
+        let is_call_site = expanded_line.expansions.last().is_some_and(|call_site| {
+            source_map.locate(call_site.line_number) == (MAIN_FILE_ID, line_number)
+        });
+        // the lines the compiler skips, i.e the nested calls and the REPT blocks
+        let mut stripped_tokens = tokens
+            .iter()
+            .filter(|token| token.token_type != Assembly8086Tokens::Space);
+        let is_commented = stripped_tokens
+            .next()
+            .is_some_and(|token| token.token_type == Assembly8086Tokens::Comment)
+            && stripped_tokens.next().is_some();
+        if !is_call_site || is_commented {
+            continue;
+        }
+        expanded_lines.push(format!(
+            "{}{}",
+            "    ".repeat(expanded_line.expansions.len() - 1),
+            expanded_line.text.trim()
+        ));
+    }
+    match expanded_lines.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(expanded_lines.join("\n"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unicase::UniCase;
+
+    use crate::compiler::{
+        compile_program, compile_str, try_compile_code, types_structs::CompileOptions,
+    };
+
+    use super::get_macro_expansion;
+
+    #[test]
+    fn local_labels_in_every_expansion() {
+        let code = "wait_zero macro reg
+                local again
+            again:
+                dec reg
+                jnz again
+            endm
+            wait_zero cx
+            wait_zero dx";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(program.bytes, vec![0x49, 0x75, 0xFD, 0x4A, 0x75, 0xFD]);
+        assert!(program
+            .label_offset_map
+            .contains_key(&UniCase::new("??0000".to_string())));
+        assert!(program
+            .label_offset_map
+            .contains_key(&UniCase::new("??0001".to_string())));
+
+        assert!(compile_str("local again\nagain: inc ax", false).is_err());
+    }
+
+    #[test]
+    fn label_on_a_macro_call_after_an_expansion() {
+        let code = "inc_both macro
+                inc ax
+                inc bx
+            endm
+            inc_both
+            again: inc_both
+            jmp again";
+        let (bytes, _) = compile_str(code, false).unwrap();
+        assert_eq!(bytes, vec![0x40, 0x43, 0x40, 0x43, 0xEB, 0xFC]);
+    }
+
+    #[test]
+    fn rept_irp_and_irpc() {
+        let code = "COUNT equ 2
+            rept COUNT
+                inc ax
+            endm
+            irp reg, <bx, cx>
+                inc reg
+            endm
+            irpc n, 12
+                mov al, n
+            endm";
+        let (bytes, _) = compile_str(code, false).unwrap();
+        assert_eq!(bytes, vec![0x40, 0x40, 0x43, 0x41, 0xB0, 0x01, 0xB0, 0x02]);
+
+        assert!(compile_str("rept 2\ninc ax", false).is_err());
+        assert!(compile_str("rept -1\ninc ax\nendm", false).is_err());
+        assert!(compile_str("irp <1, 2>\ninc ax\nendm", false).is_err());
+    }
+
+    #[test]
+    fn parameters_in_strings() {
+        let code = "irpc c, AB
+                mov al, '&c&'
+            endm
+            greet macro name, mark:=<!>
+                local msg
+                msg db 'Hi &name&mark, name'
+            endm
+            greet Bob
+            greet <Al>, ?";
+        let (bytes, _) = compile_str(code, false).unwrap();
+        assert_eq!(bytes[..4], [0xB0, b'A', 0xB0, b'B']);
+        assert_eq!(bytes[4..], b"Hi Bob!, nameHi Al?, name"[..]);
+    }
+
+    #[test]
+    fn default_and_required_parameters() {
+        let code = "inc_by macro reg:REQ, times:=<2>
+                rept times
+                    inc reg
+                endm
+            endm
+            inc_by ax
+            inc_by bx, 1";
+        let (bytes, _) = compile_str(code, false).unwrap();
+        assert_eq!(bytes, vec![0x40, 0x40, 0x43]);
+
+        assert!(compile_str(&format!("{}\ninc_by", code), false).is_err());
+        assert!(compile_str(&format!("{}\ninc_by ax, 1, 2", code), false).is_err());
+    }
+
+    #[test]
+    fn macros_calling_macros() {
+        let code = "inc_reg macro reg
+                inc reg
+            endm
+            inc_pair macro first, second
+                inc_reg first
+                inc_reg <second>
+            endm
+            inc_pair ax, bx";
+        let (bytes, _) = compile_str(code, false).unwrap();
+        assert_eq!(bytes, vec![0x40, 0x43]);
+
+        let code = "forever macro
+                inc ax
+                forever
+            endm
+            forever";
+        assert!(compile_str(code, false).is_err());
+    }
+
+    #[test]
+    fn duplicate_parameters_are_a_warning() {
+        let code = "inc_twice macro p1, p1
+                inc p1
+            endm
+            inc_twice ax, bx";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(program.bytes, vec![0x43]);
+        assert_eq!(program.warnings.len(), 1);
+    }
+
+    #[test]
+    fn errors_and_bytes_point_into_the_macro() {
+        let code = "load macro reg, value
+                mov reg, value
+            endm
+            outer macro
+                load al, 300h
+            endm
+            load ax, 1
+            outer";
+        let errors = try_compile_code(code, &CompileOptions::default()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_short_string(),
+            "2:17: Expected a 8bit value after MOV got Number16bit(768) insted \
+            (in expansion of macro \"load\" at line 5, in expansion of macro \"outer\" at line 8)"
+        );
+
+        let program = compile_program(&code.replace("300h", "3"), false).unwrap();
+        let compiled_bytes = &program.compiled_bytes_ref[0];
+        assert_eq!(compiled_bytes.line_number, 1);
+        assert_eq!(compiled_bytes.column_number, 16);
+        let call_site = compiled_bytes.call_site.as_ref().unwrap();
+        assert_eq!(
+            (call_site.name.as_str(), call_site.line_number),
+            ("load", 6)
+        );
+    }
+
+    #[test]
+    fn expansion_preview() {
+        let code = "inc_reg macro reg
+                inc reg
+            endm
+            inc_pair macro first, second
+                local done
+                inc_reg first
+                rept 2
+                    inc_reg second
+                endm
+            done:
+            endm
+            inc_pair ax, bx
+            inc ax";
+        let options = CompileOptions::default();
+        assert_eq!(
+            get_macro_expansion(code, 11, &options).unwrap().unwrap(),
+            "    inc ax\n        inc bx\n        inc bx\n??0000:"
+        );
+        assert_eq!(get_macro_expansion(code, 12, &options).unwrap(), None);
+    }
+}
@@ -3,11 +3,20 @@ use std::collections::HashMap;
 use unicase::UniCase;
 
 pub mod compilation_error;
+pub mod definitions;
+pub mod includes;
+mod labels;
 pub mod lexer;
+pub mod listing;
+pub mod macros;
+mod program_end;
+mod segments;
 pub mod tests;
 pub mod tokens;
 
 pub(crate) mod compilation_utils;
+mod conditionals;
+mod expressions;
 mod parsers;
 pub(crate) mod tokenized_line;
 pub mod types_structs;
@@ -18,6 +27,7 @@ pub mod suggestions_utils;
 
 use compilation_error::CompilationError;
 use lexer::Lexer;
+use listing::{get_listing_lines, get_listing_symbols, Listing};
 use tokens::instructions::Instructions;
 
 use crate::{
@@ -33,8 +43,13 @@ use self::{
         check_is_label,
         error_if_hasnt_consumed_all_ins, // find_data_line_num,
         get_full_line_error_starting_from_i,
+        get_load_address,
         is_org_defined,
     },
+    expressions::{expand_constants, fold_expressions, is_location_label},
+    includes::{expand_includes, SourceMap},
+    labels::LabelScopes,
+    macros::{expand_macros, MacroSourceMap},
     parsers::{
         adc::parse_adc,
         add::parse_add,
@@ -63,23 +78,22 @@ use self::{
         shl::parse_shl,
         sub::parse_sub,
         test_ins::parse_test,
-        utils::iterate_with_seperator,
-        var::parse_var_declaration,
+        var::{parse_var_declaration, LabelAddressContext},
         xchg::parse_xchg,
         xor::parse_xor,
     },
+    program_end::ProgramEnd,
+    segments::{is_segment_line, Segments},
     tokenized_line::TokenizedLine,
-    tokens::{
-        assembler_directives::AssemblerDirectives, data::DefineData, Assembly8086Tokens, Token,
-    },
+    tokens::{assembler_directives::AssemblerDirectives, Assembly8086Tokens, Token},
     types_structs::{
-        ArrayIndex, CompiledBytesIndexedLineNumber, CompiledBytesReference, CompiledLine,
-        IsLabelBeforeRef, Label, LabelAddressMap, LabelRefrenceList, LineNumber,
-        MacroBoundsDefintionMap, MacroReferenceList, ProcDefinitionLineNumberMap,
-        ProcReferenceList, VariableAddressDefinitionMap, VariableAddressMap, VariableReferenceList,
-        VariableType,
+        CompileOptions, CompiledBytesIndexedLineNumber, CompiledBytesReference, CompiledLine,
+        CompiledProgram, Encoding, IsLabelBeforeRef, Label, LabelAddressMap, LabelOffsetMap,
+        LabelRefrenceList, LineNumber, MacroBoundsDefintionMap, MacroReferenceList, NumberOfBytes,
+        ProcDefinitionLineNumberMap, ProcReferenceList, VariableAddressDefinitionMap,
+        VariableAddressMap, VariableReferenceList, VariableSize, VariableType,
     },
-    utils::get_jmp_code_compiled_line,
+    utils::{get_exit_compiled_lines, get_jmp_code_compiled_line, get_startup_compiled_lines},
 };
 
 fn strip_space_and_comments_and_iterate_labels(
@@ -102,6 +116,8 @@ fn compile(
     line_number: usize,
     lexed_strings: &[Token],
     is_org_defined: bool,
+    encoding: Encoding,
+    segments: &Segments,
     compiled_line_offset_maps: Option<&CompiledLineLabelRef>,
     variable_address_map: Option<&VariableAddressMap>,
 ) -> Result<CompiledLine, CompilationError> {
@@ -110,6 +126,24 @@ fn compile(
 
     let (lexed_str_without_spaces, label) =
         strip_space_and_comments_and_iterate_labels(lexed_strings);
+    let folded_line = fold_expressions(
+        &lexed_str_without_spaces,
+        &mut LabelAddressContext {
+            is_org_defined,
+            segments,
+            maps: ParseRegMemFnArgMaps {
+                label_idx_map: &mut compiled_line.label_idx_map,
+                variable_ref_map: &mut compiled_line.variable_reference_map,
+                variable_abs_address_map: variable_address_map
+                    .unwrap_or(&VariableAddressMap::default()),
+                compiled_line_offset_maps,
+            },
+        },
+    )?;
+    let lexed_str_without_spaces = match &folded_line {
+        Some(folded_line) => folded_line.iter().collect(),
+        None => lexed_str_without_spaces,
+    };
     if let Some(label) = label {
         compiled_line.labels.push(label);
         i += 2;
@@ -126,12 +160,84 @@ fn compile(
         return Ok(compiled_line);
     }
 
+    // the segments are found before the lines are compiled
+    if is_segment_line(&lexed_str_without_spaces[i..]) {
+        return Ok(compiled_line);
+    }
     let tokenized_line = TokenizedLine::new(&lexed_str_without_spaces, len_lexed_strings);
 
     let token = &lexed_str_without_spaces[i];
     if let Assembly8086Tokens::AssemblerDirectives(dir) = &token.token_type {
         match dir {
-            AssemblerDirectives::Org | AssemblerDirectives::Macro | AssemblerDirectives::EndM => {}
+            AssemblerDirectives::Org
+            | AssemblerDirectives::Macro
+            | AssemblerDirectives::EndM
+            | AssemblerDirectives::Local
+            | AssemblerDirectives::Rept
+            | AssemblerDirectives::Irp
+            | AssemblerDirectives::Irpc
+            | AssemblerDirectives::Model
+            | AssemblerDirectives::Stack
+            | AssemblerDirectives::Segment
+            | AssemblerDirectives::EndS
+            | AssemblerDirectives::Assume
+            | AssemblerDirectives::End => {}
+            AssemblerDirectives::Startup => {
+                // the programs without .DATA already have DS at their data
+                if segments.is_segment_name("@data") {
+                    for generated_line in get_startup_compiled_lines(token) {
+                        compiled_line.extend(compile(
+                            line_number,
+                            &generated_line,
+                            is_org_defined,
+                            encoding,
+                            segments,
+                            compiled_line_offset_maps,
+                            variable_address_map,
+                        )?);
+                    }
+                }
+                error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i + 1, ".STARTUP", 0)?;
+                return Ok(compiled_line);
+            }
+            AssemblerDirectives::Exit => {
+                let return_code = match lexed_str_without_spaces.get(i + 1..).unwrap_or_default() {
+                    [] => None,
+                    [return_code] => match return_code.token_type {
+                        Assembly8086Tokens::Number8bit(return_code) => Some(return_code),
+                        _ => {
+                            return Err(CompilationError::error_with_token(
+                                return_code,
+                                &format!(
+                                "Expected a return code from 0 to 255 after .EXIT, Got {} insted",
+                                return_code.token_type
+                            ),
+                            ))
+                        }
+                    },
+                    [_, token, ..] => {
+                        return Err(CompilationError::error_with_token(
+                            token,
+                            &format!(
+                                "Expected only the return code after .EXIT, Got {} insted",
+                                token.token_type
+                            ),
+                        ))
+                    }
+                };
+                for generated_line in get_exit_compiled_lines(token, return_code) {
+                    compiled_line.extend(compile(
+                        line_number,
+                        &generated_line,
+                        is_org_defined,
+                        encoding,
+                        segments,
+                        compiled_line_offset_maps,
+                        variable_address_map,
+                    )?);
+                }
+                return Ok(compiled_line);
+            }
             AssemblerDirectives::Data => {
                 if is_org_defined {
                     let jmp_ins = get_jmp_code_compiled_line(token);
@@ -161,10 +267,29 @@ fn compile(
                 }
             }
             AssemblerDirectives::Code => {
-                // push code into compiled_line.labels don't change the other values already in compiled_line
-                compiled_line.labels.push("code".to_string());
+                // the jmp of .DATA goes to .CODE, without ORG they are segments
+                if is_org_defined {
+                    compiled_line.labels.push("code".to_string());
+                }
             }
             AssemblerDirectives::Offset
+            | AssemblerDirectives::Dup
+            | AssemblerDirectives::Equ
+            | AssemblerDirectives::Include
+            | AssemblerDirectives::If
+            | AssemblerDirectives::Ife
+            | AssemblerDirectives::Ifdef
+            | AssemblerDirectives::Ifndef
+            | AssemblerDirectives::Else
+            | AssemblerDirectives::Endif
+            | AssemblerDirectives::Err
+            | AssemblerDirectives::ErrE
+            | AssemblerDirectives::Echo
+            | AssemblerDirectives::Out
+            | AssemblerDirectives::Ptr
+            | AssemblerDirectives::Short
+            | AssemblerDirectives::Near
+            | AssemblerDirectives::Far
             | AssemblerDirectives::AsWord
             | AssemblerDirectives::AsByte => {}
         }
@@ -178,12 +303,24 @@ fn compile(
     let compiled_bytes = &mut compiled_line.compiled_bytes;
     let compiled_bytes_ref = &mut compiled_line.compiled_bytes_ref;
     let variable_ref_map = &mut compiled_line.variable_reference_map;
+    let bytes_saved = &mut compiled_line.bytes_saved;
 
     match &token.token_type {
         Assembly8086Tokens::Character(_) => {
             i = parse_var_declaration(
                 &tokenized_line,
                 i,
+                &mut LabelAddressContext {
+                    is_org_defined,
+                    segments,
+                    maps: ParseRegMemFnArgMaps {
+                        label_idx_map: &mut compiled_line.label_idx_map,
+                        variable_ref_map,
+                        variable_abs_address_map: variable_address_map
+                            .unwrap_or(&VariableAddressMap::default()),
+                        compiled_line_offset_maps,
+                    },
+                },
                 compiled_bytes,
                 compiled_bytes_ref,
                 &mut compiled_line.variable_abs_address_map,
@@ -231,6 +368,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "ADD", 2)?;
@@ -254,6 +393,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "ADD", 2)?;
@@ -277,6 +418,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "ADD", 2)?;
@@ -300,6 +443,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "ADD", 2)?;
@@ -323,6 +468,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "ADD", 2)?;
@@ -346,6 +493,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "ADD", 2)?;
@@ -369,6 +518,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "ADD", 2)?;
@@ -392,6 +543,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "ADD", 2)?;
@@ -438,6 +591,8 @@ fn compile(
                     compiled_bytes,
                     compiled_bytes_ref,
                     addressing_mode,
+                    encoding,
+                    bytes_saved,
                 )?;
 
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "XOR", 2)?;
@@ -1206,6 +1361,18 @@ fn compile(
                 error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "RET", 0)?;
                 Ok(compiled_line)
             }
+            Instructions::Retf => {
+                convert_and_push_instructions!(
+                    compiled_bytes,
+                    compiled_bytes_ref,
+                    (
+                       token => vec![0xCB]
+                    )
+                );
+                i += 1;
+                error_if_hasnt_consumed_all_ins(&lexed_str_without_spaces, i, "RETF", 0)?;
+                Ok(compiled_line)
+            }
             Instructions::Proc => {
                 let proc_name_token = tokenized_line.get(
                     i + 1,
@@ -1368,36 +1535,84 @@ pub(crate) struct CompiledLineLabelRef<'a> {
     var_line_num_map: &'a HashMap<Label, (VariableType, CompiledBytesIndexedLineNumber)>,
     label_addr_map: &'a HashMap<UniCase<String>, LineNumber>,
     proc_compiled_bytes_line_start_map: &'a ProcDefinitionStartAndEndBytesMap,
+    label_scopes: &'a LabelScopes,
+    segments: &'a Segments,
+    is_org_defined: bool,
+    long_conditional_jumps: bool,
 }
 
 impl<'a> CompiledLineLabelRef<'a> {
-    pub fn find_label_offset_or_proc_offset(
+    // the offset of the label (or) proc from the start of the code
+    pub fn find_label_or_proc_address(
         &self,
         label: &Label,
         line_number: LineNumber,
-    ) -> Option<i16> {
-        let label_off = self.find_label_offset(label, line_number);
-        if let Some((label_off, is_label_before_ref)) = label_off {
-            return Some(label_off as i16 * if is_label_before_ref { -1 } else { 1 });
-        }
-        let proc_off = self.find_proc_offset(label, line_number);
-        if let Some(proc_off) = proc_off {
-            return Some(proc_off);
+    ) -> Option<u16> {
+        let definition_line_number = self
+            .label_addr_map
+            .get(&self.label_scopes.get_key(label, line_number))
+            .or_else(|| {
+                self.proc_compiled_bytes_line_start_map
+                    .get(label)
+                    .map(|(start, _)| start)
+            })?;
+        Some(get_line_offset(
+            self.compiled_bytes,
+            self.segments,
+            *definition_line_number,
+        ))
+    }
+
+    // the segment of a SEGMENT name, i.e @data
+    pub fn find_segment(&self, name: &str) -> Option<u16> {
+        self.segments.find_segment(name, self.compiled_bytes)
+    }
+
+    // the segment the variable (or) label is defined in
+    pub fn find_segment_of(&self, label: &Label) -> Option<u16> {
+        let definition_line_number = self
+            .var_line_num_map
+            .get(label)
+            .map(|(_, line_number)| line_number)
+            .or_else(|| self.label_addr_map.get(label))
+            .or_else(|| {
+                self.proc_compiled_bytes_line_start_map
+                    .get(label)
+                    .map(|(start, _)| start)
+            })?;
+        Some(
+            self.segments
+                .get_line_segment_address(*definition_line_number, self.compiled_bytes),
+        )
+    }
+
+    // the segment and the offset of the label (or) proc, i.e jmp far ptr label
+    pub fn find_far_address(&self, label: &Label, line_number: LineNumber) -> Option<(u16, u16)> {
+        let offset = self.find_label_or_proc_address(label, line_number)?;
+        let (load_segment, load_offset) = get_load_address(self.is_org_defined);
+        if !self.segments.is_segmented() {
+            return Some((load_segment, load_offset.wrapping_add(offset)));
         }
-        None
+        Some((self.find_segment_of(label)?, offset))
     }
 
     pub fn find_label_offset(&self, label: &Label, line_number: LineNumber) -> Option<(u16, bool)> {
-        let label_addr = self.label_addr_map.get(&UniCase::new(label.to_string()));
+        let label_addr = self
+            .label_addr_map
+            .get(&self.label_scopes.get_key(label, line_number));
         let label_addr = match label_addr {
             None => return None,
             Some(label_addr) => label_addr,
         };
         let (offset, is_label_before_ref) =
-            calc_offset(self.compiled_bytes, line_number, *label_addr);
+            calc_offset(self.compiled_bytes, self.segments, line_number, *label_addr);
         Some((offset, is_label_before_ref))
     }
 
+    pub fn is_long_conditional_jump_allowed(&self) -> bool {
+        self.long_conditional_jumps
+    }
+
     pub fn find_var_as_label_offset(
         &self,
         label: &Label,
@@ -1409,8 +1624,12 @@ impl<'a> CompiledLineLabelRef<'a> {
             None => return None,
             Some(var_defn_line_num) => var_defn_line_num.1,
         };
-        let (offset, is_label_before_ref) =
-            calc_offset(self.compiled_bytes, line_number, var_defn_line_num);
+        let (offset, is_label_before_ref) = calc_offset(
+            self.compiled_bytes,
+            self.segments,
+            line_number,
+            var_defn_line_num,
+        );
         Some((offset, is_label_before_ref))
     }
 
@@ -1424,23 +1643,31 @@ impl<'a> CompiledLineLabelRef<'a> {
         };
 
         let mut offset = 0_i16;
+        let is_same_segment = |(line_number, _): &(LineNumber, &Vec<u8>)| {
+            self.segments
+                .is_same_segment(*line_number, proc_reference_line_num)
+        };
         if proc_defined_line_num < proc_reference_line_num {
             // label defined on top of reference
-            for bytes in self
+            for (_, bytes) in self
                 .compiled_bytes
                 .iter()
+                .enumerate()
                 .take(proc_reference_line_num)
                 .skip(proc_defined_line_num)
+                .filter(is_same_segment)
             {
                 offset -= bytes.len() as i16;
             }
         } else {
             // defined on bottom
-            for bytes in self
+            for (_, bytes) in self
                 .compiled_bytes
                 .iter()
+                .enumerate()
                 .take(proc_defined_line_num)
                 .skip(proc_reference_line_num + 1)
+                .filter(is_same_segment)
             {
                 offset += bytes.len() as i16;
             }
@@ -1450,38 +1677,59 @@ impl<'a> CompiledLineLabelRef<'a> {
     }
 }
 
+// only the lines in the segment of the reference are counted
 fn calc_offset(
     compiled_bytes: &[Vec<u8>],
+    segments: &Segments,
     label_ref: LineNumber,
     label_addr: LineNumber,
 ) -> (u16, IsLabelBeforeRef) {
-    let mut offset = 0;
-    if label_addr < label_ref {
-        for bytes in compiled_bytes.iter().take(label_ref).skip(label_addr) {
-            offset += bytes.len();
-        }
-    } else {
+    let (start, end) = match label_addr < label_ref {
+        true => (label_addr, label_ref),
         // i.e label is refernced before it is defined
-        for bytes in compiled_bytes.iter().take(label_addr).skip(label_ref + 1) {
-            offset += bytes.len();
-        }
-    }
-
-    let is_label_before_ref = label_ref > label_addr;
+        false => (label_ref + 1, label_addr),
+    };
+    let offset: usize = compiled_bytes
+        .iter()
+        .enumerate()
+        .take(end)
+        .skip(start)
+        .filter(|(line_number, _)| segments.is_same_segment(*line_number, label_ref))
+        .map(|(_, bytes)| bytes.len())
+        .sum();
+
+    // a label on the line itself is before the reference, i.e `here: jmp here`
+    let is_label_before_ref = label_ref >= label_addr;
     (offset as u16, is_label_before_ref)
 }
 
-type IsVariable = bool;
+// the offset of the line from the start of its segment
+fn get_line_offset(
+    compiled_bytes: &[Vec<u8>],
+    segments: &Segments,
+    line_number: LineNumber,
+) -> u16 {
+    compiled_bytes
+        .iter()
+        .enumerate()
+        .take(line_number)
+        .filter(|(other_line_number, _)| segments.is_same_segment(*other_line_number, line_number))
+        .map(|(_, bytes)| bytes.len() as u16)
+        .sum()
+}
 
+// the jumps are compiled SHORT first and only grow when their label is too far, which
+// moves the labels after them, so the lines are compiled again until no line changes its size
 #[allow(clippy::too_many_arguments)]
 fn mark_labels(
-    // label_ref: &LabelRefrenceList,
-    variable_and_label_ref_list: &[(IsVariable, Label, LineNumber, LineNumber)],
+    // the compiled bytes line and the tokenized line of every line that references an address
+    ref_lines: &[(LineNumber, LineNumber)],
 
-    tokenized_line: &Vec<Vec<Token>>,
+    tokenized_line: &[Vec<Token>],
 
     compiled_bytes: &mut [Vec<u8>],
     compiled_bytes_ref: &mut [Vec<CompiledBytesReference>],
+    bytes_saved: &mut [usize],
 
     label_addr_map: &LabelAddressMap,
 
@@ -1493,106 +1741,128 @@ fn mark_labels(
     var_abs_addr_map: &mut VariableAddressMap,
 
     proc_compiled_bytes_line_start_map: &ProcDefinitionStartAndEndBytesMap,
+    label_scopes: &LabelScopes,
+    segments: &Segments,
 
     is_org_defined: bool,
-    idx: usize,
-) -> Result<bool, CompilationError> {
-    if idx >= variable_and_label_ref_list.len() {
-        return Ok(true);
-    }
-    let (_, _, line_number, tokenized_line_idx) = &variable_and_label_ref_list[idx];
-    let line_number = *line_number;
-    for _ in 0..(variable_and_label_ref_list.len() - idx) {
-        let compiled_tokens = compile(
-            line_number,
-            &tokenized_line[*tokenized_line_idx],
-            is_org_defined,
-            Some(&CompiledLineLabelRef {
-                compiled_bytes,
-                label_addr_map,
-                proc_compiled_bytes_line_start_map,
-                var_line_num_map: var_ref_compiled_bytes_line_num_map,
-                // is_org_defined,
-            }),
-            Some(var_abs_addr_map),
-        )?;
-        let prev_compiled_bytes_len = compiled_bytes[line_number].len();
-
-        compiled_bytes[line_number] = compiled_tokens.compiled_bytes;
-        compiled_bytes_ref[line_number] = compiled_tokens.compiled_bytes_ref;
-
-        let curr_compiled_bytes_len = compiled_bytes[line_number].len();
-
-        if prev_compiled_bytes_len != curr_compiled_bytes_len {
-            // update the variables
-            calculate_variable_offset_map(
-                var_addr_def_map,
-                var_abs_addr_map,
-                compiled_bytes,
+    compile_options: &CompileOptions,
+) -> Result<(), CompilationError> {
+    // a line only grows from the short to the long jump, so every pass but the last grows a line
+    for _ in 0..=ref_lines.len() + 1 {
+        let mut is_size_changed = false;
+        for (line_number, tokenized_line_idx) in ref_lines {
+            let line_number = *line_number;
+            let compiled_tokens = compile(
+                line_number,
+                &tokenized_line[*tokenized_line_idx],
                 is_org_defined,
-            );
-
-            if idx != 0 {
-                return Ok(false);
-            } else {
-                continue;
+                compile_options.encoding,
+                segments,
+                Some(&CompiledLineLabelRef {
+                    compiled_bytes,
+                    label_addr_map,
+                    proc_compiled_bytes_line_start_map,
+                    var_line_num_map: var_ref_compiled_bytes_line_num_map,
+                    label_scopes,
+                    segments,
+                    is_org_defined,
+                    long_conditional_jumps: compile_options.long_conditional_jumps,
+                }),
+                Some(var_abs_addr_map),
+            )?;
+            let prev_compiled_bytes_len = compiled_bytes[line_number].len();
+
+            compiled_bytes[line_number] = compiled_tokens.compiled_bytes;
+            compiled_bytes_ref[line_number] = compiled_tokens.compiled_bytes_ref;
+            bytes_saved[line_number] = compiled_tokens.bytes_saved;
+
+            if prev_compiled_bytes_len != compiled_bytes[line_number].len() {
+                is_size_changed = true;
+                // update the variables
+                calculate_variable_offset_map(
+                    var_addr_def_map,
+                    var_abs_addr_map,
+                    compiled_bytes,
+                    segments,
+                    is_org_defined,
+                );
             }
         }
-
-        if mark_labels(
-            variable_and_label_ref_list,
-            tokenized_line,
-            compiled_bytes,
-            compiled_bytes_ref,
-            label_addr_map,
-            var_ref_compiled_bytes_line_num_map,
-            var_addr_def_map,
-            var_abs_addr_map,
-            proc_compiled_bytes_line_start_map,
-            is_org_defined,
-            idx + 1,
-        )? {
-            return Ok(true);
-        } else if idx != 0 {
-            return Ok(false);
-        } else {
-            continue;
+        if !is_size_changed {
+            return Ok(());
         }
     }
-    Ok(false)
+
+    let (_, tokenized_line_idx) = ref_lines[0];
+    Err(CompilationError::error_with_token(
+        &tokenized_line[tokenized_line_idx][0],
+        "The sizes of the jumps don't settle, Please report this!",
+    ))
 }
 
 fn calculate_variable_offset_map(
     var_addr_def_map: &VariableAddressDefinitionMap,
     var_abs_addr_map: &mut VariableAddressMap,
     compiled_bytes: &[Vec<u8>],
+    segments: &Segments,
     is_org_defined: bool,
 ) {
     // calc offset addr for each var
     for (var_label, (var_type, label_definition_line_number)) in var_addr_def_map {
-        let (offset, _) = calc_offset(compiled_bytes, 0, *label_definition_line_number);
+        let offset = get_line_offset(compiled_bytes, segments, *label_definition_line_number);
         let org_offset = if is_org_defined { 0x100 } else { 0x00 };
-        var_abs_addr_map.insert(var_label.clone(), (*var_type, offset + org_offset));
+        let size = compiled_bytes
+            .get(*label_definition_line_number)
+            .map_or(0, |bytes| bytes.len()) as NumberOfBytes;
+        let variable_size = VariableSize {
+            length: size / var_type.get_size(),
+            size,
+        };
+        var_abs_addr_map.insert(
+            var_label.clone(),
+            (*var_type, offset + org_offset, variable_size),
+        );
     }
 }
 
+fn calculate_label_offset_map(
+    label_addr_map: &LabelAddressMap,
+    compiled_bytes: &[Vec<u8>],
+    segments: &Segments,
+    is_org_defined: bool,
+) -> LabelOffsetMap {
+    let org_offset = if is_org_defined { 0x100 } else { 0x00 };
+    label_addr_map
+        .iter()
+        .filter(|(label, _)| !is_location_label(label))
+        .map(|(label, line_number)| {
+            let offset = get_line_offset(compiled_bytes, segments, *line_number);
+            (label.clone(), offset + org_offset)
+        })
+        .collect()
+}
+
 fn get_err_if_already_defined_label<T>(
     label_key: UniCase<String>,
+    label_name: &Label,
     line: &[Token],
     label_addr_map: &HashMap<Label, T>,
     already_defined_line_number: LineNumber,
 ) -> Option<CompilationError> {
-    let idx = line
+    // the label of .CODE isn't written on the line
+    let token = line
         .iter()
-        .position(|_token| _token.token_type == Assembly8086Tokens::Character(label_key.clone()))
-        .unwrap();
-    let token = &line[idx];
+        .find(|token| token.token_type == Assembly8086Tokens::Character(label_name.clone()))
+        .or_else(|| {
+            line.iter()
+                .find(|token| token.token_type != Assembly8086Tokens::Space)
+        })?;
     if label_addr_map.contains_key(&label_key) {
         return Some(CompilationError::error_with_token(
             token,
             &format!(
                 "The label \"{}\" is already defined in line {}, Please use a different name.",
-                label_key,
+                label_name,
                 (already_defined_line_number + 1)
             ),
         ));
@@ -1600,16 +1870,96 @@ fn get_err_if_already_defined_label<T>(
     None
 }
 
+// a far Jcc is still compiled as the inverted Jcc over a JMP NEAR, so the bytes stay the same
 pub fn compile_lines(
     code: &str,
     debug_print: bool,
 ) -> Result<(Vec<u8>, Vec<CompiledBytesReference>, bool), Vec<CompilationError>> {
+    let program = compile_program(code, debug_print)?;
+    Ok((
+        program.bytes,
+        program.compiled_bytes_ref,
+        program.is_org_defined,
+    ))
+}
+
+fn relocate_errors(
+    mut compilation_errors: Vec<CompilationError>,
+    macro_source_map: &MacroSourceMap,
+    source_map: &SourceMap,
+) -> Vec<CompilationError> {
+    for err in &mut compilation_errors {
+        err.relocate_expansion(macro_source_map);
+        err.relocate(source_map);
+    }
+    compilation_errors
+}
+
+// only checks the code for errors without compiling it to the end
+pub fn try_compile_code(
+    code: &str,
+    compile_options: &CompileOptions,
+) -> Result<(), Vec<CompilationError>> {
+    let (code, source_map) = expand_includes(code, &compile_options.source_files)?;
+    let mut lexer = Lexer::new();
+    lexer.tokenize(&code);
+
+    let mut compilation_errors = Vec::new();
+    let mut macro_source_map = MacroSourceMap::default();
+
+    match compile_lines_perform_var_label_substiution(
+        &mut lexer,
+        &code,
+        compile_options,
+        &mut Vec::new(),
+        &mut compilation_errors,
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut LabelAddressMap::new(),
+        &mut LabelRefrenceList::new(),
+        &mut VariableAddressDefinitionMap::new(),
+        &mut VariableReferenceList::new(),
+        &mut ProcDefinitionLineNumberMap::new(),
+        &mut ProcReferenceList::new(),
+        &mut MacroBoundsDefintionMap::new(),
+        &mut MacroReferenceList::new(),
+        &mut macro_source_map,
+        &mut LabelScopes::default(),
+        &mut Segments::default(),
+        &mut ProgramEnd::default(),
+    ) {
+        Some(_) => Ok(()),
+        None => Err(relocate_errors(
+            compilation_errors,
+            &macro_source_map,
+            &source_map,
+        )),
+    }
+}
+
+pub fn compile_program(
+    code: &str,
+    debug_print: bool,
+) -> Result<CompiledProgram, Vec<CompilationError>> {
+    compile_program_with_options(code, &CompileOptions::default(), debug_print)
+}
+
+pub fn compile_program_with_options(
+    code: &str,
+    compile_options: &CompileOptions,
+    debug_print: bool,
+) -> Result<CompiledProgram, Vec<CompilationError>> {
+    let (code, source_map) = expand_includes(code, &compile_options.source_files)?;
+    let code = code.as_str();
     let mut lexer = Lexer::new();
     lexer.tokenize(code);
 
     let mut compilation_errors = Vec::new();
     let mut compiled_bytes_lines_vec = Vec::new();
     let mut compiled_bytes_ref_lines_vec = Vec::new();
+    let mut bytes_saved_lines_vec = Vec::new();
 
     let mut label_addr_map = LabelAddressMap::new();
     let mut label_ref = LabelRefrenceList::new();
@@ -1617,38 +1967,154 @@ pub fn compile_lines(
     let mut var_addr_def_map = VariableAddressDefinitionMap::new();
     let mut var_ref = VariableReferenceList::new();
 
+    let mut proc_line_num_map = ProcDefinitionLineNumberMap::new();
+    let mut macro_line_num_map = MacroBoundsDefintionMap::new();
+    let mut macro_ref_list = MacroReferenceList::new();
+    let mut macro_source_map = MacroSourceMap::default();
+    let mut messages = Vec::new();
+    let mut warnings = Vec::new();
+    let mut label_scopes = LabelScopes::default();
+    let mut segments = Segments::default();
+    let mut program_end = ProgramEnd::default();
+
     match compile_lines_perform_var_label_substiution(
         &mut lexer,
+        code,
+        compile_options,
+        &mut messages,
         &mut compilation_errors,
+        &mut warnings,
         &mut compiled_bytes_lines_vec,
         &mut compiled_bytes_ref_lines_vec,
+        &mut bytes_saved_lines_vec,
         &mut label_addr_map,
         &mut label_ref,
         &mut var_addr_def_map,
         &mut var_ref,
-        &mut ProcDefinitionLineNumberMap::new(),
+        &mut proc_line_num_map,
         &mut ProcReferenceList::new(),
-        &mut MacroBoundsDefintionMap::new(),
-        &mut MacroReferenceList::new(),
+        &mut macro_line_num_map,
+        &mut macro_ref_list,
+        &mut macro_source_map,
+        &mut label_scopes,
+        &mut segments,
+        &mut program_end,
     ) {
         Some(is_org_defined) => {
-            let compiled_bytes = compiled_bytes_lines_vec.into_iter().flatten().collect();
-            let compiled_bytes_ref = compiled_bytes_ref_lines_vec
-                .into_iter()
-                .flatten()
-                .collect::<Vec<CompiledBytesReference>>();
+            let mut variable_address_map = VariableAddressMap::new();
+            calculate_variable_offset_map(
+                &var_addr_def_map,
+                &mut variable_address_map,
+                &compiled_bytes_lines_vec,
+                &segments,
+                is_org_defined,
+            );
+            let label_offset_map = calculate_label_offset_map(
+                &label_addr_map,
+                &compiled_bytes_lines_vec,
+                &segments,
+                is_org_defined,
+            );
 
+            let load_address = get_load_address(is_org_defined);
+            let line_addresses =
+                segments.get_line_addresses(&compiled_bytes_lines_vec, load_address);
+            // the program starts at the label of END (or) at .STARTUP
+            let entry_address = match program_end.get_entry_line(
+                &label_scopes,
+                &label_addr_map,
+                &proc_line_num_map,
+            ) {
+                Ok(entry_line) => entry_line.map(|line_number| line_addresses[line_number]),
+                Err(err) => return Err(relocate_errors(vec![err], &macro_source_map, &source_map)),
+            };
+            let mut listing = Listing {
+                segment: load_address.0,
+                lines: get_listing_lines(
+                    &macro_source_map.get_expanded_code(code),
+                    &compiled_bytes_lines_vec,
+                    &macro_source_map,
+                    &line_addresses,
+                ),
+                symbols: get_listing_symbols(
+                    &label_addr_map,
+                    &variable_address_map,
+                    &var_addr_def_map,
+                    &proc_line_num_map,
+                    &macro_line_num_map,
+                    &compiled_bytes_lines_vec,
+                    &line_addresses,
+                ),
+            };
+            listing.relocate(&source_map);
             if debug_print {
-                lexer.print_with_compiled_tokens(&compiled_bytes_ref);
+                println!("{}", listing);
+                for message in &messages {
+                    println!("{}", message);
+                }
+                let expanded_code = macro_source_map.get_expanded_code(code);
+                for warning in &warnings {
+                    warning.print_compilation_warning(&expanded_code);
+                }
+            }
+
+            let (bytes, mut compiled_bytes_ref, mut segment_layout) =
+                segments.place(compiled_bytes_lines_vec, compiled_bytes_ref_lines_vec);
+            let entry_point = match (&mut segment_layout, entry_address) {
+                (Some(segment_layout), Some((segment, offset))) => {
+                    segment_layout.code_segment = segment.wrapping_sub(segment_layout.load_segment);
+                    segment_layout.instruction_pointer = offset;
+                    None
+                }
+                (None, Some((_, offset))) => Some(offset),
+                (_, None) => None,
+            };
+            let bytes_saved = bytes_saved_lines_vec.iter().sum();
+            for compiled_bytes in &mut compiled_bytes_ref {
+                if let Some(expanded_line) =
+                    macro_source_map.get_expanded_line(compiled_bytes.line_number)
+                {
+                    let (line_number, column_number, _) = macro_source_map.locate(
+                        compiled_bytes.line_number,
+                        compiled_bytes.column_number,
+                        0,
+                    );
+                    compiled_bytes.line_number = line_number;
+                    compiled_bytes.column_number = column_number;
+                    compiled_bytes.call_site = expanded_line.expansions.last().cloned();
+                    if let Some(call_site) = &mut compiled_bytes.call_site {
+                        call_site.relocate(&source_map);
+                    }
+                }
+                let (file_id, line_number) = source_map.locate(compiled_bytes.line_number);
+                compiled_bytes.file_id = file_id;
+                compiled_bytes.line_number = line_number;
             }
 
-            Ok((compiled_bytes, compiled_bytes_ref, is_org_defined))
+            Ok(CompiledProgram {
+                bytes,
+                compiled_bytes_ref,
+                is_org_defined,
+                variable_address_map,
+                label_offset_map,
+                listing,
+                warnings: relocate_errors(warnings, &macro_source_map, &source_map),
+                source_map,
+                messages,
+                bytes_saved,
+                segment_layout,
+                entry_point,
+            })
         }
         None => {
             if !compilation_errors.is_empty() {
-                Err(compilation_errors)
+                Err(relocate_errors(
+                    compilation_errors,
+                    &macro_source_map,
+                    &source_map,
+                ))
             } else {
-                Ok((Vec::new(), Vec::new(), false))
+                Ok(CompiledProgram::default())
             }
         }
     }
@@ -1663,416 +2129,17 @@ type ProcDefinitionStartAndEndBytesMap = HashMap<
         Option<EndCompiledBytesIndexedLineNumber>,
     ),
 >;
-type MacroBoundsDefintionMapWithOption = HashMap<Label, (ArrayIndex, Option<ArrayIndex>)>;
-type TokenAndItsArrayIndex<'a> = Option<(&'a Assembly8086Tokens, usize)>;
-fn get_first_two_non_space_characters_token_types<'a>(
-    line: &'a [&'a Token],
-    i: usize,
-) -> (TokenAndItsArrayIndex<'a>, TokenAndItsArrayIndex<'a>) {
-    let mut first: Option<(&Assembly8086Tokens, usize)> = None;
-    let mut second = None;
-    let mut j = 0_usize;
-    for token in &line[i..] {
-        if token.token_type == Assembly8086Tokens::Space {
-            continue;
-        }
-        if first.is_none() {
-            first = Some((&token.token_type, i + j));
-        } else if second.is_none() {
-            second = Some((&token.token_type, i + j));
-            break;
-        }
-        j += 1;
-    }
-    (first, second)
-}
-
-fn find_macro_bounds(
-    lexer: &Lexer,
-) -> Result<MacroBoundsDefintionMapWithOption, Vec<CompilationError>> {
-    // iterate lexer to find the macro bounds it should be in the form
-    // character macro arguments
-    // ....
-    // ....
-    // endm (or) character endm
-
-    let mut macro_defn_map: MacroBoundsDefintionMapWithOption = HashMap::new();
-    let mut current_macro_label = None;
-    let mut compilaton_errors = Vec::<CompilationError>::new();
-
-    for (i, tokens_vec) in lexer.tokens.iter().enumerate() {
-        let toeken_ref_vec = tokens_vec.iter().collect::<Vec<&Token>>();
-        let first_and_second_tokens =
-            get_first_two_non_space_characters_token_types(&toeken_ref_vec, 0);
-        match first_and_second_tokens {
-            (
-                Some((Assembly8086Tokens::Character(macro_label), token_idx)),
-                Some((Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Macro), _)),
-            ) => {
-                if let Some((_, line_num, tokn_idx)) = current_macro_label {
-                    let line: &Vec<Token> = &lexer.tokens[line_num];
-                    let token = &line[tokn_idx];
-                    compilaton_errors.push(CompilationError::error_with_token(token, &format!("You have not closed this MACRO with name {}, Nested macros are not supported", macro_label)));
-                }
-                macro_defn_map.insert(macro_label.clone(), (i, None));
-                current_macro_label = Some((macro_label.clone(), i, token_idx));
-            }
-            (
-                Some((Assembly8086Tokens::Character(macro_label), macro_label_idx)),
-                Some((Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::EndM), _)),
-            ) => {
-                match &current_macro_label {
-                    None => {
-                        compilaton_errors.push(CompilationError::error_with_token(
-                            &tokens_vec[macro_label_idx+1],
-                            &format!(
-                                "The macro \"{}\" is not defined, Please define it before ending it. Maybe you are trying to define a recursive Macro ? It isn't supported",
-                                macro_label
-                            ),
-                        ));
-                    }
-                    Some((current_macro_label, _, _)) => {
-                        if current_macro_label != macro_label {
-                            compilaton_errors.push(CompilationError::error_with_token(
-                                &tokens_vec[macro_label_idx+1],
-                                &format!(
-                                    "The macro \"{}\" is not defined, Please define it before ending it. Maybe you are trying to define a recursive Macro ? It isn't supported",
-                                    macro_label
-                                ),
-                            ));
-                        }
-                    }
-                }
-                if let Some((_, end_idx)) = macro_defn_map.get_mut(macro_label) {
-                    *end_idx = Some(i);
-                    current_macro_label = None;
-                } else {
-                    compilaton_errors.push(CompilationError::error_with_token(
-                        &tokens_vec[macro_label_idx + 1],
-                        &format!(
-                            "The macro \"{}\" is not defined, Please define it before ending it.",
-                            macro_label
-                        ),
-                    ));
-                }
-            }
-            (
-                Some((
-                    Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::EndM),
-                    end_macro_idx,
-                )),
-                None,
-            ) => match &current_macro_label {
-                None => {
-                    compilaton_errors.push(CompilationError::error_with_token(
-                            &tokens_vec[end_macro_idx+1],
-                            "No macro defintion found, define a macro using `Name MACRO p1, p2, p3 syntax`, Please define it before ending it. Maybe you are trying to define a recursive Macro ? It isn't supported",
-                        ));
-                }
-                Some((current_macro_label, _, _)) => {
-                    if let Some((_, end_idx)) = macro_defn_map.get_mut(current_macro_label) {
-                        *end_idx = Some(i);
-                    } else {
-                        compilaton_errors.push(CompilationError::error_with_token(
-                                &tokens_vec[end_macro_idx+1],
-                                &format!(
-                                    "The macro \"{}\" is not defined, Please define it before ending it.",
-                                    current_macro_label
-                                ),
-                            ));
-                    }
-                }
-            },
-            (
-                Some((_, idx)),
-                Some((Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Macro), _)),
-            ) => {
-                compilaton_errors.push(CompilationError::error_with_token(
-                    &tokens_vec[idx + 1],
-                    "Macro name must be a character",
-                ));
-            }
-            (
-                Some((_, idx)),
-                Some((Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::EndM), _)),
-            ) => {
-                compilaton_errors.push(CompilationError::error_with_token(
-                    &tokens_vec[idx + 1],
-                    "Macro name must be a character",
-                ));
-            }
-
-            (
-                Some((Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Macro), idx)),
-                _,
-            ) => {
-                compilaton_errors.push(CompilationError::error_with_token(
-                    &tokens_vec[idx+1],
-                    "Need to define a name for the macro with the folling syntax `Name MACRO p1, p2, p3`",
-                ));
-            }
-            _ => {}
-        }
-    }
-
-    if !compilaton_errors.is_empty() {
-        return Err(compilaton_errors);
-    }
-
-    Ok(macro_defn_map)
-}
-
-fn find_macro_name(tokens: &[Token]) -> Result<Option<usize>, CompilationError> {
-    let mut i = 0;
-    let (lexed_str_without_spaces, label) = strip_space_and_comments_and_iterate_labels(tokens);
-    if label.is_some() {
-        i += 2;
-    }
-    // check if the next token is db (or) dw
-    let first_and_second_tokens =
-        get_first_two_non_space_characters_token_types(&lexed_str_without_spaces, i);
-    match first_and_second_tokens {
-        (
-            Some((Assembly8086Tokens::Character(_), label_idx)),
-            Some((Assembly8086Tokens::Data(DefineData::Db), _))
-            | Some((Assembly8086Tokens::Data(DefineData::Dw), _)),
-        ) => {
-            return Err(CompilationError::error_with_token(
-                lexed_str_without_spaces[label_idx],
-                "The macro name cannot be a variable",
-            ));
-        }
-        (
-            Some((Assembly8086Tokens::Character(_), _)),
-            Some((Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Macro), _)),
-        ) => {}
-        (Some((Assembly8086Tokens::Character(_), label_idx)), _) => {
-            let token_ref = lexed_str_without_spaces[label_idx];
-            // iterate tokens to find this tokenidx in the tokens
-            let token_idx = tokens.iter().position(|token| token.is_abs_eq(token_ref));
-            return Ok(token_idx);
-        }
-        _ => {}
-    }
-
-    Ok(None)
-}
-
-fn get_args_seperated_by_comma_from_line(
-    line: &[Token],
-    start_idx: usize,
-) -> Result<Vec<usize>, CompilationError> {
-    // get only certain toekns from the line
-    // line = token1 arg1, arg2, arg3
-    // return = arg1, arg2, arg3
-    let mut args: Vec<usize> = Vec::<usize>::new();
-    let mut new_line = Vec::new();
-    for tokn in line[start_idx..].iter() {
-        new_line.push(tokn.clone());
-    }
-
-    let (stripped_line, _) = strip_space_and_comments_and_iterate_labels(new_line.as_slice());
-    iterate_with_seperator(
-        0,
-        stripped_line.len(),
-        &TokenizedLine::new(&stripped_line, stripped_line.len() as u32),
-        &Assembly8086Tokens::Comma,
-        |token| {
-            let token_idx = line.iter().position(|tokn| tokn == token).unwrap();
-            args.push(token_idx);
-            Ok(())
-        },
-    )?;
-    Ok(args)
-}
-
-fn get_parameter_to_argument<'a>(
-    line: &'a [Token],
-    macro_ref_start_index: usize,
-    macro_definitin_line: &'a [Token],
-    // macro_def_start_index: usize,
-) -> Result<HashMap<&'a Token, &'a Token>, CompilationError> {
-    // iterate to find the idex
-    let macro_def_start_index = macro_definitin_line
-        .iter()
-        .position(|token| {
-            token.token_type == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Macro)
-        })
-        .unwrap();
-    let mut parameter_to_argument = HashMap::<&Token, &Token>::new();
-
-    let parameter_args =
-        get_args_seperated_by_comma_from_line(macro_definitin_line, macro_def_start_index + 1)?;
-    let argument_args = get_args_seperated_by_comma_from_line(line, macro_ref_start_index)?;
-
-    if parameter_args.len() != argument_args.len() {
-        return Err(CompilationError::error_with_token(
-            &line[macro_ref_start_index - 1],
-            &format!(
-                "The macro \"{}\" is defined with {} arguments, but you have passed {} arguments",
-                line[macro_ref_start_index - 1].token_type,
-                parameter_args.len(),
-                argument_args.len()
-            ),
-        ));
-    }
-
-    for (parameter, argument) in parameter_args.iter().zip(argument_args.iter()) {
-        let macro_def_parameter = &macro_definitin_line[*parameter];
-        let macro_ref_argument = &line[*argument];
-        parameter_to_argument.insert(macro_def_parameter, macro_ref_argument);
-    }
-
-    Ok(parameter_to_argument)
-}
-
-fn expand_macros(
-    lexer: &mut Lexer,
-    macro_bounds: HashMap<Label, (usize, usize)>,
-    macro_ref_list: &mut MacroReferenceList,
-) -> Result<(), Vec<CompilationError>> {
-    let mut compilation_errors = Vec::<CompilationError>::new();
-    let mut changes: Vec<(usize, usize, Vec<Vec<Token>>)> = Vec::new();
-    let mut line_number = 0;
-
-    for tokens_vec in &lexer.tokens {
-        line_number += 1;
-
-        let macro_name_index = match find_macro_name(tokens_vec) {
-            Ok(macro_name_index) => macro_name_index,
-            Err(err) => {
-                compilation_errors.push(err);
-                continue;
-            }
-        };
-        if let Some(macro_reference_label_index) = macro_name_index {
-            let macro_label = &tokens_vec[macro_reference_label_index];
-            let macro_label_str = match &macro_label.token_type {
-                Assembly8086Tokens::Character(c) => c,
-                _ => {
-                    compilation_errors.push(CompilationError::error_with_token(
-                        macro_label,
-                        "This shouldn't happen, Please report this! Error: 906 in expand macros",
-                    ));
-                    continue;
-                }
-            };
-
-            let macro_bounds = match macro_bounds.get(macro_label_str) {
-                Some(macro_bounds) => macro_bounds,
-                None => {
-                    compilation_errors.push(CompilationError::error_with_token(
-                        macro_label,
-                        "This shouldn't happen, Unable to get macro bounds. Please report this! Error: 916 in expand macros",
-                    ));
-                    continue;
-                }
-            };
-
-            macro_ref_list.push((macro_label_str.clone(), macro_label.clone(), line_number));
-
-            let (start_idx, end_idx) = *macro_bounds;
-            // convert vec<Toekn> to vec<&Token>
-            let macro_ref_line_start = tokens_vec;
-            let macro_def_line_start = &lexer.tokens[start_idx];
-
-            let parameters_to_arguments_map = match get_parameter_to_argument(
-                macro_ref_line_start,
-                macro_reference_label_index + 1,
-                macro_def_line_start.as_slice(),
-            ) {
-                Ok(parameters_to_arguments_map) => parameters_to_arguments_map,
-                Err(err) => {
-                    compilation_errors.push(err);
-                    continue;
-                }
-            };
-
-            // start from start_idx and end at end_idx push all the tokens into the lexer
-            let mut temp_vec = Vec::new();
-            for i in (start_idx + 1)..=(end_idx - 1) {
-                let mut tokens = lexer.tokens[i].clone();
-                for token in &mut tokens {
-                    if let Some(argument) = parameters_to_arguments_map.get(token) {
-                        let new_token = Token::new(
-                            argument.token_type.clone(),
-                            argument.line_number,
-                            argument.column_number,
-                            argument.token_length,
-                        );
-                        *token = new_token;
-                    }
-                }
-                temp_vec.push(tokens);
-            }
-            changes.push((line_number - 1, macro_reference_label_index, temp_vec));
-        }
-    }
-
-    // change the macro defition lines to ';' comment token use iterators
-    lexer.tokens = lexer
-        .tokens
-        .clone()
-        .into_iter()
-        .enumerate()
-        .map(|(i, tokens)| {
-            // check if the line is a by iterating the macro_bounds using iteratos
-            let is_in_macro = macro_bounds.iter().any(|(_, (start, end))| {
-                if i >= *start && i <= *end {
-                    return true;
-                }
-                false
-            });
-            if is_in_macro {
-                let mut tokens = tokens.clone();
-                let first = tokens.first();
-                if let Some(first) = first {
-                    let mut first_clone = first.clone();
-                    first_clone.token_type = Assembly8086Tokens::Comment;
-                    tokens.insert(0, first_clone);
-                }
-                tokens
-            } else {
-                tokens
-            }
-        })
-        .collect();
-
-    // sort chages by line number
-    changes.sort_by(|(line_number1, _, _), (line_number2, _, _)| line_number1.cmp(line_number2));
-
-    // make the changes into the lexer
-    // delete the lexer the chanes line number
-    // replace the single line with maybe mutlpile lines defined in chages
-    let mut index_offset = 0;
-    for (change_line_number, ref_label_idx, change) in changes {
-        // preserve label: from the macro expansion
-        let line = &lexer.tokens[change_line_number];
-        let ref_label_idx = if ref_label_idx > 0 {
-            ref_label_idx - 1
-        } else {
-            ref_label_idx
-        };
-        let front = &line[..ref_label_idx]; // don't include the macro name
-        lexer.tokens[change_line_number + index_offset] = front.to_vec();
-        // expand the macro
-        for tokens in change {
-            index_offset += 1;
-            lexer
-                .tokens
-                .insert(change_line_number + index_offset, tokens);
-        }
-    }
-
-    Ok(())
-}
-
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn compile_lines_perform_var_label_substiution(
     lexer: &mut Lexer,
+    code: &str,
+    compile_options: &CompileOptions,
+    echo_messages: &mut Vec<String>,
     compilation_errors: &mut Vec<CompilationError>,
+    warnings: &mut Vec<CompilationError>,
     compiled_bytes_lines_vec: &mut Vec<Vec<u8>>,
     compiled_bytes_ref_lines_vec: &mut Vec<Vec<CompiledBytesReference>>,
+    bytes_saved_lines_vec: &mut Vec<usize>,
     label_addr_map: &mut LabelAddressMap,
     label_ref: &mut LabelRefrenceList,
     var_addr_def_map: &mut VariableAddressDefinitionMap,
@@ -2082,6 +2149,10 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
 
     macro_line_num_map: &mut MacroBoundsDefintionMap,
     macro_ref_list: &mut MacroReferenceList,
+    macro_source_map: &mut MacroSourceMap,
+    label_scopes: &mut LabelScopes,
+    segments: &mut Segments,
+    program_end: &mut ProgramEnd,
 ) -> Option<bool> {
     let is_org_defined = match is_org_defined(&lexer.tokens) {
         Ok(is_org_defined) => is_org_defined,
@@ -2091,55 +2162,33 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
         }
     };
 
-    let macro_bounds = match find_macro_bounds(lexer) {
-        Ok(macro_bounds) => macro_bounds,
-        Err(err) => {
-            compilation_errors.extend(err);
-            return None;
-        }
-    };
-
-    // check if the end is defined in all the keys if not push to compilation errror
-    // and convert macro_bonds to HashMap<Label, (usize, usize)>
-    let macro_bounds = match macro_bounds
-        .into_iter()
-        .map(|(label, (start, end))| match end {
-            None => {
-                let line = &lexer.tokens[start];
-                // find the first non space char
-                let token = line
-                    .iter()
-                    .find(|token| token.token_type != Assembly8086Tokens::Space)
-                    .unwrap();
-                compilation_errors.push(CompilationError::error_with_token(
-                    token,
-                    &format!(
-                        "The macro \"{}\" is not ended, Please end it with `ENDM`",
-                        label
-                    ),
-                ));
-                None
-            }
-            Some(end) => Some((label, (start, end))),
-        })
-        .collect::<Option<HashMap<Label, (usize, usize)>>>()
-    {
-        Some(macro_bounds) => macro_bounds,
-        None => return None,
-    };
-    macro_line_num_map.extend(macro_bounds.clone());
-
+    if let Err(err) = expand_macros(
+        lexer,
+        code,
+        &compile_options.defines,
+        macro_line_num_map,
+        macro_ref_list,
+        macro_source_map,
+        warnings,
+    ) {
+        compilation_errors.extend(err);
+        return None;
+    }
     if !compilation_errors.is_empty() {
         return None;
     }
+    let code = &macro_source_map.get_expanded_code(code);
 
-    match expand_macros(lexer, macro_bounds, macro_ref_list) {
-        Ok(()) => {}
-        Err(err) => {
-            compilation_errors.extend(err);
-            return None;
-        }
-    };
+    if let Err(err) = expand_constants(lexer, code, &compile_options.defines, echo_messages) {
+        compilation_errors.extend(err);
+        return None;
+    }
+    *program_end = ProgramEnd::new(&mut lexer.tokens, warnings, compilation_errors);
+    *label_scopes = LabelScopes::new(&lexer.tokens, warnings);
+    *segments = Segments::new(&lexer.tokens, is_org_defined, compilation_errors);
+    if !compilation_errors.is_empty() {
+        return None;
+    }
 
     type CompiledBytesIndexedLineNumber = LineNumber;
     let mut label_compiled_bytes_line_number_map =
@@ -2152,18 +2201,40 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
     let mut labels_used_as_offsets = Vec::new();
 
     for (i, line) in lexer.tokens.iter().enumerate() {
-        match compile(i, line, is_org_defined, None, None) {
+        match compile(
+            i,
+            line,
+            is_org_defined,
+            compile_options.encoding,
+            segments,
+            None,
+            None,
+        ) {
             Ok(compiled_line) => {
+                if segments.is_outside_of_segments(i)
+                    && (!compiled_line.compiled_bytes.is_empty()
+                        || !compiled_line.labels.is_empty()
+                        || !compiled_line.proc_definition_map.is_empty())
+                {
+                    let (stripped_line, _) = strip_space_and_comments_and_iterate_labels(line);
+                    compilation_errors.push(CompilationError::error_with_token(
+                        stripped_line[0],
+                        "The line isn't in a segment, Please put it after .CODE (or) .DATA (or) in a SEGMENT.",
+                    ));
+                    continue;
+                }
                 let compiled_bytes_line = compiled_line.compiled_bytes;
                 let compiled_bytes_ref_line = compiled_line.compiled_bytes_ref;
+                let bytes_saved_line = compiled_line.bytes_saved;
 
                 // Pushing all the labels into a map after checking if they are already defined
                 for label_str in compiled_line.labels {
-                    let label_key = UniCase::new(label_str.to_string().clone());
+                    let label_key = label_scopes.get_key(&label_str, i);
                     let already_defined_line_number = *label_addr_map.get(&label_key).unwrap_or(&0);
                     // check if the label is already defined
                     if let Some(err) = get_err_if_already_defined_label(
                         label_key.clone(),
+                        &UniCase::new(label_str),
                         line,
                         label_addr_map,
                         already_defined_line_number,
@@ -2190,6 +2261,7 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
                     // check if the label is already defined
                     if let Some(err) = get_err_if_already_defined_label(
                         label_key.clone(),
+                        &label_key,
                         line,
                         var_addr_def_map,
                         already_defined_line_number,
@@ -2222,6 +2294,7 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
                         ProcDefitionType::Proc => {
                             if let Some(err) = get_err_if_already_defined_label(
                                 label_key.clone(),
+                                &label_key,
                                 line,
                                 &proc_compiled_bytes_line_number_map,
                                 already_defined_line_number,
@@ -2279,7 +2352,7 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
 
                 // Pushing all the labels that reference a particular label
                 for (label_str, (token, _, is_offset)) in compiled_line.label_idx_map {
-                    let label = UniCase::new(label_str);
+                    let label = label_scopes.get_key(&label_str, i);
                     if is_offset {
                         labels_used_as_offsets.push(label.clone());
                     }
@@ -2317,6 +2390,7 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
                 // pushing the compiled bytes and ref into a vec
                 compiled_bytes_lines_vec.push(compiled_bytes_line);
                 compiled_bytes_ref_lines_vec.push(compiled_bytes_ref_line);
+                bytes_saved_lines_vec.push(bytes_saved_line);
             }
             Err(err) => {
                 compilation_errors.push(err);
@@ -2475,14 +2549,38 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
 
     // check if all labels are defined
     let mut label_errors = false;
-    for (label, token, _, _) in &mut *label_ref {
+    for (label, token, _, tokenized_line_number) in &mut *label_ref {
         if !label_addr_map.contains_key(label) && !var_addr_def_map.contains_key(label) {
             label_errors = true;
-            compilation_errors.push(CompilationError::error_with_token(
+            compilation_errors.push(label_scopes.get_undefined_label_error(
                 token,
-                &format!("The label \"{}\" is Undefined, Please define it.", label),
+                *tokenized_line_number,
+                label_addr_map,
             ));
         }
+        // the jumps and calls are relative to their segment, the FAR ones have the segment of the label
+        if let Some(line_number) = label_addr_map.get(label) {
+            if !labels_used_as_offsets.contains(label)
+                && !segments.is_far_reference(*tokenized_line_number, token.column_number)
+                && !segments.is_same_segment(*line_number, *tokenized_line_number)
+            {
+                label_errors = true;
+                let segment_name = |line_number| {
+                    segments
+                        .get_name(line_number)
+                        .map_or(String::new(), |name| name.to_string())
+                };
+                compilation_errors.push(CompilationError::error_with_token(
+                    token,
+                    &format!(
+                        "The label \"{}\" is in the segment \"{}\", Please move it to the segment \"{}\" to jump to it.",
+                        label,
+                        segment_name(*line_number),
+                        segment_name(*tokenized_line_number)
+                    ),
+                ));
+            }
+        }
         if let Some((VariableType::Byte, line_number)) = var_addr_def_map.get(label) {
             if labels_used_as_offsets.contains(label) {
                 continue;
@@ -2508,41 +2606,48 @@ pub(crate) fn compile_lines_perform_var_label_substiution(
         var_addr_def_map,
         &mut var_abs_addr_map,
         compiled_bytes_lines_vec,
+        segments,
         is_org_defined,
     );
 
-    let mut var_label_ref = Vec::new();
-    for (var, _, var_line_number, tokenized_line_number) in var_ref.iter() {
-        var_label_ref.push((true, var.clone(), *var_line_number, *tokenized_line_number));
-    }
-
-    for (label, _, label_line_number, tokenized_line_number) in label_ref.iter() {
-        var_label_ref.push((
-            false,
-            label.clone(),
-            *label_line_number,
-            *tokenized_line_number,
-        ));
-    }
-
-    match mark_labels(
-        &var_label_ref,
+    let mut ref_lines = var_ref
+        .iter()
+        .map(|(_, _, line_number, tokenized_line_number)| (*line_number, *tokenized_line_number))
+        .chain(
+            label_ref
+                .iter()
+                .map(|(_, _, line_number, tokenized_line_number)| {
+                    (*line_number, *tokenized_line_number)
+                }),
+        )
+        .chain(
+            segments
+                .get_reference_lines()
+                .into_iter()
+                .map(|line_number| (line_number, line_number)),
+        )
+        .collect::<Vec<(LineNumber, LineNumber)>>();
+    ref_lines.sort();
+    ref_lines.dedup();
+
+    if let Err(err) = mark_labels(
+        &ref_lines,
         &lexer.tokens,
         compiled_bytes_lines_vec,
         compiled_bytes_ref_lines_vec,
+        bytes_saved_lines_vec,
         &label_compiled_bytes_line_number_map,
         &var_compiled_bytes_line_number_map,
         var_addr_def_map,
         &mut var_abs_addr_map,
         &proc_compiled_bytes_line_number_map,
+        label_scopes,
+        segments,
         is_org_defined,
-        0,
+        compile_options,
     ) {
-        Ok(_) => (),
-        Err(err) => {
-            compilation_errors.push(err);
-        }
-    };
+        compilation_errors.push(err);
+    }
 
     if !compilation_errors.is_empty() {
         return None;
@@ -1,5 +1,7 @@
 use crate::compiler::{
-    compilation_error::CompilationError, tokenized_line::TokenizedLine, CompiledBytesReference,
+    compilation_error::CompilationError,
+    tokenized_line::TokenizedLine,
+    types_structs::{CompiledBytesReference, Encoding},
 };
 
 use super::pattern_extractors::{
@@ -13,6 +15,8 @@ pub(in crate::compiler) fn parse_add(
     compiled_bytes: &mut Vec<u8>,
     compiled_bytes_ref: &mut Vec<CompiledBytesReference>,
     addressing_mode: AddressingMode,
+    encoding: Encoding,
+    bytes_saved: &mut usize,
 ) -> Result<usize, CompilationError> {
     let ins = CompilingBytesForInstruction {
         reg_16bit_and_anything_ins: 0x03,
@@ -40,12 +44,25 @@ pub(in crate::compiler) fn parse_add(
         compiled_bytes,
         compiled_bytes_ref,
         addressing_mode,
+        encoding,
+        bytes_saved,
     )
 }
 
 #[cfg(test)]
 mod tests16bit {
-    use crate::{compile_and_compare_ins, compiler::compile_str, test_compile};
+    use crate::{
+        compile_and_compare_ins,
+        compiler::{
+            compile_program_with_options, compile_str,
+            types_structs::{CompileOptions, Encoding},
+        },
+        cpu::{
+            instructions::test_macro::execute_code_with_options,
+            registers::{Flag, Register},
+        },
+        test_compile,
+    };
     use pretty_assertions::assert_eq;
 
     test_compile!(
@@ -159,7 +176,7 @@ mod tests16bit {
         endp main
         ",
         |instructions: &Vec<u8>| {
-            assert_eq!(instructions, &[0x05, 0x05, 0x00, 0x40, 0x8B, 0xC3]);
+            assert_eq!(instructions, &[0x05, 0x04, 0x00, 0x40, 0x8B, 0xC3]);
         }
     );
 
@@ -191,6 +208,67 @@ mod tests16bit {
         }
     );
 
+    #[test]
+    fn smallest_encoding() {
+        let code = "
+        ADD CX, 0xFFFF
+        SUB [0x10], 0xFFF0
+        ADD AX, 0xFFFF
+        TEST CX, 0xFFFF
+        ";
+        let compile_options = CompileOptions {
+            encoding: Encoding::Smallest,
+            ..Default::default()
+        };
+        let program = compile_program_with_options(code, &compile_options, false).unwrap();
+        assert_eq!(
+            program.bytes,
+            vec![
+                0x83, 0xC1, 0xFF, // ADD CX
+                0x83, 0x2E, 0x10, 0x00, 0xF0, // SUB [0x10]
+                0x05, 0xFF, 0xFF, // ADD AX
+                0xF7, 0xC1, 0xFF, 0xFF, // TEST CX
+            ]
+        );
+        assert_eq!(program.bytes_saved, 2);
+
+        let (bytes, _) = compile_str(code, false).unwrap();
+        assert_eq!(bytes[..4], [0x81, 0xC1, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn smallest_encoding_runs_the_same() {
+        let code = "
+        MOV BX, 0x05
+        ADD BX, 0xFFFF
+        MOV CX, 0x10
+        SUB CX, 1
+        ADD DX, 0xFF80
+        MOV SI, 0x07
+        AND SI, 0xFFFE
+        MOV AX, 0x20
+        MOV [0x200], AX
+        SUB [0x200], 0xFFF0
+        CMP DX, 0xFF80
+        ";
+        let compile_options = CompileOptions {
+            encoding: Encoding::Smallest,
+            ..Default::default()
+        };
+        let program = compile_program_with_options(code, &compile_options, false).unwrap();
+        assert_eq!(program.bytes_saved, 5);
+        for compile_options in [compile_options, CompileOptions::default()] {
+            let (cpu, mem) = execute_code_with_options(code, &compile_options);
+            assert_eq!(cpu.get_register(Register::BX), 0x0004);
+            assert_eq!(cpu.get_register(Register::CX), 0x000F);
+            assert_eq!(cpu.get_register(Register::DX), 0xFF80);
+            assert_eq!(cpu.get_register(Register::SI), 0x0006);
+            let data_segment = cpu.get_register(Register::DS);
+            assert_eq!(mem.peek_word(data_segment, 0x200), 0x0030);
+            assert!(cpu.get_flag(Flag::ZF));
+        }
+    }
+
     compile_and_compare_ins!(
         add_indexed_addr_and_reg,
         "
@@ -1,7 +1,12 @@
 use crate::{
     compiler::{
         compilation_error::CompilationError,
-        parsers::utils::push_instruction,
+        parsers::{
+            pattern_extractors::offset_label_pattern::{
+                get_distance_operator_len, is_far_ptr, parse_far_label,
+            },
+            utils::push_instruction,
+        },
         tokenized_line::TokenizedLine,
         tokens::Assembly8086Tokens,
         types_structs::{LineNumber, ProcReferenceMap},
@@ -26,12 +31,33 @@ pub(in crate::compiler) fn parse_call(
         None,
     )?;
 
+    let distance_operator_len =
+        get_distance_operator_len(tokenized_line, i + 1, "CALL", false, true)?;
     let high_token = tokenized_line.get(
-        i + 1,
+        i + 1 + distance_operator_len,
         "Expected arguments after CALL got nothing".to_string(),
         None,
     )?;
 
+    if is_far_ptr(tokenized_line, i + 1) {
+        let (label, far_address) =
+            parse_far_label(high_token, line_number, compiled_line_ref_with_offset_maps)?;
+        let (segment, offset) = far_address.unwrap_or_else(|| {
+            proc_ref_map.insert(label.clone(), i + 1 + distance_operator_len);
+            (0, 0)
+        });
+        convert_and_push_instructions!(
+            compiled_bytes,
+            compiled_bytes_ref,
+            (
+                token => vec![0x9A],
+                // the offset and then the segment of the label
+                high_token => [offset.to_le_bytes(), segment.to_le_bytes()].concat()
+            )
+        );
+        return Ok(i + 1 + distance_operator_len);
+    }
+
     match &high_token.token_type {
         Assembly8086Tokens::Character(label) => {
             let offset_bytes_from_line_and_is_label_before_ref =
@@ -53,7 +79,7 @@ pub(in crate::compiler) fn parse_call(
             let addr = match addr {
                 Some(addr) => addr,
                 None => {
-                    proc_ref_map.insert(label.clone(), i + 1);
+                    proc_ref_map.insert(label.clone(), i + 1 + distance_operator_len);
                     0_u16
                 }
             };
@@ -69,7 +95,7 @@ pub(in crate::compiler) fn parse_call(
                 )
             );
 
-            Ok(i + 1)
+            Ok(i + 1 + distance_operator_len)
         }
         _ => Err(CompilationError::error_with_token(
             high_token,
@@ -121,6 +147,7 @@ fn calc_offset(offset_bytes: u16, is_jmp_after_label: bool) -> u16 {
 #[cfg(test)]
 mod tests {
     use crate::{
+        compile_and_compare_ins,
         compiler::{compile_str, tests::generate_num_ins},
         test_compile,
     };
@@ -218,4 +245,31 @@ mod tests {
             );
         }
     );
+
+    compile_and_compare_ins!(
+        call_near_ptr,
+        "
+    CALL NEAR PTR main
+    PROC main
+    RET
+    ENDP main
+    ",
+        &[0xE8, 0x00, 0x00, 0xC3]
+    );
+
+    compile_and_compare_ins!(
+        call_far_ptr,
+        "
+    CALL FAR PTR main
+    main:
+    RETF
+    ",
+        &[0x9A, 0x05, 0x00, 0x00, 0x01, 0xCB]
+    );
+
+    #[test]
+    fn call_short_is_an_error() {
+        assert!(compile_str("call short main\n main: ret", false).is_err());
+        assert!(compile_str("call far ptr 0x12", false).is_err());
+    }
 }
@@ -1,5 +1,7 @@
 use crate::compiler::{
-    compilation_error::CompilationError, tokenized_line::TokenizedLine, CompiledBytesReference,
+    compilation_error::CompilationError,
+    tokenized_line::TokenizedLine,
+    types_structs::{CompiledBytesReference, Encoding},
 };
 
 use super::pattern_extractors::{
@@ -13,6 +15,8 @@ pub(in crate::compiler) fn parse_cmp(
     compiled_bytes: &mut Vec<u8>,
     compiled_bytes_ref: &mut Vec<CompiledBytesReference>,
     addressing_mode: AddressingMode,
+    encoding: Encoding,
+    bytes_saved: &mut usize,
 ) -> Result<usize, CompilationError> {
     let ins = CompilingBytesForInstruction {
         reg_16bit_and_anything_ins: 0x3B,
@@ -40,6 +44,8 @@ pub(in crate::compiler) fn parse_cmp(
         compiled_bytes,
         compiled_bytes_ref,
         addressing_mode,
+        encoding,
+        bytes_saved,
     )
 }
 
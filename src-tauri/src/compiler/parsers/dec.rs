@@ -66,12 +66,11 @@ pub(in crate::compiler) fn parse_dec(
             let variable_type = variable_address_map
                 .unwrap_or(&VariableAddressMap::new())
                 .get(high_char)
-                .unwrap_or(&(VariableType::Byte, 0_u16))
-                .0;
+                .map_or(VariableType::Byte, |(var_type, _, _)| *var_type);
 
             let instruction = match variable_type {
                 VariableType::Byte => vec![0xFE, 0x0E],
-                VariableType::Word => vec![0xFF, 0x0E],
+                _ => vec![0xFF, 0x0E],
             };
 
             convert_and_push_instructions!(
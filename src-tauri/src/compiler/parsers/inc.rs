@@ -68,12 +68,11 @@ pub(in crate::compiler) fn parse_inc(
             let variable_type = variable_address_map
                 .unwrap_or(&VariableAddressMap::new())
                 .get(high_char)
-                .unwrap_or(&(VariableType::Byte, 0_u16))
-                .0;
+                .map_or(VariableType::Byte, |(var_type, _, _)| *var_type);
 
             let instruction = match variable_type {
                 VariableType::Byte => vec![0xFE, 0x06],
-                VariableType::Word => vec![0xFF, 0x06],
+                _ => vec![0xFF, 0x06],
             };
 
             convert_and_push_instructions!(
@@ -146,7 +146,16 @@ label:
 
 #[cfg(test)]
 mod test_16_bit_jmp_compile {
-    use crate::{compile_and_compare_ins, compiler::compile_str, test_compile};
+    use unicase::UniCase;
+
+    use crate::{
+        compile_and_compare_ins,
+        compiler::{
+            compile_program, compile_program_with_options, compile_str,
+            types_structs::CompileOptions,
+        },
+        test_compile,
+    };
     fn generate_inc_ins(size: u16) -> String {
         let mut ins = String::new();
         for _ in 0..size {
@@ -250,4 +259,75 @@ mod test_16_bit_jmp_compile {
         ",
         &[0xEA, 0x00, 0x01, 0x00, 0x02]
     );
+
+    compile_and_compare_ins!(
+        jmp_short_and_near_ptr,
+        "
+        label: 
+        JMP SHORT label
+        jmp near ptr label
+        je short label
+        ",
+        &[0xEB, 0xFE, 0xEB, 0xFC, 0x74, 0xFA]
+    );
+
+    compile_and_compare_ins!(
+        jmp_far_ptr,
+        "
+        org 100h
+        label:
+        JMP FAR PTR label
+        ",
+        &[0xEA, 0x00, 0x01, 0x00, 0x07]
+    );
+
+    #[test]
+    fn jmp_far_and_too_far_short() {
+        assert!(compile_str("label: je far ptr label", false).is_err());
+        assert!(compile_str("label: jmp far label", false).is_err());
+        let code = format!("jmp short label\n{}label: inc ax", generate_inc_ins(0x80));
+        assert!(compile_str(&code, false).is_err());
+        let code = format!("je short label\n{}label: inc ax", generate_inc_ins(0x80));
+        assert!(compile_str(&code, false).is_err());
+    }
+
+    #[test]
+    fn far_conditional_jumps() {
+        let code = format!("ja label\n{}label: inc ax", generate_inc_ins(0x80));
+        let errors = compile_program(&code, false).unwrap_err();
+        assert_eq!(
+            errors[0].to_short_string(),
+            "1:4: The label label is too far for JA, It must be within 127 bytes, Please jump over a JMP to it with JBE (or) allow the long conditional jumps."
+        );
+
+        let compile_options = CompileOptions {
+            long_conditional_jumps: true,
+            ..Default::default()
+        };
+        let program = compile_program_with_options(&code, &compile_options, false).unwrap();
+        assert_eq!(program.bytes[..5], [0x76, 0x03, 0xE9, 0x80, 0x00]);
+    }
+
+    #[test]
+    fn relaxed_jumps_move_the_labels_and_variables() {
+        // jmp l1 only becomes too far after jmp l2 grows
+        let code = format!(
+            "jmp l1\njmp l2\nmov ax, var\n{}l1: inc bx\n{}l2: hlt\nvar dw 5",
+            generate_inc_ins(0x7A),
+            generate_inc_ins(0x80)
+        );
+        let program = compile_program(&code, false).unwrap();
+        assert_eq!(
+            program.bytes[..9],
+            [0xE9, 0x80, 0x00, 0xE9, 0xFE, 0x00, 0xA1, 0x05, 0x01]
+        );
+        assert_eq!(
+            program.label_offset_map[&UniCase::new("l1".to_string())],
+            0x83
+        );
+        assert_eq!(
+            program.variable_address_map[&UniCase::new("var".to_string())].1,
+            0x105
+        );
+    }
 }
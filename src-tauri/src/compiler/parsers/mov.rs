@@ -5,7 +5,7 @@ use crate::{
         compilation_error::CompilationError,
         tokens::{
             indexed_addressing_types::IndexedAddressingTypes, registers16bit::Registers16bit,
-            registers8bit::Registers8bit,
+            registers8bit::Registers8bit, Token,
         },
         CompiledBytesReference, TokenizedLine,
     },
@@ -20,7 +20,10 @@ use super::{
         compile_two_arguments_patterns::parse_indexed_addr_and_reg,
         AddressingMode,
     },
-    utils::{get_8bit_register, get_as_0xc0_0xff_pattern, get_idx_from_token, push_instruction},
+    utils::{
+        get_16bit_register, get_8bit_register, get_as_0xc0_0xff_pattern, get_idx_from_token,
+        push_instruction,
+    },
 };
 
 pub(in crate::compiler) fn parse_mov(
@@ -41,6 +44,18 @@ pub(in crate::compiler) fn parse_mov(
             high_token,
             low_token,
         } => {
+            let high_reg = get_16bit_register(&high_token);
+            let low_reg = get_16bit_register(&low_token);
+            if high_reg.is_segment() || low_reg.is_segment() {
+                return parse_mov_segment_register(
+                    token,
+                    (&high_token, high_reg),
+                    (&low_token, low_reg),
+                    i,
+                    compiled_bytes,
+                    compiled_bytes_ref,
+                );
+            }
             let high_reg_idx = get_idx_from_token(&high_token)?;
             let low_reg_idx = get_idx_from_token(&low_token)?;
             let ins = get_as_0xc0_0xff_pattern(high_reg_idx, low_reg_idx);
@@ -79,7 +94,7 @@ pub(in crate::compiler) fn parse_mov(
             num,
         } => {
             let high_reg_idx = get_idx_from_token(&high_token)?;
-            let num = num.get_as_u16();
+            let num = num.get_as_sign_extended_u16();
             let ins = (num & 0xFF) as u8;
             let ins2 = (num >> 8) as u8;
             convert_and_push_instructions!(
@@ -163,7 +178,7 @@ pub(in crate::compiler) fn parse_mov(
                        &high_token=> address_bytes.to_vec()
                     )
                 );
-                Ok(i + 3)
+                Ok(tokenized_line.len())
             }
             _ => {
                 let reg_idx = get_8bit_register(&low_token).get_as_idx();
@@ -176,7 +191,7 @@ pub(in crate::compiler) fn parse_mov(
                        &low_token=> address_bytes.to_vec()
                     )
                 );
-                Ok(i + 3)
+                Ok(tokenized_line.len())
             }
         },
         // 0x100, 0x20
@@ -192,7 +207,7 @@ pub(in crate::compiler) fn parse_mov(
                 (
                     token => vec![0xC7, 0x06],
                    &high_token=> address_bytes.to_vec(),
-                   &low_token=> (num as u16).to_le_bytes().to_vec()
+                   &low_token=> (num as i8 as u16).to_le_bytes().to_vec()
                 )
             );
             Ok(tokenized_line.len())
@@ -304,6 +319,52 @@ pub(in crate::compiler) fn parse_mov(
     }
 }
 
+// MOV DS..SS, AX..DI | MOV AX..DI, CS..SS
+fn parse_mov_segment_register(
+    token: &Token,
+    (high_token, high_reg): (&Token, &Registers16bit),
+    (low_token, low_reg): (&Token, &Registers16bit),
+    i: usize,
+    compiled_bytes: &mut Vec<u8>,
+    compiled_bytes_ref: &mut Vec<CompiledBytesReference>,
+) -> Result<usize, CompilationError> {
+    if high_reg.is_segment() && low_reg.is_segment() {
+        return Err(CompilationError::new_without_suggestions(
+            low_token.line_number,
+            low_token.column_number,
+            low_token.token_length,
+            "Can't move a segment register into a segment register, Please move it through a general purpose register (ex: AX)",
+        ));
+    }
+    if high_reg == &Registers16bit::CS {
+        return Err(CompilationError::new_without_suggestions(
+            high_token.line_number,
+            high_token.column_number,
+            high_token.token_length,
+            "Can't move into CS, Please use a far JMP (or) a far CALL insted",
+        ));
+    }
+    let (opcode, segment_reg, reg_token) = if high_reg.is_segment() {
+        (0x8E, high_reg, low_token)
+    } else {
+        (0x8C, low_reg, high_token)
+    };
+    let segment_idx = segment_reg
+        .get_segment_as_idx()
+        .expect("the register is checked to be a segment");
+    let reg_idx = get_idx_from_token(reg_token)?;
+    let ins = get_as_0xc0_0xff_pattern(segment_idx, reg_idx);
+    convert_and_push_instructions!(
+        compiled_bytes,
+        compiled_bytes_ref,
+        (
+            token => vec![opcode],
+            low_token => vec![ins]
+        )
+    );
+    Ok(i + 3)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{compiler::compile_str, test_compile};
@@ -340,6 +401,29 @@ mod tests {
         }
     );
 
+    test_compile!(
+        test_compile_str_mov_ds_ax,
+        "MOV DS, AX",
+        |compiled_instructions: &Vec<u8>| {
+            assert_eq!(compiled_instructions, &[0x8e, 0xd8]);
+        }
+    );
+
+    test_compile!(
+        test_compile_str_mov_bx_es,
+        "MOV BX, ES",
+        |compiled_instructions: &Vec<u8>| {
+            assert_eq!(compiled_instructions, &[0x8c, 0xc3]);
+        }
+    );
+
+    #[test]
+    fn test_mov_segment_register_errors() {
+        assert!(compile_str("mov ds, es", false).is_err());
+        assert!(compile_str("mov cs, ax", false).is_err());
+        assert!(compile_str("mov ds, 0x1234", false).is_err());
+    }
+
     // write tests for 16 bit registers but with a instant mov value
     test_compile!(
         test_compile_str_mov_ax_0x1234,
@@ -765,4 +849,29 @@ mod tests8bit {
             0x97, 0x00, 0x89, 0x95, 0xF1, 0x7A, 0x89, 0xA0, 0xE6, 0x11, 0x89, 0xA1, 0xD5, 0x00,
         ]
     );
+
+    compile_and_compare_ins!(
+        mov_size_ptr_operators,
+        "
+        var db 1
+        var2 dw 1
+        mov al, byte ptr [bx]
+        mov ax, WORD PTR [bx+si+2]
+        mov word ptr var2, 5
+        mov byte ptr [var2], 5
+        mov byte ptr var, al
+        mov b.[var], cl
+        ",
+        vec![
+            0x01, 0x01, 0x00, 0x8A, 0x07, 0x8B, 0x40, 0x02, 0xC7, 0x06, 0x01, 0x00, 0x05, 0x00,
+            0xC6, 0x06, 0x01, 0x00, 0x05, 0xA2, 0x00, 0x00, 0x88, 0x0E, 0x00, 0x00,
+        ]
+    );
+
+    #[test]
+    fn mov_ambiguous_memory_size() {
+        assert!(compile_str("mov [bx], 5", false).is_err());
+        assert!(compile_str("mov word ptr [bx], 5", false).is_err());
+        assert!(compile_str("mov b.[si], 5", false).is_err());
+    }
 }
@@ -69,12 +69,11 @@ pub(in crate::compiler) fn parse_mul(
             let variable_type = variable_address_map
                 .unwrap_or(&VariableAddressMap::new())
                 .get(high_char)
-                .unwrap_or(&(VariableType::Byte, 0_u16))
-                .0;
+                .map_or(VariableType::Byte, |(var_type, _, _)| *var_type);
 
             let instruction = match variable_type {
                 VariableType::Byte => vec![0xF6, 0x26],
-                VariableType::Word => vec![0xF7, 0x26],
+                _ => vec![0xF7, 0x26],
             };
 
             convert_and_push_instructions!(
@@ -3,7 +3,7 @@ use crate::{
         compilation_error::CompilationError,
         parsers::utils::{get_8bit_register, get_idx_from_token, push_instruction},
         tokenized_line::TokenizedLine,
-        types_structs::CompiledBytesReference,
+        types_structs::{CompiledBytesReference, Encoding},
     },
     convert_and_push_instructions,
     utils::Either,
@@ -36,6 +36,7 @@ pub(in crate::compiler) struct CompilingBytesForInstruction {
     pub addr8bit_and_num: u8,
     pub addr_num_sub_ins: u8,
 }
+#[allow(clippy::too_many_arguments)]
 pub(in crate::compiler) fn compile_two_args_whole_ins(
     tokenized_line: &TokenizedLine,
     i: usize,
@@ -43,6 +44,8 @@ pub(in crate::compiler) fn compile_two_args_whole_ins(
     compiled_bytes: &mut Vec<u8>,
     compiled_bytes_ref: &mut Vec<CompiledBytesReference>,
     addressing_mode: AddressingMode,
+    encoding: Encoding,
+    bytes_saved: &mut usize,
 ) -> Result<usize, CompilationError> {
     let token = tokenized_line.get(
         i,
@@ -83,27 +86,26 @@ pub(in crate::compiler) fn compile_two_args_whole_ins(
                     compiled_bytes_ref,
                     (
                         token => vec![ax_and_num_ins.unwrap()],
-                        &low_token => num.get_as_u16().to_le_bytes().to_vec()
+                        &low_token => num.get_as_sign_extended_u16().to_le_bytes().to_vec()
                     )
                 );
             } else {
-                let is_8_bit_ins = if reg16bit_and_8bit_num.is_some() {
-                    match num {
-                        Either::Left(_) => true,
-                        Either::Right(_) => false,
-                    }
-                } else {
-                    false
-                };
-                let and_ins = if is_8_bit_ins {
-                    vec![reg16bit_and_8bit_num.unwrap()]
-                } else {
-                    vec![reg16bit_and_16bit_num]
+                let num_8bit = match num {
+                    Either::Left(num) => Some(num),
+                    Either::Right(num) => get_sign_extended_8bit_number(num, encoding),
                 };
-                let num_vec = if is_8_bit_ins {
-                    num.get_as_bytes()
-                } else {
-                    num.get_as_u16().to_le_bytes().to_vec()
+                let (and_ins, num_vec) = match (reg16bit_and_8bit_num, num_8bit) {
+                    (Some(ins), Some(num_8bit)) => {
+                        // emu8086 writes the 16bit numbers in 16bits
+                        if let Either::Right(_) = num {
+                            *bytes_saved += 1;
+                        }
+                        (vec![ins], vec![num_8bit])
+                    }
+                    _ => (
+                        vec![reg16bit_and_16bit_num],
+                        num.get_as_sign_extended_u16().to_le_bytes().to_vec(),
+                    ),
                 };
                 convert_and_push_instructions!(
                     compiled_bytes,
@@ -155,13 +157,23 @@ pub(in crate::compiler) fn compile_two_args_whole_ins(
             address_bytes,
             num,
         } => {
+            let (ins, num) = match (
+                addr16bit_and_8bit_num,
+                get_sign_extended_8bit_number(num, encoding),
+            ) {
+                (Some(ins), Some(num)) => {
+                    *bytes_saved += 1;
+                    (ins, vec![num])
+                }
+                _ => (addr16bit_and_16bit_num, num.to_le_bytes().to_vec()),
+            };
             convert_and_push_instructions!(
                 compiled_bytes,
                 compiled_bytes_ref,
                 (
-                    token => vec![addr16bit_and_16bit_num, addr_num_sub_ins],
+                    token => vec![ins, addr_num_sub_ins],
                    &high_token=> address_bytes.to_vec(),
-                    &low_token => num.to_le_bytes().to_vec()
+                    &low_token => num
                 )
             );
             Ok(tokenized_line.len())
@@ -198,7 +210,7 @@ pub(in crate::compiler) fn compile_two_args_whole_ins(
             };
             let num = match is_ins_u8 {
                 true => num.to_le_bytes().to_vec(),
-                false => (num as u16).to_le_bytes().to_vec(),
+                false => (num as i8 as u16).to_le_bytes().to_vec(),
             };
             convert_and_push_instructions!(
                 compiled_bytes,
@@ -286,3 +298,12 @@ pub(in crate::compiler) fn compile_two_args_whole_ins(
         }
     }
 }
+
+// emu8086 only uses the 8bit form for the numbers written in 8bits, the 16bit number
+// 0xFF80..=0xFFFF is also the 8bit number sign extended
+fn get_sign_extended_8bit_number(num: u16, encoding: Encoding) -> Option<u8> {
+    match (encoding, num as i16) {
+        (Encoding::Smallest, -0x80..=0x7F) => Some(num as u8),
+        _ => None,
+    }
+}
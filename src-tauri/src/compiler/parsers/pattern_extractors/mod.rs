@@ -11,6 +11,7 @@ use crate::{
         },
         tokenized_line::{self, TokenizedLine},
         tokens::{
+            assembler_directives::AssemblerDirectives,
             indexed_addressing_types::IndexedAddressingTypes, registers16bit::Registers16bit,
             registers8bit::Registers8bit, Assembly8086Tokens, Token,
         },
@@ -463,6 +464,29 @@ pub(crate) fn parse_two_arguments_line<'a>(
                     })
                 }
 
+                Assembly8086Tokens::Number8bit(_) | Assembly8086Tokens::Number16bit(_) => {
+                    let is_size_defined = tokenized_line
+                        .slice(i + 1, compact_high_until)
+                        .iter()
+                        .any(|token| {
+                            token.token_type
+                                == Assembly8086Tokens::AssemblerDirectives(
+                                    AssemblerDirectives::AsWord,
+                                )
+                        });
+                    match is_size_defined {
+                        true => Err(get_unsupported_indexed_addressing_error(
+                            ins,
+                            high_token,
+                            &low_token,
+                        )),
+                        false => Err(CompilationError::error_with_token(
+                            high_token,
+                            "The size of the memory is ambiguous, Please use BYTE PTR (or) WORD PTR",
+                        )),
+                    }
+                }
+
                 _ => Err(CompilationError::error_with_token(
                     &low_token,
                     &format!(
@@ -521,6 +545,24 @@ pub(crate) fn parse_two_arguments_line<'a>(
             }
         }
 
+        Assembly8086Tokens::ByteIndexedAddressing(_) => {
+            check_comma(tokenized_line, high_token, compact_high_until)?;
+            let low_token = match compact_low_token {
+                Some(low_token) => low_token,
+                None => tokenized_line
+                    .get(
+                        compact_high_until + 1,
+                        format!("Expected 8bit value after {:?} got nothing", high_token)
+                            .to_string(),
+                        None,
+                    )?
+                    .clone(),
+            };
+            Err(get_unsupported_indexed_addressing_error(
+                ins, high_token, &low_token,
+            ))
+        }
+
         _ => Err(CompilationError::new_without_suggestions(
             high_token.line_number,
             high_token.column_number,
@@ -532,3 +574,19 @@ pub(crate) fn parse_two_arguments_line<'a>(
         )),
     }
 }
+
+// the sized [bx], [si], [di] (or) [bp] can only be used along with a 16bit register
+fn get_unsupported_indexed_addressing_error(
+    ins: &str,
+    high_token: &Token,
+    low_token: &Token,
+) -> CompilationError {
+    CompilationError::error_between_tokens(
+        high_token,
+        low_token,
+        &format!(
+            "{} of [BX], [SI], [DI] (or) [BP] and {} isn't supported, Please use a variable (or) a direct address like [0x102]",
+            ins, low_token.token_type
+        ),
+    )
+}
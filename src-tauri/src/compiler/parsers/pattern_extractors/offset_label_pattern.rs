@@ -32,6 +32,7 @@ pub(in crate::compiler) fn parse_labeled_relative_offset(
     compiled_line_ref_with_offset_maps: Option<&CompiledLineLabelRef>,
     instruction_compile_data: &mut LabeledInstructionCompileData,
 ) -> Result<usize, CompilationError> {
+    let is_far = is_far_ptr(tokenized_line, i + 1);
     let (i, token, high_token, is_offset) = parse_token_high_token_and_is_offset_defined(
         tokenized_line,
         i,
@@ -39,6 +40,25 @@ pub(in crate::compiler) fn parse_labeled_relative_offset(
         instruction_name,
     )?;
 
+    if is_far {
+        let (label, far_address) =
+            parse_far_label(high_token, line_number, compiled_line_ref_with_offset_maps)?;
+        let (segment, offset) = far_address.unwrap_or_else(|| {
+            label_idx_map.insert(label.to_string(), (high_token.clone(), 1, false));
+            (0, 0)
+        });
+        compile_single_ins_similar_as_jmp(
+            i,
+            token,
+            high_token,
+            instruction_compile_data.clone(),
+            Offset::SegmentedAddressing(segment, offset),
+            compiled_bytes,
+            compiled_bytes_ref,
+        );
+        return Ok(i + 1);
+    }
+
     instruction_compile_data.is_offset = is_offset;
 
     let offset_case = parse_single_label_or_variable(
@@ -55,6 +75,13 @@ pub(in crate::compiler) fn parse_labeled_relative_offset(
         },
     )?;
 
+    error_if_too_far_for_short(
+        tokenized_line,
+        i,
+        high_token,
+        matches!(offset_case, Offset::U16(_)),
+    )?;
+
     Ok(compile_single_ins_similar_as_jmp(
         i,
         token,
@@ -118,8 +145,10 @@ pub(in crate::compiler) fn parse_label_pattern_full(
         "This shouldn't happen please report this!".to_string(),
         None,
     )?;
+    let distance_operator_len =
+        get_distance_operator_len(tokenized_line, i + 1, instruction_name, true, false)?;
     let high_token = tokenized_line.get(
-        i + 1,
+        i + 1 + distance_operator_len,
         "Expected a label/seg:addr, got nothing!".to_string(),
         None,
     )?;
@@ -133,6 +162,13 @@ pub(in crate::compiler) fn parse_label_pattern_full(
         label_idx_map,
         compiled_line_ref_with_offset_maps,
     )?;
+    error_if_too_far_for_short(
+        tokenized_line,
+        i + 1,
+        high_token,
+        matches!(addr_mode, LabeledOffsetCase::U16(_)),
+    )?;
+    let i = i + distance_operator_len;
 
     match addr_mode {
         LabeledOffsetCase::U8(num) => {
@@ -146,6 +182,16 @@ pub(in crate::compiler) fn parse_label_pattern_full(
             );
             Ok(i + 1)
         }
+        LabeledOffsetCase::U16(_)
+            if !compiled_line_ref_with_offset_maps
+                .is_some_and(|maps| maps.is_long_conditional_jump_allowed()) =>
+        {
+            Err(get_too_far_for_conditional_jump_error(
+                instruction_name,
+                &ins_16bit,
+                high_token,
+            ))
+        }
         LabeledOffsetCase::U16(num) => {
             convert_and_push_instructions!(
                 compiled_bytes,
@@ -414,6 +460,7 @@ pub(in crate::compiler) fn parse_token_high_token_and_is_offset_defined<'a>(
     if is_offset {
         i += 1;
     }
+    i += get_distance_operator_len(tokenized_line, i + 1, instruction_name, true, true)?;
 
     let high_token = tokenized_line.get(
         i + 1,
@@ -426,6 +473,96 @@ pub(in crate::compiler) fn parse_token_high_token_and_is_offset_defined<'a>(
     Ok((i, token, high_token, is_offset))
 }
 
+// SHORT, NEAR PTR (or) FAR PTR before a label, returns the number of tokens they take
+pub(in crate::compiler) fn get_distance_operator_len(
+    tokenized_line: &TokenizedLine,
+    i: usize,
+    instruction_name: &str,
+    is_short_allowed: bool,
+    is_far_allowed: bool,
+) -> Result<usize, CompilationError> {
+    let directive = |i: usize| match tokenized_line.get(i, String::new(), None) {
+        Ok(token) => match &token.token_type {
+            Assembly8086Tokens::AssemblerDirectives(directive) => Some((token, directive.clone())),
+            _ => None,
+        },
+        Err(_) => None,
+    };
+    let is_ptr = |i: usize| matches!(directive(i), Some((_, directive)) if directive == AssemblerDirectives::Ptr);
+    match directive(i) {
+        Some((_, AssemblerDirectives::Short)) if is_short_allowed => Ok(1),
+        Some((token, AssemblerDirectives::Short)) => Err(CompilationError::error_with_token(
+            token,
+            &format!("{} can't be SHORT, Please remove SHORT", instruction_name),
+        )),
+        Some((_, AssemblerDirectives::Near)) if is_ptr(i + 1) => Ok(2),
+        Some((_, AssemblerDirectives::Far)) if is_far_allowed && is_ptr(i + 1) => Ok(2),
+        Some((token, AssemblerDirectives::Far)) if !is_far_allowed => {
+            Err(CompilationError::error_with_token(
+                token,
+                &format!("{} can't be FAR, Please remove FAR PTR", instruction_name),
+            ))
+        }
+        _ => Ok(0),
+    }
+}
+
+pub(in crate::compiler) fn is_far_ptr(tokenized_line: &TokenizedLine, i: usize) -> bool {
+    matches!(
+        tokenized_line.get(i, String::new(), None),
+        Ok(token) if token.token_type == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Far)
+    )
+}
+
+// the segment and the offset of a label
+type FarAddress = (u16, u16);
+
+// FAR PTR label, the label can be in another segment so the segment and the offset
+// of the label are used, None until the labels are found
+pub(in crate::compiler) fn parse_far_label<'a>(
+    token: &'a Token,
+    line_number: LineNumber,
+    compiled_line_ref_with_offset_maps: Option<&CompiledLineLabelRef>,
+) -> Result<(&'a Label, Option<FarAddress>), CompilationError> {
+    match &token.token_type {
+        Assembly8086Tokens::Character(label) => Ok((
+            label,
+            compiled_line_ref_with_offset_maps
+                .and_then(|maps| maps.find_far_address(label, line_number)),
+        )),
+        _ => Err(CompilationError::error_with_token(
+            token,
+            &format!(
+                "Expected a label after FAR PTR, Got {} insted",
+                token.token_type
+            ),
+        )),
+    }
+}
+
+// the label of a SHORT jump must be within -128..127 bytes
+pub(in crate::compiler) fn error_if_too_far_for_short(
+    tokenized_line: &TokenizedLine,
+    i: usize,
+    label_token: &Token,
+    is_long: bool,
+) -> Result<(), CompilationError> {
+    let is_short = matches!(
+        tokenized_line.get(i, String::new(), None),
+        Ok(token) if token.token_type == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Short)
+    );
+    if is_short && is_long {
+        return Err(CompilationError::error_with_token(
+            label_token,
+            &format!(
+                "The label {} is too far for a SHORT jump, It must be within 127 bytes",
+                label_token.token_type
+            ),
+        ));
+    }
+    Ok(())
+}
+
 fn get_offset_bytes_from_line_from_maps(
     label: &Label,
     line_number: LineNumber,
@@ -473,6 +610,49 @@ fn calc_offset(
     }
 }
 
+// the long form of a Jcc is the inverted Jcc jumping over a JMP NEAR to the label
+fn get_too_far_for_conditional_jump_error(
+    instruction_name: &str,
+    long_ins: &[u8],
+    label_token: &Token,
+) -> CompilationError {
+    let inverted_ins = match long_ins {
+        [0x0B, 0xC9, ..] => "OR CX, CX and JNZ",
+        [ins, ..] => get_conditional_jump_name(*ins),
+        [] => "",
+    };
+    CompilationError::error_with_token(
+        label_token,
+        &format!(
+            "The label {} is too far for {}, It must be within 127 bytes, Please jump over a JMP to it with {} (or) allow the long conditional jumps.",
+            label_token.token_type,
+            instruction_name.to_uppercase(),
+            inverted_ins
+        ),
+    )
+}
+
+fn get_conditional_jump_name(ins: u8) -> &'static str {
+    match ins {
+        0x70 => "JO",
+        0x71 => "JNO",
+        0x72 => "JB",
+        0x73 => "JAE",
+        0x74 => "JE",
+        0x75 => "JNE",
+        0x76 => "JBE",
+        0x77 => "JA",
+        0x78 => "JS",
+        0x79 => "JNS",
+        0x7A => "JP",
+        0x7B => "JNP",
+        0x7C => "JL",
+        0x7D => "JGE",
+        0x7E => "JLE",
+        _ => "JG",
+    }
+}
+
 fn get_address(
     label: &Label,
     variable_address_map: Option<&VariableAddressMap>,
@@ -481,6 +661,6 @@ fn get_address(
         None => None,
         Some(variable_address_map) => variable_address_map
             .get(label)
-            .map(|(_, address)| address.to_le_bytes()),
+            .map(|(_, address, _)| address.to_le_bytes()),
     }
 }
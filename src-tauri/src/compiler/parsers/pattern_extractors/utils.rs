@@ -192,8 +192,7 @@ pub(in crate::compiler) fn evaluate_ins<'a>(
                         indexing_type = IndexingType::VariableIndexing;
                         let var_type = variable_abs_address_map
                             .get(label)
-                            .unwrap_or(&(VariableType::Word, 0))
-                            .0;
+                            .map_or(VariableType::Word, |(var_type, _, _)| *var_type);
                         // variable_type = Some(var_type);
                         if var_type == VariableType::Byte && variable_type.is_none() {
                             variable_type = Some(VariableType::Byte);
@@ -373,7 +372,8 @@ pub(in crate::compiler) fn evaluate_ins<'a>(
         // Assembly8086Tokens::IndexedAddressing(addressing_type)
         match variable_type {
             VariableType::Byte => Assembly8086Tokens::ByteIndexedAddressing(addressing_type),
-            VariableType::Word => Assembly8086Tokens::IndexedAddressing(addressing_type),
+            // the wider variables are accessed a word at a time, i.e les bx, [farptr]
+            _ => Assembly8086Tokens::IndexedAddressing(addressing_type),
         }
     }
 
@@ -498,18 +498,17 @@ pub(in crate::compiler) fn get_label_address_or_push_into_ref(
 ) -> Either<Address, Number> {
     let placeholder = [0x00, 0x00];
     match variable_abs_offset_bytes_map.get(label) {
-        Some((_, abs_addr)) => {
+        Some((_, abs_addr, _)) => {
             let ins = (abs_addr & 0xFF) as u8;
             let ins2 = (abs_addr >> 8) as u8;
             Either::Left([ins, ins2])
         }
         None => {
-            let offset: Option<i16> = match compiled_line_offset_maps {
+            let offset = match compiled_line_offset_maps {
                 None => None,
                 Some(compiled_line_offset_maps) => {
                     let start_line_num = 0;
-                    compiled_line_offset_maps
-                        .find_label_offset_or_proc_offset(label, start_line_num)
+                    compiled_line_offset_maps.find_label_or_proc_address(label, start_line_num)
                 }
             };
             match offset {
@@ -529,7 +528,7 @@ pub(in crate::compiler) fn get_label_address_or_push_into_ref(
                         true => 0x100,
                         false => 0x00,
                     };
-                    Either::Right(Either::<u8, u16>::from(offset + optional_offset))
+                    Either::Right(Either::<u8, u16>::from((offset + optional_offset) as i16))
                 }
             }
         }
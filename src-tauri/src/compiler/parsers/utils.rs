@@ -84,16 +84,6 @@ macro_rules! convert_and_push_instructions {
     };
 }
 
-pub(super) fn if_num_8bit_to_16bit(token: Assembly8086Tokens) -> Assembly8086Tokens {
-    match token {
-        Assembly8086Tokens::Number8bit(num) => {
-            let num = num as u16;
-            Assembly8086Tokens::Number16bit(num)
-        }
-        _ => token,
-    }
-}
-
 pub(super) fn get_16bit_register(token: &Token) -> &Registers16bit {
     match &token.token_type {
         Assembly8086Tokens::Register16bit(reg) => reg,
@@ -172,7 +162,7 @@ pub(super) fn get_label_address_or_push_into_ref(
     var_ref_map: &mut VariableReferenceMap,
 ) -> [u8; 2] {
     match variable_abs_offset_bytes_map.get(label) {
-        Some((_, abs_addr)) => {
+        Some((_, abs_addr, _)) => {
             let ins = (abs_addr & 0xFF) as u8;
             let ins2 = (abs_addr >> 8) as u8;
             [ins, ins2]
@@ -185,35 +175,6 @@ pub(super) fn get_label_address_or_push_into_ref(
     }
 }
 
-pub(crate) fn iterate_with_seperator(
-    start_index: usize,
-    end_index: usize,
-    tokenized_line: &TokenizedLine,
-    seperator: &Assembly8086Tokens,
-    mut callback: impl FnMut(&Token) -> Result<(), CompilationError>,
-) -> Result<usize, CompilationError> {
-    let mut i = start_index;
-    while i < end_index {
-        let token = match tokenized_line.get(
-            i,
-            "This shouldn't happen, Report this! Err: iterate_with_seperator:174".to_string(),
-            None,
-        ) {
-            Ok(token) => token,
-            Err(err) => return Err(err),
-        };
-
-        callback(token)?;
-        i += 1;
-        if i < end_index {
-            check_token(tokenized_line, token, i, seperator)?;
-            i += 1;
-        }
-    }
-
-    Ok(i)
-}
-
 // pub(in crate::compiler::parsers) fn unimplemented_instruction_addressing_mode(
 //     token: &Token,
 //     len: usize,
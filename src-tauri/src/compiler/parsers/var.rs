@@ -1,124 +1,345 @@
 use crate::{
     compiler::{
         compilation_error::CompilationError,
+        compilation_utils::get_load_address,
+        lexer::parse_wide_number,
         parsers::utils::push_instruction,
+        segments::Segments,
+        suggestions::SuggestionType,
         suggestions_utils::{get_16bit_number_suggestion, get_8bit_number_suggestion},
         tokenized_line::TokenizedLine,
-        tokens::{data::DefineData, Assembly8086Tokens},
-        types_structs::{CompiledBytesReference, VariableAddressDefinitionMap, VariableType},
+        tokens::{assembler_directives::AssemblerDirectives, data::DefineData, Assembly8086Tokens},
+        types_structs::{
+            CompiledBytesReference, Label, VariableAddressDefinitionMap, VariableType,
+        },
     },
     convert_and_push_instructions,
+    utils::Either,
 };
 
-use super::utils::{check_comma, get_token_as_label, if_num_8bit_to_16bit, iterate_with_seperator};
+use super::{
+    pattern_extractors::{
+        reg_mem_pattern::ParseRegMemFnArgMaps, utils::get_label_address_or_push_into_ref,
+    },
+    utils::{check_token, get_token_as_label},
+};
 
-fn to_bytes(
-    i: usize,
+// the data of a variable can't be larger than a segment
+const MAX_DATA_SIZE: usize = 0x10000;
+
+fn get_expected_number(variable_type: VariableType) -> &'static str {
+    match variable_type {
+        VariableType::Byte => "8 bit number",
+        VariableType::Word => "16 bit number",
+        VariableType::DWord => "32 bit number",
+        VariableType::QWord => "64 bit number",
+        VariableType::TByte => "80 bit number",
+    }
+}
+
+fn get_number_suggestion(variable_type: VariableType) -> Vec<SuggestionType> {
+    match variable_type {
+        VariableType::Byte => get_8bit_number_suggestion(),
+        _ => get_16bit_number_suggestion(),
+    }
+}
+
+// Signed values are stored in two's complement, so a byte holds -128 to 255
+fn get_number_bytes(number: i128, variable_type: VariableType) -> Option<Vec<u8>> {
+    let bits = variable_type.get_size() as u32 * 8;
+    if number < -(1 << (bits - 1)) || number >= (1 << bits) {
+        return None;
+    }
+    Some(number.to_le_bytes()[..variable_type.get_size() as usize].to_vec())
+}
+
+// The state needed to put the address of a label in the data, i.e dw label (or) dd label
+pub(in crate::compiler) struct LabelAddressContext<'a> {
+    pub is_org_defined: bool,
+    pub segments: &'a Segments,
+    pub maps: ParseRegMemFnArgMaps<'a>,
+}
+
+impl LabelAddressContext<'_> {
+    // the segment of a SEGMENT name (or) @data, the load segment until the segments are placed
+    pub fn find_segment(&self, name: &str) -> Option<u16> {
+        if !self.segments.is_segment_name(name) {
+            return None;
+        }
+        let (load_segment, _) = get_load_address(self.is_org_defined);
+        match self.maps.compiled_line_offset_maps {
+            Some(maps) => maps.find_segment(name),
+            None => Some(load_segment),
+        }
+    }
+
+    // the segment a variable (or) label is in, i.e SEG var
+    pub fn get_segment_of(&self, label: &Label) -> u16 {
+        let (load_segment, _) = get_load_address(self.is_org_defined);
+        match self.maps.compiled_line_offset_maps {
+            Some(maps) if self.segments.is_segmented() => {
+                maps.find_segment_of(label).unwrap_or(load_segment)
+            }
+            _ => load_segment,
+        }
+    }
+}
+
+// A single initializer i.e 0x12, -1, 'str', ?, label (or) 3 dup(1, 2), returns
+// the bytes and the index after it
+fn parse_initializer(
     tokenized_line: &TokenizedLine,
-    compiled_bytes: &mut Vec<u8>,
-    compiled_bytes_ref: &mut Vec<CompiledBytesReference>,
-) -> Result<usize, CompilationError> {
-    let last_token = tokenized_line.get(
-        tokenized_line.len() - 1,
-        "This shouldn't happen, Please report this".to_string(),
-        None,
+    i: usize,
+    variable_type: VariableType,
+    context: &mut LabelAddressContext,
+) -> Result<(Vec<u8>, usize), CompilationError> {
+    let token = tokenized_line.get(
+        i,
+        format!(
+            "Expected {}, Got nothing!",
+            get_expected_number(variable_type)
+        ),
+        Some(vec![get_number_suggestion(variable_type)]),
+    )?;
+    let is_dup = tokenized_line.tokens.get(i + 1).is_some_and(|token| {
+        token.token_type == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Dup)
+    });
+    if is_dup {
+        return parse_dup(tokenized_line, i, variable_type, context);
+    }
+
+    let (sign, number_idx) = match token.token_type {
+        Assembly8086Tokens::Minus => (-1, i + 1),
+        Assembly8086Tokens::Plus => (1, i + 1),
+        _ => (1, i),
+    };
+    let number_token = tokenized_line.get(
+        number_idx,
+        format!("Expected a number after {}, Got nothing!", token.token_type),
+        Some(vec![get_number_suggestion(variable_type)]),
     )?;
-    if i > tokenized_line.len() {
-        return Err(CompilationError::new_with_suggestions(
-            last_token.line_number,
-            last_token.column_number,
-            last_token.token_length,
-            "Expected 8 bit number, Got nothing!",
-            vec![get_8bit_number_suggestion()],
+    let number = match &number_token.token_type {
+        Assembly8086Tokens::Number8bit(number) => Some(*number as i128),
+        Assembly8086Tokens::Number16bit(number) => Some(*number as i128),
+        // the numbers that are too wide for the lexer
+        Assembly8086Tokens::Character(text) => parse_wide_number(text).map(|number| number as i128),
+        _ => None,
+    };
+    if let Some(number) = number {
+        let number = sign * number;
+        let bytes = get_number_bytes(number, variable_type).ok_or_else(|| {
+            CompilationError::error_between_tokens(
+                token,
+                number_token,
+                &format!(
+                    "The value {} doesn't fit in a {:?}, Expected a {}",
+                    number,
+                    variable_type,
+                    get_expected_number(variable_type)
+                ),
+            )
+        })?;
+        return Ok((bytes, number_idx + 1));
+    }
+    if number_idx != i {
+        return Err(CompilationError::error_with_token(
+            number_token,
+            &format!(
+                "Expected a number after {}, Got {}, Insted",
+                token.token_type, number_token.token_type
+            ),
         ));
     }
-    let j = iterate_with_seperator(
-        i,
-        tokenized_line.len(),
-        tokenized_line,
-        &Assembly8086Tokens::Comma,
-        |token| match token.token_type {
-            Assembly8086Tokens::Number8bit(number) => {
-                convert_and_push_instructions!(
-                    compiled_bytes,
-                    compiled_bytes_ref,
-                    (
-                        token => vec![number]
-                    )
-                );
-                Ok(())
+
+    let bytes = match (&token.token_type, variable_type) {
+        // uninitialized data is zero filled
+        (Assembly8086Tokens::QuestionMark, _) => vec![0; variable_type.get_size() as usize],
+        // every character of a string is a byte
+        (Assembly8086Tokens::String(string), VariableType::Byte) => string
+            .chars()
+            .map(|c| u8::try_from(c as u32))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| {
+                CompilationError::new_without_suggestions(
+                    token.line_number,
+                    token.column_number,
+                    token.token_length,
+                    "Only the characters that fit in a byte can be used in a string",
+                )
+            })?,
+        // a word holds 2 characters, the first one is the high byte like MASM packs them
+        (Assembly8086Tokens::String(string), VariableType::Word)
+            if (1..=2).contains(&string.chars().count()) =>
+        {
+            let word = string.chars().try_fold(0u16, |word, c| {
+                u8::try_from(c as u32).map(|byte| (word << 8) | byte as u16)
+            });
+            word.map_err(|_| {
+                CompilationError::new_without_suggestions(
+                    token.line_number,
+                    token.column_number,
+                    token.token_length,
+                    "Only the characters that fit in a byte can be used in a string",
+                )
+            })?
+            .to_le_bytes()
+            .to_vec()
+        }
+        // a near pointer is the offset, a far pointer is the offset and the segment
+        (Assembly8086Tokens::Character(label), VariableType::Word | VariableType::DWord) => {
+            let address = match get_label_address_or_push_into_ref(
+                i,
+                label,
+                token,
+                context.is_org_defined,
+                true,
+                VariableType::Word,
+                context.maps.variable_abs_address_map,
+                context.maps.variable_ref_map,
+                context.maps.label_idx_map,
+                context.maps.compiled_line_offset_maps,
+            ) {
+                Either::Left(address) => u16::from_le_bytes(address),
+                Either::Right(offset) => offset.get_as_u16(),
+            };
+            let mut bytes = address.to_le_bytes().to_vec();
+            if variable_type == VariableType::DWord {
+                bytes.extend(context.get_segment_of(label).to_le_bytes());
             }
-            _ => Err(CompilationError::new_without_suggestions(
+            bytes
+        }
+        _ => {
+            return Err(CompilationError::new_without_suggestions(
                 token.line_number,
                 token.column_number,
                 token.token_length,
-                &format!("Expected 8 bit number, Got {}, Insted", token.token_type),
-            )),
-        },
-    )?;
-
-    Ok(j)
+                &format!(
+                    "Expected {}, Got {}, Insted",
+                    get_expected_number(variable_type),
+                    token.token_type
+                ),
+            ))
+        }
+    };
+    Ok((bytes, i + 1))
 }
 
-fn to_words(
-    i: usize,
+// count dup(initializers), the initializers are repeated count times
+fn parse_dup(
     tokenized_line: &TokenizedLine,
-    compiled_bytes: &mut Vec<u8>,
-    compiled_bytes_ref: &mut Vec<CompiledBytesReference>,
-) -> Result<usize, CompilationError> {
-    let mut j = i;
-    let last_token = tokenized_line.get(
-        tokenized_line.len() - 1,
-        "This shouldn't happen, Please report this".to_string(),
-        None,
+    i: usize,
+    variable_type: VariableType,
+    context: &mut LabelAddressContext,
+) -> Result<(Vec<u8>, usize), CompilationError> {
+    let count_token = tokenized_line.tokens[i];
+    let count = match count_token.token_type {
+        Assembly8086Tokens::Number8bit(count) => count as usize,
+        Assembly8086Tokens::Number16bit(count) => count as usize,
+        _ => {
+            return Err(CompilationError::new_without_suggestions(
+                count_token.line_number,
+                count_token.column_number,
+                count_token.token_length,
+                &format!(
+                    "Expected the number of times to duplicate, Got {}, Insted",
+                    count_token.token_type
+                ),
+            ))
+        }
+    };
+    let dup_token = tokenized_line.tokens[i + 1];
+    check_token(
+        tokenized_line,
+        dup_token,
+        i + 2,
+        &Assembly8086Tokens::OpenParenthesis,
     )?;
-    if j > tokenized_line.len() {
-        return Err(CompilationError::new_with_suggestions(
-            last_token.line_number,
-            last_token.column_number,
-            last_token.token_length,
-            "Expected 8 bit number, Got nothing!",
-            vec![get_16bit_number_suggestion()],
-        ));
-    }
-    while j < tokenized_line.len() {
-        let data_token = tokenized_line.get(
-            j,
-            "This shouldn't happen, Please report this".to_string(),
+
+    let mut bytes = Vec::new();
+    let mut j = i + 3;
+    loop {
+        let (initializer_bytes, k) = parse_initializer(tokenized_line, j, variable_type, context)?;
+        bytes.extend(initializer_bytes);
+        let token = tokenized_line.get(
+            k,
+            "Expected ) to close the dup, Got nothing!".to_string(),
             None,
         )?;
-        let changed_token = if_num_8bit_to_16bit(data_token.token_type.clone());
-        match changed_token {
-            Assembly8086Tokens::Number16bit(number) => {
-                convert_and_push_instructions!(
-                    compiled_bytes,
-                    compiled_bytes_ref,
-                    (
-                        data_token => vec![number as u8, (number >> 8) as u8]
-                    )
-                );
-                j += 1;
-                if j < tokenized_line.len() - 1 {
-                    check_comma(tokenized_line, data_token, j)?;
-                    j += 1;
-                }
+        match token.token_type {
+            Assembly8086Tokens::Comma => j = k + 1,
+            Assembly8086Tokens::CloseParenthesis => {
+                j = k + 1;
+                break;
             }
             _ => {
                 return Err(CompilationError::new_without_suggestions(
-                    data_token.line_number,
-                    data_token.column_number,
-                    data_token.column_number + data_token.token_length,
-                    &format!("Expected 16 bit number, Got {}", data_token.token_type),
+                    token.line_number,
+                    token.column_number,
+                    token.token_length,
+                    &format!("Expected , (or) ), Got {}, Insted", token.token_type),
                 ))
             }
         }
     }
-    Ok(j)
+
+    if bytes.len() * count > MAX_DATA_SIZE {
+        return Err(CompilationError::new_without_suggestions(
+            count_token.line_number,
+            count_token.column_number,
+            count_token.token_length,
+            &format!(
+                "The duplicated data is {} bytes, it doesn't fit in a segment",
+                bytes.len() * count
+            ),
+        ));
+    }
+    Ok((bytes.repeat(count), j))
+}
+
+// The comma separated initializers of a db, dw, dd, dq (or) dt
+fn parse_initializers(
+    i: usize,
+    tokenized_line: &TokenizedLine,
+    variable_type: VariableType,
+    context: &mut LabelAddressContext,
+    compiled_bytes: &mut Vec<u8>,
+    compiled_bytes_ref: &mut Vec<CompiledBytesReference>,
+) -> Result<usize, CompilationError> {
+    let mut i = i;
+    loop {
+        let token = tokenized_line.get(
+            i,
+            format!(
+                "Expected {}, Got nothing!",
+                get_expected_number(variable_type)
+            ),
+            Some(vec![get_number_suggestion(variable_type)]),
+        )?;
+        let (bytes, j) = parse_initializer(tokenized_line, i, variable_type, context)?;
+        convert_and_push_instructions!(
+            compiled_bytes,
+            compiled_bytes_ref,
+            (
+                token => bytes
+            )
+        );
+        if j >= tokenized_line.len() {
+            return Ok(j);
+        }
+        check_token(
+            tokenized_line,
+            tokenized_line.tokens[j - 1],
+            j,
+            &Assembly8086Tokens::Comma,
+        )?;
+        i = j + 1;
+    }
 }
 
 pub(in crate::compiler) fn parse_var_declaration(
     tokenized_line: &TokenizedLine,
     i: usize,
+    context: &mut LabelAddressContext,
     compiled_bytes: &mut Vec<u8>,
     compiled_bytes_ref: &mut Vec<CompiledBytesReference>,
     var_abs_address_map: &mut VariableAddressDefinitionMap,
@@ -128,35 +349,55 @@ pub(in crate::compiler) fn parse_var_declaration(
         "This shouldn't happen, Please report this".to_string(),
         None,
     )?;
-    let define_data =
-        tokenized_line.get(i + 1, "Expected db (or) dw, Got nothing!".to_string(), None)?;
+    let define_data = tokenized_line.get(
+        i + 1,
+        "Expected db, dw, dd, dq (or) dt, Got nothing!".to_string(),
+        None,
+    )?;
     let var_label = get_token_as_label(variable_token);
-    match &define_data.token_type {
+    let variable_type = match &define_data.token_type {
         Assembly8086Tokens::Data(data) => match data {
-            DefineData::Db => {
-                let i = to_bytes(i + 2, tokenized_line, compiled_bytes, compiled_bytes_ref)?;
-                var_abs_address_map.insert(var_label.clone(), (VariableType::Byte, 0));
-
-                Ok(i)
-            }
-            DefineData::Dw => {
-                let i = to_words(i + 2, tokenized_line, compiled_bytes, compiled_bytes_ref)?;
-                var_abs_address_map.insert(var_label.clone(), (VariableType::Word, 0));
-                Ok(i)
-            }
+            DefineData::Db => VariableType::Byte,
+            DefineData::Dw => VariableType::Word,
+            DefineData::Dd => VariableType::DWord,
+            DefineData::Dq => VariableType::QWord,
+            DefineData::Dt => VariableType::TByte,
         },
-        _ => Err(CompilationError::new_without_suggestions(
-            define_data.line_number,
-            define_data.column_number,
-            define_data.column_number + define_data.token_length,
-            &format!("Expected db (or) dw, Got {} insted", define_data.token_type),
-        )),
-    }
+        _ => {
+            return Err(CompilationError::new_without_suggestions(
+                define_data.line_number,
+                define_data.column_number,
+                define_data.column_number + define_data.token_length,
+                &format!(
+                    "Expected db, dw, dd, dq (or) dt, Got {} insted",
+                    define_data.token_type
+                ),
+            ))
+        }
+    };
+    let i = parse_initializers(
+        i + 2,
+        tokenized_line,
+        variable_type,
+        context,
+        compiled_bytes,
+        compiled_bytes_ref,
+    )?;
+    var_abs_address_map.insert(var_label.clone(), (variable_type, 0));
+    Ok(i)
 }
 
 #[cfg(test)]
 mod test_variable_declaration {
-    use crate::{compiler::compile_str, test_compile};
+    use unicase::UniCase;
+
+    use crate::{
+        compiler::{
+            compile_program, compile_str,
+            types_structs::{VariableSize, VariableType},
+        },
+        test_compile,
+    };
 
     test_compile!(
         dec_var_1,
@@ -203,6 +444,26 @@ mod test_variable_declaration {
         }
     );
 
+    test_compile!(
+        dec_var_string,
+        "
+        msg db 'Hi, ''you''$', 0x0D, \"A\", 'B'
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(instructions, &b"Hi, 'you'$\rAB".to_vec());
+        }
+    );
+
+    test_compile!(
+        dec_var_string_word,
+        "
+        msg dw 'AB', 'C', 0x1234
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(instructions, &vec![0x42, 0x41, 0x43, 0x00, 0x34, 0x12]);
+        }
+    );
+
     test_compile!(
         dec_var_1_word,
         "
@@ -243,4 +504,161 @@ mod test_variable_declaration {
             )
         }
     );
+
+    test_compile!(
+        dec_var_dup,
+        "
+        buf db 3 dup(0x90), ?, 2 dup('ab', ?)
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(
+                instructions,
+                &vec![0x90, 0x90, 0x90, 0x00, 0x61, 0x62, 0x00, 0x61, 0x62, 0x00]
+            );
+        }
+    );
+
+    test_compile!(
+        dec_var_nested_dup_words,
+        "
+        matrix dw 2 dup(3 dup(?), 0x1234), 5
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(
+                instructions,
+                &vec![
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x34, 0x12, 0x05, 0x00
+                ]
+            );
+        }
+    );
+
+    #[test]
+    fn variables_record_their_length_and_size() {
+        let program = compile_program(
+            "
+            org 100h
+            .data
+            buf db 80 dup(0)
+            matrix dw 4 dup(3 dup(?))
+            msg db 'hello$'
+            farptrs dd 2 dup(?)
+            code:
+            hlt",
+            false,
+        )
+        .unwrap();
+        let get = |name: &str| {
+            program
+                .variable_address_map
+                .get(&UniCase::new(name.to_string()))
+                .map(|(variable_type, _, size)| (*variable_type, *size))
+                .unwrap()
+        };
+        assert_eq!(
+            get("buf"),
+            (
+                VariableType::Byte,
+                VariableSize {
+                    length: 80,
+                    size: 80
+                }
+            )
+        );
+        assert_eq!(
+            get("matrix"),
+            (
+                VariableType::Word,
+                VariableSize {
+                    length: 12,
+                    size: 24
+                }
+            )
+        );
+        assert_eq!(
+            get("msg"),
+            (VariableType::Byte, VariableSize { length: 6, size: 6 })
+        );
+        assert_eq!(
+            get("farptrs"),
+            (VariableType::DWord, VariableSize { length: 2, size: 8 })
+        );
+    }
+
+    #[test]
+    fn invalid_data_is_an_error() {
+        assert!(compile_str("var db 0x1234", false).is_err());
+        assert!(compile_str("var db 2 dup(1", false).is_err());
+        assert!(compile_str("var db 2 dup 1", false).is_err());
+        assert!(compile_str("var dw 'abc'", false).is_err());
+        assert!(compile_str("var db 300 dup(300 dup(1))", false).is_err());
+        assert!(compile_str("var db 1,", false).is_err());
+    }
+
+    test_compile!(
+        dec_var_signed,
+        "
+        var db -1, -128, 255, +5
+        var2 dw -300, -1
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(
+                instructions,
+                &vec![0xFF, 0x80, 0xFF, 0x05, 0xD4, 0xFE, 0xFF, 0xFF]
+            );
+        }
+    );
+
+    test_compile!(
+        dec_var_wide,
+        "
+        d dd 0x12345678, -2
+        q dq 1
+        t dt 0FFFFh
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(
+                instructions,
+                &vec![
+                    0x78, 0x56, 0x34, 0x12, 0xFE, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+                ]
+            );
+        }
+    );
+
+    test_compile!(
+        dec_var_pointers,
+        "
+        org 100h
+        .data
+        farptr dd start
+        dvar dd 0x12345678
+        table dw start, dvar
+        code:
+        start: les bx, [farptr]
+        mov ax, [dvar+2]
+        ",
+        |instructions: &Vec<u8>| {
+            assert_eq!(
+                instructions[2..],
+                vec![
+                    0x0E, 0x01, 0x00, 0x07, 0x78, 0x56, 0x34, 0x12, 0x0E, 0x01, 0x06, 0x01, 0xC4,
+                    0x1E, 0x02, 0x01, 0xA1, 0x08, 0x01
+                ]
+            );
+        }
+    );
+
+    #[test]
+    fn out_of_range_values_are_errors() {
+        assert!(compile_str("var db -129", false).is_err());
+        assert!(compile_str("var db 256", false).is_err());
+        assert!(compile_str("var dw 70000", false).is_err());
+        assert!(compile_str("var dw -32769", false).is_err());
+        assert!(compile_str("var db -x", false).is_err());
+        assert!(compile_str("var dq label", false).is_err());
+        assert!(compile_str("var dd 'ab'", false).is_err());
+    }
 }
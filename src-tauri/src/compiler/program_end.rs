@@ -0,0 +1,208 @@
+use super::{
+    compilation_error::CompilationError,
+    labels::LabelScopes,
+    strip_space_and_comments_and_iterate_labels,
+    tokens::{assembler_directives::AssemblerDirectives, Assembly8086Tokens, Token},
+    types_structs::{LabelAddressMap, LineNumber, ProcDefinitionLineNumberMap},
+};
+
+fn get_directive(stripped_line: &[&Token]) -> Option<AssemblerDirectives> {
+    match stripped_line.first().map(|token| &token.token_type) {
+        Some(Assembly8086Tokens::AssemblerDirectives(directive)) => Some(directive.clone()),
+        _ => None,
+    }
+}
+
+// END (or) END entry, the program starts at the label of END (or) at .STARTUP
+// and the lines after END aren't assembled
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ProgramEnd {
+    // the label (or) the proc after END
+    entry_token: Option<Token>,
+    // the line of .STARTUP, it's the entry when END doesn't have one
+    startup_line: Option<LineNumber>,
+}
+
+impl ProgramEnd {
+    // the lines after END are removed, the code in them is a warning
+    pub fn new(
+        lines: &mut [Vec<Token>],
+        warnings: &mut Vec<CompilationError>,
+        errors: &mut Vec<CompilationError>,
+    ) -> Self {
+        let mut program_end = ProgramEnd::default();
+        let mut is_ended = false;
+        let mut is_warned = false;
+        for (line_number, line) in lines.iter_mut().enumerate() {
+            let (stripped_line, label) = strip_space_and_comments_and_iterate_labels(line);
+            if is_ended {
+                if let Some(token) = stripped_line.first().filter(|_| !is_warned) {
+                    warnings.push(CompilationError::error_with_token(
+                        token,
+                        "The code after END isn't assembled, Please move it before END.",
+                    ));
+                    is_warned = true;
+                }
+                *line = Vec::new();
+                continue;
+            }
+            let start = match label {
+                Some(_) => 2,
+                None => 0,
+            };
+            let stripped_line = stripped_line.get(start..).unwrap_or_default();
+            match get_directive(stripped_line) {
+                Some(AssemblerDirectives::Startup) => {
+                    program_end.startup_line.get_or_insert(line_number);
+                }
+                Some(AssemblerDirectives::End) => {
+                    match get_entry_token(stripped_line) {
+                        Ok(entry_token) => program_end.entry_token = entry_token,
+                        Err(err) => errors.push(err),
+                    }
+                    is_ended = true;
+                }
+                _ => {}
+            }
+        }
+        program_end
+    }
+
+    // the line the program starts at, None when it starts at the first byte
+    pub fn get_entry_line(
+        &self,
+        label_scopes: &LabelScopes,
+        label_addr_map: &LabelAddressMap,
+        proc_line_num_map: &ProcDefinitionLineNumberMap,
+    ) -> Result<Option<LineNumber>, CompilationError> {
+        let entry_token = match &self.entry_token {
+            Some(entry_token) => entry_token,
+            None => return Ok(self.startup_line),
+        };
+        let name = match &entry_token.token_type {
+            Assembly8086Tokens::Character(name) => name,
+            _ => unreachable!(),
+        };
+        let key = label_scopes.get_key(name, entry_token.line_number as LineNumber);
+        match (label_addr_map.get(&key), proc_line_num_map.get(name)) {
+            (Some(line_number), _) | (None, Some((line_number, _))) => Ok(Some(*line_number)),
+            (None, None) => Err(CompilationError::error_with_token(
+                entry_token,
+                &format!("The label \"{}\" is Undefined, Please define it.", name),
+            )),
+        }
+    }
+}
+
+// END main, the label is optional
+fn get_entry_token(stripped_line: &[&Token]) -> Result<Option<Token>, CompilationError> {
+    match stripped_line.get(1..).unwrap_or_default() {
+        [] => Ok(None),
+        [token] if matches!(token.token_type, Assembly8086Tokens::Character(_)) => {
+            Ok(Some((*token).clone()))
+        }
+        [token] => Err(CompilationError::error_with_token(
+            token,
+            &format!(
+                "Expected the label the program starts at after END, Got {} insted",
+                token.token_type
+            ),
+        )),
+        [_, token, ..] => Err(CompilationError::error_with_token(
+            token,
+            &format!(
+                "Expected only the label the program starts at after END, Got {} insted",
+                token.token_type
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{compile_program, compile_str};
+
+    #[test]
+    fn test_end_sets_the_entry_of_the_segments() {
+        let code = "
+            .MODEL SMALL
+            .STACK 100h
+            .DATA
+            msg db 'Hi$'
+            .CODE
+            PROC print
+                mov ah, 9
+                int 21h
+                ret
+            ENDP print
+            main:
+            .STARTUP
+                mov dx, offset msg
+                call print
+            .EXIT 3
+            END main
+        ";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(
+            &program.bytes[0x10..],
+            &[
+                0xB4, 0x09, 0xCD, 0x21, 0xC3, // print
+                0xB8, 0x00, 0x01, 0x8E, 0xD8, // .STARTUP
+                0xBA, 0x00, 0x00, 0xE8, 0xF0, 0xFF, // mov dx, offset msg; call print
+                0xB8, 0x03, 0x4C, 0xCD, 0x21, // .EXIT 3
+            ]
+        );
+        let layout = program.segment_layout.unwrap();
+        assert_eq!(
+            (layout.code_segment, layout.instruction_pointer),
+            (0x01, 0x05)
+        );
+        // the segment of .STARTUP is relocated
+        assert_eq!(layout.relocations, vec![0x16]);
+        assert_eq!(program.entry_point, None);
+        assert!(program.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_end_with_a_single_segment() {
+        let code = "
+            org 100h
+            jmp main
+            x db 1
+            PROC main
+                mov al, x
+            .EXIT
+            ENDP main
+            END main
+        ";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(program.entry_point, Some(0x103));
+        assert_eq!(
+            &program.bytes[3..],
+            &[0xA0, 0x02, 0x01, 0xB4, 0x4C, 0xCD, 0x21]
+        );
+
+        // .STARTUP is the entry without a label after END
+        let program = compile_program("mov ax, 1\n.STARTUP\nhlt\nEND", false).unwrap();
+        assert_eq!(program.entry_point, Some(0x03));
+        let program = compile_program("mov ax, 1\nhlt", false).unwrap();
+        assert_eq!(program.entry_point, None);
+    }
+
+    #[test]
+    fn test_code_after_end_is_a_warning() {
+        let program = compile_program("start:\nhlt\nEND start\nmov ax, 1\ninc ax", false).unwrap();
+        assert_eq!(program.bytes, [0xF4]);
+        assert_eq!(program.warnings.len(), 1);
+        assert!(program.warnings[0].to_short_string().starts_with("4:1:"));
+    }
+
+    #[test]
+    fn test_end_errors() {
+        assert!(compile_str("hlt\nEND main", false).is_err());
+        assert!(compile_str("main:\nhlt\nEND main 1", false).is_err());
+        assert!(compile_str("hlt\nEND 1", false).is_err());
+        assert!(compile_str(".EXIT 0x1234", false).is_err());
+        assert!(compile_str(".EXIT 1, 2", false).is_err());
+    }
+}
@@ -0,0 +1,753 @@
+use std::collections::HashMap;
+
+use unicase::UniCase;
+
+use crate::{consts::Word, formats::exe::DEFAULT_STACK_SIZE};
+
+use super::{
+    compilation_error::CompilationError,
+    compilation_utils::get_load_address,
+    lexer::parse_wide_number,
+    strip_space_and_comments_and_iterate_labels,
+    tokens::{
+        assembler_directives::AssemblerDirectives, data::DefineData,
+        registers16bit::Registers16bit, Assembly8086Tokens, Token,
+    },
+    types_structs::{CompiledBytesReference, Label, LineNumber, ProgramSegment, SegmentLayout},
+};
+
+// the names MASM gives the segments of .CODE, .DATA and .STACK
+const CODE_SEGMENT: &str = "_TEXT";
+const DATA_SEGMENT: &str = "_DATA";
+const STACK_SEGMENT: &str = "STACK";
+const PARAGRAPH_SIZE: usize = 0x10;
+// the models that have a single code and data segment
+const MODELS: [&str; 2] = ["TINY", "SMALL"];
+
+// @data is the segment of .DATA and @code the one of .CODE
+fn get_segment_name(name: &str) -> &str {
+    match name {
+        _ if name.eq_ignore_ascii_case("@data") => DATA_SEGMENT,
+        _ if name.eq_ignore_ascii_case("@code") => CODE_SEGMENT,
+        _ => name,
+    }
+}
+
+fn get_directive<'a>(stripped_line: &[&'a Token], idx: usize) -> Option<&'a AssemblerDirectives> {
+    match stripped_line.get(idx).map(|token| &token.token_type) {
+        Some(Assembly8086Tokens::AssemblerDirectives(directive)) => Some(directive),
+        _ => None,
+    }
+}
+
+// name SEGMENT (or) name ENDS
+pub(in crate::compiler) fn is_segment_line(stripped_line: &[&Token]) -> bool {
+    matches!(
+        get_directive(stripped_line, 1),
+        Some(AssemblerDirectives::Segment | AssemblerDirectives::EndS)
+    )
+}
+
+#[derive(Debug, Clone)]
+struct SegmentDefinition {
+    name: Label,
+    // a SEGMENT STACK (or) the segment of .STACK, the stacks are placed after the others
+    is_stack: bool,
+    // the bytes .STACK reserves after the lines of the segment
+    stack_size: usize,
+}
+
+// The segments of a program that uses SEGMENT (or) .DATA and .CODE, every segment starts at
+// its own paragraph and the offsets of its lines start from 0. The programs without them
+// (or) with ORG 100h are a single segment
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Segments {
+    definitions: Vec<SegmentDefinition>,
+    // the segment of every line, None outside of the segments
+    line_segments: Vec<Option<usize>>,
+    // the segments ASSUME gives the segment registers
+    assumes: HashMap<Registers16bit, usize>,
+    // the words that hold a segment, i.e mov ax, @data (or) dd label, as the line,
+    // the column of the value and the index of the word in the bytes of the value
+    references: Vec<(LineNumber, u32, usize)>,
+}
+
+impl Segments {
+    pub fn new(
+        lines: &[Vec<Token>],
+        is_org_defined: bool,
+        errors: &mut Vec<CompilationError>,
+    ) -> Self {
+        let mut segments = Segments::default();
+        // a program with ORG 100h is a .COM program, the directives don't change anything
+        if is_org_defined {
+            return segments;
+        }
+
+        let mut open_segments = Vec::new();
+        let mut assumes = Vec::new();
+        for line in lines {
+            let (stripped_line, label) = strip_space_and_comments_and_iterate_labels(line);
+            let start = match label {
+                Some(_) => 2,
+                None => 0,
+            };
+            let stripped_line = stripped_line.get(start..).unwrap_or_default();
+            let mut is_closed = false;
+            match (
+                get_directive(stripped_line, 0),
+                get_directive(stripped_line, 1),
+            ) {
+                (Some(AssemblerDirectives::Data), _) => {
+                    open_segments.pop();
+                    open_segments.push(segments.define(DATA_SEGMENT, false));
+                }
+                (Some(AssemblerDirectives::Code), _) => {
+                    open_segments.pop();
+                    open_segments.push(segments.define(CODE_SEGMENT, false));
+                }
+                (Some(AssemblerDirectives::Stack), _) => {
+                    let stack_size = match get_stack_size(stripped_line) {
+                        Ok(stack_size) => stack_size,
+                        Err(err) => {
+                            errors.push(err);
+                            DEFAULT_STACK_SIZE as usize
+                        }
+                    };
+                    let idx = segments.define(STACK_SEGMENT, true);
+                    segments.definitions[idx].stack_size = stack_size;
+                    // the segments are closed by .STACK
+                    open_segments.clear();
+                }
+                (Some(AssemblerDirectives::Model), _) => {
+                    if let Err(err) = check_model(stripped_line) {
+                        errors.push(err);
+                    }
+                }
+                (Some(AssemblerDirectives::Assume), _) => match get_assumes(stripped_line) {
+                    Ok(line_assumes) => assumes.extend(line_assumes),
+                    Err(err) => errors.push(err),
+                },
+                (_, Some(AssemblerDirectives::Segment)) => {
+                    // the combine type STACK makes it the stack of the program
+                    let is_stack = stripped_line.iter().skip(2).any(|token| {
+                        matches!(&token.token_type, Assembly8086Tokens::Character(name) if name.eq_ignore_ascii_case(STACK_SEGMENT))
+                    });
+                    let name = get_name(stripped_line[0]);
+                    open_segments.push(segments.define(&name, is_stack));
+                }
+                (_, Some(AssemblerDirectives::EndS)) => {
+                    let name = get_name(stripped_line[0]);
+                    match open_segments.last() {
+                        Some(idx) if segments.definitions[*idx].name == UniCase::new(name) => {
+                            is_closed = true;
+                        }
+                        Some(idx) => errors.push(CompilationError::error_with_token(
+                            stripped_line[0],
+                            &format!(
+                                "The segment \"{}\" isn't the open segment, Please close the segment \"{}\" first.",
+                                get_name(stripped_line[0]),
+                                segments.definitions[*idx].name
+                            ),
+                        )),
+                        None => errors.push(CompilationError::error_with_token(
+                            stripped_line[0],
+                            &format!(
+                                "The segment \"{}\" isn't open, Please open it with {} SEGMENT.",
+                                get_name(stripped_line[0]),
+                                get_name(stripped_line[0])
+                            ),
+                        )),
+                    }
+                }
+                _ => {}
+            }
+            // the ENDS line is still in the segment
+            segments.line_segments.push(open_segments.last().copied());
+            if is_closed {
+                open_segments.pop();
+            }
+        }
+
+        for (register_token, name_token) in assumes {
+            let name = get_name(name_token);
+            if name.eq_ignore_ascii_case("NOTHING") {
+                continue;
+            }
+            match (&register_token.token_type, segments.find(&name)) {
+                (Assembly8086Tokens::Register16bit(register), Some(idx)) => {
+                    segments.assumes.entry(register.clone()).or_insert(idx);
+                }
+                (_, _) => errors.push(CompilationError::error_with_token(
+                    name_token,
+                    &format!(
+                        "The segment \"{}\" isn't defined, Please define it with {} SEGMENT.",
+                        name, name
+                    ),
+                )),
+            }
+        }
+
+        if segments.is_segmented() {
+            segments.references = get_references(lines, &segments);
+        } else {
+            segments.line_segments.clear();
+        }
+        segments
+    }
+
+    fn define(&mut self, name: &str, is_stack: bool) -> usize {
+        match self.find(name) {
+            Some(idx) => {
+                self.definitions[idx].is_stack |= is_stack;
+                idx
+            }
+            None => {
+                self.definitions.push(SegmentDefinition {
+                    name: UniCase::new(name.to_string()),
+                    is_stack,
+                    stack_size: 0,
+                });
+                self.definitions.len() - 1
+            }
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        let name = get_segment_name(name);
+        self.definitions
+            .iter()
+            .position(|definition| definition.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn is_segmented(&self) -> bool {
+        !self.definitions.is_empty()
+    }
+
+    pub fn is_segment_name(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    fn get_line_segment(&self, line_number: LineNumber) -> Option<usize> {
+        self.line_segments.get(line_number).copied().flatten()
+    }
+
+    // the name of the segment the line is in
+    pub fn get_name(&self, line_number: LineNumber) -> Option<&Label> {
+        self.get_line_segment(line_number)
+            .map(|idx| &self.definitions[idx].name)
+    }
+
+    pub fn is_same_segment(&self, line_number: LineNumber, other_line_number: LineNumber) -> bool {
+        self.get_line_segment(line_number) == self.get_line_segment(other_line_number)
+    }
+
+    // the code and the data of a program with segments have to be in one
+    pub fn is_outside_of_segments(&self, line_number: LineNumber) -> bool {
+        self.is_segmented() && self.get_line_segment(line_number).is_none()
+    }
+
+    // the lines that use a segment, they are compiled again once the segments are placed
+    pub fn get_reference_lines(&self) -> Vec<LineNumber> {
+        let mut lines = self
+            .references
+            .iter()
+            .map(|(line_number, _, _)| *line_number)
+            .collect::<Vec<LineNumber>>();
+        lines.dedup();
+        lines
+    }
+
+    // the value at the column has the segment of a label after its offset, i.e jmp far ptr label
+    pub fn is_far_reference(&self, line_number: LineNumber, column_number: u32) -> bool {
+        self.references.contains(&(line_number, column_number, 1))
+    }
+
+    // the stacks are placed after the other segments
+    fn get_placement(&self) -> Vec<usize> {
+        let (stacks, others): (Vec<usize>, Vec<usize>) =
+            (0..self.definitions.len()).partition(|idx| self.definitions[*idx].is_stack);
+        others.into_iter().chain(stacks).collect()
+    }
+
+    // the bytes of the lines of every segment
+    fn get_sizes(&self, compiled_bytes: &[Vec<u8>]) -> Vec<usize> {
+        let mut sizes = vec![0; self.definitions.len()];
+        for (line_number, bytes) in compiled_bytes.iter().enumerate() {
+            if let Some(idx) = self.get_line_segment(line_number) {
+                sizes[idx] += bytes.len();
+            }
+        }
+        sizes
+    }
+
+    // the paragraph of every segment from the start of the program and the one after them
+    fn get_paragraphs(&self, compiled_bytes: &[Vec<u8>]) -> (Vec<usize>, usize) {
+        let sizes = self.get_sizes(compiled_bytes);
+        let mut paragraphs = vec![0; self.definitions.len()];
+        let mut paragraph = 0;
+        for idx in self.get_placement() {
+            paragraphs[idx] = paragraph;
+            paragraph += (sizes[idx] + self.definitions[idx].stack_size).div_ceil(PARAGRAPH_SIZE);
+        }
+        (paragraphs, paragraph)
+    }
+
+    // the segment of a SEGMENT name (or) @data once the program is loaded
+    pub fn find_segment(&self, name: &str, compiled_bytes: &[Vec<u8>]) -> Option<Word> {
+        let idx = self.find(name)?;
+        let (paragraphs, _) = self.get_paragraphs(compiled_bytes);
+        Some(get_segmented_load_segment().wrapping_add(paragraphs[idx] as Word))
+    }
+
+    // the segment the line is in once the program is loaded
+    pub fn get_line_segment_address(
+        &self,
+        line_number: LineNumber,
+        compiled_bytes: &[Vec<u8>],
+    ) -> Word {
+        let paragraph = match self.get_line_segment(line_number) {
+            Some(idx) => self.get_paragraphs(compiled_bytes).0[idx],
+            None => 0,
+        };
+        get_segmented_load_segment().wrapping_add(paragraph as Word)
+    }
+
+    // the segment and offset of every line once the program is loaded,
+    // a single segment starts at the load address
+    pub fn get_line_addresses(
+        &self,
+        compiled_bytes: &[Vec<u8>],
+        load_address: (Word, Word),
+    ) -> Vec<(Word, Word)> {
+        let (load_segment, mut offset) = load_address;
+        let (paragraphs, _) = self.get_paragraphs(compiled_bytes);
+        let mut segment_offsets = vec![0 as Word; self.definitions.len()];
+        compiled_bytes
+            .iter()
+            .enumerate()
+            .map(
+                |(line_number, bytes)| match self.get_line_segment(line_number) {
+                    Some(idx) => {
+                        let address = (
+                            load_segment.wrapping_add(paragraphs[idx] as Word),
+                            segment_offsets[idx],
+                        );
+                        segment_offsets[idx] =
+                            segment_offsets[idx].wrapping_add(bytes.len() as Word);
+                        address
+                    }
+                    None => {
+                        let address = (load_segment, offset);
+                        offset = offset.wrapping_add(bytes.len() as Word);
+                        address
+                    }
+                },
+            )
+            .collect()
+    }
+
+    // The bytes of the program with every segment at its paragraph, the bytes references
+    // are in the same order. There isn't a layout for the programs that are a single segment
+    pub fn place(
+        &self,
+        compiled_bytes: Vec<Vec<u8>>,
+        compiled_bytes_ref: Vec<Vec<CompiledBytesReference>>,
+    ) -> (Vec<u8>, Vec<CompiledBytesReference>, Option<SegmentLayout>) {
+        if !self.is_segmented() {
+            return (
+                compiled_bytes.into_iter().flatten().collect(),
+                compiled_bytes_ref.into_iter().flatten().collect(),
+                None,
+            );
+        }
+        let (paragraphs, end_paragraph) = self.get_paragraphs(&compiled_bytes);
+        let sizes = self.get_sizes(&compiled_bytes);
+
+        let mut bytes = Vec::new();
+        let mut placed_bytes_ref = Vec::new();
+        let mut segments = Vec::new();
+        let mut line_starts = vec![0; compiled_bytes.len()];
+        for idx in self.get_placement() {
+            // the stack of .STACK isn't written, it is after the bytes
+            if sizes[idx] > 0 {
+                bytes.resize(paragraphs[idx] * PARAGRAPH_SIZE, 0x00);
+            }
+            for (line_number, line_bytes) in compiled_bytes.iter().enumerate() {
+                if self.get_line_segment(line_number) != Some(idx) {
+                    continue;
+                }
+                line_starts[line_number] = bytes.len();
+                bytes.extend(line_bytes);
+                placed_bytes_ref.extend(compiled_bytes_ref[line_number].iter().cloned());
+            }
+            segments.push(ProgramSegment {
+                name: self.definitions[idx].name.to_string(),
+                segment: paragraphs[idx] as Word,
+                size: sizes[idx],
+            });
+        }
+
+        let mut relocations = Vec::new();
+        for (line_number, column_number, word_idx) in &self.references {
+            let mut offset = line_starts[*line_number];
+            for bytes_ref in &compiled_bytes_ref[*line_number] {
+                if bytes_ref.column_number == *column_number
+                    && bytes_ref.bytes.len() >= (word_idx + 1) * 2
+                {
+                    relocations.push(offset + word_idx * 2);
+                    break;
+                }
+                offset += bytes_ref.bytes.len();
+            }
+        }
+
+        let code_segment = self
+            .assumes
+            .get(&Registers16bit::CS)
+            .copied()
+            .or_else(|| self.find(CODE_SEGMENT))
+            .or_else(|| {
+                self.get_placement()
+                    .into_iter()
+                    .find(|idx| Some(*idx) != self.find(DATA_SEGMENT))
+            })
+            .unwrap_or_default();
+        let data_segment = self
+            .assumes
+            .get(&Registers16bit::DS)
+            .copied()
+            .or_else(|| self.find(DATA_SEGMENT))
+            .unwrap_or(code_segment);
+        // without a stack segment the stack is after the program
+        let (stack_segment, stack_pointer) =
+            match self.assumes.get(&Registers16bit::SS).copied().or_else(|| {
+                self.definitions
+                    .iter()
+                    .position(|definition| definition.is_stack)
+            }) {
+                Some(idx) => (
+                    paragraphs[idx],
+                    (sizes[idx] + self.definitions[idx].stack_size) as Word,
+                ),
+                None => (end_paragraph, DEFAULT_STACK_SIZE),
+            };
+
+        let layout = SegmentLayout {
+            load_segment: get_segmented_load_segment(),
+            segments,
+            code_segment: paragraphs[code_segment] as Word,
+            instruction_pointer: 0x00,
+            data_segment: paragraphs[data_segment] as Word,
+            stack_segment: stack_segment as Word,
+            stack_pointer,
+            relocations,
+        };
+        (bytes, placed_bytes_ref, Some(layout))
+    }
+}
+
+fn get_segmented_load_segment() -> Word {
+    get_load_address(false).0
+}
+
+fn get_name(token: &Token) -> String {
+    match &token.token_type {
+        Assembly8086Tokens::Character(name) => name.to_string(),
+        token_type => token_type.to_string(),
+    }
+}
+
+// .STACK (or) .STACK size
+fn get_stack_size(stripped_line: &[&Token]) -> Result<usize, CompilationError> {
+    match stripped_line.get(1).map(|token| &token.token_type) {
+        None => Ok(DEFAULT_STACK_SIZE as usize),
+        Some(Assembly8086Tokens::Number8bit(size)) => Ok(*size as usize),
+        Some(Assembly8086Tokens::Number16bit(size)) => Ok(*size as usize),
+        Some(token_type) => Err(CompilationError::error_with_token(
+            stripped_line[1],
+            &format!(
+                "Expected the size of the stack after .STACK, Got {} insted",
+                token_type
+            ),
+        )),
+    }
+}
+
+// .MODEL SMALL
+fn check_model(stripped_line: &[&Token]) -> Result<(), CompilationError> {
+    match stripped_line.get(1).map(|token| &token.token_type) {
+        Some(Assembly8086Tokens::Character(model))
+            if MODELS
+                .iter()
+                .any(|supported| model.eq_ignore_ascii_case(supported)) =>
+        {
+            Ok(())
+        }
+        Some(token_type) => Err(CompilationError::error_with_token(
+            stripped_line[1],
+            &format!(
+                "The model {} isn't supported, Please use .MODEL SMALL (or) .MODEL TINY",
+                token_type
+            ),
+        )),
+        None => Err(CompilationError::error_with_token(
+            stripped_line[0],
+            "Expected a model after .MODEL, Got nothing!",
+        )),
+    }
+}
+
+// ASSUME CS:code, DS:data, the segments are found once all of them are defined
+fn get_assumes<'a>(
+    stripped_line: &[&'a Token],
+) -> Result<Vec<(&'a Token, &'a Token)>, CompilationError> {
+    let mut assumes = Vec::new();
+    for assume in stripped_line[1..].split(|token| token.token_type == Assembly8086Tokens::Comma) {
+        match assume {
+            [register, colon, name]
+                if matches!(
+                    register.token_type,
+                    Assembly8086Tokens::Register16bit(
+                        Registers16bit::CS
+                            | Registers16bit::DS
+                            | Registers16bit::ES
+                            | Registers16bit::SS
+                    )
+                ) && colon.token_type == Assembly8086Tokens::Colon
+                    && matches!(name.token_type, Assembly8086Tokens::Character(_)) =>
+            {
+                assumes.push((*register, *name))
+            }
+            [token, ..] => {
+                return Err(CompilationError::error_with_token(
+                    token,
+                    &format!(
+                        "Expected a segment register and a segment i.e DS:data, Got {} insted",
+                        token.token_type
+                    ),
+                ))
+            }
+            [] => {
+                return Err(CompilationError::error_with_token(
+                    stripped_line[0],
+                    "Expected a segment register and a segment after ASSUME, Got nothing!",
+                ))
+            }
+        }
+    }
+    Ok(assumes)
+}
+
+// the values that are a segment, i.e @data, SEG var, the segment of dd label (or) jmp far ptr label
+// and the MOV AX, @data of .STARTUP
+fn get_references(lines: &[Vec<Token>], segments: &Segments) -> Vec<(LineNumber, u32, usize)> {
+    let mut references = Vec::new();
+    for (line_number, line) in lines.iter().enumerate() {
+        let (stripped_line, _) = strip_space_and_comments_and_iterate_labels(line);
+        let startup_token = stripped_line.iter().find(|token| {
+            token.token_type
+                == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Startup)
+        });
+        if let Some(startup_token) = startup_token.filter(|_| segments.is_segment_name("@data")) {
+            references.push((line_number, startup_token.column_number, 0));
+            continue;
+        }
+        let is_far_pointer_data = stripped_line
+            .iter()
+            .any(|token| token.token_type == Assembly8086Tokens::Data(DefineData::Dd));
+        let is_code_or_data = stripped_line.iter().any(|token| {
+            matches!(
+                token.token_type,
+                Assembly8086Tokens::Instruction(_) | Assembly8086Tokens::Data(_)
+            )
+        });
+        if !is_code_or_data {
+            continue;
+        }
+        for (i, token) in stripped_line.iter().enumerate() {
+            let name = match &token.token_type {
+                Assembly8086Tokens::Character(name) => name,
+                _ => continue,
+            };
+            let previous_token_type = i
+                .checked_sub(1)
+                .map(|previous| &stripped_line[previous].token_type);
+            let is_after_far_ptr = i >= 2
+                && stripped_line[i - 2].token_type
+                    == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Far)
+                && stripped_line[i - 1].token_type
+                    == Assembly8086Tokens::AssemblerDirectives(AssemblerDirectives::Ptr);
+            if segments.is_segment_name(name) || name.eq_ignore_ascii_case("SEG") {
+                references.push((line_number, token.column_number, 0));
+            } else if is_after_far_ptr {
+                // the offset of the label is the first word and its segment the second
+                references.push((line_number, token.column_number, 1));
+            } else if is_far_pointer_data
+                && parse_wide_number(name).is_none()
+                && matches!(
+                    previous_token_type,
+                    Some(Assembly8086Tokens::Data(_) | Assembly8086Tokens::Comma)
+                )
+            {
+                // the offset is the first word and the segment the second
+                references.push((line_number, token.column_number, 1));
+            }
+        }
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{
+        compile_program, compile_str,
+        types_structs::{ProgramSegment, SegmentLayout},
+    };
+
+    fn get_segment(name: &str, segment: u16, size: usize) -> ProgramSegment {
+        ProgramSegment {
+            name: name.to_string(),
+            segment,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_simplified_directives() {
+        let code = "
+            .MODEL SMALL
+            .STACK 100h
+            .DATA
+            msg db 'Hi$'
+            .CODE
+                mov ax, @data
+                mov ds, ax
+                mov dx, offset msg
+                hlt
+        ";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(
+            program.bytes,
+            [
+                0x48, 0x69, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, // _DATA
+                0xB8, 0x00, 0x01, 0x8E, 0xD8, 0xBA, 0x00, 0x00, 0xF4 // _TEXT
+            ]
+        );
+        assert_eq!(
+            program.segment_layout,
+            Some(SegmentLayout {
+                load_segment: 0x100,
+                segments: vec![
+                    get_segment("_DATA", 0, 3),
+                    get_segment("_TEXT", 1, 9),
+                    get_segment("STACK", 2, 0),
+                ],
+                code_segment: 1,
+                instruction_pointer: 0,
+                data_segment: 0,
+                stack_segment: 2,
+                stack_pointer: 0x100,
+                relocations: vec![0x11],
+            })
+        );
+    }
+
+    #[test]
+    fn test_segment_ends_and_assume() {
+        let code = "
+            data SEGMENT
+                msg db 1
+                far_ptr dd start
+            data ENDS
+            stk SEGMENT STACK
+                buf dw 0x10 dup(0)
+            stk ENDS
+            code SEGMENT
+                ASSUME CS:code, DS:data, SS:stk
+            start:
+                mov ax, data
+                mov bx, seg msg
+                jmp start
+            code ENDS
+        ";
+        let program = compile_program(code, false).unwrap();
+        let layout = program.segment_layout.unwrap();
+        assert_eq!(
+            layout.segments,
+            vec![
+                get_segment("data", 0, 5),
+                get_segment("code", 1, 8),
+                get_segment("stk", 2, 0x20),
+            ]
+        );
+        assert_eq!(layout.code_segment, 1);
+        assert_eq!(layout.data_segment, 0);
+        assert_eq!((layout.stack_segment, layout.stack_pointer), (2, 0x20));
+        // the segment of the far pointer, mov ax, data and seg msg
+        assert_eq!(layout.relocations, vec![0x03, 0x11, 0x14]);
+        assert_eq!(&program.bytes[0x01..0x05], &[0x00, 0x00, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn test_far_jumps_between_segments() {
+        let code = "
+            c1 SEGMENT
+            start:
+                jmp far ptr other
+            back:
+                hlt
+            c1 ENDS
+            c2 SEGMENT
+            other:
+                call far ptr back
+            c2 ENDS
+            END start
+        ";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(
+            program.bytes,
+            [
+                0xEA, 0x00, 0x00, 0x01, 0x01, 0xF4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, // c1
+                0x9A, 0x05, 0x00, 0x00, 0x01, // c2
+            ]
+        );
+        // the segments of the labels
+        assert_eq!(
+            program.segment_layout.unwrap().relocations,
+            vec![0x03, 0x13]
+        );
+    }
+
+    #[test]
+    fn test_org_keeps_the_flat_model() {
+        let code = "
+            org 100h
+            .data
+            x db 1
+            .code
+            mov al, x
+        ";
+        let program = compile_program(code, false).unwrap();
+        assert_eq!(program.segment_layout, None);
+        assert_eq!(program.bytes, [0xEB, 0x01, 0x01, 0xA0, 0x02, 0x01]);
+    }
+
+    #[test]
+    fn test_segment_errors() {
+        // the segment that is closed isn't the open one
+        assert!(compile_str("a SEGMENT\nhlt\nb ENDS", false).is_err());
+        // the line is before the first segment
+        assert!(compile_str("hlt\n.code\nhlt", false).is_err());
+        // the jump is to a label in another segment
+        assert!(compile_str(
+            "c1 SEGMENT\nfoo:\nhlt\nc1 ENDS\nc2 SEGMENT\njmp foo\nc2 ENDS",
+            false
+        )
+        .is_err());
+        assert!(compile_str(".model huge\n.code\nhlt", false).is_err());
+        assert!(compile_str(".code\nASSUME DS:nowhere\nhlt", false).is_err());
+    }
+}
@@ -63,7 +63,7 @@ pub(super) fn get_all_variables_suggestions(
         .iter()
         .map(|(x, y)| match y.0 {
             VariableType::Byte => SuggestionType::Variables8bit(x.clone()),
-            VariableType::Word => SuggestionType::Variables16bit(x.clone()),
+            _ => SuggestionType::Variables16bit(x.clone()),
         })
         .collect()
 }
@@ -1,6 +1,8 @@
 // write tests
 // run tests with cargo test
 
+use super::types_structs::CompileOptions;
+
 #[macro_export]
 macro_rules! test_compile {
     ($name:ident, $code:expr, $expected_fn:expr) => {
@@ -29,6 +31,24 @@ macro_rules! compile_and_compare_ins {
             assert_eq!(compiled_instructions, &$expected_ins);
         });
     };
+    ($test_name: ident, $code: expr, $expected_ins: expr, $compile_options: expr) => {
+        #[test]
+        fn $test_name() {
+            let program =
+                $crate::compiler::compile_program_with_options($code, &$compile_options, false)
+                    .unwrap();
+            assert_eq!(&program.bytes, &$expected_ins);
+        }
+    };
+}
+
+// the far Jcc are compiled as the inverted Jcc over a JMP NEAR
+#[allow(dead_code)]
+pub(crate) fn get_long_jumps_options() -> CompileOptions {
+    CompileOptions {
+        long_conditional_jumps: true,
+        ..Default::default()
+    }
 }
 
 #[allow(dead_code)]
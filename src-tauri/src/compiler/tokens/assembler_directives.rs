@@ -9,11 +9,54 @@ pub(crate) enum AssemblerDirectives {
     Data,
     #[strum(serialize = ".code")]
     Code,
+    // the segments of the program, i.e .MODEL SMALL, .STACK 100h (or) data SEGMENT ... data ENDS
+    #[strum(serialize = ".model")]
+    Model,
+    #[strum(serialize = ".stack")]
+    Stack,
+    Segment,
+    EndS,
+    Assume,
+    // where the program starts and ends, i.e .STARTUP ... .EXIT ... END main
+    End,
+    #[strum(serialize = ".startup")]
+    Startup,
+    #[strum(serialize = ".exit")]
+    Exit,
 
     Macro,
     EndM,
+    Local,
+    // the blocks that are repeated, they end with ENDM like a macro
+    Rept,
+    Irp,
+    Irpc,
 
     Offset,
+    Dup,
+    Equ,
+    Include,
+
+    // conditional assembly and the messages of it
+    If,
+    Ife,
+    Ifdef,
+    Ifndef,
+    Else,
+    Endif,
+    #[strum(serialize = ".err")]
+    Err,
+    #[strum(serialize = ".erre")]
+    ErrE,
+    Echo,
+    #[strum(serialize = "%out")]
+    Out,
+
+    // i.e byte ptr [bx], jmp short label
+    Ptr,
+    Short,
+    Near,
+    Far,
 
     #[strum(serialize = "w.")]
     AsWord,
@@ -5,4 +5,7 @@ use strum_macros::{Display, EnumIter, EnumString, IntoStaticStr};
 pub(crate) enum DefineData {
     Dw,
     Db,
+    Dd,
+    Dq,
+    Dt,
 }
@@ -60,6 +60,7 @@ pub(crate) enum Instructions {
     Proc,
     Push,
     Ret,
+    Retf,
     Sbb,
     Shl,
     Sub,
@@ -46,8 +46,8 @@ pub(crate) enum Assembly8086Tokens {
     Number8bit(u8),
 
     // Strings
-    // ex: "Hello World"
-    // String,
+    // ex: "Hello World", 'it''s', the single character ones are 8 bit numbers
+    String(String),
 
     // Operators
     // ex: +, -, *, /, %, &, |, ^, ~, <<, >>, &&, ||, !, ==,
@@ -62,6 +62,14 @@ pub(crate) enum Assembly8086Tokens {
     CloseSquareBracket,
     Plus,
     Minus,
+    Multiply,
+    Divide,
+    // i.e count = 10
+    Equals,
+    OpenParenthesis,
+    CloseParenthesis,
+    // uninitialized data, i.e db ?
+    QuestionMark,
 
     // i.e this like this
     // [bx], [dx], [si], [di]
@@ -100,6 +108,13 @@ impl std::fmt::Display for Assembly8086Tokens {
             Assembly8086Tokens::IndexedAddressing(_) => write!(f, "IndexedAddressing"),
             Assembly8086Tokens::ByteIndexedAddressing(_) => write!(f, "ByteIndexedAddressing"),
             Assembly8086Tokens::Minus => write!(f, "-"),
+            Assembly8086Tokens::Multiply => write!(f, "*"),
+            Assembly8086Tokens::Divide => write!(f, "/"),
+            Assembly8086Tokens::Equals => write!(f, "="),
+            Assembly8086Tokens::OpenParenthesis => write!(f, "("),
+            Assembly8086Tokens::CloseParenthesis => write!(f, ")"),
+            Assembly8086Tokens::QuestionMark => write!(f, "?"),
+            Assembly8086Tokens::String(string) => write!(f, "'{}'", string.replace('\'', "''")),
         }
     }
 }
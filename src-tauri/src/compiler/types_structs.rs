@@ -1,15 +1,37 @@
 use std::collections::HashMap;
 
-use crate::utils::Either;
+use crate::{consts::Word, utils::Either};
 
-use super::Token;
-use serde::Serialize;
+use super::{
+    compilation_error::CompilationError,
+    includes::{FileId, SourceFiles, SourceMap, MAIN_FILE_ID},
+    listing::Listing,
+    macros::MacroExpansionSite,
+    Token,
+};
+use serde::{Deserialize, Serialize};
 use unicase::UniCase;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VariableType {
     Byte,
     Word,
+    DWord,
+    QWord,
+    TByte,
+}
+
+impl VariableType {
+    // the number of bytes of a single element
+    pub fn get_size(&self) -> NumberOfBytes {
+        match self {
+            VariableType::Byte => 1,
+            VariableType::Word => 2,
+            VariableType::DWord => 4,
+            VariableType::QWord => 8,
+            VariableType::TByte => 10,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -32,8 +54,16 @@ pub(crate) type LabelRefrenceList = Vec<(Label, Token, LineNumber, LineNumber)>;
 pub type Variable = Label;
 // The map used to store where a particular variable is being referenced
 pub type VariableReferenceMap = HashMap<Variable, (VariableType, ArrayIndex)>;
+// The number of elements of a variable and the bytes they take, i.e LENGTH and SIZE
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VariableSize {
+    pub length: NumberOfBytes,
+    pub size: NumberOfBytes,
+}
 // The map used to store where a particular variable is being defined
-pub type VariableAddressMap = HashMap<Variable, (VariableType, NumberOfBytes)>;
+pub type VariableAddressMap = HashMap<Variable, (VariableType, NumberOfBytes, VariableSize)>;
+// i.e the offset of a label or proc from the start of the code segment
+pub type LabelOffsetMap = HashMap<Label, NumberOfBytes>;
 // The map used to store where a particular variable is being defined  (i.e. the line number)
 pub type VariableAddressDefinitionMap = HashMap<Variable, (VariableType, LineNumber)>;
 // The list used to store where a particular variable is being referenced
@@ -54,11 +84,14 @@ pub(crate) type MacroBoundsDefintionMap = HashMap<Label, (ArrayIndex, ArrayIndex
 //      CompiledBytes { bytes: [0x8B], line_number: 1, column_number: 1 }
 //      CompiledBytes { bytes: [0xC3], line_number: 1, column_number: 2 }
 // ]
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CompiledBytesReference {
     pub bytes: Vec<u8>,
     pub line_number: u32,
     pub column_number: u32,
+    pub file_id: FileId,
+    // the macro call in the code the bytes are expanded from, the line is the one in the macro
+    pub call_site: Option<MacroExpansionSite>,
 }
 
 /// Initializer for CompiledBytesReference
@@ -68,10 +101,81 @@ impl CompiledBytesReference {
             bytes,
             line_number,
             column_number,
+            file_id: MAIN_FILE_ID,
+            call_site: None,
         }
     }
 }
 
+// Everything a successful compilation produces that is needed to load and debug the program
+#[derive(Debug, Default)]
+pub struct CompiledProgram {
+    pub bytes: Vec<u8>,
+    pub compiled_bytes_ref: Vec<CompiledBytesReference>,
+    pub is_org_defined: bool,
+    pub variable_address_map: VariableAddressMap,
+    pub label_offset_map: LabelOffsetMap,
+    pub listing: Listing,
+    pub source_map: SourceMap,
+    // the messages of ECHO and %OUT
+    pub messages: Vec<String>,
+    pub warnings: Vec<CompilationError>,
+    // the bytes the smallest encoding saves over the emu8086 one
+    pub bytes_saved: usize,
+    // None for the programs that are a single segment
+    pub segment_layout: Option<SegmentLayout>,
+    // the offset END (or) .STARTUP starts a single segment program at,
+    // None when it starts at the first byte. The layout has the one of the segments
+    pub entry_point: Option<Word>,
+}
+
+// A segment of the program, it starts at a paragraph from the start of the program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramSegment {
+    pub name: String,
+    pub segment: Word,
+    // the bytes of its lines, the stack of .STACK isn't in the bytes of the program
+    pub size: usize,
+}
+
+// Where the segments of a program with SEGMENT (or) .DATA and .CODE are placed and what
+// the registers start with, the segments are relative to the load segment
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SegmentLayout {
+    // the segment the program is assembled for, the value of @data is after it
+    pub load_segment: Word,
+    pub segments: Vec<ProgramSegment>,
+    pub code_segment: Word,
+    pub instruction_pointer: Word,
+    pub data_segment: Word,
+    pub stack_segment: Word,
+    pub stack_pointer: Word,
+    // the offsets of the words in the bytes that hold a segment, i.e mov ax, @data
+    pub relocations: Vec<usize>,
+}
+
+// How the numbers of ADD, SUB, AND, OR, XOR, CMP, ADC, SBB and TEST are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Encoding {
+    // the same bytes as emu8086
+    #[default]
+    Emu8086,
+    // the sign extended 8bit number whenever it fits, i.e ADD CX, 0xFFFF is 0x83 0xC1 0xFF
+    Smallest,
+}
+
+// What a program is compiled with besides its code
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+    pub source_files: SourceFiles,
+    // the constants defined from outside the code, i.e -D DEBUG=1
+    pub defines: Vec<(String, String)>,
+    // a Jcc too far for a short jump is compiled as the inverted Jcc over a JMP NEAR,
+    // otherwise it is an error
+    pub long_conditional_jumps: bool,
+    pub encoding: Encoding,
+}
+
 type AsOffset = bool; // This variable is used to check if a variable is being used as an offset
 /// This is a helper struct to pack and send all the vars needed to compile a line
 #[derive(Debug)]
@@ -85,6 +189,8 @@ pub(crate) struct CompiledLine {
 
     pub proc_definition_map: ProcDefinitionMap,
     pub proc_reference_map: ProcReferenceMap,
+    // the bytes the smallest encoding saved over the emu8086 one in the line
+    pub bytes_saved: usize,
 }
 
 impl CompiledLine {
@@ -98,6 +204,7 @@ impl CompiledLine {
             variable_abs_address_map: std::collections::HashMap::new(),
             proc_definition_map: ProcDefinitionMap::new(),
             proc_reference_map: ProcReferenceMap::new(),
+            bytes_saved: 0,
         }
     }
 
@@ -111,5 +218,6 @@ impl CompiledLine {
             .extend(other.variable_abs_address_map);
         self.proc_definition_map.extend(other.proc_definition_map);
         self.proc_reference_map.extend(other.proc_reference_map);
+        self.bytes_saved += other.bytes_saved;
     }
 }
@@ -3,10 +3,83 @@ use unicase::UniCase;
 use super::{
     compilation_error::CompilationError,
     lexer::Lexer,
-    tokens::{instructions::Instructions, Assembly8086Tokens, Token},
-    types_structs::{CompiledBytesReference, Label},
+    tokens::{
+        instructions::Instructions, registers16bit::Registers16bit, registers8bit::Registers8bit,
+        Assembly8086Tokens, Token,
+    },
+    types_structs::Label,
 };
 
+// the tokens of the instructions a directive generates, they are at the directive
+fn get_generated_line(token: &Token, token_types: Vec<Assembly8086Tokens>) -> Vec<Token> {
+    token_types
+        .into_iter()
+        .map(|token_type| {
+            Token::new(
+                token_type,
+                token.line_number,
+                token.column_number,
+                token.token_length,
+            )
+        })
+        .collect()
+}
+
+// MOV AX, @data; MOV DS, AX
+pub(in crate::compiler) fn get_startup_compiled_lines(token: &Token) -> Vec<Vec<Token>> {
+    vec![
+        get_generated_line(
+            token,
+            vec![
+                Assembly8086Tokens::Instruction(Instructions::Mov),
+                Assembly8086Tokens::Register16bit(Registers16bit::AX),
+                Assembly8086Tokens::Comma,
+                Assembly8086Tokens::Character(UniCase::new("@data".to_string())),
+            ],
+        ),
+        get_generated_line(
+            token,
+            vec![
+                Assembly8086Tokens::Instruction(Instructions::Mov),
+                Assembly8086Tokens::Register16bit(Registers16bit::DS),
+                Assembly8086Tokens::Comma,
+                Assembly8086Tokens::Register16bit(Registers16bit::AX),
+            ],
+        ),
+    ]
+}
+
+// MOV AH, 4Ch (or) MOV AX, 4Cxxh with the return code; INT 21h
+pub(in crate::compiler) fn get_exit_compiled_lines(
+    token: &Token,
+    return_code: Option<u8>,
+) -> Vec<Vec<Token>> {
+    let mov_line = match return_code {
+        Some(return_code) => vec![
+            Assembly8086Tokens::Instruction(Instructions::Mov),
+            Assembly8086Tokens::Register16bit(Registers16bit::AX),
+            Assembly8086Tokens::Comma,
+            Assembly8086Tokens::Number16bit(0x4C00 | return_code as u16),
+        ],
+        None => vec![
+            Assembly8086Tokens::Instruction(Instructions::Mov),
+            Assembly8086Tokens::Register8bit(Registers8bit::AH),
+            Assembly8086Tokens::Comma,
+            Assembly8086Tokens::Number8bit(0x4C),
+        ],
+    };
+    vec![
+        get_generated_line(token, mov_line),
+        get_generated_line(
+            token,
+            vec![
+                Assembly8086Tokens::Instruction(Instructions::Int),
+                Assembly8086Tokens::Number8bit(0x21),
+            ],
+        ),
+    ]
+}
+
 pub(in crate::compiler) fn get_jmp_code_compiled_line(token: &Token) -> Vec<Token> {
     [
         Token::new(
@@ -78,38 +151,3 @@ pub(crate) fn get_label_token_from_line<'a>(
         _ => false,
     })
 }
-
-impl Lexer {
-    pub fn print_with_compiled_tokens(&self, compiled_tokens: &[CompiledBytesReference]) {
-        // print a formatted headding
-        println!(
-            "| {0: <30} | {1: <10} | {2: <10} | {3: <10} | {4: <10} |",
-            "Token", "Line", "Column", "Length", "Bytes"
-        );
-
-        for token_list in &self.tokens {
-            // find the compiled token that matches the line and column number
-            for token in token_list {
-                let mut bytes = String::new();
-                for compiled_token in compiled_tokens {
-                    if compiled_token.line_number == token.line_number
-                        && compiled_token.column_number == token.column_number
-                    {
-                        for byte in &compiled_token.bytes {
-                            bytes.push_str(&format!("{:02X} ", byte));
-                        }
-                    }
-                }
-                println!(
-                    "| {0: <30} | {1: <10} | {2: <10} | {3: <10} | {4: <10} |",
-                    format!("{}", token.token_type),
-                    token.line_number,
-                    token.column_number,
-                    token.token_length,
-                    bytes
-                );
-            }
-            println!();
-        }
-    }
-}
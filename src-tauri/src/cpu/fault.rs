@@ -0,0 +1,11 @@
+use serde::Serialize;
+
+use crate::consts::Byte;
+
+// A fault is raised when the cpu can't make sense of what it is executing,
+// execution can go on after it but the state is most likely garbage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Fault {
+    UnknownInstruction(Byte),
+    UnknownInterrupt(Byte),
+}
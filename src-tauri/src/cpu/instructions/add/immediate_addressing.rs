@@ -39,7 +39,7 @@ impl CPU {
     fn get_data_add(&mut self, mem: &Memory, instruction: Byte) -> Word {
         match instruction {
             0x81 => self.consume_word(mem),
-            0x83 => self.consume_byte(mem) as Word,
+            0x83 => self.consume_byte(mem) as i8 as Word,
             x => panic!("ADD instruction not implemented! for {}", x),
         }
     }
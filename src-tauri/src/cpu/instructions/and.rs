@@ -60,7 +60,7 @@ impl CPU {
         let ins = self.consume_instruction(mem);
         let reg_idx = ins - 0xE0;
         let num = if is_num_u8 {
-            self.consume_byte(mem) as u16
+            self.consume_byte(mem) as i8 as u16
         } else {
             self.consume_word(mem)
         };
@@ -84,7 +84,7 @@ impl CPU {
         let addr = self.consume_word(mem);
         let addr_val = self.read_word_from_pointer(mem, addr);
         let num = if is_num_u8 {
-            self.consume_byte(mem) as u16
+            self.consume_byte(mem) as i8 as u16
         } else {
             self.consume_word(mem)
         };
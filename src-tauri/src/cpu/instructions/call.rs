@@ -1,4 +1,4 @@
-use crate::{cpu::CPU, memory::Memory};
+use crate::{consts::U20, cpu::CPU, memory::Memory};
 
 impl CPU {
     pub(in crate::cpu) fn execute_call_and_16bitaddr(&mut self, mem: &mut Memory) {
@@ -7,6 +7,62 @@ impl CPU {
         self.push_stack(mem, curr_ip);
         self.set_instruction_pointer_from_16bitoffset(offset);
     }
+
+    pub(in crate::cpu) fn execute_call_far(&mut self, mem: &mut Memory) {
+        let offset = self.consume_word(mem);
+        let segment = self.consume_word(mem);
+        // the code segment is pushed first so RETF pops the instruction pointer first
+        self.push_stack(mem, self.code_segment);
+        self.push_stack(mem, self.instruction_pointer);
+        self.set_code_segment(segment);
+        self.set_instruction_pointer(offset);
+    }
+
+    // CALL BX (or) CALL [0x102] (or) CALL [BX+0x10], the offset of the procedure is in them
+    pub(in crate::cpu) fn execute_call_abs_address(&mut self, mem: &mut Memory) {
+        let ins = self.consume_instruction(mem);
+        let offset = match ins {
+            0xD0..=0xD7 => self.get_16bit_register_by_index(ins % 8),
+            0x16 => {
+                let address = self.consume_word(mem);
+                mem.read_word(self.data_segment, address)
+            }
+            0x50..=0x57 => {
+                let address = self.consume_byte_and_get_cummulative_offset(mem, ins % 8);
+                self.read_word_from_u20(mem, address)
+            }
+            0x90..=0x97 => {
+                let address = self.consume_word_and_get_cummulative_offset(mem, ins % 8);
+                self.read_word_from_u20(mem, address)
+            }
+            _ => {
+                let address = self.get_offset_from_index_of_indexed_registers(ins % 8);
+                self.read_word_from_u20(mem, address)
+            }
+        };
+        self.push_stack(mem, self.instruction_pointer);
+        self.set_instruction_pointer(offset);
+    }
+
+    // CALL FAR [0x102] (or) CALL FAR [BX+0x10], the offset and then the segment are in memory
+    pub(in crate::cpu) fn execute_call_far_abs_address(&mut self, mem: &mut Memory) {
+        let ins = self.consume_instruction(mem);
+        let address = match ins {
+            0x1E => {
+                let offset = self.consume_word(mem);
+                U20::from((self.data_segment as u32) * 0x10 + offset as u32)
+            }
+            0x58..=0x5F => self.consume_byte_and_get_cummulative_offset(mem, ins % 8),
+            0x98..=0x9F => self.consume_word_and_get_cummulative_offset(mem, ins % 8),
+            _ => self.get_offset_from_index_of_indexed_registers(ins % 8),
+        };
+        let offset = self.read_word_from_u20(mem, address.clone());
+        let segment = self.read_word_from_u20(mem, address + U20::from(2_u32));
+        self.push_stack(mem, self.code_segment);
+        self.push_stack(mem, self.instruction_pointer);
+        self.set_code_segment(segment);
+        self.set_instruction_pointer(offset);
+    }
 }
 
 #[cfg(test)]
@@ -30,4 +86,72 @@ mod tests {
         let (cpu, _) = run_code(code, 4);
         assert_eq!(cpu.ax, 3);
     }
+
+    #[test]
+    fn far_call_and_retf() {
+        let code = "
+    CALL FAR PTR add_one
+    inc ax
+    hlt
+    add_one:
+    inc bx
+    RETF
+    ";
+        let (cpu, _) = run_code(code, 4);
+        assert_eq!((cpu.ax, cpu.bx), (1, 1));
+        assert_eq!(cpu.get_code_segment(), 0x100);
+        assert_eq!(cpu.instruction_pointer, 0x06);
+        assert_eq!(cpu.stack_pointer, 0xFFFE);
+    }
+
+    #[test]
+    fn call_register_and_memory() {
+        // CALL BX, CALL [BX], CALL FAR [BX]
+        let code = "
+    mov bx, offset add_one
+    call_bx db 0xFF, 0xD3
+    mov bx, offset procs
+    call_near db 0xFF, 0x17
+    mov bx, offset far_proc
+    call_far db 0xFF, 0x1F
+    hlt
+    add_one:
+    inc ax
+    ret
+    add_two:
+    add ax, 2
+    retf
+    procs dw offset add_one
+    far_proc dw offset add_two, 0x100
+    ";
+        let (cpu, _) = run_code(code, 12);
+        assert_eq!(cpu.ax, 4);
+        assert_eq!(cpu.get_code_segment(), 0x100);
+        assert_eq!(cpu.instruction_pointer, 0x0F);
+        assert_eq!(cpu.stack_pointer, 0xFFFE);
+    }
+
+    #[test]
+    fn call_memory_with_displacement() {
+        // CALL [BX+2], CALL [BX+0x0002], CALL FAR [BX+4], CALL FAR [BX+0x0008]
+        let code = "
+    mov bx, offset procs
+    call_near_8bit db 0xFF, 0x57, 0x02
+    call_near_16bit db 0xFF, 0x97, 0x02, 0x00
+    call_far_8bit db 0xFF, 0x5F, 0x04
+    call_far_16bit db 0xFF, 0x9F, 0x08, 0x00
+    hlt
+    add_one:
+    inc ax
+    ret
+    add_two:
+    add ax, 2
+    retf
+    procs dw 0, offset add_one, offset add_two, 0x100, offset add_two, 0x100
+    ";
+        let (cpu, _) = run_code(code, 14);
+        assert_eq!(cpu.ax, 6);
+        assert_eq!(cpu.get_code_segment(), 0x100);
+        assert_eq!(cpu.stack_pointer, 0xFFFE);
+    }
 }
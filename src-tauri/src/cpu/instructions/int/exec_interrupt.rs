@@ -1,34 +1,45 @@
-use crate::{cpu::CPU, memory::Memory};
+use crate::{
+    consts::Byte,
+    cpu::{fault::Fault, interrupt::Interrupt, CPU},
+    memory::Memory,
+};
 
 impl CPU {
-    pub(in crate::cpu) fn execute_interrupt(&mut self, mem: &mut Memory) {
+    pub(in crate::cpu) fn execute_interrupt(&mut self, mem: &mut Memory) -> Option<Interrupt> {
+        // get the interrupt vector
+        let interrupt_arg = self.consume_byte(mem);
+        self.execute_interrupt_number(mem, interrupt_arg)
+    }
+
+    // INT 3 and INTO don't have the number after the opcode
+    pub(in crate::cpu) fn execute_interrupt_number(
+        &mut self,
+        mem: &mut Memory,
+        interrupt_arg: Byte,
+    ) -> Option<Interrupt> {
+        let (segment, offset) = match interrupt_arg {
+            // terminate the program
+            0x20 => return Some(Interrupt::Exit(0)),
+            // move cs to F400 and ip to 0190
+            0x10 => (0xF400, 0x0190),
+            // move cs to F400 and ip to 0200
+            0x21 => (0xF400, 0x0200),
+            _ => {
+                self.raise_fault(Fault::UnknownInterrupt(interrupt_arg));
+                return None;
+            }
+        };
+
         // push the current instruction pointer to the stack
         self.push_stack(mem, self.get_flags_as_16bit_number());
         self.push_stack(mem, self.get_code_segment());
-        let next_ins_offset = 0x01;
-        self.push_stack(mem, self.get_instruciton_pointer() + next_ins_offset);
+        self.push_stack(mem, self.get_instruciton_pointer());
 
         // convert interrupt flag to a 16 bit number
         self.interrupt_disable_flag = true;
 
-        // get the interrupt vector
-        let interrupt_arg = self.consume_byte(mem);
-
-        match interrupt_arg {
-            0x10 => {
-                // move cs to F400 and ip to 0190
-                self.set_code_segment(0xF400);
-                self.set_instruction_pointer(0x0190);
-            }
-            0x21 => {
-                // move cs to F400 and ip to 0190
-                self.set_code_segment(0xF400);
-                self.set_instruction_pointer(0x0200);
-            }
-
-            _ => {
-                panic!("Unknown interrupt: {}", interrupt_arg);
-            }
-        }
+        self.set_code_segment(segment);
+        self.set_instruction_pointer(offset);
+        None
     }
 }
@@ -1,5 +1,6 @@
 use crate::{
-    cpu::{interrupt::Interrupt, CPU},
+    consts::Word,
+    cpu::{fault::Fault, interrupt::Interrupt, keyboard, CPU},
     memory::Memory,
 };
 
@@ -7,20 +8,67 @@ pub mod exec_interrupt;
 pub mod procedures;
 
 impl CPU {
-    pub(crate) fn execute_bios_di(&mut self, mem: &mut Memory) -> Interrupt {
+    pub(crate) fn execute_bios_di(&mut self, mem: &mut Memory) -> Option<Interrupt> {
         self.consume_instruction(mem); // 0xFF
         self.consume_instruction(mem); // 0xCD
         let interrupt_arg = self.consume_byte(mem);
 
         match interrupt_arg {
-            0x21 => {
-                let val = self.get_dx_low() as char;
-                Interrupt::Print(val.to_string())
-            }
+            0x21 => self.execute_dos_function(mem),
 
             _ => {
-                panic!("Unknown interrupt: {}", interrupt_arg);
+                self.raise_fault(Fault::UnknownInterrupt(interrupt_arg));
+                None
+            }
+        }
+    }
+}
+
+// the size of the BIOS DI pseudo instruction, i.e FF FF CD xx
+const BIOS_DI_SIZE: Word = 4;
+
+impl CPU {
+    // the INT 21h functions, the ones that aren't supported print DL
+    fn execute_dos_function(&mut self, mem: &mut Memory) -> Option<Interrupt> {
+        match self.get_ax_high() {
+            // read a character, 0x01 echoes it
+            0x01 | 0x07 | 0x08 => {
+                let key = match keyboard::pop_key(mem) {
+                    Some(key) => key,
+                    None => {
+                        // wait by running the same function again
+                        self.instruction_pointer =
+                            self.instruction_pointer.wrapping_sub(BIOS_DI_SIZE);
+                        return Some(Interrupt::Read);
+                    }
+                };
+                self.set_ax_low(key);
+                match self.get_ax_high() {
+                    0x01 => Some(Interrupt::Print((key as char).to_string())),
+                    _ => None,
+                }
+            }
+            // the '$' terminated string at DS:DX
+            0x09 => {
+                let mut output = String::new();
+                let mut offset = self.dx;
+                for _ in 0..Word::MAX {
+                    let byte = mem.read_byte(self.data_segment, offset);
+                    if byte == b'$' {
+                        break;
+                    }
+                    output.push(byte as char);
+                    offset = offset.wrapping_add(1);
+                }
+                Some(Interrupt::Print(output))
+            }
+            // 0xFF in AL when a key is waiting
+            0x0B => {
+                self.set_ax_low(if keyboard::has_key(mem) { 0xFF } else { 0x00 });
+                None
             }
+            0x4C => Some(Interrupt::Exit(self.get_ax_low())),
+            _ => Some(Interrupt::Print((self.get_dx_low() as char).to_string())),
         }
     }
 }
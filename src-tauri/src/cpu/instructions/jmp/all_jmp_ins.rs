@@ -153,7 +153,10 @@ impl CPU {
 
 #[cfg(test)]
 mod tests {
-    use crate::cpu::instructions::test_macro::execute_code;
+    use crate::{
+        compiler::tests::get_long_jumps_options,
+        cpu::instructions::test_macro::{execute_code, execute_code_with_options},
+    };
 
     fn generate_inc_x80() -> String {
         let mut code = String::new();
@@ -191,7 +194,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x0001);
     }
 
@@ -223,7 +226,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x0001);
     }
 
@@ -255,7 +258,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x0001);
     }
 
@@ -287,7 +290,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x0001);
     }
 
@@ -319,7 +322,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x0082);
     }
 
@@ -351,7 +354,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x0001);
     }
 
@@ -383,7 +386,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x0001);
     }
 
@@ -398,7 +401,7 @@ mod tests {
             INC AX
         ";
         let (cpu, _) = execute_code(code);
-        assert_eq!(cpu.ax, 0x0002);
+        assert_eq!(cpu.ax, 0x0001);
     }
 
     #[test]
@@ -415,8 +418,8 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
-        assert_eq!(cpu.ax, 0x0082);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
+        assert_eq!(cpu.ax, 0x0001);
     }
 
     #[test]
@@ -447,7 +450,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x0001);
     }
 
@@ -479,7 +482,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x01);
     }
 
@@ -511,7 +514,7 @@ mod tests {
             generate_inc_x80()
         );
 
-        let (cpu, _) = execute_code(&code);
+        let (cpu, _) = execute_code_with_options(&code, &get_long_jumps_options());
         assert_eq!(cpu.ax, 0x01);
     }
 }
@@ -24,6 +24,13 @@ impl CPU {
     generate_8bit_jmp_method!(jmp, exec_fn);
     generate_16bit_jmp_method!(jmp, exec_fn);
     generate_mem_jmp_method!(jmp, exec_fn_abs);
+
+    pub(in crate::cpu) fn execute_jmp_far(&mut self, mem: &mut Memory) {
+        let offset = self.consume_word(mem);
+        let segment = self.consume_word(mem);
+        self.set_code_segment(segment);
+        self.set_instruction_pointer(offset);
+    }
 }
 #[cfg(test)]
 mod test_8bit_jmp {
@@ -100,6 +107,20 @@ mod test_16_bit_jmp {
         assert_eq!(cpu.ax, 0x0002);
     }
 
+    #[test]
+    fn jmp_far() {
+        let code = "
+        jmp far ptr label
+        INC AX
+        label:
+        INC BX
+        ";
+        let (cpu, _) = run_code(code, 2);
+        assert_eq!((cpu.ax, cpu.bx), (0, 1));
+        assert_eq!(cpu.get_code_segment(), 0x100);
+        assert_eq!(cpu.instruction_pointer, 0x07);
+    }
+
     #[test]
     fn test_jmp_var() {
         let code = "
@@ -1,4 +1,4 @@
-use crate::{cpu::CPU, memory::Memory};
+use crate::{consts::Byte, cpu::CPU, memory::Memory};
 
 pub mod direct_addressing;
 pub mod immediate_addressing;
@@ -42,6 +42,16 @@ impl CPU {
         }
     }
 
+    pub(in crate::cpu) fn execute_mov_register_segment(&mut self, mem: &mut Memory, opcode: Byte) {
+        let instruction = self.consume_instruction(mem);
+        // only the registers are supported, the segment is the index in the middle 3 bits
+        if instruction < 0xC0 || (instruction >> 3) & 0x07 > 0x03 {
+            self.execute_unknown_ins(mem, opcode);
+            return;
+        }
+        self.mov_segment_register_addressing(opcode, instruction);
+    }
+
     pub(in crate::cpu) fn execute_mov_indexed_addr_16bit_register(&mut self, mem: &mut Memory) {
         let exec_fn = |_: &mut CPU, _: u16, val: u16| -> Option<u16> { Some(val) };
         self.consume_bytes_and_parse_mem_as_first_arg_double_ins(mem, &exec_fn);
@@ -70,4 +80,18 @@ mod mov_indexed_addr_tests {
         assert_eq!(cpu.read_word_from_pointer(&mem, 0x1236), 0x200);
         assert_eq!(cpu.read_word_from_pointer(&mem, 0x1238), 0x300);
     }
+
+    #[test]
+    fn test_mov_segment_registers() {
+        let code = "
+        mov ax, 0x1234
+        mov es, ax
+        mov bx, cs
+        mov cx, es
+        ";
+        let (cpu, _) = execute_code(code);
+        assert_eq!(cpu.extra_segment, 0x1234);
+        assert_eq!(cpu.bx, cpu.code_segment);
+        assert_eq!(cpu.cx, 0x1234);
+    }
 }
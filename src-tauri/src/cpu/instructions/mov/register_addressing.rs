@@ -7,6 +7,23 @@ impl CPU {
         self.set_16bit_register_by_index(write_index, reg);
     }
 
+    pub(super) fn mov_segment_register_addressing(&mut self, opcode: Byte, instruction: Byte) {
+        let segment_index = (instruction >> 3) & 0x07;
+        let reg_index = instruction & 0x07;
+        match opcode {
+            // MOV DS, AX
+            0x8E => {
+                let reg = self.get_16bit_register_by_index(reg_index);
+                self.set_segment_register_by_index(segment_index, reg);
+            }
+            // MOV AX, DS
+            _ => {
+                let segment = self.get_segment_register_by_index(segment_index);
+                self.set_16bit_register_by_index(reg_index, segment);
+            }
+        }
+    }
+
     pub(super) fn mov_8bit_register_addressing(&mut self, instruction: Byte) {
         let (source_index, write_index) = self.get_index_from_c0_ff_pattern(instruction);
         let reg = self.get_8bit_register_by_index(source_index % 8);
@@ -115,8 +115,8 @@ mod test {
         pop di 
 
         pop es
-        pop ss
         pop ds
+        pop ss
     ";
         let (cpu, _) = execute_code(code);
 
@@ -129,8 +129,8 @@ mod test {
         assert_eq!(cpu.destination_index, 0x106);
 
         assert_eq!(cpu.extra_segment, 0x107);
-        assert_eq!(cpu.stack_segment, 0x108);
-        assert_eq!(cpu.data_segment, 0x109);
+        assert_eq!(cpu.data_segment, 0x108);
+        assert_eq!(cpu.stack_segment, 0x109);
     }
 
     #[test]
@@ -6,6 +6,26 @@ impl CPU {
         let ptr = self.pop_stack(mem);
         self.set_instruction_pointer(ptr);
     }
+
+    pub(in crate::cpu) fn execute_ret_and_16bitdata(&mut self, mem: &mut Memory) {
+        let size = self.consume_word(mem);
+        self.execute_ret(mem);
+        self.stack_pointer = self.stack_pointer.wrapping_add(size);
+    }
+
+    pub(in crate::cpu) fn execute_retf(&mut self, mem: &mut Memory) {
+        let ip = self.pop_stack(mem);
+        let cs = self.pop_stack(mem);
+        self.set_code_segment(cs);
+        self.set_instruction_pointer(ip);
+    }
+
+    pub(in crate::cpu) fn execute_retf_and_16bitdata(&mut self, mem: &mut Memory) {
+        // the bytes of the arguments are removed from the stack after the return address
+        let size = self.consume_word(mem);
+        self.execute_retf(mem);
+        self.stack_pointer = self.stack_pointer.wrapping_add(size);
+    }
 }
 
 #[cfg(test)]
@@ -17,4 +37,20 @@ mod test {
         let (cpu, _) = execute_code("PUSH 0x03 \n ret");
         assert_eq!(cpu.instruction_pointer, 0x03);
     }
+
+    #[test]
+    fn ret_and_16bitdata_test() {
+        // RET 4
+        let (cpu, _) =
+            execute_code("PUSH 0x01 \n PUSH 0x02 \n PUSH 0x0A \n ret_4 db 0xC2, 0x04, 0x00");
+        assert_eq!(cpu.instruction_pointer, 0x0A);
+        assert_eq!(cpu.stack_pointer, 0xFFFE);
+    }
+
+    #[test]
+    fn retf_test() {
+        let (cpu, _) = execute_code("PUSH 0x0200 \n PUSH 0x03 \n retf");
+        assert_eq!(cpu.get_code_segment(), 0x200);
+        assert_eq!(cpu.instruction_pointer, 0x03);
+    }
 }
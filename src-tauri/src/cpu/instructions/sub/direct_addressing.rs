@@ -58,7 +58,7 @@ impl CPU {
             0x83 => {
                 // 8bit add
                 let data_from_mem = self.read_word_from_pointer(mem, data_address);
-                let num: u16 = self.consume_byte(mem) as u16;
+                let num: u16 = self.consume_byte(mem) as i8 as u16;
                 let (result, _) = self.sub_16bit_with_overflow_and_set_flags(data_from_mem, num);
                 self.write_word_from_pointer(mem, data_address, result);
             }
@@ -36,10 +36,8 @@ impl CPU {
                 let data_high = self.consume_instruction(mem);
                 ((data_high as Word) << 8) | (data_low as Word)
             }
-            0x83 => {
-                let data_low = self.consume_instruction(mem);
-                0xFF << 8 | (data_low as Word)
-            }
+            // the byte is sign extended
+            0x83 => self.consume_instruction(mem) as i8 as Word,
             x => panic!("SUB instruction not implemented! for {}", x),
         }
     }
@@ -1,4 +1,8 @@
-use crate::{compiler::compile_lines, cpu::CPU, memory::Memory};
+use crate::{
+    compiler::{compile_lines, compile_program_with_options, types_structs::CompileOptions},
+    cpu::CPU,
+    memory::Memory,
+};
 
 #[deprecated(note = "please use `run_code` instead")]
 #[macro_export]
@@ -88,11 +92,19 @@ pub fn run_code(code: &str, cycles: usize) -> (CPU, Memory) {
 }
 
 pub fn execute_code(code: &str) -> (CPU, Memory) {
+    execute_code_with_options(code, &CompileOptions::default())
+}
+
+pub fn execute_code_with_options(code: &str, compile_options: &CompileOptions) -> (CPU, Memory) {
     let mut cpu = CPU::new();
     let mut mem = Memory::new();
     cpu.reset(&mut mem);
 
-    compile_code_for_tests(code, &mut cpu, &mut mem);
+    let program = compile_program_with_options(code, compile_options, false).unwrap();
+    if program.is_org_defined {
+        cpu.set_org_defined()
+    }
+    cpu.write_instructions(&mut mem, &program.bytes);
 
     // calc the number of non blank lines in code
     let cycles = code.lines().filter(|line| !line.is_empty()).count();
@@ -3,4 +3,8 @@ use serde::Serialize;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum Interrupt {
     Print(String),
+    // the program is waiting for a key, the interrupt runs again once one is pushed
+    Read,
+    // the program terminated with the given return code
+    Exit(u8),
 }
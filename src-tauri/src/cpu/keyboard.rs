@@ -0,0 +1,89 @@
+use crate::{
+    consts::{Byte, Word},
+    memory::Memory,
+};
+
+// The keyboard buffer of the BIOS data area, a circular buffer of (ascii, scan code)
+// words with the offsets of its head and tail in front of it. Keeping it in memory
+// makes the keys the program consumed come back when stepping back.
+const BIOS_DATA_SEGMENT: Word = 0x0040;
+const HEAD_OFFSET: Word = 0x001A;
+const TAIL_OFFSET: Word = 0x001C;
+const BUFFER_START: Word = 0x001E;
+const BUFFER_END: Word = 0x003E;
+
+fn next_offset(offset: Word) -> Word {
+    if offset + 2 >= BUFFER_END {
+        BUFFER_START
+    } else {
+        offset + 2
+    }
+}
+
+pub(crate) fn reset_keyboard_buffer(mem: &mut Memory) {
+    mem.write_instructions(
+        BIOS_DATA_SEGMENT,
+        HEAD_OFFSET,
+        &[BUFFER_START as Byte, 0x00, BUFFER_START as Byte, 0x00],
+    );
+}
+
+// adds a key to the buffer, false when the buffer is full and the key got dropped
+pub fn push_key(mem: &mut Memory, key: Byte) -> bool {
+    let tail = mem.peek_word(BIOS_DATA_SEGMENT, TAIL_OFFSET);
+    let new_tail = next_offset(tail);
+    if new_tail == mem.peek_word(BIOS_DATA_SEGMENT, HEAD_OFFSET) {
+        return false;
+    }
+    // the scan codes aren't emulated, only the ascii byte is
+    mem.write_instructions(BIOS_DATA_SEGMENT, tail, &[key, 0x00]);
+    mem.write_instructions(BIOS_DATA_SEGMENT, TAIL_OFFSET, &new_tail.to_le_bytes());
+    true
+}
+
+pub fn has_key(mem: &Memory) -> bool {
+    mem.peek_word(BIOS_DATA_SEGMENT, HEAD_OFFSET) != mem.peek_word(BIOS_DATA_SEGMENT, TAIL_OFFSET)
+}
+
+pub(crate) fn pop_key(mem: &mut Memory) -> Option<Byte> {
+    if !has_key(mem) {
+        return None;
+    }
+    let head = mem.peek_word(BIOS_DATA_SEGMENT, HEAD_OFFSET);
+    let key = mem.peek_byte(BIOS_DATA_SEGMENT, head);
+    mem.write_instructions(
+        BIOS_DATA_SEGMENT,
+        HEAD_OFFSET,
+        &next_offset(head).to_le_bytes(),
+    );
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cpu::CPU, memory::Memory};
+
+    use super::{has_key, pop_key, push_key};
+
+    #[test]
+    fn keys_come_out_in_order_and_the_buffer_fills_up() {
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        cpu.reset(&mut mem);
+        assert!(!has_key(&mem));
+
+        // one slot is always left empty to tell a full buffer from an empty one
+        for key in 0..15 {
+            assert!(push_key(&mut mem, b'a' + key));
+        }
+        assert!(!push_key(&mut mem, b'z'));
+
+        assert_eq!(pop_key(&mut mem), Some(b'a'));
+        assert!(push_key(&mut mem, b'z'));
+        for key in 1..15 {
+            assert_eq!(pop_key(&mut mem), Some(b'a' + key));
+        }
+        assert_eq!(pop_key(&mut mem), Some(b'z'));
+        assert_eq!(pop_key(&mut mem), None);
+    }
+}
@@ -5,10 +5,13 @@ use crate::{
     Memory,
 };
 
-use self::{interrupt::Interrupt, ports_handler::Ports};
+use self::{fault::Fault, interrupt::Interrupt, ports_handler::Ports};
+pub mod fault;
 pub mod instructions;
 pub mod interrupt;
+pub mod keyboard;
 pub mod ports_handler;
+pub mod registers;
 pub(in crate::cpu) mod utils;
 
 macro_rules! generate_byte_access_methods {
@@ -39,7 +42,7 @@ macro_rules! generate_byte_access_methods {
     };
 }
 
-#[derive(Serialize, Clone, Copy)]
+#[derive(Debug, Serialize, Clone, Copy)]
 pub struct CPU {
     // Memory
     instruction_pointer: Word,
@@ -72,6 +75,10 @@ pub struct CPU {
 
     // Ports
     pub ports: Ports,
+
+    // Faults raised by the last executed instructions
+    #[serde(skip)]
+    fault: Option<Fault>,
 }
 
 impl CPU {
@@ -109,6 +116,10 @@ impl CPU {
         self.cx = value;
     }
 
+    pub fn get_stack_pointer(&self) -> Word {
+        self.stack_pointer
+    }
+
     pub fn get_code_segment(&self) -> Word {
         self.code_segment
     }
@@ -174,6 +185,8 @@ impl CPU {
             negative_flag: false,
 
             ports: Ports::new(),
+
+            fault: None,
         }
     }
 
@@ -204,9 +217,11 @@ impl CPU {
         self.extra_segment = 0x0100;
 
         self.ports.reset();
+        self.fault = None;
         mem.reset();
         self.write_0x10_interrupt_procedure(mem);
         self.write_0x21_interrupt_procedure(mem);
+        keyboard::reset_keyboard_buffer(mem);
     }
 
     pub fn set_org_defined(&mut self) {
@@ -218,7 +233,7 @@ impl CPU {
     }
 
     fn consume_instruction(&mut self, mem: &Memory) -> Byte {
-        let opcode = mem.read_byte(self.code_segment, self.instruction_pointer);
+        let opcode = mem.peek_byte(self.code_segment, self.instruction_pointer);
         self.instruction_pointer += 1;
         opcode
     }
@@ -234,7 +249,7 @@ impl CPU {
     }
 
     fn peek_instruction(&self, mem: &Memory) -> Byte {
-        mem.read_byte(self.code_segment, self.instruction_pointer)
+        mem.peek_byte(self.code_segment, self.instruction_pointer)
     }
 
     fn execute_nop(&mut self, mem: &mut Memory) {
@@ -245,10 +260,21 @@ impl CPU {
         println!("-----------------------------------");
         println!("Unknown instruction: {:X}", _opcode);
         println!("-----------------------------------");
+        self.fault = Some(Fault::UnknownInstruction(_opcode));
         self.execute_nop(mem);
     }
 
+    pub(in crate::cpu) fn raise_fault(&mut self, fault: Fault) {
+        self.fault = Some(fault);
+    }
+
+    pub fn take_fault(&mut self) -> Option<Fault> {
+        self.fault.take()
+    }
+
     pub fn execute(&mut self, mem: &mut Memory) -> Option<Interrupt> {
+        // every instruction is a new step in the memory history
+        mem.advance_time();
         let opcode = self.consume_instruction(mem);
         match opcode {
             // ADD [0x1234], AL
@@ -547,9 +573,15 @@ impl CPU {
             0x8A => self.execute_mov_register_byte(mem),
             0x8B => self.execute_mov_register_word(mem),
 
+            // MOV 16bit register, segment register
+            0x8C => self.execute_mov_register_segment(mem, opcode),
+
             // LEA 16bit reg, mem
             0x8D => self.exec_lea_reg_mem(mem),
 
+            // MOV segment register, 16bit register
+            0x8E => self.execute_mov_register_segment(mem, opcode),
+
             // POP ...
             0x8F => {
                 let opcode = self.peek_instruction(mem);
@@ -573,6 +605,9 @@ impl CPU {
             0x90 => self.execute_nop(mem),
             0x91..=0x97 => self.execute_xchg_ax(opcode),
 
+            // CALL FAR segment:offset
+            0x9A => self.execute_call_far(mem),
+
             // MOV AL, [0x102]
             0xA0 => self.execute_mov_al_direct_addressing(mem),
             // MOV AX, [0x102]
@@ -594,9 +629,18 @@ impl CPU {
             0xB0..=0xB7 => self.execute_direct_mov_byte(mem, opcode),
             0xB8..=0xBF => self.execute_direct_mov_word(mem, opcode),
 
+            // RET 0x04
+            0xC2 => self.execute_ret_and_16bitdata(mem),
+
             // RET
             0xC3 => self.execute_ret(mem),
 
+            // RETF 0x04
+            0xCA => self.execute_retf_and_16bitdata(mem),
+
+            // RETF
+            0xCB => self.execute_retf(mem),
+
             // LES 16bit register, mem
             0xC4 => self.exec_les_16_bit_reg_mem(mem),
 
@@ -618,8 +662,15 @@ impl CPU {
                 }
             }
 
+            // INT 3
+            0xCC => return self.execute_interrupt_number(mem, 0x03),
+
             // INT
-            0xCD => self.execute_interrupt(mem),
+            0xCD => return self.execute_interrupt(mem),
+
+            // INTO
+            0xCE if self.overflow_flag => return self.execute_interrupt_number(mem, 0x04),
+            0xCE => {}
 
             // IRET
             0xCF => self.execute_iret(mem),
@@ -638,6 +689,9 @@ impl CPU {
             // CALL 16 bit address
             0xE8 => self.execute_call_and_16bitaddr(mem),
 
+            // JMP FAR segment:offset
+            0xEA => self.execute_jmp_far(mem),
+
             // JMP 16bit-memory
             0xE9 => self.execute_jmp_16bit(mem),
 
@@ -724,6 +778,14 @@ impl CPU {
                     0x06 => self.execute_inc_address_16bit(mem),
                     // DEC [0x1234]
                     0x0E => self.execute_dec_address_16bit(mem),
+                    // CALL [0x1234] | CALL [BX+0x12] | CALL BX
+                    0x10..=0x17 | 0x50..=0x57 | 0x90..=0x97 | 0xD0..=0xD7 => {
+                        self.execute_call_abs_address(mem)
+                    }
+                    // CALL FAR [0x1234] | CALL FAR [BX+0x12]
+                    0x18..=0x1F | 0x58..=0x5F | 0x98..=0x9F => {
+                        self.execute_call_far_abs_address(mem)
+                    }
                     // JMP [0x1234]
                     0x20..=0x27 => self.execute_jmp_abs_address(mem),
                     // PUSH indexed addr no offset
@@ -733,10 +795,7 @@ impl CPU {
                     // PUSH indexed addr with 16bit-offset
                     0xB0..=0xB7 => self.execute_push_indexed_addressing_with_16bit_offset(mem),
                     // BIOS DI
-                    0xFF => {
-                        let int = self.execute_bios_di(mem);
-                        return Some(int);
-                    }
+                    0xFF => return self.execute_bios_di(mem),
                     _ => self.execute_unknown_ins(mem, 0xFF),
                 }
             }
@@ -789,7 +848,7 @@ impl CPU {
 impl CPU {
     fn pop_stack(&mut self, mem: &mut Memory) -> Word {
         let sp = self.stack_pointer;
-        let value = self.read_word_from_pointer(mem, sp);
+        let value = mem.read_word(self.stack_segment, sp);
         self.stack_pointer = sp.wrapping_add(2);
         value
     }
@@ -812,7 +871,7 @@ impl CPU {
 
         // go from 0xFFFE to sp
         for sp in (self.stack_pointer..0xFFFF).step_by(2).rev() {
-            let value = self.read_word_from_pointer(mem, sp);
+            let value = mem.read_word(self.stack_segment, sp);
             println!(
                 "0x{:04X}: 0x{:04X}: |   0x{:04X}   |",
                 self.stack_segment, sp, value
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::consts::{Byte, Word};
+
+use super::CPU;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, Display, EnumIter, Serialize, Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum Register {
+    AX,
+    BX,
+    CX,
+    DX,
+    SI,
+    DI,
+    BP,
+    SP,
+    CS,
+    DS,
+    ES,
+    SS,
+    IP,
+    AH,
+    AL,
+    BH,
+    BL,
+    CH,
+    CL,
+    DH,
+    DL,
+    Flags,
+}
+
+impl Register {
+    pub fn is_8bit(&self) -> bool {
+        matches!(
+            self,
+            Register::AH
+                | Register::AL
+                | Register::BH
+                | Register::BL
+                | Register::CH
+                | Register::CL
+                | Register::DH
+                | Register::DL
+        )
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, Display, EnumIter, Serialize, Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum Flag {
+    CF,
+    ZF,
+    SF,
+    OF,
+    PF,
+    AF,
+    IF,
+    DF,
+}
+
+impl CPU {
+    pub fn get_register(&self, register: Register) -> Word {
+        match register {
+            Register::AX => self.ax,
+            Register::BX => self.bx,
+            Register::CX => self.cx,
+            Register::DX => self.dx,
+            Register::SI => self.source_index,
+            Register::DI => self.destination_index,
+            Register::BP => self.base_pointer,
+            Register::SP => self.stack_pointer,
+            Register::CS => self.code_segment,
+            Register::DS => self.data_segment,
+            Register::ES => self.extra_segment,
+            Register::SS => self.stack_segment,
+            Register::IP => self.instruction_pointer,
+            Register::AH => self.get_ax_high() as Word,
+            Register::AL => self.get_ax_low() as Word,
+            Register::BH => self.get_bx_high() as Word,
+            Register::BL => self.get_bx_low() as Word,
+            Register::CH => self.get_cx_high() as Word,
+            Register::CL => self.get_cx_low() as Word,
+            Register::DH => self.get_dx_high() as Word,
+            Register::DL => self.get_dx_low() as Word,
+            Register::Flags => self.get_flags_as_16bit_number(),
+        }
+    }
+
+    // 8bit registers only take the low byte of the value
+    pub fn set_register(&mut self, register: Register, value: Word) {
+        let low_byte = value as Byte;
+        match register {
+            Register::AX => self.ax = value,
+            Register::BX => self.bx = value,
+            Register::CX => self.cx = value,
+            Register::DX => self.dx = value,
+            Register::SI => self.source_index = value,
+            Register::DI => self.destination_index = value,
+            Register::BP => self.base_pointer = value,
+            Register::SP => self.stack_pointer = value,
+            Register::CS => self.code_segment = value,
+            Register::DS => self.data_segment = value,
+            Register::ES => self.extra_segment = value,
+            Register::SS => self.stack_segment = value,
+            Register::IP => self.instruction_pointer = value,
+            Register::AH => self.set_ax_high(low_byte),
+            Register::AL => self.set_ax_low(low_byte),
+            Register::BH => self.set_bx_high(low_byte),
+            Register::BL => self.set_bx_low(low_byte),
+            Register::CH => self.set_cx_high(low_byte),
+            Register::CL => self.set_cx_low(low_byte),
+            Register::DH => self.set_dx_high(low_byte),
+            Register::DL => self.set_dx_low(low_byte),
+            Register::Flags => self.set_flags_from_u16(value),
+        }
+    }
+
+    pub fn get_flag(&self, flag: Flag) -> bool {
+        match flag {
+            Flag::CF => self.carry_flag,
+            Flag::ZF => self.zero_flag,
+            Flag::SF => self.negative_flag,
+            Flag::OF => self.overflow_flag,
+            Flag::PF => self.pairity_flag,
+            Flag::AF => self.auxiliary_carry_flag,
+            Flag::IF => self.interrupt_disable_flag,
+            Flag::DF => self.direction_flag,
+        }
+    }
+
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
+        match flag {
+            Flag::CF => self.carry_flag = value,
+            Flag::ZF => self.zero_flag = value,
+            Flag::SF => self.negative_flag = value,
+            Flag::OF => self.overflow_flag = value,
+            Flag::PF => self.pairity_flag = value,
+            Flag::AF => self.auxiliary_carry_flag = value,
+            Flag::IF => self.interrupt_disable_flag = value,
+            Flag::DF => self.direction_flag = value,
+        }
+    }
+}
@@ -28,6 +28,26 @@ impl CPU {
         }
     }
 
+    pub(in crate::cpu) fn get_segment_register_by_index(&self, index: u8) -> Word {
+        match index {
+            0x00 => self.extra_segment,
+            0x01 => self.code_segment,
+            0x02 => self.stack_segment,
+            0x03 => self.data_segment,
+            _ => panic!("Invalid register index! This can't happen!"),
+        }
+    }
+
+    pub(in crate::cpu) fn set_segment_register_by_index(&mut self, index: u8, value: Word) {
+        match index {
+            0x00 => self.extra_segment = value,
+            0x01 => self.code_segment = value,
+            0x02 => self.stack_segment = value,
+            0x03 => self.data_segment = value,
+            _ => panic!("Invalid register index! This can't happen!"),
+        }
+    }
+
     pub(in crate::cpu) fn get_8bit_register_by_index(&self, index: u8) -> Byte {
         match index {
             0x00 => self.get_ax_low(),
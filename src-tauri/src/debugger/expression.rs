@@ -0,0 +1,457 @@
+use std::str::FromStr;
+
+use unicase::UniCase;
+
+use crate::{
+    compiler::types_structs::{Label, VariableAddressMap, VariableType},
+    consts::Word,
+    cpu::{
+        registers::{Flag, Register},
+        CPU,
+    },
+    memory::Memory,
+};
+
+// The expressions used by conditional breakpoints, the syntax is close to C
+// with registers, flags and variables as operands and assembly like memory accesses
+// for ex: ax == 0x10 && [var1] > 3
+//         zf || w.[bx+si] != 0xFFFF
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Number(i64),
+    Register(Register),
+    Flag(Flag),
+    // evaluates to the offset of the variable
+    Variable(Label),
+    // reads the memory at DS:address, when the size isn't given the type of the
+    // variable is used for [var] and a byte is read for everything else
+    Memory(Option<VariableType>, Box<Expression>),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    BitwiseNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOperator {
+    fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "||" => Some(BinaryOperator::Or),
+            "&&" => Some(BinaryOperator::And),
+            "|" => Some(BinaryOperator::BitwiseOr),
+            "^" => Some(BinaryOperator::BitwiseXor),
+            "&" => Some(BinaryOperator::BitwiseAnd),
+            "==" => Some(BinaryOperator::Equal),
+            "!=" => Some(BinaryOperator::NotEqual),
+            "<" => Some(BinaryOperator::Less),
+            "<=" => Some(BinaryOperator::LessOrEqual),
+            ">" => Some(BinaryOperator::Greater),
+            ">=" => Some(BinaryOperator::GreaterOrEqual),
+            "+" => Some(BinaryOperator::Add),
+            "-" => Some(BinaryOperator::Subtract),
+            "*" => Some(BinaryOperator::Multiply),
+            "/" => Some(BinaryOperator::Divide),
+            "%" => Some(BinaryOperator::Remainder),
+            _ => None,
+        }
+    }
+
+    // same ordering as C, higher binds tighter
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::BitwiseOr => 3,
+            BinaryOperator::BitwiseXor => 4,
+            BinaryOperator::BitwiseAnd => 5,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 6,
+            BinaryOperator::Less
+            | BinaryOperator::LessOrEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterOrEqual => 7,
+            BinaryOperator::Add | BinaryOperator::Subtract => 8,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 9,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ExpressionToken {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 22] = [
+    "||", "&&", "==", "!=", "<=", ">=", "|", "^", "&", "<", ">", "+", "-", "*", "/", "%", "!", "~",
+    "(", ")", "[", "]",
+];
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '@' | '.' | '?' | '$')
+}
+
+fn tokenize(source: &str) -> Result<Vec<ExpressionToken>, String> {
+    let chars = source.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if is_name_char(c) {
+            let name = chars[i..]
+                .iter()
+                .take_while(|c| is_name_char(**c))
+                .collect::<String>();
+            i += name.chars().count();
+            if c.is_ascii_digit() {
+                tokens.push(ExpressionToken::Number(parse_number(&name)?));
+            } else {
+                tokens.push(ExpressionToken::Name(name));
+            }
+            continue;
+        }
+        let symbol = SYMBOLS.iter().find(|symbol| {
+            symbol
+                .chars()
+                .enumerate()
+                .all(|(j, symbol_char)| chars.get(i + j) == Some(&symbol_char))
+        });
+        match symbol {
+            Some(symbol) => {
+                tokens.push(ExpressionToken::Symbol(symbol));
+                i += symbol.len();
+            }
+            None => return Err(format!("Unexpected character \"{}\" in the expression.", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+// the same number formats the assembler accepts i.e 16, 0x10, 10h, 10000b, 20o
+fn parse_number(token_string: &str) -> Result<i64, String> {
+    let number = token_string.to_lowercase().replace('_', "");
+    let parsed = if let Some(stripped) = number.strip_prefix("0x") {
+        i64::from_str_radix(stripped, 16)
+    } else if let Some(stripped) = number.strip_suffix('h') {
+        i64::from_str_radix(stripped, 16)
+    } else if let Some(stripped) = number.strip_suffix('b') {
+        i64::from_str_radix(stripped, 2)
+    } else if let Some(stripped) = number.strip_suffix('o') {
+        i64::from_str_radix(stripped, 8)
+    } else {
+        number.parse::<i64>()
+    };
+    parsed.map_err(|_| format!("\"{}\" is not a valid number.", token_string))
+}
+
+struct Parser {
+    tokens: Vec<ExpressionToken>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&ExpressionToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<ExpressionToken> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next() {
+            Some(ExpressionToken::Symbol(s)) if s == symbol => Ok(()),
+            _ => Err(format!("Expected \"{}\" in the expression.", symbol)),
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expression, String> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(ExpressionToken::Symbol(symbol)) = self.peek() {
+            let operator = match BinaryOperator::from_symbol(symbol) {
+                Some(operator) if operator.precedence() >= min_precedence => operator,
+                _ => break,
+            };
+            self.next();
+            let rhs = self.parse_binary(operator.precedence() + 1)?;
+            lhs = Expression::Binary(operator, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        let operator = match self.peek() {
+            Some(ExpressionToken::Symbol("-")) => UnaryOperator::Negate,
+            Some(ExpressionToken::Symbol("!")) => UnaryOperator::Not,
+            Some(ExpressionToken::Symbol("~")) => UnaryOperator::BitwiseNot,
+            _ => return self.parse_primary(),
+        };
+        self.next();
+        Ok(Expression::Unary(operator, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_memory(&mut self, size: Option<VariableType>) -> Result<Expression, String> {
+        self.expect("[")?;
+        let address = self.parse_binary(1)?;
+        self.expect("]")?;
+        Ok(Expression::Memory(size, Box::new(address)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(ExpressionToken::Number(number)) => Ok(Expression::Number(number)),
+            Some(ExpressionToken::Symbol("(")) => {
+                let expression = self.parse_binary(1)?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Some(ExpressionToken::Symbol("[")) => {
+                self.position -= 1;
+                self.parse_memory(None)
+            }
+            Some(ExpressionToken::Name(name)) => {
+                let size = match name.to_lowercase().as_str() {
+                    "b." | "byte" => Some(VariableType::Byte),
+                    "w." | "word" => Some(VariableType::Word),
+                    "d." | "dword" => Some(VariableType::DWord),
+                    _ => None,
+                };
+                if let Some(size) = size {
+                    if matches!(self.peek(), Some(ExpressionToken::Name(ptr)) if ptr.eq_ignore_ascii_case("ptr"))
+                    {
+                        self.next();
+                    }
+                    return self.parse_memory(Some(size));
+                }
+                if let Ok(register) = Register::from_str(&name) {
+                    return Ok(Expression::Register(register));
+                }
+                if let Ok(flag) = Flag::from_str(&name) {
+                    return Ok(Expression::Flag(flag));
+                }
+                Ok(Expression::Variable(UniCase::new(name)))
+            }
+            Some(ExpressionToken::Symbol(symbol)) => {
+                Err(format!("Unexpected \"{}\" in the expression.", symbol))
+            }
+            None => Err("Unexpected end of the expression.".to_string()),
+        }
+    }
+}
+
+pub struct EvaluationContext<'a> {
+    pub cpu: &'a CPU,
+    pub mem: &'a Memory,
+    pub variables: &'a VariableAddressMap,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let expression = parser.parse_binary(1)?;
+        match parser.next() {
+            None => Ok(expression),
+            Some(_) => Err("Unexpected tokens at the end of the expression.".to_string()),
+        }
+    }
+
+    // every variable the expression refers to
+    pub fn get_variables(&self) -> Vec<&Label> {
+        match self {
+            Expression::Number(_) | Expression::Register(_) | Expression::Flag(_) => vec![],
+            Expression::Variable(label) => vec![label],
+            Expression::Memory(_, expression) | Expression::Unary(_, expression) => {
+                expression.get_variables()
+            }
+            Expression::Binary(_, lhs, rhs) => {
+                let mut variables = lhs.get_variables();
+                variables.extend(rhs.get_variables());
+                variables
+            }
+        }
+    }
+
+    pub fn evaluate(&self, context: &EvaluationContext) -> Result<i64, String> {
+        match self {
+            Expression::Number(number) => Ok(*number),
+            Expression::Register(register) => Ok(context.cpu.get_register(*register) as i64),
+            Expression::Flag(flag) => Ok(context.cpu.get_flag(*flag) as i64),
+            Expression::Variable(label) => match context.variables.get(label) {
+                Some((_, offset, _)) => Ok(*offset as i64),
+                None => Err(format!("The variable \"{}\" is not defined.", label)),
+            },
+            Expression::Memory(size, address) => {
+                let size = match (size, address.as_ref()) {
+                    (Some(size), _) => *size,
+                    (None, Expression::Variable(label)) => match context.variables.get(label) {
+                        Some((var_type, _, _)) => *var_type,
+                        None => return Err(format!("The variable \"{}\" is not defined.", label)),
+                    },
+                    (None, _) => VariableType::Byte,
+                };
+                let offset = address.evaluate(context)? as Word;
+                let segment = context.cpu.get_register(Register::DS);
+                // little endian, the values wider than 64 bits are cut
+                let bytes = size.get_size().min(8);
+                Ok((0..bytes).rev().fold(0, |value, i| {
+                    (value << 8) | context.mem.peek_byte(segment, offset.wrapping_add(i)) as i64
+                }))
+            }
+            Expression::Unary(operator, expression) => {
+                let value = expression.evaluate(context)?;
+                Ok(match operator {
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::Not => (value == 0) as i64,
+                    UnaryOperator::BitwiseNot => !value,
+                })
+            }
+            Expression::Binary(BinaryOperator::Or, lhs, rhs) => {
+                Ok((lhs.evaluate(context)? != 0 || rhs.evaluate(context)? != 0) as i64)
+            }
+            Expression::Binary(BinaryOperator::And, lhs, rhs) => {
+                Ok((lhs.evaluate(context)? != 0 && rhs.evaluate(context)? != 0) as i64)
+            }
+            Expression::Binary(operator, lhs, rhs) => {
+                let lhs = lhs.evaluate(context)?;
+                let rhs = rhs.evaluate(context)?;
+                Ok(match operator {
+                    BinaryOperator::BitwiseOr => lhs | rhs,
+                    BinaryOperator::BitwiseXor => lhs ^ rhs,
+                    BinaryOperator::BitwiseAnd => lhs & rhs,
+                    BinaryOperator::Equal => (lhs == rhs) as i64,
+                    BinaryOperator::NotEqual => (lhs != rhs) as i64,
+                    BinaryOperator::Less => (lhs < rhs) as i64,
+                    BinaryOperator::LessOrEqual => (lhs <= rhs) as i64,
+                    BinaryOperator::Greater => (lhs > rhs) as i64,
+                    BinaryOperator::GreaterOrEqual => (lhs >= rhs) as i64,
+                    BinaryOperator::Add => lhs.wrapping_add(rhs),
+                    BinaryOperator::Subtract => lhs.wrapping_sub(rhs),
+                    BinaryOperator::Multiply => lhs.wrapping_mul(rhs),
+                    BinaryOperator::Divide | BinaryOperator::Remainder if rhs == 0 => {
+                        return Err("Division by zero in the expression.".to_string())
+                    }
+                    BinaryOperator::Divide => lhs.wrapping_div(rhs),
+                    BinaryOperator::Remainder => lhs.wrapping_rem(rhs),
+                    BinaryOperator::Or | BinaryOperator::And => unreachable!(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unicase::UniCase;
+
+    use crate::{
+        compiler::types_structs::{VariableAddressMap, VariableSize, VariableType},
+        cpu::{registers::Register, CPU},
+        memory::Memory,
+    };
+
+    use super::{BinaryOperator, EvaluationContext, Expression};
+
+    fn evaluate(source: &str, cpu: &CPU, mem: &Memory) -> Result<i64, String> {
+        let mut variables = VariableAddressMap::new();
+        variables.insert(
+            UniCase::new("var1".to_string()),
+            (VariableType::Byte, 0x10, VariableSize::default()),
+        );
+        variables.insert(
+            UniCase::new("var2".to_string()),
+            (VariableType::Word, 0x11, VariableSize::default()),
+        );
+        let context = EvaluationContext {
+            cpu,
+            mem,
+            variables: &variables,
+        };
+        Expression::parse(source)?.evaluate(&context)
+    }
+
+    #[test]
+    fn operators_follow_c_precedence() {
+        let cpu = CPU::new();
+        let mem = Memory::new();
+        assert_eq!(evaluate("1 + 2 * 3", &cpu, &mem), Ok(7));
+        assert_eq!(evaluate("(1 + 2) * 3", &cpu, &mem), Ok(9));
+        assert_eq!(evaluate("10h - 2 - 3", &cpu, &mem), Ok(11));
+        assert_eq!(evaluate("1 | 2 == 2", &cpu, &mem), Ok(1));
+        assert_eq!(evaluate("!0 && -1 < 0", &cpu, &mem), Ok(1));
+        assert_eq!(
+            Expression::parse("ax == 1 || bx"),
+            Ok(Expression::Binary(
+                BinaryOperator::Or,
+                Box::new(Expression::Binary(
+                    BinaryOperator::Equal,
+                    Box::new(Expression::Register(Register::AX)),
+                    Box::new(Expression::Number(1))
+                )),
+                Box::new(Expression::Register(Register::BX))
+            ))
+        );
+    }
+
+    #[test]
+    fn registers_flags_and_variables() {
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        cpu.set_register(Register::AX, 0x1234);
+        mem.write_byte(0x100, 0x10, 0x05);
+        mem.write_word(0x100, 0x11, 0xABCD);
+
+        assert_eq!(evaluate("ax == 0x1234 && al == 34h", &cpu, &mem), Ok(1));
+        assert_eq!(evaluate("zf", &cpu, &mem), Ok(0));
+        assert_eq!(evaluate("var2", &cpu, &mem), Ok(0x11));
+        assert_eq!(evaluate("[var1] > 3", &cpu, &mem), Ok(1));
+        assert_eq!(evaluate("[VAR2]", &cpu, &mem), Ok(0xABCD));
+        assert_eq!(evaluate("[var2 + 1]", &cpu, &mem), Ok(0xAB));
+        assert_eq!(evaluate("w.[0x10]", &cpu, &mem), Ok(0xCD05));
+        assert_eq!(evaluate("word ptr [var1]", &cpu, &mem), Ok(0xCD05));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let cpu = CPU::new();
+        let mem = Memory::new();
+        assert!(evaluate("ax ==", &cpu, &mem).is_err());
+        assert!(evaluate("(ax", &cpu, &mem).is_err());
+        assert!(evaluate("ax # 1", &cpu, &mem).is_err());
+        assert!(evaluate("0xZZ", &cpu, &mem).is_err());
+        assert!(evaluate("ax / 0", &cpu, &mem).is_err());
+        assert!(evaluate("[unknown] > 1", &cpu, &mem).is_err());
+    }
+}
@@ -0,0 +1,1472 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use unicase::UniCase;
+
+use crate::{
+    compiler::{
+        includes::MAIN_FILE_ID,
+        types_structs::{CompiledProgram, SegmentLayout, VariableAddressMap},
+    },
+    consts::{Byte, Word},
+    cpu::{fault::Fault, interrupt::Interrupt, registers::Register, CPU},
+    disassembler::{disassemble_instruction, Symbols},
+    formats::get_start_offset,
+    loader::{load_binary, load_segments, BinaryKind},
+    memory::{Memory, MemoryAccess, MemoryAccessKind},
+};
+
+use self::{
+    expression::{EvaluationContext, Expression},
+    timeline::Timeline,
+    tracer::{get_port_write, PortAccess, TraceFormat, Tracer},
+};
+
+pub mod expression;
+pub mod timeline;
+pub mod tracer;
+
+pub const DEFAULT_INSTRUCTION_BUDGET: usize = 100_000;
+
+// CALL, CALL FAR, INT 3, INT and INTO, the CALLs through FF are checked by is_call
+const CALL_OPCODES: [Byte; 5] = [0xE8, 0x9A, 0xCC, 0xCD, 0xCE];
+// RET 0x04, RET, RETF 0x04, RETF and IRET
+const RET_OPCODES: [Byte; 5] = [0xC2, 0xC3, 0xCA, 0xCB, 0xCF];
+const HLT_OPCODE: Byte = 0xF4;
+
+type SourceLine = u32;
+type Address = (Word, Word); // i.e segment, offset
+pub type WatchpointId = u32;
+pub type MemoryChanges = Vec<(usize, Byte)>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum StopReason {
+    // stopped before executing the instruction of the breakpoint line
+    Breakpoint(SourceLine),
+    // stopped after the instruction that triggered the watchpoint
+    Watchpoint(WatchpointHit),
+    // the requested step finished
+    StepComplete,
+    Halted,
+    // the program terminated with the given return code
+    Exited(Byte),
+    // the program waits for a key, continuing after pushing one reads it
+    WaitingForInput,
+    Fault(Fault),
+    // stepping back reached the oldest recorded instruction
+    HistoryStart,
+    // ran for the whole instruction budget without stopping
+    BudgetExhausted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum WatchpointHit {
+    Memory {
+        id: WatchpointId,
+        access: MemoryAccess,
+    },
+    Register {
+        id: WatchpointId,
+        register: Register,
+        old_value: Word,
+        new_value: Word,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchTarget {
+    // `length` bytes starting from segment:offset
+    Memory {
+        segment: Word,
+        offset: Word,
+        length: Word,
+    },
+    // the bytes of the variable in the data segment
+    Variable(String),
+    // fires whenever the value of the register changes, the access kind is ignored
+    Register(Register),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(&self, access_kind: MemoryAccessKind) -> bool {
+        match self {
+            WatchKind::Read => access_kind == MemoryAccessKind::Read,
+            WatchKind::Write => access_kind == MemoryAccessKind::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watchpoint {
+    pub target: WatchTarget,
+    pub kind: WatchKind,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Breakpoint {
+    // the source is kept around to show it back to the user
+    condition: Option<(String, Expression)>,
+    // the breakpoint only stops once it has been hit this many times
+    hit_count: Option<u32>,
+    hits: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BreakpointInfo {
+    pub line: SourceLine,
+    pub condition: Option<String>,
+    pub hit_count: Option<u32>,
+    pub hits: u32,
+}
+
+// what gets written into the memory every time the program is started
+#[derive(Debug, Clone)]
+enum ProgramImage {
+    Assembled {
+        bytes: Vec<u8>,
+        is_org_defined: bool,
+        segment_layout: Option<SegmentLayout>,
+        entry_point: Option<Word>,
+    },
+    Binary {
+        bytes: Vec<u8>,
+        command_tail: String,
+    },
+}
+
+impl Default for ProgramImage {
+    fn default() -> Self {
+        ProgramImage::Assembled {
+            bytes: Vec::new(),
+            is_org_defined: false,
+            segment_layout: None,
+            entry_point: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    program: ProgramImage,
+    variables: VariableAddressMap,
+    symbols: Symbols,
+
+    line_addresses: BTreeMap<SourceLine, Address>,
+    address_lines: HashMap<Address, SourceLine>,
+    breakpoints: HashMap<Address, Breakpoint>,
+
+    watchpoints: BTreeMap<WatchpointId, Watchpoint>,
+    next_watchpoint_id: WatchpointId,
+
+    timeline: Timeline,
+    // only Some while tracing, so the untraced path stays a single check
+    tracer: Option<Tracer>,
+    // the writes of OUT, only Some while they are recorded
+    port_writes: Option<Vec<PortAccess>>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // resets the cpu and writes the program at the start of the code segment,
+    // the line to address mapping is calculated from where the program got written
+    pub fn load_program(&mut self, cpu: &mut CPU, mem: &mut Memory, program: &CompiledProgram) {
+        self.program = ProgramImage::Assembled {
+            bytes: program.bytes.clone(),
+            is_org_defined: program.is_org_defined,
+            segment_layout: program.segment_layout.clone(),
+            entry_point: program.entry_point,
+        };
+        self.variables = program.variable_address_map.clone();
+        self.symbols = Symbols::from_program(program);
+        self.load_into(cpu, mem);
+
+        // breakpoints of the previous program might not exist anymore
+        let breakpoints = self
+            .breakpoints
+            .drain()
+            .filter_map(|(address, breakpoint)| {
+                let line = self.address_lines.get(&address)?;
+                Some((*line, breakpoint))
+            })
+            .collect::<Vec<(SourceLine, Breakpoint)>>();
+
+        self.line_addresses.clear();
+        self.address_lines.clear();
+        let addresses = get_compiled_bytes_addresses(
+            program,
+            (cpu.get_code_segment(), get_start_offset(program)),
+        );
+        for (compiled_bytes, address) in program.compiled_bytes_ref.iter().zip(addresses) {
+            // the breakpoints are on the lines of the editor, the included files aren't in it
+            if compiled_bytes.file_id != MAIN_FILE_ID {
+                continue;
+            }
+            self.line_addresses
+                .entry(compiled_bytes.line_number)
+                .or_insert(address);
+            self.address_lines
+                .entry(address)
+                .or_insert(compiled_bytes.line_number);
+            // a breakpoint on a macro call stops at the start of its expansion
+            if let Some(call_site) = compiled_bytes
+                .call_site
+                .as_ref()
+                .filter(|call_site| call_site.file_id == MAIN_FILE_ID)
+            {
+                self.line_addresses
+                    .entry(call_site.line_number)
+                    .or_insert(address);
+            }
+        }
+
+        for (line, breakpoint) in breakpoints {
+            if let Some(address) = self.get_breakpoint_address(line) {
+                self.breakpoints.insert(
+                    address,
+                    Breakpoint {
+                        hits: 0,
+                        ..breakpoint
+                    },
+                );
+            }
+        }
+    }
+
+    // a .COM or .EXE binary, there is no source code so the breakpoints and symbols are dropped
+    pub fn load_binary(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        bytes: &[u8],
+        command_tail: &str,
+    ) -> Result<BinaryKind, String> {
+        let kind = load_binary(cpu, mem, bytes, command_tail)?;
+        self.program = ProgramImage::Binary {
+            bytes: bytes.to_vec(),
+            command_tail: command_tail.to_string(),
+        };
+        self.variables.clear();
+        self.symbols = Symbols::default();
+        self.line_addresses.clear();
+        self.address_lines.clear();
+        self.breakpoints.clear();
+        self.timeline.clear();
+        Ok(kind)
+    }
+
+    fn load_into(&mut self, cpu: &mut CPU, mem: &mut Memory) {
+        match &self.program {
+            ProgramImage::Assembled {
+                bytes,
+                is_org_defined,
+                segment_layout,
+                entry_point,
+            } => {
+                cpu.reset(mem);
+                if *is_org_defined {
+                    cpu.set_org_defined();
+                }
+                match segment_layout {
+                    Some(segment_layout) => load_segments(cpu, mem, bytes, segment_layout),
+                    None => cpu.write_instructions(mem, bytes),
+                }
+                if let Some(entry_point) = entry_point {
+                    cpu.set_register(Register::IP, *entry_point);
+                }
+            }
+            ProgramImage::Binary {
+                bytes,
+                command_tail,
+            } => {
+                // it got loaded once already, so it can't fail
+                let _ = load_binary(cpu, mem, bytes, command_tail);
+            }
+        }
+        for breakpoint in self.breakpoints.values_mut() {
+            breakpoint.hits = 0;
+        }
+        self.timeline.clear();
+        if self.tracer.is_some() {
+            self.tracer = Some(Tracer::default());
+        }
+    }
+
+    // A breakpoint on a line without any instructions is moved to the next line that has one
+    fn get_breakpoint_address(&self, line: SourceLine) -> Option<Address> {
+        self.line_addresses
+            .range(line..)
+            .next()
+            .map(|(_, address)| *address)
+    }
+
+    // replaces the breakpoints with the given lines, the conditions of the lines that
+    // still have a breakpoint are kept. returns the lines the breakpoints ended up on
+    pub fn set_breakpoints(&mut self, lines: &[SourceLine]) -> Vec<SourceLine> {
+        let mut breakpoints = HashMap::new();
+        for line in lines {
+            if let Some(address) = self.get_breakpoint_address(*line) {
+                let breakpoint = self.breakpoints.remove(&address).unwrap_or_default();
+                breakpoints.insert(address, breakpoint);
+            }
+        }
+        self.breakpoints = breakpoints;
+        self.get_breakpoint_lines()
+    }
+
+    // adds a breakpoint that only stops when the condition is true and it has been hit `hit_count` times
+    pub fn set_conditional_breakpoint(
+        &mut self,
+        line: SourceLine,
+        condition: Option<&str>,
+        hit_count: Option<u32>,
+    ) -> Result<SourceLine, String> {
+        let address = match self.get_breakpoint_address(line) {
+            Some(address) => address,
+            None => {
+                return Err(format!(
+                    "There are no instructions on or after line {}.",
+                    line + 1
+                ))
+            }
+        };
+        let condition = match condition.map(str::trim) {
+            Some(source) if !source.is_empty() => {
+                let expression = Expression::parse(source)?;
+                if let Some(variable) = expression
+                    .get_variables()
+                    .into_iter()
+                    .find(|variable| !self.variables.contains_key(*variable))
+                {
+                    return Err(format!("The variable \"{}\" is not defined.", variable));
+                }
+                Some((source.to_string(), expression))
+            }
+            _ => None,
+        };
+        self.breakpoints.insert(
+            address,
+            Breakpoint {
+                condition,
+                hit_count,
+                hits: 0,
+            },
+        );
+        Ok(self.address_lines[&address])
+    }
+
+    pub fn get_breakpoint_lines(&self) -> Vec<SourceLine> {
+        let mut lines = self
+            .breakpoints
+            .keys()
+            .filter_map(|address| self.address_lines.get(address).copied())
+            .collect::<Vec<SourceLine>>();
+        lines.sort();
+        lines
+    }
+
+    pub fn get_breakpoints(&self) -> Vec<BreakpointInfo> {
+        let mut breakpoints = self
+            .breakpoints
+            .iter()
+            .filter_map(|(address, breakpoint)| {
+                Some(BreakpointInfo {
+                    line: *self.address_lines.get(address)?,
+                    condition: breakpoint
+                        .condition
+                        .as_ref()
+                        .map(|(source, _)| source.clone()),
+                    hit_count: breakpoint.hit_count,
+                    hits: breakpoint.hits,
+                })
+            })
+            .collect::<Vec<BreakpointInfo>>();
+        breakpoints.sort_by_key(|breakpoint| breakpoint.line);
+        breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> Result<WatchpointId, String> {
+        match &watchpoint.target {
+            WatchTarget::Memory { length: 0, .. } => {
+                return Err("The watched memory range can't be empty.".to_string())
+            }
+            WatchTarget::Variable(name)
+                if !self.variables.contains_key(&UniCase::new(name.clone())) =>
+            {
+                return Err(format!("The variable \"{}\" is not defined.", name))
+            }
+            _ => (),
+        }
+        let id = self.next_watchpoint_id;
+        self.next_watchpoint_id += 1;
+        self.watchpoints.insert(id, watchpoint);
+        Ok(id)
+    }
+
+    pub fn remove_watchpoint(&mut self, id: WatchpointId) -> bool {
+        self.watchpoints.remove(&id).is_some()
+    }
+
+    pub fn get_watchpoints(&self) -> Vec<(WatchpointId, Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|(id, watchpoint)| (*id, watchpoint.clone()))
+            .collect()
+    }
+
+    // how many steps can be stepped back without replaying from a checkpoint
+    pub fn set_history_depth(&mut self, mem: &mut Memory, depth: usize) {
+        self.timeline.set_depth(depth);
+        mem.set_history_depth(depth);
+    }
+
+    pub fn set_checkpoint_interval(&mut self, interval: usize) {
+        self.timeline.set_checkpoint_interval(interval);
+    }
+
+    pub fn get_symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    // turning tracing off drops the records
+    pub fn set_tracing(&mut self, is_tracing: bool) {
+        match (is_tracing, &self.tracer) {
+            (true, None) => self.tracer = Some(Tracer::default()),
+            (false, _) => self.tracer = None,
+            (true, Some(_)) => {}
+        }
+    }
+
+    // records what OUT writes from now on, without tracing every instruction
+    pub fn record_port_writes(&mut self) {
+        self.port_writes = Some(Vec::new());
+    }
+
+    pub fn get_port_writes(&self) -> &[PortAccess] {
+        self.port_writes.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn get_trace(&self, format: TraceFormat) -> Option<String> {
+        self.tracer.as_ref().map(|tracer| tracer.write(format))
+    }
+
+    pub fn get_line(&self, cpu: &CPU) -> Option<SourceLine> {
+        self.address_lines.get(&get_address(cpu)).copied()
+    }
+
+    // the address of the breakpoint at CS:IP when its condition holds
+    fn get_breakpoint_address_if_condition_met(&self, cpu: &CPU, mem: &Memory) -> Option<Address> {
+        let address = get_address(cpu);
+        let breakpoint = self.breakpoints.get(&address)?;
+        if let Some((_, condition)) = &breakpoint.condition {
+            let context = EvaluationContext {
+                cpu,
+                mem,
+                variables: &self.variables,
+            };
+            // a condition that can't be evaluated stops so the user can see what went wrong
+            if condition.evaluate(&context).is_ok_and(|value| value == 0) {
+                return None;
+            }
+        }
+        Some(address)
+    }
+
+    // counts the hit and checks the condition of the breakpoint at CS:IP
+    fn hit_breakpoint(&mut self, cpu: &CPU, mem: &Memory) -> Option<SourceLine> {
+        let address = self.get_breakpoint_address_if_condition_met(cpu, mem)?;
+        let breakpoint = self.breakpoints.get_mut(&address)?;
+        breakpoint.hits += 1;
+        if breakpoint.hit_count.unwrap_or(0) > breakpoint.hits {
+            return None;
+        }
+        self.address_lines.get(&address).copied()
+    }
+
+    fn get_watched_range(&self, target: &WatchTarget, cpu: &CPU) -> Option<(usize, usize)> {
+        match target {
+            WatchTarget::Memory {
+                segment,
+                offset,
+                length,
+            } => {
+                let start = (*segment as usize) * 0x10 + (*offset as usize);
+                Some((start, start + (*length as usize)))
+            }
+            WatchTarget::Variable(name) => {
+                let (var_type, offset, _) = self.variables.get(&UniCase::new(name.clone()))?;
+                let start = (cpu.get_register(Register::DS) as usize) * 0x10 + (*offset as usize);
+                Some((start, start + var_type.get_size() as usize))
+            }
+            WatchTarget::Register(_) => None,
+        }
+    }
+
+    fn get_watched_register_values(&self, cpu: &CPU) -> Vec<(WatchpointId, Register, Word)> {
+        self.watchpoints
+            .iter()
+            .filter_map(|(id, watchpoint)| match watchpoint.target {
+                WatchTarget::Register(register) => {
+                    Some((*id, register, cpu.get_register(register)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn get_watchpoint_hit(
+        &self,
+        cpu: &CPU,
+        accesses: &[MemoryAccess],
+        register_values: &mut [(WatchpointId, Register, Word)],
+    ) -> Option<WatchpointHit> {
+        for (id, register, old_value) in register_values.iter_mut() {
+            let new_value = cpu.get_register(*register);
+            if new_value != *old_value {
+                let hit = WatchpointHit::Register {
+                    id: *id,
+                    register: *register,
+                    old_value: *old_value,
+                    new_value,
+                };
+                *old_value = new_value;
+                return Some(hit);
+            }
+        }
+
+        self.get_memory_watchpoint_hit(cpu, accesses)
+    }
+
+    fn get_memory_watchpoint_hit(
+        &self,
+        cpu: &CPU,
+        accesses: &[MemoryAccess],
+    ) -> Option<WatchpointHit> {
+        for access in accesses {
+            for (id, watchpoint) in &self.watchpoints {
+                if !watchpoint.kind.matches(access.kind) {
+                    continue;
+                }
+                if let Some((start, end)) = self.get_watched_range(&watchpoint.target, cpu) {
+                    if (start..end).contains(&access.address) {
+                        return Some(WatchpointHit::Memory {
+                            id: *id,
+                            access: *access,
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// the address every compiled bytes is written at, a program that is a single segment
+// starts at the offset it's assembled for and the segments have their bytes one after another
+fn get_compiled_bytes_addresses(program: &CompiledProgram, code_address: Address) -> Vec<Address> {
+    let (code_segment, mut offset) = code_address;
+    let segment_layout = match &program.segment_layout {
+        Some(segment_layout) => segment_layout,
+        None => {
+            return program
+                .compiled_bytes_ref
+                .iter()
+                .map(|compiled_bytes| {
+                    let address = (code_segment, offset);
+                    offset = offset.wrapping_add(compiled_bytes.bytes.len() as Word);
+                    address
+                })
+                .collect()
+        }
+    };
+    let segments = &segment_layout.segments;
+    let mut idx = 0;
+    let mut offset = 0;
+    program
+        .compiled_bytes_ref
+        .iter()
+        .map(|compiled_bytes| {
+            // the bytes of the next segment start once the segment is full
+            while offset >= segments[idx].size && idx + 1 < segments.len() {
+                idx += 1;
+                offset = 0;
+            }
+            let segment = segment_layout
+                .load_segment
+                .wrapping_add(segments[idx].segment);
+            let address = (segment, offset as Word);
+            offset += compiled_bytes.bytes.len();
+            address
+        })
+        .collect()
+}
+
+fn get_address(cpu: &CPU) -> Address {
+    (cpu.get_code_segment(), cpu.get_instruciton_pointer())
+}
+
+fn peek_opcode(cpu: &CPU, mem: &Memory) -> Byte {
+    mem.peek_byte(cpu.get_code_segment(), cpu.get_instruciton_pointer())
+}
+
+// CALL BX (or) CALL [BX] is FF /2 and CALL FAR [BX] is FF /3
+fn is_call(cpu: &CPU, mem: &Memory) -> bool {
+    let (segment, offset) = get_address(cpu);
+    match mem.peek_byte(segment, offset) {
+        0xFF => matches!(
+            (mem.peek_byte(segment, offset.wrapping_add(1)) >> 3) & 0x07,
+            2 | 3
+        ),
+        opcode => CALL_OPCODES.contains(&opcode),
+    }
+}
+
+// execution commands
+impl Debugger {
+    // executes a single instruction and records it so it can be stepped back
+    pub fn execute_instruction(&mut self, cpu: &mut CPU, mem: &mut Memory) -> Option<Interrupt> {
+        self.timeline.record(cpu, mem);
+        if let Some(port_writes) = &mut self.port_writes {
+            port_writes.extend(get_port_write(cpu, mem));
+        }
+        let tracer = match &mut self.tracer {
+            Some(tracer) => tracer,
+            None => return cpu.execute(mem),
+        };
+        let pending = tracer.begin(cpu, mem, Some(&self.symbols));
+        let interrupt = cpu.execute(mem);
+        tracer.end(pending, cpu, mem);
+        interrupt
+    }
+
+    // restarts the loaded program and runs it until something stops it
+    pub fn run(&mut self, cpu: &mut CPU, mem: &mut Memory, budget: usize) -> (StopReason, String) {
+        self.load_into(cpu, mem);
+        self.execute_until(cpu, mem, budget, true, |_, _| false)
+    }
+
+    pub fn continue_execution(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        budget: usize,
+    ) -> (StopReason, String) {
+        self.execute_until(cpu, mem, budget, false, |_, _| false)
+    }
+
+    // executes a single instruction, CALL and INT are executed until they return,
+    // the instruction is decoded to know where they return to
+    pub fn step_over(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        budget: usize,
+    ) -> (StopReason, String) {
+        if !is_call(cpu, mem) {
+            return self.execute_until(cpu, mem, budget, false, |_, _| true);
+        }
+        let (code_segment, offset) = get_address(cpu);
+        let instruction = disassemble_instruction(mem, code_segment, offset, None);
+        let return_offset = offset.wrapping_add(instruction.length as Word);
+        let stack_pointer = cpu.get_stack_pointer();
+        self.execute_until(cpu, mem, budget, false, |_, cpu| {
+            cpu.get_code_segment() == code_segment
+                && cpu.get_instruciton_pointer() == return_offset
+                && cpu.get_stack_pointer() >= stack_pointer
+        })
+    }
+
+    // runs until the procedure that is currently executing returns
+    pub fn step_out(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        budget: usize,
+    ) -> (StopReason, String) {
+        let stack_pointer = cpu.get_stack_pointer();
+        self.execute_until(cpu, mem, budget, false, |opcode, cpu| {
+            RET_OPCODES.contains(&opcode) && cpu.get_stack_pointer() > stack_pointer
+        })
+    }
+
+    fn execute_until(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        budget: usize,
+        stop_on_first_breakpoint: bool,
+        is_done: impl Fn(Byte, &CPU) -> bool,
+    ) -> (StopReason, String) {
+        // memory accesses are only recorded when they are being watched
+        let is_watching_memory = self
+            .watchpoints
+            .values()
+            .any(|watchpoint| !matches!(watchpoint.target, WatchTarget::Register(_)));
+        mem.set_access_recording(is_watching_memory);
+        let result = self.execute_steps(cpu, mem, budget, stop_on_first_breakpoint, is_done);
+        mem.set_access_recording(false);
+        result
+    }
+
+    fn execute_steps(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        budget: usize,
+        stop_on_first_breakpoint: bool,
+        is_done: impl Fn(Byte, &CPU) -> bool,
+    ) -> (StopReason, String) {
+        let mut output = String::new();
+        let mut register_values = self.get_watched_register_values(cpu);
+        for i in 0..budget {
+            // a breakpoint we are already sitting on shouldn't stop us again
+            if i > 0 || stop_on_first_breakpoint {
+                if let Some(line) = self.hit_breakpoint(cpu, mem) {
+                    return (StopReason::Breakpoint(line), output);
+                }
+            }
+
+            let opcode = peek_opcode(cpu, mem);
+            match self.execute_instruction(cpu, mem) {
+                Some(Interrupt::Print(s)) => output.push_str(&s),
+                Some(Interrupt::Exit(code)) => return (StopReason::Exited(code), output),
+                Some(Interrupt::Read) => return (StopReason::WaitingForInput, output),
+                None => {}
+            }
+
+            if let Some(fault) = cpu.take_fault() {
+                return (StopReason::Fault(fault), output);
+            }
+            let accesses = mem.take_accesses();
+            if let Some(hit) = self.get_watchpoint_hit(cpu, &accesses, &mut register_values) {
+                return (StopReason::Watchpoint(hit), output);
+            }
+            if opcode == HLT_OPCODE {
+                return (StopReason::Halted, output);
+            }
+            if is_done(opcode, cpu) {
+                return (StopReason::StepComplete, output);
+            }
+        }
+        (StopReason::BudgetExhausted, output)
+    }
+}
+
+// time travel, the console output of undone instructions is not taken back
+impl Debugger {
+    // puts back the cpu and memory from before the last executed instruction,
+    // returns the writes that got undone
+    fn rewind_instruction(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        changes: &mut BTreeMap<usize, Byte>,
+    ) -> Option<Vec<MemoryAccess>> {
+        let record = match self.timeline.pop_step(mem) {
+            Some(record) => record,
+            None => {
+                self.replay_from_checkpoint(cpu, mem)?;
+                self.timeline.pop_step(mem)?
+            }
+        };
+        *cpu = record.cpu;
+        self.timeline.discard_checkpoints_after(record.memory_time);
+        let mut undone_writes = mem
+            .rewind_to(record.memory_time)
+            .into_iter()
+            .map(|(address, old_value, new_value)| {
+                changes.insert(address, old_value);
+                MemoryAccess {
+                    address,
+                    kind: MemoryAccessKind::Write,
+                    value: new_value,
+                }
+            })
+            .collect::<Vec<MemoryAccess>>();
+        // in the order the instruction made them
+        undone_writes.reverse();
+        Some(undone_writes)
+    }
+
+    // when the steps run out the last checkpoint is restored and the instructions since then
+    // are executed again, which brings back their step records
+    fn replay_from_checkpoint(&mut self, cpu: &mut CPU, mem: &mut Memory) -> Option<()> {
+        let current_time = mem.get_time();
+        let checkpoint = self.timeline.get_checkpoint_before(current_time)?;
+        *cpu = checkpoint.cpu;
+        mem.restore_snapshot(checkpoint.memory_time, &checkpoint.memory);
+        self.timeline.clear_steps();
+        // the replayed instructions are already in the trace
+        let tracer = self.tracer.take();
+        while mem.get_time() < current_time {
+            self.execute_instruction(cpu, mem);
+            cpu.take_fault();
+        }
+        self.tracer = tracer;
+        Some(())
+    }
+
+    pub fn step_back(&mut self, cpu: &mut CPU, mem: &mut Memory) -> (StopReason, MemoryChanges) {
+        let mut changes = BTreeMap::new();
+        let reason = match self.rewind_instruction(cpu, mem, &mut changes) {
+            Some(_) => StopReason::StepComplete,
+            None => StopReason::HistoryStart,
+        };
+        (reason, changes.into_iter().collect())
+    }
+
+    // steps back until a breakpoint or until an undone instruction triggers a watchpoint,
+    // only writes are recorded so read watchpoints don't fire going backwards, the hits
+    // of the breakpoints are taken back as their lines are undone
+    pub fn reverse_continue(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        budget: usize,
+    ) -> (StopReason, MemoryChanges) {
+        let mut changes = BTreeMap::new();
+        let reason = self.rewind_until_stopped(cpu, mem, budget, &mut changes);
+        (reason, changes.into_iter().collect())
+    }
+
+    fn rewind_until_stopped(
+        &mut self,
+        cpu: &mut CPU,
+        mem: &mut Memory,
+        budget: usize,
+        changes: &mut BTreeMap<usize, Byte>,
+    ) -> StopReason {
+        for _ in 0..budget {
+            let cpu_after = *cpu;
+            let left_breakpoint = self.get_breakpoint_address_if_condition_met(cpu, mem);
+            let undone_writes = match self.rewind_instruction(cpu, mem, changes) {
+                Some(undone_writes) => undone_writes,
+                None => return StopReason::HistoryStart,
+            };
+            if let Some(breakpoint) =
+                left_breakpoint.and_then(|address| self.breakpoints.get_mut(&address))
+            {
+                breakpoint.hits = breakpoint.hits.saturating_sub(1);
+            }
+
+            for (id, watchpoint) in &self.watchpoints {
+                if let WatchTarget::Register(register) = watchpoint.target {
+                    let old_value = cpu.get_register(register);
+                    let new_value = cpu_after.get_register(register);
+                    if old_value != new_value {
+                        return StopReason::Watchpoint(WatchpointHit::Register {
+                            id: *id,
+                            register,
+                            old_value,
+                            new_value,
+                        });
+                    }
+                }
+            }
+            if let Some(hit) = self.get_memory_watchpoint_hit(cpu, &undone_writes) {
+                return StopReason::Watchpoint(hit);
+            }
+            // the hit of the line we are back at is still counted
+            let is_hit = self
+                .get_breakpoint_address_if_condition_met(cpu, mem)
+                .and_then(|address| self.breakpoints.get(&address))
+                .is_some_and(|breakpoint| breakpoint.hit_count.unwrap_or(0) <= breakpoint.hits);
+            if is_hit {
+                if let Some(line) = self.get_line(cpu) {
+                    return StopReason::Breakpoint(line);
+                }
+            }
+        }
+        StopReason::BudgetExhausted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compiler::compile_program,
+        cpu::{fault::Fault, keyboard, registers::Register, CPU},
+        memory::{Memory, MemoryAccessKind},
+    };
+
+    use unicase::UniCase;
+
+    use super::{
+        Debugger, StopReason, WatchKind, WatchTarget, Watchpoint, WatchpointHit,
+        DEFAULT_INSTRUCTION_BUDGET,
+    };
+
+    fn load(code: &str) -> (Debugger, CPU, Memory) {
+        let program = compile_program(code, false).unwrap();
+        let mut debugger = Debugger::new();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+        (debugger, cpu, mem)
+    }
+
+    #[test]
+    fn breakpoint_on_empty_line_moves_to_next_instruction() {
+        let code = "mov ax, 0x10\n\n; comment\ninc ax\nhlt";
+        let (mut debugger, _, _) = load(code);
+        assert_eq!(debugger.set_breakpoints(&[1, 4]), vec![3, 4]);
+        assert_eq!(debugger.set_breakpoints(&[5]), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn breakpoint_on_macro_call_stops_at_its_expansion() {
+        let code = "inc_twice macro reg
+                inc reg
+                inc reg
+            endm
+            mov ax, 1
+            inc_twice ax
+            hlt";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        debugger.set_breakpoints(&[5]);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(1));
+        assert_eq!(cpu.get_ax_low(), 1);
+    }
+
+    #[test]
+    fn run_and_continue_stop_on_breakpoints() {
+        let code = "
+            mov cx, 0x03
+        l1:
+            inc ax
+            dec cx
+            jnz l1
+            hlt
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        debugger.set_breakpoints(&[3]);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 0);
+
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 1);
+
+        debugger.set_breakpoints(&[]);
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(cpu.get_ax_low(), 3);
+    }
+
+    #[test]
+    fn run_starts_at_the_label_of_end() {
+        let code = "
+            PROC double
+                add ax, ax
+                ret
+            ENDP double
+            main:
+                mov ax, 3
+                call double
+                hlt
+            END main
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        assert_eq!(cpu.get_instruciton_pointer(), 0x03);
+        debugger.set_breakpoints(&[7]);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(7));
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(cpu.get_ax_low(), 6);
+    }
+
+    #[test]
+    fn run_stops_when_budget_is_exhausted() {
+        let (mut debugger, mut cpu, mut mem) = load("l1: inc ax \n jmp l1");
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::BudgetExhausted);
+        assert_eq!(cpu.get_ax_low(), 5);
+    }
+
+    #[test]
+    fn step_over_and_out_of_procedures() {
+        let code = "
+            jmp start
+        PROC inc_twice
+            inc ax
+            inc ax
+            ret
+        ENDP inc_twice
+        start:
+            call inc_twice
+            inc bx
+            hlt
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        debugger.step_over(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(debugger.get_line(&cpu), Some(8));
+
+        let (reason, _) = debugger.step_over(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::StepComplete);
+        assert_eq!(debugger.get_line(&cpu), Some(9));
+        assert_eq!(cpu.get_ax_low(), 2);
+
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        debugger.step_over(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        // step into the procedure
+        cpu.execute(&mut mem);
+        assert_eq!(debugger.get_line(&cpu), Some(3));
+        let (reason, _) = debugger.step_out(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::StepComplete);
+        assert_eq!(debugger.get_line(&cpu), Some(9));
+        assert_eq!(cpu.get_ax_low(), 2);
+    }
+
+    #[test]
+    fn step_over_and_out_of_indirect_and_far_calls() {
+        // CALL BX, RET 2 and INTO are written as bytes
+        let code = "
+            jmp start
+        PROC add_two
+            add ax, 2
+            ret_2 db 0xC2, 0x02, 0x00
+        ENDP add_two
+        far_inc:
+            inc ax
+            retf
+        start:
+            push 0
+            mov bx, offset add_two
+            call_bx db 0xFF, 0xD3
+            call far ptr far_inc
+            into db 0xCE
+            hlt
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        for _ in 0..3 {
+            debugger.step_over(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        }
+        assert_eq!(debugger.get_line(&cpu), Some(12));
+        let stack_pointer = cpu.get_stack_pointer();
+        let (reason, _) = debugger.step_over(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::StepComplete);
+        assert_eq!(debugger.get_line(&cpu), Some(13));
+        assert_eq!(cpu.get_stack_pointer(), stack_pointer.wrapping_add(2));
+        debugger.step_over(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(debugger.get_line(&cpu), Some(14));
+        debugger.step_over(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(debugger.get_line(&cpu), Some(15));
+        assert_eq!(cpu.get_ax_low(), 3);
+
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        for _ in 0..3 {
+            debugger.step_over(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        }
+        // step into the procedures
+        cpu.execute(&mut mem);
+        assert_eq!(debugger.get_line(&cpu), Some(3));
+        let (reason, _) = debugger.step_out(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::StepComplete);
+        assert_eq!(debugger.get_line(&cpu), Some(13));
+        cpu.execute(&mut mem);
+        assert_eq!(debugger.get_line(&cpu), Some(7));
+        let (reason, _) = debugger.step_out(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::StepComplete);
+        assert_eq!(debugger.get_line(&cpu), Some(14));
+        assert_eq!(cpu.get_ax_low(), 3);
+    }
+
+    #[test]
+    fn run_collects_console_output_and_faults() {
+        let code = "
+            mov dl, 0x41
+            int 0x21
+            inc dl
+            int 0x21
+            int 0x99
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        let (reason, output) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Fault(Fault::UnknownInterrupt(0x99)));
+        assert_eq!(output, "AB");
+    }
+
+    #[test]
+    fn dos_functions_print_strings_wait_for_keys_and_exit() {
+        let code = "
+            org 100h
+            .data
+            msg db 0x48, 0x69, 0x24
+            code:
+            mov ah, 0x09
+            mov dx, offset msg
+            int 0x21
+            mov ah, 0x01
+            int 0x21
+            mov ah, 0x4C
+            int 0x21
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        let (reason, output) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::WaitingForInput);
+        assert_eq!(output, "Hi");
+
+        // waiting doesn't move the program forward
+        let (reason, _) = debugger.continue_execution(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::WaitingForInput);
+
+        assert!(keyboard::push_key(&mut mem, b'x'));
+        let (reason, output) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(output, "x");
+        assert_eq!(reason, StopReason::Exited(b'x'));
+    }
+
+    #[test]
+    fn conditional_breakpoints_and_hit_counts() {
+        let code = "
+            mov cx, 0x05
+        l1:
+            inc ax
+            dec cx
+            jnz l1
+            hlt
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        assert_eq!(
+            debugger.set_conditional_breakpoint(3, Some("ax == 2 || cx == 1"), None),
+            Ok(3)
+        );
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 2);
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 4);
+
+        debugger
+            .set_conditional_breakpoint(3, None, Some(3))
+            .unwrap();
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 2);
+        assert_eq!(debugger.get_breakpoints()[0].hits, 3);
+
+        assert!(debugger
+            .set_conditional_breakpoint(3, Some("[var1] > 3"), None)
+            .is_err());
+        assert!(debugger
+            .set_conditional_breakpoint(3, Some("ax =="), None)
+            .is_err());
+    }
+
+    #[test]
+    fn watchpoints_on_variables_and_memory() {
+        let code = "
+            mov al, var1
+            mov bx, 0x02
+            mov var2, bx
+            mov [0x20], 0x0102
+            hlt
+            var1 db 0x05
+            var2 dw 0x00
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        let read_id = debugger
+            .add_watchpoint(Watchpoint {
+                target: WatchTarget::Variable("VAR1".to_string()),
+                kind: WatchKind::Read,
+            })
+            .unwrap();
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        match reason {
+            StopReason::Watchpoint(WatchpointHit::Memory { id, access }) => {
+                assert_eq!(id, read_id);
+                assert_eq!(access.kind, MemoryAccessKind::Read);
+                assert_eq!(access.value, 0x05);
+            }
+            reason => panic!("Expected a watchpoint but got {:?}", reason),
+        }
+        assert!(debugger.remove_watchpoint(read_id));
+
+        debugger
+            .add_watchpoint(Watchpoint {
+                target: WatchTarget::Variable("var2".to_string()),
+                kind: WatchKind::Write,
+            })
+            .unwrap();
+        debugger
+            .add_watchpoint(Watchpoint {
+                target: WatchTarget::Memory {
+                    segment: 0x100,
+                    offset: 0x1F,
+                    length: 2,
+                },
+                kind: WatchKind::Write,
+            })
+            .unwrap();
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert!(matches!(
+            reason,
+            StopReason::Watchpoint(WatchpointHit::Memory { id: 1, access }) if access.value == 0x02
+        ));
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert!(matches!(
+            reason,
+            StopReason::Watchpoint(WatchpointHit::Memory { id: 2, access }) if access.address == 0x1020
+        ));
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+
+        assert!(debugger
+            .add_watchpoint(Watchpoint {
+                target: WatchTarget::Variable("var3".to_string()),
+                kind: WatchKind::Write,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn register_watchpoints_fire_on_change() {
+        let code = "
+            mov ax, 0x00
+            mov bx, 0x10
+            mov bx, 0x10
+            mov bl, 0x11
+            hlt
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        debugger
+            .add_watchpoint(Watchpoint {
+                target: WatchTarget::Register(Register::BX),
+                kind: WatchKind::Write,
+            })
+            .unwrap();
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(
+            reason,
+            StopReason::Watchpoint(WatchpointHit::Register {
+                id: 0,
+                register: Register::BX,
+                old_value: 0x00,
+                new_value: 0x10
+            })
+        );
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(
+            reason,
+            StopReason::Watchpoint(WatchpointHit::Register {
+                id: 0,
+                register: Register::BX,
+                old_value: 0x10,
+                new_value: 0x11
+            })
+        );
+        assert_eq!(debugger.get_line(&cpu), Some(5));
+    }
+
+    #[test]
+    fn step_back_restores_cpu_and_memory() {
+        let code = "
+            mov bx, 0x05
+            mov var1, bx
+            inc bx
+            mov var1, bx
+            hlt
+            var1 dw 0x00
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(cpu.get_register(Register::BX), 0x06);
+        let var1_offset = debugger.variables[&UniCase::new("var1".to_string())].1;
+        assert_eq!(mem.peek_word(0x100, var1_offset), 0x06);
+
+        // hlt
+        let (reason, changes) = debugger.step_back(&mut cpu, &mut mem);
+        assert_eq!(reason, StopReason::StepComplete);
+        assert!(changes.is_empty());
+        assert_eq!(debugger.get_line(&cpu), Some(5));
+
+        let (_, changes) = debugger.step_back(&mut cpu, &mut mem);
+        let var1_address = 0x1000 + var1_offset as usize;
+        assert_eq!(
+            changes,
+            vec![(var1_address, 0x05), (var1_address + 1, 0x00)]
+        );
+        assert_eq!(mem.peek_word(0x100, var1_offset), 0x05);
+        assert_eq!(debugger.get_line(&cpu), Some(4));
+
+        debugger.step_back(&mut cpu, &mut mem);
+        assert_eq!(cpu.get_register(Register::BX), 0x05);
+        debugger.step_back(&mut cpu, &mut mem);
+        debugger.step_back(&mut cpu, &mut mem);
+        assert_eq!(mem.peek_word(0x100, var1_offset), 0x00);
+        assert_eq!(cpu.get_register(Register::BX), 0x00);
+        let (reason, _) = debugger.step_back(&mut cpu, &mut mem);
+        assert_eq!(reason, StopReason::HistoryStart);
+
+        // executing again after rewinding gives the same result
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(mem.peek_word(0x100, var1_offset), 0x06);
+    }
+
+    #[test]
+    fn reverse_continue_stops_on_breakpoints_and_watchpoints() {
+        let code = "
+            mov cx, 0x03
+        l1:
+            inc ax
+            mov var1, ax
+            dec cx
+            jnz l1
+            hlt
+            var1 dw 0x00
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(cpu.get_ax_low(), 3);
+
+        debugger.set_breakpoints(&[3]);
+        let (reason, _) = debugger.reverse_continue(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 2);
+
+        debugger.set_breakpoints(&[]);
+        debugger
+            .add_watchpoint(Watchpoint {
+                target: WatchTarget::Variable("var1".to_string()),
+                kind: WatchKind::Write,
+            })
+            .unwrap();
+        let (reason, _) = debugger.reverse_continue(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert!(matches!(
+            reason,
+            StopReason::Watchpoint(WatchpointHit::Memory { id: 0, access }) if access.value == 0x02
+        ));
+        assert_eq!(debugger.get_line(&cpu), Some(4));
+
+        let (reason, _) = debugger.reverse_continue(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert!(matches!(
+            reason,
+            StopReason::Watchpoint(WatchpointHit::Memory { id: 0, access }) if access.value == 0x01
+        ));
+        let (reason, _) = debugger.reverse_continue(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::HistoryStart);
+        assert_eq!(cpu.get_ax_low(), 0);
+    }
+
+    #[test]
+    fn reverse_continue_takes_back_the_hits() {
+        let code = "
+            mov cx, 0x03
+        l1:
+            inc ax
+            mov var1, ax
+            dec cx
+            jnz l1
+            hlt
+            var1 dw 0x00
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        debugger
+            .set_conditional_breakpoint(3, None, Some(2))
+            .unwrap();
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 1);
+        debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+
+        // the first hit is below the hit count in both directions
+        let (reason, _) = debugger.reverse_continue(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 2);
+        assert_eq!(debugger.get_breakpoints()[0].hits, 3);
+        let (reason, _) = debugger.reverse_continue(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 1);
+        let (reason, _) = debugger.reverse_continue(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::HistoryStart);
+        assert_eq!(debugger.get_breakpoints()[0].hits, 0);
+
+        // and the hits count again going forward
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 1);
+
+        // a condition that doesn't hold going backwards doesn't stop
+        debugger.set_breakpoints(&[]);
+        debugger
+            .set_conditional_breakpoint(3, Some("ax == 0"), None)
+            .unwrap();
+        let (reason, _) = debugger.reverse_continue(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Breakpoint(3));
+        assert_eq!(cpu.get_ax_low(), 0);
+    }
+
+    #[test]
+    fn step_back_past_the_history_depth_uses_checkpoints() {
+        let code = "
+            mov cx, 0x0A
+        l1:
+            inc ax
+            mov var1, ax
+            dec cx
+            jnz l1
+            hlt
+            var1 dw 0x00
+        ";
+        let (mut debugger, mut cpu, mut mem) = load(code);
+        debugger.set_history_depth(&mut mem, 3);
+        debugger.set_checkpoint_interval(8);
+        debugger.run(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(cpu.get_ax_low(), 0x0A);
+        let var1_offset = debugger.variables[&UniCase::new("var1".to_string())].1;
+
+        let mut states = vec![];
+        loop {
+            let (reason, _) = debugger.step_back(&mut cpu, &mut mem);
+            if reason == StopReason::HistoryStart {
+                break;
+            }
+            states.push((cpu.get_ax_low(), mem.peek_word(0x100, var1_offset)));
+        }
+        // mov cx + 10 iterations of 4 instructions + hlt
+        assert_eq!(states.len(), 42);
+        assert_eq!(states[2], (0x0A, 0x0A));
+        assert_eq!(states[3], (0x0A, 0x09));
+        assert_eq!(states[4], (0x09, 0x09));
+        assert_eq!(states[40], (0x00, 0x00));
+        assert_eq!(states[41], (0x00, 0x00));
+        assert_eq!(debugger.get_line(&cpu), Some(1));
+    }
+}
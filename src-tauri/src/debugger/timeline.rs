@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+
+use crate::{
+    consts::Byte,
+    cpu::CPU,
+    memory::{Memory, DEFAULT_HISTORY_DEPTH},
+};
+
+pub const DEFAULT_CHECKPOINT_INTERVAL: usize = 10_000;
+const MAX_CHECKPOINTS: usize = 16;
+
+// The state before an instruction got executed, the memory it changed
+// is kept in the memory history after `memory_time`
+#[derive(Debug, Clone, Copy)]
+pub(super) struct StepRecord {
+    pub cpu: CPU,
+    pub memory_time: usize,
+}
+
+// A full copy of the machine, used to go back further than the step records reach
+#[derive(Debug)]
+pub(super) struct Checkpoint {
+    pub cpu: CPU,
+    pub memory: Vec<Byte>,
+    pub memory_time: usize,
+}
+
+// The executed steps, only the last `depth` steps are kept and every
+// `checkpoint_interval` steps a checkpoint is taken
+#[derive(Debug)]
+pub struct Timeline {
+    steps: VecDeque<StepRecord>,
+    checkpoints: VecDeque<Checkpoint>,
+    depth: usize,
+    checkpoint_interval: usize,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            steps: VecDeque::new(),
+            checkpoints: VecDeque::new(),
+            depth: DEFAULT_HISTORY_DEPTH,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn clear(&mut self) {
+        self.steps.clear();
+        self.checkpoints.clear();
+    }
+
+    pub(super) fn clear_steps(&mut self) {
+        self.steps.clear();
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        while self.steps.len() > depth {
+            self.steps.pop_front();
+        }
+    }
+
+    pub fn set_checkpoint_interval(&mut self, interval: usize) {
+        self.checkpoint_interval = interval.max(1);
+    }
+
+    // has to be called right before the instruction gets executed
+    pub(super) fn record(&mut self, cpu: &CPU, mem: &Memory) {
+        let memory_time = mem.get_time();
+        let is_checkpoint_missing = self
+            .checkpoints
+            .back()
+            .is_none_or(|checkpoint| checkpoint.memory_time < memory_time);
+        if memory_time.is_multiple_of(self.checkpoint_interval) && is_checkpoint_missing {
+            self.checkpoints.push_back(Checkpoint {
+                cpu: *cpu,
+                memory: mem.get_snapshot(),
+                memory_time,
+            });
+            if self.checkpoints.len() > MAX_CHECKPOINTS {
+                self.checkpoints.pop_front();
+            }
+        }
+
+        self.steps.push_back(StepRecord {
+            cpu: *cpu,
+            memory_time,
+        });
+        if self.steps.len() > self.depth {
+            self.steps.pop_front();
+        }
+    }
+
+    // the last step, as long as the memory it wrote can still be rewound
+    pub(super) fn pop_step(&mut self, mem: &Memory) -> Option<StepRecord> {
+        let record = self.steps.back()?;
+        if record.memory_time < mem.get_oldest_time() {
+            self.steps.clear();
+            return None;
+        }
+        self.steps.pop_back()
+    }
+
+    // the checkpoints after the given time don't exist anymore once the time is rewound
+    pub(super) fn discard_checkpoints_after(&mut self, memory_time: usize) {
+        while self
+            .checkpoints
+            .back()
+            .is_some_and(|checkpoint| checkpoint.memory_time > memory_time)
+        {
+            self.checkpoints.pop_back();
+        }
+    }
+
+    pub(super) fn get_checkpoint_before(&self, memory_time: usize) -> Option<&Checkpoint> {
+        self.checkpoints
+            .iter()
+            .rev()
+            .find(|checkpoint| checkpoint.memory_time < memory_time)
+    }
+}
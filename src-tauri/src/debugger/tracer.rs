@@ -0,0 +1,477 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::{
+    consts::{Byte, Word},
+    cpu::{
+        registers::{Flag, Register},
+        CPU,
+    },
+    disassembler::{disassemble_instruction, Symbols},
+    memory::{Memory, MemoryAccess, MemoryAccessKind},
+};
+
+// the oldest records are dropped after this many, like the history
+pub const DEFAULT_TRACE_LIMIT: usize = 100_000;
+
+// IP is left out, every record has CS:IP of its instruction
+const TRACED_REGISTERS: [Register; 12] = [
+    Register::AX,
+    Register::BX,
+    Register::CX,
+    Register::DX,
+    Register::SI,
+    Register::DI,
+    Register::BP,
+    Register::SP,
+    Register::CS,
+    Register::DS,
+    Register::ES,
+    Register::SS,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceFormat {
+    JsonLines,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PortAccessKind {
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PortAccess {
+    pub port: Word,
+    pub kind: PortAccessKind,
+    pub value: Word,
+    // a word is written to (or) read from the port and the one after it
+    pub is_word: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TraceRecord {
+    pub step: usize,
+    pub segment: Word,
+    pub offset: Word,
+    pub bytes: Vec<Byte>,
+    pub disassembly: String,
+    // (register, before, after) of the registers that changed
+    pub registers: Vec<(Register, Word, Word)>,
+    pub flags: Vec<(Flag, bool, bool)>,
+    pub memory: Vec<MemoryAccess>,
+    pub ports: Vec<PortAccess>,
+}
+
+// The state before the instruction, the record is completed after it executed
+pub(super) struct PendingRecord {
+    cpu: CPU,
+    record: TraceRecord,
+    was_recording_accesses: bool,
+}
+
+#[derive(Debug)]
+pub struct Tracer {
+    records: VecDeque<TraceRecord>,
+    limit: usize,
+    step: usize,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRACE_LIMIT)
+    }
+}
+
+// the port and the size of IN and OUT, the value is in AL or AX
+fn get_port_access(cpu: &CPU, mem: &Memory) -> Option<(Word, PortAccessKind, bool)> {
+    let segment = cpu.get_code_segment();
+    let offset = cpu.get_instruciton_pointer();
+    let immediate = || mem.peek_byte(segment, offset.wrapping_add(1)) as Word;
+    let dx = cpu.get_register(Register::DX);
+    match mem.peek_byte(segment, offset) {
+        0xE4 => Some((immediate(), PortAccessKind::In, false)),
+        0xE5 => Some((immediate(), PortAccessKind::In, true)),
+        0xE6 => Some((immediate(), PortAccessKind::Out, false)),
+        0xE7 => Some((immediate(), PortAccessKind::Out, true)),
+        0xEC => Some((dx, PortAccessKind::In, false)),
+        0xED => Some((dx, PortAccessKind::In, true)),
+        0xEE => Some((dx, PortAccessKind::Out, false)),
+        0xEF => Some((dx, PortAccessKind::Out, true)),
+        _ => None,
+    }
+}
+
+fn get_accumulator(cpu: &CPU, is_word: bool) -> Word {
+    match is_word {
+        true => cpu.get_register(Register::AX),
+        false => cpu.get_register(Register::AL),
+    }
+}
+
+// the value OUT writes, taken before the instruction executes
+pub(super) fn get_port_write(cpu: &CPU, mem: &Memory) -> Option<PortAccess> {
+    match get_port_access(cpu, mem)? {
+        (port, PortAccessKind::Out, is_word) => Some(PortAccess {
+            port,
+            kind: PortAccessKind::Out,
+            value: get_accumulator(cpu, is_word),
+            is_word,
+        }),
+        _ => None,
+    }
+}
+
+impl Tracer {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            limit,
+            step: 0,
+        }
+    }
+
+    pub(super) fn begin(
+        &mut self,
+        cpu: &CPU,
+        mem: &mut Memory,
+        symbols: Option<&Symbols>,
+    ) -> PendingRecord {
+        let segment = cpu.get_code_segment();
+        let offset = cpu.get_instruciton_pointer();
+        let instruction = disassemble_instruction(mem, segment, offset, symbols);
+        let was_recording_accesses = mem.is_recording_accesses();
+        if !was_recording_accesses {
+            mem.set_access_recording(true);
+        }
+        self.step += 1;
+        PendingRecord {
+            cpu: *cpu,
+            record: TraceRecord {
+                step: self.step,
+                segment,
+                offset,
+                bytes: instruction.bytes.clone(),
+                disassembly: instruction.to_string(),
+                registers: Vec::new(),
+                flags: Vec::new(),
+                memory: Vec::new(),
+                ports: Vec::new(),
+            },
+            was_recording_accesses,
+        }
+    }
+
+    pub(super) fn end(&mut self, pending: PendingRecord, cpu: &CPU, mem: &mut Memory) {
+        let PendingRecord {
+            cpu: before,
+            mut record,
+            was_recording_accesses,
+        } = pending;
+
+        for register in TRACED_REGISTERS {
+            let (old, new) = (before.get_register(register), cpu.get_register(register));
+            if old != new {
+                record.registers.push((register, old, new));
+            }
+        }
+        for flag in Flag::iter() {
+            let (old, new) = (before.get_flag(flag), cpu.get_flag(flag));
+            if old != new {
+                record.flags.push((flag, old, new));
+            }
+        }
+        if let Some((port, kind, is_word)) = get_port_access(&before, mem) {
+            let value = match kind {
+                PortAccessKind::In => get_accumulator(cpu, is_word),
+                PortAccessKind::Out => get_accumulator(&before, is_word),
+            };
+            record.ports.push(PortAccess {
+                port,
+                kind,
+                value,
+                is_word,
+            });
+        }
+
+        // the watchpoints take the accesses after the step when they are recording them
+        record.memory = mem.get_accesses();
+        if !was_recording_accesses {
+            mem.set_access_recording(false);
+        }
+
+        self.records.push_back(record);
+        if self.records.len() > self.limit {
+            self.records.pop_front();
+        }
+    }
+
+    pub fn get_records(&self) -> &VecDeque<TraceRecord> {
+        &self.records
+    }
+
+    pub fn write(&self, format: TraceFormat) -> String {
+        let mut trace = String::new();
+        if format == TraceFormat::Csv {
+            trace.push_str("step,cs,ip,bytes,disassembly,registers,flags,memory,ports\n");
+        }
+        for record in &self.records {
+            match format {
+                TraceFormat::JsonLines => {
+                    // a record only has plain fields, it always serializes
+                    trace.push_str(&serde_json::to_string(record).unwrap_or_default());
+                    trace.push('\n');
+                }
+                TraceFormat::Csv => {
+                    let _ = writeln!(trace, "{}", get_csv_row(record));
+                }
+            }
+        }
+        trace
+    }
+}
+
+fn escape_csv(field: String) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+fn join<T>(items: &[T], fmt: impl Fn(&T) -> String) -> String {
+    items.iter().map(fmt).collect::<Vec<String>>().join(" ")
+}
+
+// the lists are separated by spaces, e.g. AX=0000->0005 in the register column
+fn get_csv_row(record: &TraceRecord) -> String {
+    let fields = [
+        record.step.to_string(),
+        format!("{:04X}", record.segment),
+        format!("{:04X}", record.offset),
+        join(&record.bytes, |byte| format!("{:02X}", byte)),
+        record.disassembly.clone(),
+        join(&record.registers, |(register, old, new)| {
+            format!("{}={:04X}->{:04X}", register, old, new)
+        }),
+        join(&record.flags, |(flag, old, new)| {
+            format!("{}={}->{}", flag, *old as u8, *new as u8)
+        }),
+        join(&record.memory, |access| {
+            let kind = match access.kind {
+                MemoryAccessKind::Read => 'R',
+                MemoryAccessKind::Write => 'W',
+            };
+            format!("{}:{:05X}={:02X}", kind, access.address, access.value)
+        }),
+        join(&record.ports, |access| {
+            let kind = match access.kind {
+                PortAccessKind::In => "IN",
+                PortAccessKind::Out => "OUT",
+            };
+            format!("{}:{:02X}={:04X}", kind, access.port, access.value)
+        }),
+    ];
+    fields
+        .into_iter()
+        .map(escape_csv)
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        compiler::compile_program,
+        cpu::{registers::Register, CPU},
+        debugger::{Debugger, StopReason, DEFAULT_INSTRUCTION_BUDGET},
+        memory::{Memory, MemoryAccessKind},
+    };
+
+    use super::{get_csv_row, PortAccess, PortAccessKind, TraceFormat};
+
+    #[test]
+    fn records_deltas_accesses_and_ports() {
+        let program = compile_program(
+            "
+            org 100h
+            .data
+            var dw 0x1234
+            code:
+            mov ax, var
+            out 0x10, al
+            dec ax
+            hlt",
+            false,
+        )
+        .unwrap();
+        let mut debugger = Debugger::new();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+        debugger.set_tracing(true);
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+
+        let records = debugger.get_tracer().unwrap().get_records();
+        // the jmp over the data of .data comes first
+        assert_eq!(records.len(), 5);
+
+        let mov = &records[1];
+        assert_eq!((mov.segment, mov.offset), (0x0700, 0x0104));
+        assert_eq!(mov.bytes, vec![0xA1, 0x02, 0x01]);
+        assert_eq!(mov.registers, vec![(Register::AX, 0x0000, 0x1234)]);
+        let reads = mov
+            .memory
+            .iter()
+            .map(|access| (access.kind, access.address, access.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reads,
+            vec![
+                (MemoryAccessKind::Read, 0x7102, 0x34),
+                (MemoryAccessKind::Read, 0x7103, 0x12)
+            ]
+        );
+
+        assert_eq!(
+            records[2].ports,
+            vec![PortAccess {
+                port: 0x10,
+                kind: PortAccessKind::Out,
+                value: 0x34,
+                is_word: false
+            }]
+        );
+        assert!(!records[3].flags.is_empty());
+
+        let csv = debugger.get_trace(TraceFormat::Csv).unwrap();
+        let lines = csv.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            lines[3],
+            "3,0700,0107,E6 10,\"OUT 0x10, AL\",,,,OUT:10=0034"
+        );
+        let json = debugger.get_trace(TraceFormat::JsonLines).unwrap();
+        assert_eq!(json.lines().count(), 5);
+
+        // turning it off drops the trace
+        debugger.set_tracing(false);
+        assert!(debugger.get_trace(TraceFormat::Csv).is_none());
+    }
+
+    #[test]
+    fn stepping_back_from_a_checkpoint_is_not_traced() {
+        let program = compile_program(
+            "
+            mov cx, 0x05
+            l1:
+            inc ax
+            dec cx
+            jnz l1
+            hlt",
+            false,
+        )
+        .unwrap();
+        let mut debugger = Debugger::new();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+        debugger.set_history_depth(&mut mem, 2);
+        debugger.set_checkpoint_interval(4);
+        debugger.set_tracing(true);
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(debugger.get_tracer().unwrap().get_records().len(), 17);
+
+        // the steps run out after 2, the rest are replayed from the checkpoints
+        for _ in 0..5 {
+            let (reason, _) = debugger.step_back(&mut cpu, &mut mem);
+            assert_eq!(reason, StopReason::StepComplete);
+        }
+        let records = debugger.get_tracer().unwrap().get_records();
+        assert_eq!(records.len(), 17);
+        assert_eq!(records.back().unwrap().step, 17);
+
+        debugger.execute_instruction(&mut cpu, &mut mem);
+        let records = debugger.get_tracer().unwrap().get_records();
+        assert_eq!(records.len(), 18);
+        assert_eq!(records.back().unwrap().step, 18);
+    }
+
+    #[test]
+    fn commas_and_quotes_are_escaped_in_csv() {
+        let program = compile_program(
+            "
+            mov bx, 0x02
+            mov ax, [bx+si]
+            hlt",
+            false,
+        )
+        .unwrap();
+        let mut debugger = Debugger::new();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+        debugger.set_tracing(true);
+        debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+
+        let csv = debugger.get_trace(TraceFormat::Csv).unwrap();
+        let row = csv.lines().nth(2).unwrap();
+        assert_eq!(
+            row,
+            "2,0100,0003,8B 00,\"MOV AX, [BX+SI]\",AX=0000->8B00,,R:01002=00 R:01003=8B,"
+        );
+
+        let mut record = debugger.get_tracer().unwrap().get_records()[1].clone();
+        record.disassembly = "MOV AL, '\"'".to_string();
+        assert!(get_csv_row(&record).contains(",\"MOV AL, '\"\"'\","));
+    }
+
+    #[test]
+    fn trace_after_a_replay_has_no_duplicate_steps() {
+        let program = compile_program(
+            "
+            mov cx, 0x05
+            l1:
+            inc ax
+            dec cx
+            jnz l1
+            hlt",
+            false,
+        )
+        .unwrap();
+        let mut debugger = Debugger::new();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+        debugger.set_history_depth(&mut mem, 2);
+        debugger.set_checkpoint_interval(4);
+        debugger.set_tracing(true);
+        debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        for _ in 0..6 {
+            debugger.step_back(&mut cpu, &mut mem);
+        }
+        let (reason, _) =
+            debugger.continue_execution(&mut cpu, &mut mem, DEFAULT_INSTRUCTION_BUDGET);
+        assert_eq!(reason, StopReason::Halted);
+
+        let csv = debugger.get_trace(TraceFormat::Csv).unwrap();
+        let steps = csv
+            .lines()
+            .skip(1)
+            .map(|row| row.split(',').next().unwrap().parse::<usize>().unwrap())
+            .collect::<Vec<usize>>();
+        // the 6 steps run again after the 17 of the first run
+        assert_eq!(steps, (1..=23).collect::<Vec<usize>>());
+        let json = debugger.get_trace(TraceFormat::JsonLines).unwrap();
+        assert_eq!(json.lines().count(), 23);
+    }
+}
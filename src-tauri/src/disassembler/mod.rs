@@ -0,0 +1,660 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    compiler::types_structs::CompiledProgram,
+    consts::{Byte, Word},
+    memory::Memory,
+};
+
+const REGISTERS_8BIT: [&str; 8] = ["AL", "CL", "DL", "BL", "AH", "CH", "DH", "BH"];
+const REGISTERS_16BIT: [&str; 8] = ["AX", "CX", "DX", "BX", "SP", "BP", "SI", "DI"];
+const SEGMENT_REGISTERS: [&str; 4] = ["ES", "CS", "SS", "DS"];
+const INDEXED_REGISTERS: [&str; 8] = ["BX+SI", "BX+DI", "BP+SI", "BP+DI", "SI", "DI", "BP", "BX"];
+// i.e the reg field of the 0x80, 0x81, 0x83 opcodes and the order of 0x00..=0x3D
+const ALU_INSTRUCTIONS: [&str; 8] = ["ADD", "OR", "ADC", "SBB", "AND", "SUB", "XOR", "CMP"];
+const CONDITIONAL_JUMPS: [&str; 16] = [
+    "JO", "JNO", "JB", "JAE", "JE", "JNE", "JBE", "JA", "JS", "JNS", "JPE", "JNP", "JL", "JGE",
+    "JLE", "JG",
+];
+
+// The labels and variables of a compiled program by their offset,
+// labels are relative to the code segment and variables to the data segment
+#[derive(Debug, Default, Clone)]
+pub struct Symbols {
+    labels: HashMap<Word, String>,
+    variables: HashMap<Word, String>,
+}
+
+impl Symbols {
+    pub fn from_program(program: &CompiledProgram) -> Self {
+        let labels = program
+            .label_offset_map
+            .iter()
+            .map(|(label, offset)| (*offset, label.to_string()))
+            .collect();
+        let variables = program
+            .variable_address_map
+            .iter()
+            .map(|(variable, (_, offset, _))| (*offset, variable.to_string()))
+            .collect();
+        Self { labels, variables }
+    }
+
+    pub fn get_label(&self, offset: Word) -> Option<&String> {
+        self.labels.get(&offset)
+    }
+
+    pub fn get_variable(&self, offset: Word) -> Option<&String> {
+        self.variables.get(&offset)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DisassembledInstruction {
+    pub segment: Word,
+    pub offset: Word,
+    pub bytes: Vec<Byte>,
+    pub length: usize,
+    pub mnemonic: String,
+    pub operands: Vec<String>,
+    // the offset a jump, call or loop goes to
+    pub target: Option<Word>,
+    pub target_label: Option<String>,
+    // the variable at the direct address used by the instruction
+    pub variable: Option<String>,
+}
+
+impl std::fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        if !self.operands.is_empty() {
+            write!(f, " {}", self.operands.join(", "))?;
+        }
+        let comments = [&self.target_label, &self.variable]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<String>>();
+        if !comments.is_empty() {
+            write!(f, " ; {}", comments.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Disassembles `count` instructions starting at segment:offset,
+// bytes that aren't an instruction the cpu can execute are shown as `DB`
+pub fn disassemble(
+    mem: &Memory,
+    segment: Word,
+    offset: Word,
+    count: usize,
+    symbols: Option<&Symbols>,
+) -> Vec<DisassembledInstruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut offset = offset;
+    for _ in 0..count {
+        let instruction = disassemble_instruction(mem, segment, offset, symbols);
+        offset = offset.wrapping_add(instruction.length as Word);
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+pub fn disassemble_instruction(
+    mem: &Memory,
+    segment: Word,
+    offset: Word,
+    symbols: Option<&Symbols>,
+) -> DisassembledInstruction {
+    let mut decoder = Decoder::new(mem, segment, offset);
+    let (mnemonic, operands) = match decoder.decode() {
+        Some(decoded) => decoded,
+        None => {
+            // only the opcode is shown, the next instruction starts right after it
+            decoder.bytes.truncate(1);
+            decoder.target = None;
+            decoder.address = None;
+            ("DB", vec![fmt_byte(decoder.bytes[0])])
+        }
+    };
+
+    let target_label = decoder
+        .target
+        .and_then(|target| symbols?.get_label(target).cloned());
+    let variable = decoder
+        .address
+        .and_then(|address| symbols?.get_variable(address).cloned());
+    DisassembledInstruction {
+        segment,
+        offset,
+        length: decoder.bytes.len(),
+        bytes: decoder.bytes,
+        mnemonic: mnemonic.to_string(),
+        operands,
+        target: decoder.target,
+        target_label,
+        variable,
+    }
+}
+
+fn fmt_byte(val: Byte) -> String {
+    format!("0x{:02X}", val)
+}
+
+fn fmt_word(val: Word) -> String {
+    format!("0x{:04X}", val)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OperandSize {
+    Byte,
+    Word,
+}
+
+impl OperandSize {
+    fn from_opcode(opcode: Byte) -> Self {
+        // the lowest bit of most opcodes tells if the operands are 8 or 16 bit
+        if opcode & 0x01 == 0 {
+            OperandSize::Byte
+        } else {
+            OperandSize::Word
+        }
+    }
+
+    fn register(&self, idx: Byte) -> String {
+        match self {
+            OperandSize::Byte => REGISTERS_8BIT[(idx & 0x07) as usize].to_string(),
+            OperandSize::Word => REGISTERS_16BIT[(idx & 0x07) as usize].to_string(),
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        match self {
+            OperandSize::Byte => "b.",
+            OperandSize::Word => "w.",
+        }
+    }
+}
+
+struct Decoder<'a> {
+    mem: &'a Memory,
+    segment: Word,
+    offset: Word,
+    bytes: Vec<Byte>,
+    target: Option<Word>,
+    address: Option<Word>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(mem: &'a Memory, segment: Word, offset: Word) -> Self {
+        Self {
+            mem,
+            segment,
+            offset,
+            bytes: Vec::new(),
+            target: None,
+            address: None,
+        }
+    }
+
+    fn next_offset(&self) -> Word {
+        self.offset.wrapping_add(self.bytes.len() as Word)
+    }
+
+    fn peek_byte(&self) -> Byte {
+        self.mem.peek_byte(self.segment, self.next_offset())
+    }
+
+    fn consume_byte(&mut self) -> Byte {
+        let byte = self.peek_byte();
+        self.bytes.push(byte);
+        byte
+    }
+
+    fn consume_word(&mut self) -> Word {
+        let low = self.consume_byte() as Word;
+        let high = self.consume_byte() as Word;
+        (high << 8) | low
+    }
+
+    fn consume_immediate(&mut self, size: OperandSize) -> String {
+        match size {
+            OperandSize::Byte => fmt_byte(self.consume_byte()),
+            OperandSize::Word => fmt_word(self.consume_word()),
+        }
+    }
+
+    fn relative_jump_8bit(&mut self) -> String {
+        let rel = self.consume_byte() as i8 as i16 as Word;
+        self.jump_target(self.next_offset().wrapping_add(rel))
+    }
+
+    fn relative_jump_16bit(&mut self) -> String {
+        let rel = self.consume_word();
+        self.jump_target(self.next_offset().wrapping_add(rel))
+    }
+
+    fn jump_target(&mut self, target: Word) -> String {
+        self.target = Some(target);
+        fmt_word(target)
+    }
+
+    // the offset and then the segment, i.e JMP FAR 0x0101:0x0005
+    fn far_address(&mut self) -> String {
+        let offset = self.consume_word();
+        let segment = self.consume_word();
+        format!("{}:{}", fmt_word(segment), fmt_word(offset))
+    }
+
+    fn direct_address(&mut self) -> String {
+        let address = self.consume_word();
+        self.address = Some(address);
+        format!("[{}]", fmt_word(address))
+    }
+
+    // decodes the register/memory part of a modrm byte, the displacement gets consumed
+    fn rm_operand(&mut self, modrm: Byte, size: OperandSize) -> String {
+        let rm = modrm & 0x07;
+        match modrm >> 6 {
+            0b00 if rm == 0x06 => self.direct_address(),
+            0b00 => format!("[{}]", INDEXED_REGISTERS[rm as usize]),
+            0b01 => {
+                let disp = self.consume_byte() as i8;
+                let sign = if disp < 0 { '-' } else { '+' };
+                format!(
+                    "[{}{}{}]",
+                    INDEXED_REGISTERS[rm as usize],
+                    sign,
+                    fmt_byte(disp.unsigned_abs())
+                )
+            }
+            0b10 => {
+                let disp = self.consume_word();
+                format!("[{}+{}]", INDEXED_REGISTERS[rm as usize], fmt_word(disp))
+            }
+            _ => size.register(rm),
+        }
+    }
+
+    // memory operands get a size prefix when the other operand doesn't tell the size
+    fn sized_rm_operand(&mut self, modrm: Byte, size: OperandSize) -> String {
+        let operand = self.rm_operand(modrm, size);
+        if modrm >= 0xC0 {
+            operand
+        } else {
+            format!("{}{}", size.prefix(), operand)
+        }
+    }
+
+    // i.e OPCODE reg/mem, reg
+    fn rm_and_reg(&mut self, size: OperandSize) -> Vec<String> {
+        let modrm = self.consume_byte();
+        let rm = self.rm_operand(modrm, size);
+        vec![rm, size.register(modrm >> 3)]
+    }
+
+    // i.e OPCODE reg, reg/mem
+    fn reg_and_rm(&mut self, size: OperandSize) -> Vec<String> {
+        let mut operands = self.rm_and_reg(size);
+        operands.reverse();
+        operands
+    }
+
+    fn decode(&mut self) -> Option<(&'static str, Vec<String>)> {
+        let opcode = self.consume_byte();
+        let size = OperandSize::from_opcode(opcode);
+        let decoded = match opcode {
+            // ADD, OR, ADC, SBB, AND, SUB, XOR, CMP
+            0x00..=0x3F if opcode & 0x07 < 0x06 => {
+                let mnemonic = ALU_INSTRUCTIONS[(opcode >> 3) as usize];
+                let operands = match opcode & 0x07 {
+                    0x00 | 0x01 => self.rm_and_reg(size),
+                    0x02 | 0x03 => self.reg_and_rm(size),
+                    _ => {
+                        let imm = self.consume_immediate(size);
+                        vec![size.register(0), imm]
+                    }
+                };
+                (mnemonic, operands)
+            }
+            // PUSH ES | CS | SS | DS
+            0x06 | 0x0E | 0x16 | 0x1E => (
+                "PUSH",
+                vec![SEGMENT_REGISTERS[(opcode >> 3) as usize].to_string()],
+            ),
+            // POP ES | SS | DS
+            0x07 | 0x17 | 0x1F => (
+                "POP",
+                vec![SEGMENT_REGISTERS[(opcode >> 3) as usize].to_string()],
+            ),
+            0x40..=0x47 => ("INC", vec![OperandSize::Word.register(opcode)]),
+            0x48..=0x4F => ("DEC", vec![OperandSize::Word.register(opcode)]),
+            0x50..=0x57 => ("PUSH", vec![OperandSize::Word.register(opcode)]),
+            0x58..=0x5F => ("POP", vec![OperandSize::Word.register(opcode)]),
+            0x68 => ("PUSH", vec![self.consume_immediate(OperandSize::Word)]),
+            0x6A => ("PUSH", vec![self.consume_immediate(OperandSize::Byte)]),
+            0x70..=0x7F => (
+                CONDITIONAL_JUMPS[(opcode & 0x0F) as usize],
+                vec![self.relative_jump_8bit()],
+            ),
+            0x80 | 0x81 | 0x83 => {
+                let modrm = self.peek_byte();
+                // only direct addressing and registers are executed
+                if modrm & 0xC7 != 0x06 && modrm < 0xC0 {
+                    return None;
+                }
+                self.consume_byte();
+                let rm = self.sized_rm_operand(modrm, size);
+                let imm = if opcode == 0x83 {
+                    // sign extended to a word
+                    fmt_word(self.consume_byte() as i8 as i16 as Word)
+                } else {
+                    self.consume_immediate(size)
+                };
+                (
+                    ALU_INSTRUCTIONS[((modrm >> 3) & 0x07) as usize],
+                    vec![rm, imm],
+                )
+            }
+            0x84 | 0x85 => ("TEST", self.rm_and_reg(size)),
+            0x86 | 0x87 => ("XCHG", self.reg_and_rm(size)),
+            0x89 => ("MOV", self.rm_and_reg(size)),
+            0x8A | 0x8B => ("MOV", self.reg_and_rm(size)),
+            0x8C | 0x8E => {
+                // only the registers are supported
+                let modrm = self.peek_byte();
+                if modrm < 0xC0 || (modrm >> 3) & 0x07 > 0x03 {
+                    return None;
+                }
+                self.consume_byte();
+                let operands = vec![
+                    SEGMENT_REGISTERS[((modrm >> 3) & 0x07) as usize].to_string(),
+                    OperandSize::Word.register(modrm),
+                ];
+                match opcode {
+                    0x8E => ("MOV", operands),
+                    _ => ("MOV", operands.into_iter().rev().collect()),
+                }
+            }
+            0x8D => ("LEA", self.reg_and_rm(OperandSize::Word)),
+            0x8F => {
+                let modrm = self.peek_byte();
+                if modrm & 0x38 != 0 {
+                    return None;
+                }
+                self.consume_byte();
+                ("POP", vec![self.sized_rm_operand(modrm, OperandSize::Word)])
+            }
+            0x90 => ("NOP", vec![]),
+            0x91..=0x97 => (
+                "XCHG",
+                vec!["AX".to_string(), OperandSize::Word.register(opcode)],
+            ),
+            0x9A => ("CALL FAR", vec![self.far_address()]),
+            0xA0 | 0xA1 => ("MOV", vec![size.register(0), self.direct_address()]),
+            0xA2 | 0xA3 => ("MOV", vec![self.direct_address(), size.register(0)]),
+            0xA8 | 0xA9 => ("TEST", vec![size.register(0), self.consume_immediate(size)]),
+            0xB0..=0xB7 => (
+                "MOV",
+                vec![
+                    OperandSize::Byte.register(opcode),
+                    self.consume_immediate(OperandSize::Byte),
+                ],
+            ),
+            0xB8..=0xBF => (
+                "MOV",
+                vec![
+                    OperandSize::Word.register(opcode),
+                    self.consume_immediate(OperandSize::Word),
+                ],
+            ),
+            0xC2 => ("RET", vec![self.consume_immediate(OperandSize::Word)]),
+            0xC3 => ("RET", vec![]),
+            0xCA => ("RETF", vec![self.consume_immediate(OperandSize::Word)]),
+            0xCB => ("RETF", vec![]),
+            0xC4 => ("LES", self.reg_and_rm(OperandSize::Word)),
+            0xC6 | 0xC7 => {
+                if self.peek_byte() != 0x06 {
+                    return None;
+                }
+                let modrm = self.consume_byte();
+                let rm = self.sized_rm_operand(modrm, size);
+                ("MOV", vec![rm, self.consume_immediate(size)])
+            }
+            0xCC => ("INT", vec![fmt_byte(0x03)]),
+            0xCD => ("INT", vec![self.consume_immediate(OperandSize::Byte)]),
+            0xCE => ("INTO", vec![]),
+            0xCF => ("IRET", vec![]),
+            0xE2 => ("LOOP", vec![self.relative_jump_8bit()]),
+            0xE3 => ("JCXZ", vec![self.relative_jump_8bit()]),
+            0xE4 | 0xE5 => (
+                "IN",
+                vec![size.register(0), self.consume_immediate(OperandSize::Byte)],
+            ),
+            0xE6 | 0xE7 => (
+                "OUT",
+                vec![self.consume_immediate(OperandSize::Byte), size.register(0)],
+            ),
+            0xE8 => ("CALL", vec![self.relative_jump_16bit()]),
+            0xE9 => ("JMP", vec![self.relative_jump_16bit()]),
+            0xEA => ("JMP FAR", vec![self.far_address()]),
+            0xEB => ("JMP", vec![self.relative_jump_8bit()]),
+            0xEC | 0xED => ("IN", vec![size.register(0), "DX".to_string()]),
+            0xEE | 0xEF => ("OUT", vec!["DX".to_string(), size.register(0)]),
+            0xF4 => ("HLT", vec![]),
+            0xF6 | 0xF7 => {
+                let modrm = self.peek_byte();
+                let mnemonic = match modrm {
+                    0x06 | 0xC0..=0xC7 => "TEST",
+                    0x26 | 0xE0..=0xE7 => "MUL",
+                    0x30..=0x37 | 0x70..=0x77 | 0xB0..=0xB7 | 0xF0..=0xF7 => "DIV",
+                    0x38..=0x3F | 0x78..=0x7F | 0xB8 | 0xF8..=0xFF => "IDIV",
+                    _ => return None,
+                };
+                self.consume_byte();
+                let mut operands = vec![self.sized_rm_operand(modrm, size)];
+                if mnemonic == "TEST" {
+                    operands.push(self.consume_immediate(size));
+                }
+                (mnemonic, operands)
+            }
+            0xFE => {
+                let modrm = self.peek_byte();
+                let mnemonic = match modrm {
+                    0x06 | 0xC0..=0xC7 => "INC",
+                    0x0E | 0xC8..=0xCF => "DEC",
+                    _ => return None,
+                };
+                self.consume_byte();
+                (mnemonic, vec![self.sized_rm_operand(modrm, size)])
+            }
+            0xFF => {
+                let modrm = self.peek_byte();
+                let mnemonic = match modrm {
+                    0x06 => "INC",
+                    0x0E => "DEC",
+                    0x10..=0x17 | 0x50..=0x57 | 0x90..=0x97 | 0xD0..=0xD7 => "CALL",
+                    // the offset and the segment are in memory
+                    0x18..=0x1F | 0x58..=0x5F | 0x98..=0x9F => {
+                        self.consume_byte();
+                        return Some(("CALL FAR", vec![self.rm_operand(modrm, size)]));
+                    }
+                    0x20..=0x27 => "JMP",
+                    0x30..=0x37 | 0x70..=0x77 | 0xB0..=0xB7 => "PUSH",
+                    // the emulator's way of calling the bios procedures, i.e FF FF CD xx
+                    0xFF => {
+                        self.consume_byte();
+                        if self.consume_byte() != 0xCD {
+                            return None;
+                        }
+                        let procedure = self.consume_immediate(OperandSize::Byte);
+                        return Some(("BIOS DI", vec![procedure]));
+                    }
+                    _ => return None,
+                };
+                self.consume_byte();
+                (mnemonic, vec![self.sized_rm_operand(modrm, size)])
+            }
+            _ => return None,
+        };
+        Some(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compiler::compile_program, cpu::CPU, debugger::Debugger, memory::Memory};
+
+    use super::{disassemble, DisassembledInstruction, Symbols};
+
+    fn disassemble_code(code: &str, count: usize) -> Vec<DisassembledInstruction> {
+        let program = compile_program(code, false).unwrap();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+        let symbols = Symbols::from_program(&program);
+        disassemble(
+            &mem,
+            cpu.get_code_segment(),
+            cpu.get_instruciton_pointer(),
+            count,
+            Some(&symbols),
+        )
+    }
+
+    fn as_text(instructions: &[DisassembledInstruction]) -> Vec<String> {
+        instructions.iter().map(|ins| ins.to_string()).collect()
+    }
+
+    #[test]
+    fn registers_immediates_and_memory() {
+        let instructions = disassemble_code(
+            "
+            mov ax, 0x1234
+            mov bl, 0x12
+            add ax, bx
+            sub cx, [bx+si+0x12]
+            xor [di], dx
+            mul bx
+            int 0x21
+            mov ds, ax
+            mov bx, ss
+            ",
+            9,
+        );
+        assert_eq!(
+            as_text(&instructions),
+            vec![
+                "MOV AX, 0x1234",
+                "MOV BL, 0x12",
+                "ADD AX, BX",
+                "SUB CX, [BX+SI+0x12]",
+                "XOR [DI], DX",
+                "MUL BX",
+                "INT 0x21",
+                "MOV DS, AX",
+                "MOV BX, SS",
+            ]
+        );
+        let lengths = instructions
+            .iter()
+            .map(|ins| ins.length)
+            .collect::<Vec<usize>>();
+        assert_eq!(lengths, vec![3, 2, 2, 3, 2, 2, 2, 2, 2]);
+        assert_eq!(instructions[1].offset, 3);
+    }
+
+    #[test]
+    fn jump_targets_and_variables_use_labels() {
+        let instructions = disassemble_code(
+            "
+            org 100h
+            .data
+            var dw 0x1234
+            code:
+                mov ax, var
+                dec ax
+                jnz code
+                call print
+                hlt
+            PROC print
+                ret
+            ENDP print
+            ",
+            7,
+        );
+        let text = as_text(&instructions);
+        assert_eq!(text[0], "JMP 0x0104 ; code");
+        // the variable is decoded as code as well
+        assert_eq!(text[1], "XOR AL, 0x12");
+        assert_eq!(text[2], "MOV AX, [0x0102] ; var");
+        assert_eq!(text[4], "JNE 0x0104 ; code");
+        assert_eq!(instructions[5].mnemonic, "CALL");
+        assert_eq!(instructions[5].target_label.as_deref(), Some("print"));
+        assert_eq!(instructions[6].mnemonic, "HLT");
+    }
+
+    #[test]
+    fn unknown_bytes_are_data() {
+        let mut mem = Memory::new();
+        mem.write_instructions(0x100, 0x00, &[0x0F, 0xC6, 0x00, 0x00]);
+        let instructions = disassemble(&mem, 0x100, 0x00, 3, None);
+        assert_eq!(
+            as_text(&instructions),
+            vec!["DB 0x0F", "DB 0xC6", "ADD [BX+SI], AL"]
+        );
+        assert_eq!(instructions[2].offset, 0x02);
+    }
+
+    #[test]
+    fn far_jumps_and_returns() {
+        let mut mem = Memory::new();
+        mem.write_instructions(
+            0x100,
+            0x00,
+            &[
+                0xEA, 0x05, 0x00, 0x01, 0x01, 0x9A, 0x00, 0x00, 0x02, 0x01, 0xCB, 0xCA, 0x04, 0x00,
+            ],
+        );
+        let instructions = disassemble(&mem, 0x100, 0x00, 4, None);
+        assert_eq!(
+            as_text(&instructions),
+            vec![
+                "JMP FAR 0x0101:0x0005",
+                "CALL FAR 0x0102:0x0000",
+                "RETF",
+                "RETF 0x0004"
+            ]
+        );
+        assert_eq!(instructions[3].offset, 0x0B);
+    }
+
+    #[test]
+    fn indirect_calls_and_returns() {
+        let mut mem = Memory::new();
+        mem.write_instructions(
+            0x100,
+            0x00,
+            &[
+                0xFF, 0xD3, 0xFF, 0x16, 0x02, 0x01, 0xFF, 0x5F, 0x04, 0xC2, 0x02, 0x00, 0xCC, 0xCE,
+            ],
+        );
+        let instructions = disassemble(&mem, 0x100, 0x00, 6, None);
+        assert_eq!(
+            as_text(&instructions),
+            vec![
+                "CALL BX",
+                "CALL w.[0x0102]",
+                "CALL FAR [BX+0x04]",
+                "RET 0x0002",
+                "INT 0x03",
+                "INTO"
+            ]
+        );
+        assert_eq!(instructions[3].length, 3);
+    }
+}
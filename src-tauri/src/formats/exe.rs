@@ -0,0 +1,300 @@
+use crate::consts::Word;
+
+pub const DEFAULT_STACK_SIZE: Word = 0x400;
+// the segment of the PSP, i.e the paragraph 10h before the load module
+pub const PSP_RELATIVE_SEGMENT: Word = 0xFFF0;
+
+const SIGNATURE: [u8; 2] = *b"MZ";
+const PAGE_SIZE: usize = 512;
+const PARAGRAPH_SIZE: usize = 16;
+// the fixed part of the header, the relocation table starts right after it
+const FORMATTED_HEADER_SIZE: usize = 0x1C;
+// i.e the largest program DOS can load below 640K
+const MAX_IMAGE_SIZE: usize = 0xA0000;
+const PSP_SIZE: usize = 0x100;
+
+pub type Relocation = (Word, Word); // i.e offset, segment of the word to be relocated
+
+fn to_paragraphs(size: usize) -> usize {
+    size.div_ceil(PARAGRAPH_SIZE)
+}
+
+// The MZ header, segments are relative to the start of the load module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExeHeader {
+    pub bytes_on_last_page: Word,
+    pub pages: Word,
+    pub relocation_count: Word,
+    pub header_paragraphs: Word,
+    pub min_extra_paragraphs: Word,
+    pub max_extra_paragraphs: Word,
+    pub stack_segment: Word,
+    pub stack_pointer: Word,
+    pub checksum: Word,
+    pub instruction_pointer: Word,
+    pub code_segment: Word,
+    pub relocation_table_offset: Word,
+    pub overlay_number: Word,
+}
+
+impl ExeHeader {
+    // The stack is placed in its own segment after the image
+    pub fn new(
+        image_size: usize,
+        relocations: &[Relocation],
+        entry: (Word, Word),
+        stack_size: Word,
+    ) -> Result<Self, String> {
+        if image_size > MAX_IMAGE_SIZE {
+            return Err(format!(
+                "The program is {:#X} bytes long, an .EXE program can be at most {:#X} bytes",
+                image_size, MAX_IMAGE_SIZE
+            ));
+        }
+        let header_size = FORMATTED_HEADER_SIZE + relocations.len() * 4;
+        let header_paragraphs = to_paragraphs(header_size);
+        let file_size = header_paragraphs * PARAGRAPH_SIZE + image_size;
+
+        let stack_segment = to_paragraphs(image_size);
+        let stack_end = stack_segment * PARAGRAPH_SIZE + stack_size as usize;
+        let (code_segment, instruction_pointer) = entry;
+
+        Ok(Self {
+            bytes_on_last_page: (file_size % PAGE_SIZE) as Word,
+            pages: file_size.div_ceil(PAGE_SIZE) as Word,
+            relocation_count: relocations.len() as Word,
+            header_paragraphs: header_paragraphs as Word,
+            min_extra_paragraphs: to_paragraphs(stack_end - image_size) as Word,
+            max_extra_paragraphs: 0xFFFF,
+            stack_segment: stack_segment as Word,
+            stack_pointer: stack_size,
+            checksum: 0x00,
+            instruction_pointer,
+            code_segment,
+            relocation_table_offset: FORMATTED_HEADER_SIZE as Word,
+            overlay_number: 0x00,
+        })
+    }
+
+    // the stack of a program with its own stack segment, which can be a part of the image
+    pub fn set_stack(&mut self, image_size: usize, stack: (Word, Word)) {
+        let (stack_segment, stack_pointer) = stack;
+        let stack_end = stack_segment as usize * PARAGRAPH_SIZE + stack_pointer as usize;
+        self.stack_segment = stack_segment;
+        self.stack_pointer = stack_pointer;
+        self.min_extra_paragraphs = to_paragraphs(stack_end.saturating_sub(image_size)) as Word;
+    }
+
+    // the stack of a program that runs in the segment of the PSP, the image is at offset 100h of it
+    pub fn set_psp_stack(&mut self, image_size: usize, stack_pointer: Word) {
+        self.stack_segment = PSP_RELATIVE_SEGMENT;
+        self.stack_pointer = stack_pointer;
+        let stack_end = (stack_pointer as usize).saturating_sub(PSP_SIZE);
+        self.min_extra_paragraphs = to_paragraphs(stack_end.saturating_sub(image_size)) as Word;
+    }
+
+    pub fn is_exe(bytes: &[u8]) -> bool {
+        // DOS accepts both orders of the signature
+        bytes.starts_with(&SIGNATURE) || bytes.starts_with(b"ZM")
+    }
+
+    // the header, the relocation table and the load module of an .EXE file
+    pub fn parse(bytes: &[u8]) -> Result<(Self, Vec<Relocation>, &[u8]), String> {
+        if !Self::is_exe(bytes) {
+            return Err("The file doesn't start with the MZ signature".to_string());
+        }
+        if bytes.len() < FORMATTED_HEADER_SIZE {
+            return Err("The file is too small to have an .EXE header".to_string());
+        }
+        let read_word = |offset: usize| Word::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let header = Self {
+            bytes_on_last_page: read_word(0x02),
+            pages: read_word(0x04),
+            relocation_count: read_word(0x06),
+            header_paragraphs: read_word(0x08),
+            min_extra_paragraphs: read_word(0x0A),
+            max_extra_paragraphs: read_word(0x0C),
+            stack_segment: read_word(0x0E),
+            stack_pointer: read_word(0x10),
+            checksum: read_word(0x12),
+            instruction_pointer: read_word(0x14),
+            code_segment: read_word(0x16),
+            relocation_table_offset: read_word(0x18),
+            overlay_number: read_word(0x1A),
+        };
+
+        let table_start = header.relocation_table_offset as usize;
+        let table_end = table_start + header.relocation_count as usize * 4;
+        if table_end > bytes.len() {
+            return Err("The relocation table is outside of the file".to_string());
+        }
+        let relocations = (table_start..table_end)
+            .step_by(4)
+            .map(|offset| (read_word(offset), read_word(offset + 2)))
+            .collect();
+
+        let header_size = header.header_paragraphs as usize * PARAGRAPH_SIZE;
+        let mut file_size = header.pages as usize * PAGE_SIZE;
+        if header.bytes_on_last_page != 0 {
+            file_size = file_size.saturating_sub(PAGE_SIZE) + header.bytes_on_last_page as usize;
+        }
+        // some linkers write a size bigger than the file
+        let file_size = file_size.min(bytes.len());
+        if header_size > file_size {
+            return Err("The header is bigger than the file".to_string());
+        }
+        Ok((header, relocations, &bytes[header_size..file_size]))
+    }
+
+    // the header and the relocation table, padded to a paragraph
+    pub fn to_bytes(&self, relocations: &[Relocation]) -> Vec<u8> {
+        let mut bytes = SIGNATURE.to_vec();
+        for word in [
+            self.bytes_on_last_page,
+            self.pages,
+            self.relocation_count,
+            self.header_paragraphs,
+            self.min_extra_paragraphs,
+            self.max_extra_paragraphs,
+            self.stack_segment,
+            self.stack_pointer,
+            self.checksum,
+            self.instruction_pointer,
+            self.code_segment,
+            self.relocation_table_offset,
+            self.overlay_number,
+        ] {
+            bytes.extend(word.to_le_bytes());
+        }
+        for (offset, segment) in relocations {
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend(segment.to_le_bytes());
+        }
+        bytes.resize(self.header_paragraphs as usize * PARAGRAPH_SIZE, 0x00);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compiler::compile_program,
+        cpu::{registers::Register, CPU},
+        debugger::{Debugger, StopReason},
+        formats::to_exe,
+        loader::PSP_SEGMENT,
+        memory::Memory,
+    };
+
+    use super::{ExeHeader, DEFAULT_STACK_SIZE};
+
+    #[test]
+    fn header_fields() {
+        let header = ExeHeader::new(0x300, &[(0x0001, 0x0000)], (0x0000, 0x0000), 0x100).unwrap();
+        // 0x20 bytes of header and 0x300 bytes of image
+        assert_eq!(header.header_paragraphs, 0x02);
+        assert_eq!(header.pages, 0x02);
+        assert_eq!(header.bytes_on_last_page, 0x120);
+        assert_eq!(header.stack_segment, 0x30);
+        assert_eq!(header.stack_pointer, 0x100);
+        assert_eq!(header.min_extra_paragraphs, 0x10);
+
+        let bytes = header.to_bytes(&[(0x0001, 0x0000)]);
+        assert_eq!(bytes.len(), 0x20);
+        assert_eq!(&bytes[..2], b"MZ");
+        assert_eq!(&bytes[0x1C..0x20], &[0x01, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn parse_what_got_written() {
+        let relocations = [(0x0001, 0x0000), (0x0010, 0x0002)];
+        let header = ExeHeader::new(0x30, &relocations, (0x0001, 0x0002), 0x100).unwrap();
+        let mut bytes = header.to_bytes(&relocations);
+        bytes.extend([0x90; 0x30]);
+
+        let (parsed, parsed_relocations, image) = ExeHeader::parse(&bytes).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(parsed_relocations, relocations.to_vec());
+        assert_eq!(image, &[0x90; 0x30]);
+        assert!(ExeHeader::parse(&bytes[..0x10]).is_err());
+    }
+
+    // i.e the exported program is loaded by DOS with DS=ES=PSP
+    fn run_exe(code: &str) -> (CPU, String) {
+        let program = compile_program(code, false).unwrap();
+        let bytes = to_exe(&program, DEFAULT_STACK_SIZE).unwrap();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        debugger
+            .load_binary(&mut cpu, &mut mem, &bytes, "")
+            .unwrap();
+        let (reason, output) = debugger.run(&mut cpu, &mut mem, 100);
+        assert_eq!(reason, StopReason::Exited(0));
+        (cpu, output)
+    }
+
+    #[test]
+    fn exe_of_org_runs_in_the_psp_segment() {
+        let code = "
+            org 100h
+            jmp start
+            msg db 'hi$'
+            value dw 0x1234
+            start:
+                mov bx, value
+                mov dx, offset msg
+                mov ah, 9
+                int 21h
+                mov ax, 4C00h
+                int 21h
+        ";
+        let program = compile_program(code, false).unwrap();
+        let bytes = to_exe(&program, 0x100).unwrap();
+        // i.e the header and the program without any padding
+        assert_eq!(bytes.len(), 0x20 + program.bytes.len());
+        let (header, _, _) = ExeHeader::parse(&bytes).unwrap();
+        assert_eq!(header.code_segment, 0xFFF0);
+        assert_eq!(header.instruction_pointer, 0x100);
+        assert_eq!(header.stack_segment, 0xFFF0);
+
+        let (cpu, output) = run_exe(code);
+        assert_eq!(cpu.get_register(Register::DS), PSP_SEGMENT);
+        assert_eq!(cpu.get_register(Register::BX), 0x1234);
+        assert_eq!(output, "hi");
+    }
+
+    #[test]
+    fn exe_without_org_points_ds_to_the_image() {
+        let code = "
+            jmp start
+            msg db 'hi$'
+            value dw 0x1234
+            start:
+                mov bx, value
+                mov dx, offset msg
+                mov ah, 9
+                int 21h
+                mov ax, 4C00h
+                int 21h
+        ";
+        let (cpu, output) = run_exe(code);
+        assert_eq!(cpu.get_register(Register::DS), PSP_SEGMENT + 0x10);
+        assert_eq!(cpu.get_register(Register::BX), 0x1234);
+        assert_eq!(output, "hi");
+
+        // the entry jumps to the label of END
+        let code = "
+            msg db 'hi$'
+            main:
+                mov dx, offset msg
+                mov ah, 9
+                int 21h
+                mov ax, 4C00h
+                int 21h
+            END main
+        ";
+        let (_, output) = run_exe(code);
+        assert_eq!(output, "hi");
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::Write;
+
+const BYTES_PER_RECORD: usize = 16;
+
+const DATA_RECORD: u8 = 0x00;
+const END_OF_FILE_RECORD: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS_RECORD: u8 = 0x02;
+
+fn write_record(hex: &mut String, address: u16, record_type: u8, data: &[u8]) {
+    let [address_high, address_low] = address.to_be_bytes();
+    let mut record = vec![data.len() as u8, address_high, address_low, record_type];
+    record.extend(data);
+    let checksum = record
+        .iter()
+        .fold(0_u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    record.push(checksum);
+
+    hex.push(':');
+    for byte in record {
+        let _ = write!(hex, "{:02X}", byte);
+    }
+    hex.push('\n');
+}
+
+// The records don't cross a 64K boundary, every 64K block starts with an
+// extended segment address record, unless it's the first one
+pub fn to_intel_hex(bytes: &[u8], origin: u32) -> String {
+    let mut hex = String::new();
+    let mut address = origin;
+    let mut current_block = 0;
+    let mut remaining = bytes;
+    while !remaining.is_empty() {
+        let block = address >> 16;
+        if block != current_block {
+            let segment = (block << 12) as u16;
+            write_record(
+                &mut hex,
+                0x00,
+                EXTENDED_SEGMENT_ADDRESS_RECORD,
+                &segment.to_be_bytes(),
+            );
+            current_block = block;
+        }
+        let till_block_end = 0x10000 - (address & 0xFFFF) as usize;
+        let len = remaining.len().min(BYTES_PER_RECORD).min(till_block_end);
+        write_record(&mut hex, address as u16, DATA_RECORD, &remaining[..len]);
+        remaining = &remaining[len..];
+        address += len as u32;
+    }
+    write_record(&mut hex, 0x00, END_OF_FILE_RECORD, &[]);
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_intel_hex;
+
+    #[test]
+    fn data_and_end_of_file_records() {
+        assert_eq!(
+            to_intel_hex(&[0x01, 0x02], 0x0100),
+            ":020100000102FA\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn records_are_split_at_64k() {
+        let hex = to_intel_hex(&[0xAA; 4], 0xFFFE);
+        assert_eq!(
+            hex,
+            ":02FFFE00AAAAAD\n:020000021000EC\n:02000000AAAAAA\n:00000001FF\n"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use serde::Deserialize;
+
+use crate::{
+    compiler::types_structs::{CompiledProgram, SegmentLayout},
+    consts::Word,
+};
+
+use self::{
+    exe::{ExeHeader, DEFAULT_STACK_SIZE, PSP_RELATIVE_SEGMENT},
+    intel_hex::to_intel_hex,
+};
+
+pub mod exe;
+pub mod intel_hex;
+
+// the offset a .COM program gets loaded at, right after the PSP
+pub const COM_OFFSET: Word = 0x100;
+const MAX_COM_SIZE: usize = 0xFF00;
+const DATA_SEGMENT_STUB_SIZE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum OutputFormat {
+    Com,
+    Exe { stack_size: Option<Word> },
+    // the file starts at the origin offset, the program is placed at the offset it got assembled for
+    Bin { origin: Word },
+    // the origin is the 20bit address the first byte goes to
+    IntelHex { origin: u32 },
+}
+
+// the offset the first byte of the program is assembled for
+pub(crate) fn get_start_offset(program: &CompiledProgram) -> Word {
+    if program.is_org_defined {
+        COM_OFFSET
+    } else {
+        0x00
+    }
+}
+
+pub fn write_program(program: &CompiledProgram, format: OutputFormat) -> Result<Vec<u8>, String> {
+    match format {
+        OutputFormat::Com => to_com(program),
+        OutputFormat::Exe { stack_size } => {
+            to_exe(program, stack_size.unwrap_or(DEFAULT_STACK_SIZE))
+        }
+        OutputFormat::Bin { origin } => to_bin(program, origin),
+        OutputFormat::IntelHex { origin } => Ok(to_intel_hex(&program.bytes, origin).into_bytes()),
+    }
+}
+
+pub fn to_com(program: &CompiledProgram) -> Result<Vec<u8>, String> {
+    if !program.is_org_defined {
+        return Err("A .COM program has to start with `ORG 100h`".to_string());
+    }
+    if program
+        .entry_point
+        .is_some_and(|entry_point| entry_point != COM_OFFSET)
+    {
+        return Err(
+            "A .COM program starts at 100h, Please move the label of END to the start of the program"
+                .to_string(),
+        );
+    }
+    if program.bytes.len() > MAX_COM_SIZE {
+        return Err(format!(
+            "The program is {} bytes long, a .COM program can be at most {} bytes",
+            program.bytes.len(),
+            MAX_COM_SIZE
+        ));
+    }
+    Ok(program.bytes.clone())
+}
+
+pub fn to_bin(program: &CompiledProgram, origin: Word) -> Result<Vec<u8>, String> {
+    let start_offset = get_start_offset(program);
+    if origin > start_offset {
+        return Err(format!(
+            "The program starts at {:#06X}, the origin {:#06X} can't be after it",
+            start_offset, origin
+        ));
+    }
+    let mut bytes = vec![0x00; (start_offset - origin) as usize];
+    bytes.extend(&program.bytes);
+    Ok(bytes)
+}
+
+// DOS starts an .EXE with DS=ES=PSP, so a program with `ORG 100h` runs in the segment
+// of the PSP like a .COM, the others get an entry that points DS and ES to the image
+pub fn to_exe(program: &CompiledProgram, stack_size: Word) -> Result<Vec<u8>, String> {
+    if let Some(segment_layout) = &program.segment_layout {
+        return to_segmented_exe(&program.bytes, segment_layout);
+    }
+    if program.is_org_defined {
+        return to_psp_segment_exe(program, stack_size);
+    }
+
+    // MOV AX, <load segment>; MOV DS, AX; MOV ES, AX; JMP <entry point>
+    let mut image = program.bytes.clone();
+    let stub_offset = image.len();
+    let jmp_end = stub_offset + DATA_SEGMENT_STUB_SIZE;
+    if jmp_end > MAX_COM_SIZE {
+        return Err(format!(
+            "The program is {} bytes long, a program without segments can be at most {} bytes",
+            program.bytes.len(),
+            MAX_COM_SIZE - DATA_SEGMENT_STUB_SIZE
+        ));
+    }
+    let jmp_offset = (program.entry_point.unwrap_or(0x00) as usize).wrapping_sub(jmp_end) as Word;
+    image.extend([0xB8, 0x00, 0x00, 0x8E, 0xD8, 0x8E, 0xC0, 0xE9]);
+    image.extend(jmp_offset.to_le_bytes());
+
+    // the immediate of the MOV is the only segment reference
+    let relocations = vec![(stub_offset as Word + 1, 0x00)];
+    let entry = (0x00, stub_offset as Word);
+    let header = ExeHeader::new(image.len(), &relocations, entry, stack_size)?;
+    let mut bytes = header.to_bytes(&relocations);
+    bytes.extend(image);
+    Ok(bytes)
+}
+
+// The load module is right after the PSP, so the segment before it is the one
+// the program got assembled for, the stack is at the end of the program
+fn to_psp_segment_exe(program: &CompiledProgram, stack_size: Word) -> Result<Vec<u8>, String> {
+    if program.bytes.len() > MAX_COM_SIZE {
+        return Err(format!(
+            "The program is {} bytes long, a program with `ORG 100h` can be at most {} bytes",
+            program.bytes.len(),
+            MAX_COM_SIZE
+        ));
+    }
+    let program_end = (COM_OFFSET as usize + program.bytes.len()).next_multiple_of(0x10);
+    let stack_pointer = (program_end + stack_size as usize).min(0xFFFE) as Word;
+
+    let entry = (
+        PSP_RELATIVE_SEGMENT,
+        program.entry_point.unwrap_or(COM_OFFSET),
+    );
+    let mut header = ExeHeader::new(program.bytes.len(), &[], entry, stack_size)?;
+    header.set_psp_stack(program.bytes.len(), stack_pointer);
+    let mut bytes = header.to_bytes(&[]);
+    bytes.extend(&program.bytes);
+    Ok(bytes)
+}
+
+// The segments keep their paragraphs in the image and the stack is the one of the program,
+// the words that hold a segment are relocated to where DOS loads the program
+fn to_segmented_exe(bytes: &[u8], segment_layout: &SegmentLayout) -> Result<Vec<u8>, String> {
+    let mut image = bytes.to_vec();
+    let mut relocations = Vec::new();
+    for offset in &segment_layout.relocations {
+        let word = Word::from_le_bytes([image[*offset], image[offset + 1]])
+            .wrapping_sub(segment_layout.load_segment);
+        image[*offset..offset + 2].copy_from_slice(&word.to_le_bytes());
+        relocations.push(((offset % 0x10) as Word, (offset / 0x10) as Word));
+    }
+
+    let entry = (
+        segment_layout.code_segment,
+        segment_layout.instruction_pointer,
+    );
+    let stack = (segment_layout.stack_segment, segment_layout.stack_pointer);
+    let mut header = ExeHeader::new(image.len(), &relocations, entry, stack.1)?;
+    header.set_stack(image.len(), stack);
+    let mut bytes = header.to_bytes(&relocations);
+    bytes.extend(image);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::compile_program;
+
+    use super::{to_bin, to_com, write_program, OutputFormat};
+
+    #[test]
+    fn com_and_bin_keep_the_assembled_offsets() {
+        let program = compile_program("org 100h\nmov ax, 0x1234\nint 0x20", false).unwrap();
+        assert_eq!(
+            to_com(&program).unwrap(),
+            vec![0xB8, 0x34, 0x12, 0xCD, 0x20]
+        );
+
+        let bin = to_bin(&program, 0xFE).unwrap();
+        assert_eq!(bin, vec![0x00, 0x00, 0xB8, 0x34, 0x12, 0xCD, 0x20]);
+        assert!(to_bin(&program, 0x101).is_err());
+
+        let program = compile_program("mov ax, 0x1234", false).unwrap();
+        assert!(to_com(&program).is_err());
+        assert_eq!(
+            write_program(&program, OutputFormat::Bin { origin: 0 }).unwrap(),
+            vec![0xB8, 0x34, 0x12]
+        );
+
+        // the entry of a .COM program is its first byte
+        let program = compile_program("org 100h\nhlt\nmain:\nint 0x20\nEND main", false).unwrap();
+        assert!(to_com(&program).is_err());
+    }
+}
@@ -0,0 +1,619 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use unicase::UniCase;
+
+use crate::{
+    cli::parse_hex,
+    compiler::{
+        compile_program_with_options,
+        types_structs::{CompileOptions, CompiledProgram},
+    },
+    consts::{Byte, Word},
+    cpu::{
+        keyboard,
+        registers::{Flag, Register},
+        CPU,
+    },
+    debugger::{tracer::PortAccess, Debugger, StopReason, DEFAULT_INSTRUCTION_BUDGET},
+    memory::Memory,
+};
+
+// A grading spec, every scenario runs the submission from the start with its own
+// initial state and checks what the program ended with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Spec {
+    pub scenarios: Vec<Scenario>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub name: String,
+    pub registers: HashMap<Register, Word>,
+    pub memory: Vec<MemoryValue>,
+    pub ports: Vec<PortValue>,
+    // typed on the keyboard as the program asks for keys
+    pub input: String,
+    pub expect: Expectations,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Expectations {
+    pub registers: HashMap<Register, Word>,
+    pub flags: HashMap<Flag, bool>,
+    pub memory: Vec<MemoryValue>,
+    pub output: Option<String>,
+    // the values OUT wrote to these ports, in the order they were written
+    pub ports: Vec<PortValue>,
+    pub exit_code: Option<Byte>,
+    pub max_instructions: Option<usize>,
+}
+
+// the bytes at a variable of the data segment or at SEG:OFF in hex,
+// a value is written in the size of an element of the variable, a word otherwise,
+// a negative value is sign extended to it, the bytes can cover the whole variable
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct MemoryValue {
+    pub at: String,
+    pub bytes: Vec<Byte>,
+    pub value: Option<i128>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct PortValue {
+    pub port: Byte,
+    pub value: Byte,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub passed: bool,
+    pub instructions: usize,
+    pub failures: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GradeReport {
+    pub passed: usize,
+    pub total: usize,
+    pub cases: Vec<CaseResult>,
+}
+
+impl std::fmt::Display for GradeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for case in &self.cases {
+            let status = if case.passed { "PASS" } else { "FAIL" };
+            writeln!(
+                f,
+                "{}  {} ({} instructions)",
+                status, case.name, case.instructions
+            )?;
+            for failure in &case.failures {
+                writeln!(f, "      {}", failure)?;
+            }
+        }
+        writeln!(f, "{}/{} scenarios passed", self.passed, self.total)
+    }
+}
+
+impl GradeReport {
+    pub fn is_passed(&self) -> bool {
+        self.passed == self.total
+    }
+}
+
+pub fn parse_spec(spec: &str) -> Result<Spec, String> {
+    serde_json::from_str(spec).map_err(|error| format!("The spec is invalid: {}", error))
+}
+
+// the segment, offset and size of a memory value, i.e the size of an element for
+// a variable, the bytes can't go past the end of it
+fn resolve_address(
+    program: &CompiledProgram,
+    data_segment: Word,
+    value: &MemoryValue,
+) -> Result<(Word, Word, Word), String> {
+    let at = &value.at;
+    if let Some((segment, offset)) = at.split_once(':') {
+        return Ok((parse_hex(segment)?, parse_hex(offset)?, 2));
+    }
+    match program
+        .variable_address_map
+        .get(&UniCase::new(at.to_string()))
+    {
+        Some((_, _, variable_size)) if value.bytes.len() > variable_size.size as usize => {
+            Err(format!(
+                "The variable {} is {} bytes long, expected {} bytes at it",
+                at,
+                variable_size.size,
+                value.bytes.len()
+            ))
+        }
+        Some((variable_type, offset, _)) => {
+            Ok((data_segment, *offset as Word, variable_type.get_size()))
+        }
+        None => Err(format!("The variable {} doesn't exist", at)),
+    }
+}
+
+fn get_expected_bytes(value: &MemoryValue, size: Word) -> Vec<Byte> {
+    match value.value {
+        Some(value) => value
+            .to_le_bytes()
+            .into_iter()
+            .take(size as usize)
+            .collect(),
+        None => value.bytes.clone(),
+    }
+}
+
+// the bytes written by OUT, a word is written to the port and the one after it
+fn get_port_writes(accesses: &[PortAccess]) -> Vec<PortValue> {
+    let mut writes = Vec::new();
+    for access in accesses {
+        let port = access.port as Byte;
+        let [low_byte, high_byte] = access.value.to_le_bytes();
+        writes.push(PortValue {
+            port,
+            value: low_byte,
+        });
+        if access.is_word {
+            writes.push(PortValue {
+                port: port.wrapping_add(1),
+                value: high_byte,
+            });
+        }
+    }
+    writes
+}
+
+fn format_port_values(values: &[PortValue]) -> String {
+    let values = values
+        .iter()
+        .map(|value| format!("{:02X}={:02X}", value.port, value.value))
+        .collect::<Vec<String>>();
+    format!("[{}]", values.join(", "))
+}
+
+fn run_scenario(program: &CompiledProgram, scenario: &Scenario) -> CaseResult {
+    let mut cpu = CPU::new();
+    let mut mem = Memory::new();
+    let mut debugger = Debugger::new();
+    debugger.load_program(&mut cpu, &mut mem, program);
+    debugger.set_history_depth(&mut mem, 1);
+    let data_segment = cpu.get_register(Register::DS);
+
+    let mut result = CaseResult {
+        name: scenario.name.clone(),
+        passed: false,
+        instructions: 0,
+        failures: Vec::new(),
+    };
+
+    for (register, value) in &scenario.registers {
+        cpu.set_register(*register, *value);
+    }
+    for port in &scenario.ports {
+        cpu.set_port(port.port, port.value);
+    }
+    for value in &scenario.memory {
+        match resolve_address(program, data_segment, value) {
+            Ok((segment, offset, size)) => {
+                let bytes = get_expected_bytes(value, size);
+                for (i, byte) in bytes.iter().enumerate() {
+                    mem.write_byte(segment, offset.wrapping_add(i as Word), *byte);
+                }
+            }
+            Err(error) => result.failures.push(error),
+        }
+    }
+    if !result.failures.is_empty() {
+        return result;
+    }
+
+    let budget = scenario
+        .expect
+        .max_instructions
+        .unwrap_or(DEFAULT_INSTRUCTION_BUDGET);
+    debugger.record_port_writes();
+    let start_time = mem.get_time();
+    let mut input = scenario.input.bytes();
+    let mut output = String::new();
+    let reason = loop {
+        let executed = mem.get_time() - start_time;
+        let (reason, new_output) =
+            debugger.continue_execution(&mut cpu, &mut mem, budget.saturating_sub(executed));
+        output.push_str(&new_output);
+        match reason {
+            StopReason::WaitingForInput => match input.next() {
+                Some(key) => {
+                    keyboard::push_key(&mut mem, key);
+                }
+                None => break reason,
+            },
+            reason => break reason,
+        }
+    };
+    result.instructions = mem.get_time() - start_time;
+
+    let exit_code = match reason {
+        StopReason::Halted => Some(0),
+        StopReason::Exited(code) => Some(code),
+        StopReason::BudgetExhausted => {
+            result.failures.push(format!(
+                "The program didn't finish within {} instructions",
+                budget
+            ));
+            None
+        }
+        StopReason::WaitingForInput => {
+            result
+                .failures
+                .push("The program waited for more input than given".to_string());
+            None
+        }
+        reason => {
+            result
+                .failures
+                .push(format!("The program stopped with {:?}", reason));
+            None
+        }
+    };
+
+    let expect = &scenario.expect;
+    if let (Some(expected), Some(code)) = (expect.exit_code, exit_code) {
+        if expected != code {
+            result
+                .failures
+                .push(format!("The exit code is {}, expected {}", code, expected));
+        }
+    }
+    for register in Register::iter() {
+        if let Some(expected) = expect.registers.get(&register) {
+            let value = cpu.get_register(register);
+            if value != *expected {
+                result.failures.push(format!(
+                    "{} is {:#06X}, expected {:#06X}",
+                    register, value, expected
+                ));
+            }
+        }
+    }
+    for flag in Flag::iter() {
+        if let Some(expected) = expect.flags.get(&flag) {
+            if cpu.get_flag(flag) != *expected {
+                result.failures.push(format!(
+                    "{} is {}, expected {}",
+                    flag,
+                    cpu.get_flag(flag) as u8,
+                    *expected as u8
+                ));
+            }
+        }
+    }
+    for value in &expect.memory {
+        match resolve_address(program, data_segment, value) {
+            Ok((segment, offset, size)) => {
+                let expected = get_expected_bytes(value, size);
+                let bytes = (0..expected.len())
+                    .map(|i| mem.peek_byte(segment, offset.wrapping_add(i as Word)))
+                    .collect::<Vec<Byte>>();
+                if bytes != expected {
+                    result.failures.push(format!(
+                        "The memory at {} is {:02X?}, expected {:02X?}",
+                        value.at, bytes, expected
+                    ));
+                }
+            }
+            Err(error) => result.failures.push(error),
+        }
+    }
+    if !expect.ports.is_empty() {
+        let writes = get_port_writes(debugger.get_port_writes())
+            .into_iter()
+            .filter(|write| expect.ports.iter().any(|port| port.port == write.port))
+            .collect::<Vec<PortValue>>();
+        if writes != expect.ports {
+            result.failures.push(format!(
+                "The writes to the ports are {}, expected {}",
+                format_port_values(&writes),
+                format_port_values(&expect.ports)
+            ));
+        }
+    }
+    if let Some(expected) = &expect.output {
+        if output != *expected {
+            result.failures.push(format!(
+                "The output is {:?}, expected {:?}",
+                output, expected
+            ));
+        }
+    }
+
+    result.passed = result.failures.is_empty();
+    result
+}
+
+// Runs the submission against every scenario of the spec
+pub fn grade(code: &str, spec: &Spec) -> GradeReport {
+    grade_with_options(code, spec, &CompileOptions::default())
+}
+
+// the submission is compiled like it is run, i.e with the defines and the INCLUDE files
+pub fn grade_with_options(
+    code: &str,
+    spec: &Spec,
+    compile_options: &CompileOptions,
+) -> GradeReport {
+    let cases = match compile_program_with_options(code, compile_options, false) {
+        Ok(program) => spec
+            .scenarios
+            .iter()
+            .map(|scenario| run_scenario(&program, scenario))
+            .collect::<Vec<CaseResult>>(),
+        Err(errors) => spec
+            .scenarios
+            .iter()
+            .map(|scenario| CaseResult {
+                name: scenario.name.clone(),
+                passed: false,
+                instructions: 0,
+                failures: std::iter::once(format!(
+                    "The program has {} assembly errors",
+                    errors.len()
+                ))
+                .chain(errors.iter().map(|error| error.to_short_string()))
+                .collect(),
+            })
+            .collect(),
+    };
+    GradeReport {
+        passed: cases.iter().filter(|case| case.passed).count(),
+        total: cases.len(),
+        cases,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::compiler::{includes::SourceFiles, types_structs::CompileOptions};
+
+    use super::{grade, grade_with_options, parse_spec};
+
+    const SUBMISSION: &str = "
+        org 100h
+        .data
+        result dw 0
+        code:
+        add ax, bx
+        mov result, ax
+        mov ah, 0x01
+        int 0x21
+        out 0x10, al
+        mov ah, 0x4C
+        int 0x21
+    ";
+
+    #[test]
+    fn scenarios_pass_and_fail_with_reasons() {
+        let spec = parse_spec(
+            r#"{
+                "scenarios": [
+                    {
+                        "name": "adds",
+                        "registers": { "AX": 2, "BX": 3 },
+                        "input": "x",
+                        "expect": {
+                            "registers": { "BX": 3 },
+                            "memory": [{ "at": "result", "value": 5 }],
+                            "output": "x",
+                            "ports": [{ "port": 16, "value": 120 }],
+                            "exit_code": 120,
+                            "max_instructions": 20
+                        }
+                    },
+                    {
+                        "name": "wrong",
+                        "registers": { "AX": 1 },
+                        "memory": [{ "at": "0700:0102", "bytes": [9] }],
+                        "expect": {
+                            "memory": [{ "at": "RESULT", "bytes": [2, 0] }],
+                            "flags": { "ZF": true }
+                        }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let report = grade(SUBMISSION, &spec);
+        assert_eq!((report.passed, report.total), (1, 2));
+        assert!(report.cases[0].passed);
+        assert_eq!(
+            report.cases[1].failures,
+            vec![
+                "The program waited for more input than given",
+                "ZF is 0, expected 1",
+                "The memory at RESULT is [01, 00], expected [02, 00]",
+            ]
+        );
+        assert!(report.to_string().ends_with("1/2 scenarios passed\n"));
+    }
+
+    #[test]
+    fn values_fill_the_size_of_the_variable() {
+        let spec = parse_spec(
+            r#"{
+                "scenarios": [{
+                    "name": "dwords",
+                    "memory": [{ "at": "minus_one", "value": -1 }],
+                    "expect": {
+                        "memory": [
+                            { "at": "five", "value": 5 },
+                            { "at": "minus_one", "bytes": [255, 255, 255, 255] },
+                            { "at": "big", "value": 1311768467463790320 }
+                        ]
+                    }
+                }]
+            }"#,
+        )
+        .unwrap();
+        let code = "
+            org 100h
+            .data
+            five dd 5
+            minus_one dd 0
+            big dq 0x123456789ABCDEF0
+            code:
+            mov ah, 0x4C
+            int 0x21
+        ";
+        let report = grade(code, &spec);
+        assert_eq!(report.cases[0].failures, Vec::<String>::new());
+    }
+
+    #[test]
+    fn bytes_cover_the_whole_array() {
+        let spec = parse_spec(
+            r#"{
+                "scenarios": [{
+                    "name": "arrays",
+                    "expect": {
+                        "memory": [
+                            { "at": "m1", "value": 7 },
+                            { "at": "m1", "bytes": [7, 0, 7, 0, 7, 0, 7, 0, 7, 0, 7, 0] },
+                            { "at": "m2", "bytes": [1, 2, 3] }
+                        ]
+                    }
+                }]
+            }"#,
+        )
+        .unwrap();
+        let code = "
+            org 100h
+            .data
+            m1 dw 2 dup(3 dup(7))
+            m2 db 1, 2
+            code:
+            mov ah, 0x4C
+            int 0x21
+        ";
+        let report = grade(code, &spec);
+        assert_eq!(
+            report.cases[0].failures,
+            vec!["The variable m2 is 2 bytes long, expected 3 bytes at it"]
+        );
+    }
+
+    #[test]
+    fn submission_is_compiled_with_the_options() {
+        let spec = parse_spec(
+            r#"{
+                "scenarios": [{
+                    "name": "included",
+                    "expect": { "registers": { "AX": 42, "BX": 7 } }
+                }]
+            }"#,
+        )
+        .unwrap();
+        let code = "
+            include 'consts.inc'
+            mov ax, ANSWER
+            mov bx, COUNT
+            hlt
+        ";
+        let compile_options = CompileOptions {
+            source_files: SourceFiles::from_files(
+                [("consts.inc".to_string(), "ANSWER equ 42".to_string())].into(),
+            ),
+            defines: vec![("COUNT".to_string(), "7".to_string())],
+            ..Default::default()
+        };
+        let report = grade_with_options(code, &spec, &compile_options);
+        assert_eq!(report.cases[0].failures, Vec::<String>::new());
+
+        // the errors are in the failures
+        let report = grade(code, &spec);
+        assert!(report.cases[0].failures.len() > 1);
+        assert!(report.cases[0]
+            .failures
+            .iter()
+            .any(|failure| failure.contains("consts.inc")));
+    }
+
+    #[test]
+    fn ports_are_compared_with_every_out() {
+        let spec = parse_spec(
+            r#"{
+                "scenarios": [
+                    {
+                        "name": "counts",
+                        "expect": {
+                            "ports": [
+                                { "port": 16, "value": 1 },
+                                { "port": 16, "value": 2 },
+                                { "port": 32, "value": 52 },
+                                { "port": 33, "value": 18 },
+                                { "port": 16, "value": 3 }
+                            ]
+                        }
+                    },
+                    {
+                        "name": "last value only",
+                        "ports": [{ "port": 16, "value": 3 }],
+                        "expect": { "ports": [{ "port": 16, "value": 3 }] }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let code = "
+            mov al, 1
+            out 0x10, al
+            inc al
+            out 0x10, al
+            mov ax, 0x1234
+            mov dx, 0x20
+            out dx, ax
+            mov al, 3
+            out 0x10, al
+            out 0x30, al
+            hlt
+        ";
+        let report = grade(code, &spec);
+        assert_eq!(report.cases[0].failures, Vec::<String>::new());
+        assert_eq!(
+            report.cases[1].failures,
+            vec!["The writes to the ports are [10=01, 10=02, 10=03], expected [10=03]"]
+        );
+    }
+
+    #[test]
+    fn assembly_errors_and_slow_programs_fail() {
+        let spec = parse_spec(
+            r#"{ "scenarios": [{ "name": "fast", "expect": { "max_instructions": 3 } }] }"#,
+        )
+        .unwrap();
+        let report = grade("mov ax,", &spec);
+        assert_eq!(report.cases[0].failures.len(), 2);
+        assert_eq!(
+            report.cases[0].failures[0],
+            "The program has 1 assembly errors"
+        );
+        assert!(report.cases[0].failures[1].starts_with("1:"));
+
+        let report = grade("l1: jmp l1", &spec);
+        assert_eq!(
+            report.cases[0].failures,
+            vec!["The program didn't finish within 3 instructions"]
+        );
+        assert_eq!(report.cases[0].instructions, 3);
+    }
+}
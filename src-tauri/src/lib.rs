@@ -0,0 +1,16 @@
+pub mod cli;
+pub mod compiler;
+pub mod consts;
+pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod formats;
+pub mod grader;
+pub mod loader;
+pub mod memory;
+pub mod utils;
+
+use memory::Memory;
+
+#[cfg(test)]
+mod tests;
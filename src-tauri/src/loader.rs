@@ -0,0 +1,470 @@
+use serde::Serialize;
+
+use crate::{
+    compiler::types_structs::SegmentLayout,
+    consts::{Byte, Word},
+    cpu::{registers::Register, CPU},
+    formats::{
+        exe::{ExeHeader, Relocation},
+        COM_OFFSET,
+    },
+    memory::Memory,
+};
+
+// the segment of the PSP, the same segment assembled programs with ORG 100h run in
+pub const PSP_SEGMENT: Word = 0x0700;
+// i.e 640K, the first segment after the memory given to the program
+pub const MEMORY_TOP_SEGMENT: Word = 0xA000;
+
+const PSP_PARAGRAPHS: Word = 0x10;
+const COMMAND_TAIL_OFFSET: Word = 0x80;
+// the length byte and the carriage return take the other 2 bytes of the tail
+const MAX_COMMAND_TAIL_LENGTH: usize = 126;
+const MAX_COM_SIZE: usize = 0xFF00;
+// written in chunks so that the offsets never overflow
+const CHUNK_SIZE: usize = 0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BinaryKind {
+    Com,
+    Exe,
+}
+
+// The Program Segment Prefix, only the fields programs commonly use are filled
+pub fn write_psp(mem: &mut Memory, segment: Word, command_tail: &str) -> Result<(), String> {
+    if !command_tail.is_ascii() || command_tail.len() > MAX_COMMAND_TAIL_LENGTH {
+        return Err(format!(
+            "The command tail has to be at most {} ascii characters",
+            MAX_COMMAND_TAIL_LENGTH
+        ));
+    }
+    let mut psp = vec![0x00; (PSP_PARAGRAPHS * 0x10) as usize];
+    // INT 20h, returning to PSP:0000 terminates the program
+    psp[0x00..0x02].copy_from_slice(&[0xCD, 0x20]);
+    psp[0x02..0x04].copy_from_slice(&MEMORY_TOP_SEGMENT.to_le_bytes());
+    // INT 21h, RETF
+    psp[0x50..0x53].copy_from_slice(&[0xCD, 0x21, 0xCB]);
+    // the file names of the default FCBs are blank
+    psp[0x5D..0x68].fill(b' ');
+    psp[0x6D..0x78].fill(b' ');
+
+    let tail_start = COMMAND_TAIL_OFFSET as usize;
+    psp[tail_start] = command_tail.len() as Byte;
+    psp[tail_start + 1..tail_start + 1 + command_tail.len()]
+        .copy_from_slice(command_tail.as_bytes());
+    psp[tail_start + 1 + command_tail.len()] = 0x0D;
+
+    mem.write_instructions(segment, 0x00, &psp);
+    Ok(())
+}
+
+fn write_image(mem: &mut Memory, segment: Word, image: &[Byte]) {
+    for (i, chunk) in image.chunks(CHUNK_SIZE).enumerate() {
+        let chunk_segment = segment.wrapping_add((i * CHUNK_SIZE / 0x10) as Word);
+        mem.write_instructions(chunk_segment, 0x00, chunk);
+    }
+}
+
+// The assembled program with segments, DS and ES are the data segment of it
+pub fn load_segments(cpu: &mut CPU, mem: &mut Memory, bytes: &[Byte], layout: &SegmentLayout) {
+    write_image(mem, layout.load_segment, bytes);
+    let segment = |segment: Word| layout.load_segment.wrapping_add(segment);
+    cpu.set_register(Register::CS, segment(layout.code_segment));
+    cpu.set_register(Register::IP, layout.instruction_pointer);
+    cpu.set_register(Register::DS, segment(layout.data_segment));
+    cpu.set_register(Register::ES, segment(layout.data_segment));
+    cpu.set_register(Register::SS, segment(layout.stack_segment));
+    cpu.set_register(Register::SP, layout.stack_pointer);
+}
+
+// CS=DS=ES=SS=PSP, the program starts at 0100 and the stack at the end of the segment
+// with a 0 on it, so a RET ends the program by going to the INT 20h of the PSP
+pub fn load_com(
+    cpu: &mut CPU,
+    mem: &mut Memory,
+    bytes: &[Byte],
+    command_tail: &str,
+) -> Result<(), String> {
+    if bytes.len() > MAX_COM_SIZE {
+        return Err(format!(
+            "The .COM file is {} bytes long, it can be at most {} bytes",
+            bytes.len(),
+            MAX_COM_SIZE
+        ));
+    }
+    cpu.reset(mem);
+    write_psp(mem, PSP_SEGMENT, command_tail)?;
+    mem.write_instructions(PSP_SEGMENT, COM_OFFSET, bytes);
+    mem.write_instructions(PSP_SEGMENT, 0xFFFE, &[0x00, 0x00]);
+
+    for register in [Register::CS, Register::DS, Register::ES, Register::SS] {
+        cpu.set_register(register, PSP_SEGMENT);
+    }
+    cpu.set_register(Register::IP, COM_OFFSET);
+    cpu.set_register(Register::SP, 0xFFFE);
+    Ok(())
+}
+
+// The load module goes right after the PSP, the segments in the header and
+// the words in the relocation table are relative to it, DS=ES=PSP
+pub fn load_exe(
+    cpu: &mut CPU,
+    mem: &mut Memory,
+    bytes: &[Byte],
+    command_tail: &str,
+) -> Result<(), String> {
+    let (header, relocations, image) = ExeHeader::parse(bytes)?;
+    let load_segment = PSP_SEGMENT + PSP_PARAGRAPHS;
+    let needed_paragraphs = image.len().div_ceil(0x10) + header.min_extra_paragraphs as usize;
+    if load_segment as usize + needed_paragraphs > MEMORY_TOP_SEGMENT as usize {
+        return Err(format!(
+            "The program needs {:#X} paragraphs of memory, only {:#X} are free",
+            needed_paragraphs,
+            MEMORY_TOP_SEGMENT - load_segment
+        ));
+    }
+
+    if assumes_data_in_code_segment(&header, &relocations, image) {
+        return Err(
+            "The .EXE keeps the offsets of ORG 100h in its code segment but DOS starts it with DS at the PSP, Please export it again"
+                .to_string(),
+        );
+    }
+
+    let mut image = image.to_vec();
+    for (offset, segment) in relocations {
+        let address = segment as usize * 0x10 + offset as usize;
+        if address + 1 >= image.len() {
+            return Err(format!(
+                "The relocation {:04X}:{:04X} is outside of the program",
+                segment, offset
+            ));
+        }
+        let word = Word::from_le_bytes([image[address], image[address + 1]]);
+        let relocated = word.wrapping_add(load_segment);
+        image[address..address + 2].copy_from_slice(&relocated.to_le_bytes());
+    }
+
+    cpu.reset(mem);
+    write_psp(mem, PSP_SEGMENT, command_tail)?;
+    write_image(mem, load_segment, &image);
+
+    cpu.set_register(Register::DS, PSP_SEGMENT);
+    cpu.set_register(Register::ES, PSP_SEGMENT);
+    cpu.set_register(Register::CS, header.code_segment.wrapping_add(load_segment));
+    cpu.set_register(Register::IP, header.instruction_pointer);
+    cpu.set_register(
+        Register::SS,
+        header.stack_segment.wrapping_add(load_segment),
+    );
+    cpu.set_register(Register::SP, header.stack_pointer);
+    Ok(())
+}
+
+// i.e the .EXE of a flat program padded to the offsets of ORG 100h, which only
+// runs if DS=CS, the exported ones run in the segment of the PSP instead
+fn assumes_data_in_code_segment(
+    header: &ExeHeader,
+    relocations: &[Relocation],
+    image: &[Byte],
+) -> bool {
+    let padding = COM_OFFSET as usize;
+    relocations.is_empty()
+        && header.code_segment == 0x00
+        && header.instruction_pointer >= COM_OFFSET
+        && image.len() > padding
+        && image[..padding].iter().all(|byte| *byte == 0x00)
+}
+
+// An .EXE is recognized from its signature, everything else is run as a .COM
+pub fn load_binary(
+    cpu: &mut CPU,
+    mem: &mut Memory,
+    bytes: &[Byte],
+    command_tail: &str,
+) -> Result<BinaryKind, String> {
+    if ExeHeader::is_exe(bytes) {
+        load_exe(cpu, mem, bytes, command_tail)?;
+        Ok(BinaryKind::Exe)
+    } else {
+        load_com(cpu, mem, bytes, command_tail)?;
+        Ok(BinaryKind::Com)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compiler::compile_program,
+        cpu::{registers::Register, CPU},
+        debugger::{Debugger, StopReason},
+        formats::{
+            exe::{ExeHeader, DEFAULT_STACK_SIZE},
+            to_exe,
+        },
+        memory::Memory,
+    };
+
+    use super::{BinaryKind, PSP_SEGMENT};
+
+    #[test]
+    fn com_returns_to_the_psp() {
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        // MOV AX, 0x1234; RET
+        let kind = debugger
+            .load_binary(&mut cpu, &mut mem, &[0xB8, 0x34, 0x12, 0xC3], "/a file.txt")
+            .unwrap();
+        assert_eq!(kind, BinaryKind::Com);
+        assert_eq!(cpu.get_code_segment(), PSP_SEGMENT);
+        assert_eq!(cpu.get_instruciton_pointer(), 0x100);
+
+        // the command tail with its length and the carriage return
+        assert_eq!(mem.peek_byte(PSP_SEGMENT, 0x80), 11);
+        assert_eq!(mem.peek_byte(PSP_SEGMENT, 0x81), b'/');
+        assert_eq!(mem.peek_byte(PSP_SEGMENT, 0x8C), 0x0D);
+        assert_eq!(mem.peek_word(PSP_SEGMENT, 0x02), 0xA000);
+
+        let (reason, _) = debugger.continue_execution(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::Exited(0));
+        assert_eq!(cpu.get_register(Register::AX), 0x1234);
+    }
+
+    #[test]
+    fn exe_is_relocated() {
+        // MOV AX, 0x0001 with the immediate relocated, INT 20h
+        let image = [0xB8, 0x01, 0x00, 0xCD, 0x20];
+        let relocations = [(0x0001, 0x0000)];
+        let header = ExeHeader::new(image.len(), &relocations, (0x0000, 0x0000), 0x100).unwrap();
+        let mut bytes = header.to_bytes(&relocations);
+        bytes.extend(image);
+
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        let kind = debugger
+            .load_binary(&mut cpu, &mut mem, &bytes, "")
+            .unwrap();
+        assert_eq!(kind, BinaryKind::Exe);
+
+        let load_segment = PSP_SEGMENT + 0x10;
+        assert_eq!(cpu.get_code_segment(), load_segment);
+        assert_eq!(cpu.get_register(Register::SS), load_segment + 0x01);
+        assert_eq!(cpu.get_stack_pointer(), 0x100);
+        assert_eq!(cpu.get_register(Register::DS), PSP_SEGMENT);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::Exited(0));
+        assert_eq!(cpu.get_register(Register::AX), load_segment + 0x01);
+    }
+
+    #[test]
+    fn exported_flat_exe_round_trips() {
+        let code = "
+            org 100h
+            jmp start
+            value dw 0x1234
+            start:
+                mov bx, value
+                int 20h
+        ";
+        let program = compile_program(code, false).unwrap();
+        let bytes = to_exe(&program, DEFAULT_STACK_SIZE).unwrap();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        debugger
+            .load_binary(&mut cpu, &mut mem, &bytes, "")
+            .unwrap();
+        // the same registers as the .COM of the program
+        for register in [Register::CS, Register::DS, Register::ES, Register::SS] {
+            assert_eq!(cpu.get_register(register), PSP_SEGMENT);
+        }
+        assert_eq!(cpu.get_instruciton_pointer(), 0x100);
+        assert_eq!(mem.peek_word(PSP_SEGMENT, 0x102), 0x1234);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::Exited(0));
+        assert_eq!(cpu.get_register(Register::BX), 0x1234);
+
+        let code = "
+            value dw 0x1234
+            main:
+                mov bx, value
+                int 20h
+            END main
+        ";
+        let program = compile_program(code, false).unwrap();
+        let bytes = to_exe(&program, DEFAULT_STACK_SIZE).unwrap();
+        debugger
+            .load_binary(&mut cpu, &mut mem, &bytes, "")
+            .unwrap();
+        let load_segment = PSP_SEGMENT + 0x10;
+        assert_eq!(cpu.get_code_segment(), load_segment);
+        assert_eq!(cpu.get_register(Register::DS), PSP_SEGMENT);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::Exited(0));
+        assert_eq!(cpu.get_register(Register::DS), load_segment);
+        assert_eq!(cpu.get_register(Register::ES), load_segment);
+        assert_eq!(cpu.get_register(Register::BX), 0x1234);
+    }
+
+    #[test]
+    fn exe_padded_for_org_is_rejected() {
+        // the padding of ORG 100h and MOV AX, [0x0103]; INT 20h at IP 0100
+        let mut image = vec![0x00; 0x100];
+        image.extend([0xA1, 0x03, 0x01, 0xCD, 0x20]);
+        let header = ExeHeader::new(image.len(), &[], (0x0000, 0x0100), 0x100).unwrap();
+        let mut bytes = header.to_bytes(&[]);
+        bytes.extend(image);
+
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        let error = debugger
+            .load_binary(&mut cpu, &mut mem, &bytes, "")
+            .unwrap_err();
+        assert!(error.contains("export it again"));
+    }
+
+    #[test]
+    fn exe_returns_through_its_stack_segment() {
+        // CALL 0x0005; INT 20h; MOV AX, 0x1234; RET with SS after the image and DS at the PSP
+        let image = [0xE8, 0x02, 0x00, 0xCD, 0x20, 0xB8, 0x34, 0x12, 0xC3];
+        let header = ExeHeader::new(image.len(), &[], (0x0000, 0x0000), 0x100).unwrap();
+        let mut bytes = header.to_bytes(&[]);
+        bytes.extend(image);
+
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        debugger
+            .load_binary(&mut cpu, &mut mem, &bytes, "")
+            .unwrap();
+        assert_ne!(
+            cpu.get_register(Register::SS),
+            cpu.get_register(Register::DS)
+        );
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::Exited(0));
+        assert_eq!(cpu.get_register(Register::AX), 0x1234);
+    }
+
+    #[test]
+    fn segments_return_through_the_stack_segment() {
+        let code = "
+            .MODEL SMALL
+            .STACK 100h
+            .DATA
+            msg db 'hi$'
+            .CODE
+            PROC print
+                mov ah, 9
+                int 21h
+                ret
+            ENDP print
+            main:
+                mov ax, @data
+                mov ds, ax
+                mov dx, offset msg
+                call print
+                mov ax, 4C00h
+                int 21h
+            END main
+        ";
+        let program = compile_program(code, false).unwrap();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+        assert_ne!(
+            cpu.get_register(Register::SS),
+            cpu.get_register(Register::DS)
+        );
+
+        let (reason, output) = debugger.run(&mut cpu, &mut mem, 100);
+        assert_eq!(reason, StopReason::Exited(0));
+        assert_eq!(output, "hi");
+    }
+
+    #[test]
+    fn far_calls_return_to_their_segment() {
+        let code = "
+            .MODEL SMALL
+            .STACK 100h
+            lib SEGMENT
+            get_one:
+                mov bx, 1
+                retf
+            lib ENDS
+            .CODE
+            main:
+                call far ptr get_one
+                mov ax, 4C00h
+                add al, bl
+                int 21h
+            END main
+        ";
+        let program = compile_program(code, false).unwrap();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, 100);
+        assert_eq!(reason, StopReason::Exited(1));
+    }
+
+    #[test]
+    fn segments_of_the_program_are_loaded() {
+        let code = "
+            .MODEL SMALL
+            .STACK 100h
+            .DATA
+            value db 0x42
+            .CODE
+                mov ax, @data
+                mov ds, ax
+                mov bl, value
+                hlt
+        ";
+        let program = compile_program(code, false).unwrap();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        debugger.load_program(&mut cpu, &mut mem, &program);
+
+        assert_eq!(cpu.get_code_segment(), 0x101);
+        assert_eq!(cpu.get_instruciton_pointer(), 0x0000);
+        assert_eq!(cpu.get_register(Register::DS), 0x100);
+        assert_eq!(cpu.get_register(Register::SS), 0x102);
+        assert_eq!(cpu.get_stack_pointer(), 0x100);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(cpu.get_register(Register::AX), 0x100);
+        assert_eq!(cpu.get_register(Register::BX) & 0xFF, 0x42);
+
+        // the same program as an .EXE is relocated to where it is loaded
+        let bytes = to_exe(&program, DEFAULT_STACK_SIZE).unwrap();
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new();
+        let mut debugger = Debugger::new();
+        debugger
+            .load_binary(&mut cpu, &mut mem, &bytes, "")
+            .unwrap();
+
+        let load_segment = PSP_SEGMENT + 0x10;
+        assert_eq!(cpu.get_code_segment(), load_segment + 0x01);
+        assert_eq!(cpu.get_register(Register::SS), load_segment + 0x02);
+        assert_eq!(cpu.get_stack_pointer(), 0x100);
+
+        let (reason, _) = debugger.run(&mut cpu, &mut mem, 10);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(cpu.get_register(Register::DS), load_segment);
+        assert_eq!(cpu.get_register(Register::BX) & 0xFF, 0x42);
+    }
+}
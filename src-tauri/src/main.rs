@@ -1,39 +1,58 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-pub mod compiler;
-pub mod consts;
-pub mod cpu;
-pub mod memory;
-pub mod utils;
-
-use compiler::{
-    compilation_error::CompilationError,
-    compile_lines,
-    types_structs::{
-        CompiledBytesReference, MacroBoundsDefintionMap, MacroReferenceList,
-        ProcDefinitionLineNumberMap, ProcReferenceList,
-    },
-    utils::get_label_token_from_line,
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
 };
-use consts::Byte;
-use cpu::{interrupt::Interrupt, CPU};
-use memory::Memory;
-use std::sync::{Arc, Mutex};
 use tauri::State;
-use utils::TokenPosition;
-
-use crate::compiler::{
-    compile_lines_perform_var_label_substiution,
-    lexer::Lexer,
-    types_structs::{
-        LabelAddressMap, LabelRefrenceList, VariableAddressDefinitionMap, VariableReferenceList,
+use tauri_app::{
+    compiler::{
+        self,
+        compilation_error::CompilationError,
+        compile_program_with_options,
+        definitions::{self, DefintionTokenPosition, ReferenceTokenPosition},
+        includes::SourceFiles,
+        macros,
+        types_structs::{CompileOptions, CompiledBytesReference, Encoding},
     },
+    consts::Byte,
+    cpu::{interrupt::Interrupt, keyboard, CPU},
+    debugger::{
+        tracer::TraceFormat, BreakpointInfo, Debugger, StopReason, Watchpoint, WatchpointId,
+        DEFAULT_INSTRUCTION_BUDGET,
+    },
+    disassembler::{disassemble, DisassembledInstruction},
+    formats::{write_program, OutputFormat},
+    grader::{grade_with_options, parse_spec, GradeReport},
+    loader::BinaryKind,
+    memory::Memory,
 };
 
 type CompilationErrors = Vec<CompilationError>;
 type CompiledBytesReferences = Vec<CompiledBytesReference>;
 type MemoryChanges = Vec<(usize, Byte)>;
+type ConsoleOutput = String;
+type ExecutionResult = (CPU, StopReason, ConsoleOutput, MemoryChanges);
+// the files that can be included, by their name
+type ProjectFiles = Option<HashMap<String, String>>;
+// the constants for conditional assembly, i.e DEBUG = 1
+type Defines = Option<HashMap<String, String>>;
+
+// the long conditional jumps and the encoding are the editor's settings, they are off without them
+fn get_compile_options(
+    files: ProjectFiles,
+    defines: Defines,
+    long_conditional_jumps: Option<bool>,
+    encoding: Option<Encoding>,
+) -> CompileOptions {
+    CompileOptions {
+        source_files: SourceFiles::from_files(files.unwrap_or_default()),
+        defines: defines.unwrap_or_default().into_iter().collect(),
+        long_conditional_jumps: long_conditional_jumps.unwrap_or_default(),
+        encoding: encoding.unwrap_or_default(),
+    }
+}
 
 #[derive(Default)]
 struct MutableCpu(Arc<Mutex<CPU>>);
@@ -41,6 +60,9 @@ struct MutableCpu(Arc<Mutex<CPU>>);
 #[derive(Default)]
 struct MutableMem(Arc<Mutex<Memory>>);
 
+#[derive(Default)]
+struct MutableDebugger(Arc<Mutex<Debugger>>);
+
 #[tauri::command]
 fn set_port(cpu: State<'_, MutableCpu>, port: u8, value: Vec<u8>) -> Result<CPU, String> {
     let mut cpu = cpu.0.lock().unwrap();
@@ -64,155 +86,363 @@ fn set_port(cpu: State<'_, MutableCpu>, port: u8, value: Vec<u8>) -> Result<CPU,
 fn next(
     cpu: State<'_, MutableCpu>,
     mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
 ) -> (CPU, Option<Interrupt>, MemoryChanges) {
     let mut cpu = cpu.0.lock().unwrap();
     let mut mem = mem.0.lock().unwrap();
-    let interrupt = cpu.execute(&mut mem);
+    let mut debugger = debugger.0.lock().unwrap();
+    let interrupt = debugger.execute_instruction(&mut cpu, &mut mem);
 
     (*cpu, interrupt, mem.get_recent_new_bytes())
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn compile_code(
     code: String,
+    files: ProjectFiles,
+    defines: Defines,
+    long_conditional_jumps: Option<bool>,
+    encoding: Option<Encoding>,
     cpu: State<'_, MutableCpu>,
     mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
 ) -> Result<(CPU, CompiledBytesReferences, MemoryChanges), CompilationErrors> {
-    let (compile_bytes, compiled_bytes_ref, is_org_defined) = compile_lines(&code, true)?;
+    let compile_options = get_compile_options(files, defines, long_conditional_jumps, encoding);
+    let program = compile_program_with_options(&code, &compile_options, true)?;
     let mut cpu = cpu.0.lock().unwrap();
     let mut mem = mem.0.lock().unwrap();
-    cpu.reset(&mut mem);
-    if is_org_defined {
-        cpu.set_org_defined();
-    }
-    cpu.write_instructions(&mut mem, &compile_bytes);
-    Ok((*cpu, compiled_bytes_ref, mem.get_recent_new_bytes()))
+    let mut debugger = debugger.0.lock().unwrap();
+    debugger.load_program(&mut cpu, &mut mem, &program);
+    Ok((*cpu, program.compiled_bytes_ref, mem.get_recent_new_bytes()))
+}
+
+// runs a .COM or .EXE file instead of assembled code
+#[tauri::command]
+fn load_binary(
+    bytes: Vec<u8>,
+    command_tail: String,
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+) -> Result<(CPU, BinaryKind, MemoryChanges), String> {
+    let mut cpu = cpu.0.lock().unwrap();
+    let mut mem = mem.0.lock().unwrap();
+    let mut debugger = debugger.0.lock().unwrap();
+    let kind = debugger.load_binary(&mut cpu, &mut mem, &bytes, &command_tail)?;
+    Ok((*cpu, kind, mem.get_recent_new_bytes()))
+}
+
+// types keys for the program, returns how many fit in the keyboard buffer
+#[tauri::command]
+fn push_keys(mem: State<'_, MutableMem>, keys: String) -> usize {
+    let mut mem = mem.0.lock().unwrap();
+    keys.bytes()
+        .take_while(|key| keyboard::push_key(&mut mem, *key))
+        .count()
+}
+
+#[tauri::command]
+fn set_breakpoints(debugger: State<'_, MutableDebugger>, lines: Vec<u32>) -> Vec<u32> {
+    let mut debugger = debugger.0.lock().unwrap();
+    debugger.set_breakpoints(&lines)
+}
+
+#[tauri::command]
+fn set_conditional_breakpoint(
+    debugger: State<'_, MutableDebugger>,
+    line: u32,
+    condition: Option<String>,
+    hit_count: Option<u32>,
+) -> Result<u32, String> {
+    let mut debugger = debugger.0.lock().unwrap();
+    debugger.set_conditional_breakpoint(line, condition.as_deref(), hit_count)
+}
+
+#[tauri::command]
+fn get_breakpoints(debugger: State<'_, MutableDebugger>) -> Vec<BreakpointInfo> {
+    let debugger = debugger.0.lock().unwrap();
+    debugger.get_breakpoints()
 }
 
-type DefintionTokenPosition = TokenPosition;
-type ReferenceTokenPosition = TokenPosition;
+#[tauri::command]
+fn add_watchpoint(
+    debugger: State<'_, MutableDebugger>,
+    watchpoint: Watchpoint,
+) -> Result<WatchpointId, String> {
+    let mut debugger = debugger.0.lock().unwrap();
+    debugger.add_watchpoint(watchpoint)
+}
+
+#[tauri::command]
+fn remove_watchpoint(debugger: State<'_, MutableDebugger>, id: WatchpointId) -> bool {
+    let mut debugger = debugger.0.lock().unwrap();
+    debugger.remove_watchpoint(id)
+}
+
+#[tauri::command]
+fn get_watchpoints(debugger: State<'_, MutableDebugger>) -> Vec<(WatchpointId, Watchpoint)> {
+    let debugger = debugger.0.lock().unwrap();
+    debugger.get_watchpoints()
+}
+
+// disassembles the next `count` instructions from CS:IP
+#[tauri::command]
+fn disassemble_instructions(
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+    count: usize,
+) -> Vec<DisassembledInstruction> {
+    let cpu = cpu.0.lock().unwrap();
+    let mem = mem.0.lock().unwrap();
+    let debugger = debugger.0.lock().unwrap();
+    disassemble(
+        &mem,
+        cpu.get_code_segment(),
+        cpu.get_instruciton_pointer(),
+        count,
+        Some(debugger.get_symbols()),
+    )
+}
+
+// starts recording every executed instruction, stopping drops the trace
+#[tauri::command]
+fn set_tracing(debugger: State<'_, MutableDebugger>, is_tracing: bool) {
+    let mut debugger = debugger.0.lock().unwrap();
+    debugger.set_tracing(is_tracing);
+}
+
+#[tauri::command]
+fn get_trace(debugger: State<'_, MutableDebugger>, format: TraceFormat) -> Option<String> {
+    let debugger = debugger.0.lock().unwrap();
+    debugger.get_trace(format)
+}
+
+type DebuggerCommand =
+    fn(&mut Debugger, &mut CPU, &mut Memory, usize) -> (StopReason, ConsoleOutput);
+
+fn execute_debugger_command(
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+    max_instructions: Option<usize>,
+    command: DebuggerCommand,
+) -> ExecutionResult {
+    let mut cpu = cpu.0.lock().unwrap();
+    let mut mem = mem.0.lock().unwrap();
+    let mut debugger = debugger.0.lock().unwrap();
+    let start_time = mem.get_time();
+    let budget = max_instructions.unwrap_or(DEFAULT_INSTRUCTION_BUDGET);
+    let (stop_reason, output) = command(&mut debugger, &mut cpu, &mut mem, budget);
+    (
+        *cpu,
+        stop_reason,
+        output,
+        mem.get_new_bytes_since(start_time),
+    )
+}
+
+#[tauri::command]
+fn run(
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+    max_instructions: Option<usize>,
+) -> ExecutionResult {
+    execute_debugger_command(cpu, mem, debugger, max_instructions, Debugger::run)
+}
+
+#[tauri::command]
+fn continue_execution(
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+    max_instructions: Option<usize>,
+) -> ExecutionResult {
+    execute_debugger_command(
+        cpu,
+        mem,
+        debugger,
+        max_instructions,
+        Debugger::continue_execution,
+    )
+}
+
+#[tauri::command]
+fn step_over(
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+    max_instructions: Option<usize>,
+) -> ExecutionResult {
+    execute_debugger_command(cpu, mem, debugger, max_instructions, Debugger::step_over)
+}
+
+#[tauri::command]
+fn step_out(
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+    max_instructions: Option<usize>,
+) -> ExecutionResult {
+    execute_debugger_command(cpu, mem, debugger, max_instructions, Debugger::step_out)
+}
+
+#[tauri::command]
+fn step_back(
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+) -> ExecutionResult {
+    let mut cpu = cpu.0.lock().unwrap();
+    let mut mem = mem.0.lock().unwrap();
+    let mut debugger = debugger.0.lock().unwrap();
+    let (stop_reason, memory_changes) = debugger.step_back(&mut cpu, &mut mem);
+    (*cpu, stop_reason, String::new(), memory_changes)
+}
+
+#[tauri::command]
+fn reverse_continue(
+    cpu: State<'_, MutableCpu>,
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+    max_instructions: Option<usize>,
+) -> ExecutionResult {
+    let mut cpu = cpu.0.lock().unwrap();
+    let mut mem = mem.0.lock().unwrap();
+    let mut debugger = debugger.0.lock().unwrap();
+    let budget = max_instructions.unwrap_or(DEFAULT_INSTRUCTION_BUDGET);
+    let (stop_reason, memory_changes) = debugger.reverse_continue(&mut cpu, &mut mem, budget);
+    (*cpu, stop_reason, String::new(), memory_changes)
+}
+
+#[tauri::command]
+fn set_history_depth(
+    mem: State<'_, MutableMem>,
+    debugger: State<'_, MutableDebugger>,
+    depth: usize,
+) {
+    let mut mem = mem.0.lock().unwrap();
+    let mut debugger = debugger.0.lock().unwrap();
+    debugger.set_history_depth(&mut mem, depth);
+}
 
 #[tauri::command]
 fn get_label_and_var_address_definitions(
     code: String,
 ) -> Vec<(DefintionTokenPosition, ReferenceTokenPosition)> {
-    let mut lexer = Lexer::new();
-    lexer.tokenize(&code);
-    let old_lexer = lexer.clone();
-
-    let mut label_addr_map = LabelAddressMap::new();
-    let mut label_ref = LabelRefrenceList::new();
-
-    let mut var_addr_def_map = VariableAddressDefinitionMap::new();
-    let mut var_ref = VariableReferenceList::new();
-
-    let mut proc_line_num_map = ProcDefinitionLineNumberMap::new();
-    let mut proc_ref = ProcReferenceList::new();
-
-    let mut macro_line_num_map = MacroBoundsDefintionMap::new();
-    let mut macro_ref = MacroReferenceList::new();
-
-    compile_lines_perform_var_label_substiution(
-        &mut lexer,
-        &mut Vec::new(),
-        &mut Vec::new(),
-        &mut Vec::new(),
-        &mut label_addr_map,
-        &mut label_ref,
-        &mut var_addr_def_map,
-        &mut var_ref,
-        &mut proc_line_num_map,
-        &mut proc_ref,
-        &mut macro_line_num_map,
-        &mut macro_ref,
-    );
-
-    let mut label_and_var_address_definitions = Vec::new();
-
-    for (label, ref_token, _, _) in label_ref {
-        if let Some(line_number) = label_addr_map.get(&label) {
-            let def_token = get_label_token_from_line(&lexer, *line_number, &label).unwrap();
-            label_and_var_address_definitions.push((
-                DefintionTokenPosition::from(def_token),
-                ReferenceTokenPosition::from(&ref_token),
-            ));
-        }
-    }
-
-    for (label, _, _, tokenized_line_number) in var_ref {
-        let ref_token = get_label_token_from_line(&lexer, tokenized_line_number, &label).unwrap();
-        if let Some((_, line_number)) = var_addr_def_map.get(&label) {
-            let def_token = get_label_token_from_line(&lexer, *line_number, &label).unwrap();
-            label_and_var_address_definitions.push((
-                DefintionTokenPosition::from(def_token),
-                ReferenceTokenPosition::from(ref_token),
-            ));
-        }
-    }
-
-    for (label, ref_token, _, _) in proc_ref {
-        if let Some((line_number, _)) = proc_line_num_map.get(&label) {
-            let def_token = get_label_token_from_line(&lexer, *line_number, &label).unwrap();
-            label_and_var_address_definitions.push((
-                DefintionTokenPosition::from(def_token),
-                ReferenceTokenPosition::from(&ref_token),
-            ));
-        }
-    }
+    definitions::get_label_and_var_address_definitions(&code)
+}
 
-    for (label, ref_token, _) in macro_ref {
-        if let Some((line_number, _)) = macro_line_num_map.get(&label) {
-            let def_token = get_label_token_from_line(&old_lexer, *line_number, &label).unwrap();
-            label_and_var_address_definitions.push((
-                DefintionTokenPosition::from(def_token),
-                ReferenceTokenPosition::from(&ref_token),
-            ));
-        }
-    }
+// the .lst listing of the program, with the symbol table at the end
+#[tauri::command]
+fn get_listing(
+    code: String,
+    files: ProjectFiles,
+    defines: Defines,
+    long_conditional_jumps: Option<bool>,
+    encoding: Option<Encoding>,
+) -> Result<String, CompilationErrors> {
+    let compile_options = get_compile_options(files, defines, long_conditional_jumps, encoding);
+    let program = compile_program_with_options(&code, &compile_options, false)?;
+    Ok(program.listing.to_string())
+}
 
-    label_and_var_address_definitions
+// the lines the macro call on the line expands to, for previewing it in the editor
+#[tauri::command]
+fn expand_macro_call(
+    code: String,
+    line_number: u32,
+    files: ProjectFiles,
+    defines: Defines,
+) -> Result<Option<String>, CompilationErrors> {
+    let compile_options = get_compile_options(files, defines, None, None);
+    macros::get_macro_expansion(&code, line_number, &compile_options)
 }
 
+// the assembled program as a file of the given format
 #[tauri::command]
-fn try_compile_code(code: String) -> Result<(), Vec<CompilationError>> {
-    let mut lexer = Lexer::new();
-    lexer.tokenize(&code);
+fn export_program(
+    code: String,
+    files: ProjectFiles,
+    defines: Defines,
+    long_conditional_jumps: Option<bool>,
+    encoding: Option<Encoding>,
+    format: OutputFormat,
+) -> Result<Vec<u8>, String> {
+    let compile_options = get_compile_options(files, defines, long_conditional_jumps, encoding);
+    let program =
+        compile_program_with_options(&code, &compile_options, false).map_err(|errors| {
+            format!(
+                "The program has {} compilation errors, fix them before exporting",
+                errors.len()
+            )
+        })?;
+    write_program(&program, format)
+}
 
-    let mut compilation_errors = Vec::new();
+// runs the code against the scenarios of a json grading spec
+#[tauri::command]
+fn grade_program(
+    code: String,
+    spec: String,
+    files: ProjectFiles,
+    defines: Defines,
+    long_conditional_jumps: Option<bool>,
+    encoding: Option<Encoding>,
+) -> Result<GradeReport, String> {
+    let spec = parse_spec(&spec)?;
+    let compile_options = get_compile_options(files, defines, long_conditional_jumps, encoding);
+    Ok(grade_with_options(&code, &spec, &compile_options))
+}
 
-    match compile_lines_perform_var_label_substiution(
-        &mut lexer,
-        &mut compilation_errors,
-        &mut Vec::new(),
-        &mut Vec::new(),
-        &mut LabelAddressMap::new(),
-        &mut LabelRefrenceList::new(),
-        &mut VariableAddressDefinitionMap::new(),
-        &mut VariableReferenceList::new(),
-        &mut ProcDefinitionLineNumberMap::new(),
-        &mut ProcReferenceList::new(),
-        &mut MacroBoundsDefintionMap::new(),
-        &mut MacroReferenceList::new(),
-    ) {
-        Some(_) => Ok(()),
-        None => Err(compilation_errors),
-    }
+#[tauri::command]
+fn try_compile_code(
+    code: String,
+    files: ProjectFiles,
+    defines: Defines,
+    long_conditional_jumps: Option<bool>,
+    encoding: Option<Encoding>,
+) -> Result<(), Vec<CompilationError>> {
+    let compile_options = get_compile_options(files, defines, long_conditional_jumps, encoding);
+    compiler::try_compile_code(&code, &compile_options)
 }
 
 fn main() {
     tauri::Builder::default()
         .manage(MutableCpu::default())
         .manage(MutableMem::default())
+        .manage(MutableDebugger::default())
         .invoke_handler(tauri::generate_handler![
             try_compile_code,
             get_label_and_var_address_definitions,
             compile_code,
             next,
-            set_port
+            set_port,
+            set_breakpoints,
+            set_conditional_breakpoint,
+            get_breakpoints,
+            add_watchpoint,
+            remove_watchpoint,
+            get_watchpoints,
+            run,
+            continue_execution,
+            step_over,
+            step_out,
+            step_back,
+            reverse_continue,
+            set_history_depth,
+            disassemble_instructions,
+            get_listing,
+            expand_macro_call,
+            export_program,
+            load_binary,
+            push_keys,
+            grade_program,
+            set_tracing,
+            get_trace
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
-
-#[cfg(test)]
-mod tests;
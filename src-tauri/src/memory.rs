@@ -1,5 +1,26 @@
+use std::{cell::RefCell, collections::VecDeque};
+
+use serde::Serialize;
+
 use crate::consts::{Byte, Word, U20};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MemoryAccessKind {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemoryAccess {
+    pub address: usize,
+    pub kind: MemoryAccessKind,
+    pub value: Byte,
+}
+
+// number of steps the history goes back by default
+pub const DEFAULT_HISTORY_DEPTH: usize = 100_000;
+
+// All the writes made during a single step
 #[derive(Debug)]
 pub struct MemoryHistory {
     time: usize,
@@ -10,7 +31,17 @@ pub struct MemoryHistory {
 pub struct Memory {
     // #[serde(serialize_with = "serialize")]
     mem: Vec<Byte>,
-    history: Vec<MemoryHistory>,
+
+    // The history is a journal ordered by time, with one entry per step that wrote something.
+    // Entries older than `history_depth` steps are dropped so it can't grow forever
+    history: VecDeque<MemoryHistory>,
+    time: usize,
+    history_depth: usize,
+
+    // reads and writes are only recorded while someone is watching them,
+    // reads need to be recorded from &self so the log lives in a RefCell
+    is_recording_accesses: bool,
+    accesses: RefCell<Vec<MemoryAccess>>,
 }
 
 impl Default for Memory {
@@ -23,7 +54,11 @@ impl Memory {
     pub fn new() -> Memory {
         Memory {
             mem: vec![0x90; 0xFFFFF],
-            history: vec![],
+            history: VecDeque::new(),
+            time: 0,
+            history_depth: DEFAULT_HISTORY_DEPTH,
+            is_recording_accesses: false,
+            accesses: RefCell::new(vec![]),
         }
     }
 
@@ -31,32 +66,41 @@ impl Memory {
         ((segment as u32 * 0x10) + (address as u32)) as usize
     }
 
-    fn push_history(&mut self, time: usize, index_old_new_values_pairs: Vec<(usize, Byte, Byte)>) {
-        let time_already_exists_index = self.history.iter().position(|h| h.time == time);
-        match time_already_exists_index {
-            Some(index) => {
-                // if it exists, then add the new values to the existing time
-                let mut new_index_old_new_values_pairs =
-                    self.history[index].index_old_new_values_pairs.clone();
-                new_index_old_new_values_pairs.extend(index_old_new_values_pairs);
-                self.history[index].index_old_new_values_pairs = new_index_old_new_values_pairs;
+    fn push_history(&mut self, index: usize, old_value: Byte, new_value: Byte) {
+        match self.history.back_mut() {
+            // the step already wrote something
+            Some(history) if history.time == self.time => {
+                history
+                    .index_old_new_values_pairs
+                    .push((index, old_value, new_value));
             }
-            None => {
-                // if it doesn't exist, then add a new time
-                self.history.push(MemoryHistory {
-                    time,
-                    index_old_new_values_pairs,
+            _ => {
+                self.history.push_back(MemoryHistory {
+                    time: self.time,
+                    index_old_new_values_pairs: vec![(index, old_value, new_value)],
                 });
+                let oldest_time = self.get_oldest_time();
+                while self
+                    .history
+                    .front()
+                    .is_some_and(|history| history.time <= oldest_time)
+                {
+                    self.history.pop_front();
+                }
             }
         }
     }
 
     pub fn reset(&mut self) {
         self.mem = vec![0x90; 0xFFFFF];
+        self.history.clear();
+        self.time = 0;
+        self.accesses.get_mut().clear();
     }
 
     pub fn read_byte(&self, segment: u16, offset: u16) -> Byte {
         let address = self.get_addr(segment, offset);
+        self.record_access(address, MemoryAccessKind::Read, self.mem[address]);
         self.mem[address]
     }
 
@@ -65,38 +109,51 @@ impl Memory {
         let addr_2 = self.get_addr(segment, offset + 1);
         let byte_1 = self.mem[addr_1];
         let byte_2 = self.mem[addr_2];
+        self.record_access(addr_1, MemoryAccessKind::Read, byte_1);
+        self.record_access(addr_2, MemoryAccessKind::Read, byte_2);
         ((byte_2 as u16) << 8) | (byte_1 as u16)
     }
 
+    // reads without being recorded, used for fetching instructions and by the debugger
+    pub fn peek_byte(&self, segment: u16, offset: u16) -> Byte {
+        let address = self.get_addr(segment, offset);
+        self.mem[address]
+    }
+
+    pub fn peek_word(&self, segment: u16, offset: u16) -> Word {
+        let low_byte = self.peek_byte(segment, offset);
+        let high_byte = self.peek_byte(segment, offset.wrapping_add(1));
+        ((high_byte as u16) << 8) | (low_byte as u16)
+    }
+
     pub fn write_instructions(&mut self, segment: u16, offset: u16, data: &[Byte]) {
         for (i, byte) in data.iter().enumerate() {
-            self.write_byte_with_a_time_stamp(segment, offset + i as u16, *byte, 0);
+            let address = self.get_addr(segment, offset + i as u16);
+            self.write_byte_with_history(address, *byte);
         }
     }
 
-    fn write_byte_with_a_time_stamp(
-        &mut self,
-        segment: u16,
-        offset: u16,
-        new_data: Byte,
-        time: usize,
-    ) {
-        let address = self.get_addr(segment, offset);
+    fn write_byte_with_history(&mut self, address: usize, new_data: Byte) {
         let prev_data = self.mem[address];
-        self.push_history(time, vec![(address, prev_data, new_data)]);
+        self.push_history(address, prev_data, new_data);
         self.mem[address] = new_data;
     }
 
     pub fn write_byte(&mut self, segment: u16, offset: u16, data: Byte) {
-        self.write_byte_with_a_time_stamp(segment, offset, data, self.history.len());
+        let address = self.get_addr(segment, offset);
+        self.record_access(address, MemoryAccessKind::Write, data);
+        self.write_byte_with_history(address, data);
     }
 
     pub fn write_word(&mut self, segment: u16, offset: u16, data: Word) {
-        let time = self.history.len();
         let low_byte = (data & 0xFF) as Byte;
         let high_byte = ((data >> 8) & 0xFF) as Byte;
-        self.write_byte_with_a_time_stamp(segment, offset, low_byte, time);
-        self.write_byte_with_a_time_stamp(segment, offset + 1, high_byte, time);
+        let low_address = self.get_addr(segment, offset);
+        let high_address = self.get_addr(segment, offset + 1);
+        self.record_access(low_address, MemoryAccessKind::Write, low_byte);
+        self.record_access(high_address, MemoryAccessKind::Write, high_byte);
+        self.write_byte_with_history(low_address, low_byte);
+        self.write_byte_with_history(high_address, high_byte);
     }
 
     pub fn read_word_with_u20(&self, offset: U20) -> Word {
@@ -121,31 +178,187 @@ impl Memory {
 }
 
 impl Memory {
-    pub fn get_recent_new_bytes(&self) -> Vec<(usize, Byte)> {
-        match self.history.last() {
-            Some(history) => {
-                let time = history.time;
-                // collect all the values that match the time
-                let history_vec_at_t = self
-                    .history
-                    .iter()
-                    .filter(|h| h.time == time)
-                    .collect::<Vec<&MemoryHistory>>();
-
-                // reduce the 2d array into Vec<(usize, Byte)>
-                history_vec_at_t
-                    .iter()
-                    .flat_map(|h| {
-                        h.index_old_new_values_pairs
-                            .iter()
-                            .map(|(index, _, new_value)| (*index, *new_value))
-                            .collect::<Vec<(usize, Byte)>>()
-                    })
-                    .collect::<Vec<(usize, Byte)>>()
+    fn record_access(&self, address: usize, kind: MemoryAccessKind, value: Byte) {
+        if self.is_recording_accesses {
+            self.accesses.borrow_mut().push(MemoryAccess {
+                address,
+                kind,
+                value,
+            });
+        }
+    }
+
+    pub fn set_access_recording(&mut self, is_recording: bool) {
+        self.is_recording_accesses = is_recording;
+        self.accesses.get_mut().clear();
+    }
+
+    pub fn is_recording_accesses(&self) -> bool {
+        self.is_recording_accesses
+    }
+
+    // the accesses recorded since the last take, without taking them
+    pub fn get_accesses(&self) -> Vec<MemoryAccess> {
+        self.accesses.borrow().clone()
+    }
+
+    // returns the accesses recorded since the last call
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(self.accesses.get_mut())
+    }
+
+    pub fn get_time(&self) -> usize {
+        self.time
+    }
+
+    // starts a new step, the writes after this are recorded under the new time
+    pub fn advance_time(&mut self) {
+        self.time += 1;
+    }
+
+    // the history can only be rewound up to this time
+    pub fn get_oldest_time(&self) -> usize {
+        self.time.saturating_sub(self.history_depth)
+    }
+
+    pub fn get_history_depth(&self) -> usize {
+        self.history_depth
+    }
+
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history_depth = depth;
+        let oldest_time = self.get_oldest_time();
+        while self
+            .history
+            .front()
+            .is_some_and(|history| history.time <= oldest_time)
+        {
+            self.history.pop_front();
+        }
+    }
+
+    // undoes every write made after the given time by putting back the old values,
+    // returns the undone writes as (index, old value, new value) latest first
+    pub fn rewind_to(&mut self, time: usize) -> Vec<(usize, Byte, Byte)> {
+        let mut undone_writes = Vec::new();
+        while self
+            .history
+            .back()
+            .is_some_and(|history| history.time > time)
+        {
+            let history = self.history.pop_back().unwrap();
+            for (index, old_value, new_value) in
+                history.index_old_new_values_pairs.into_iter().rev()
+            {
+                self.mem[index] = old_value;
+                undone_writes.push((index, old_value, new_value));
             }
-            None => {
-                vec![]
+        }
+        self.time = self.time.min(time);
+        undone_writes
+    }
+
+    // a copy of the whole memory, used for checkpoints
+    pub fn get_snapshot(&self) -> Vec<Byte> {
+        self.mem.clone()
+    }
+
+    // puts back a snapshot taken at the given time, the history starts over from there
+    pub fn restore_snapshot(&mut self, time: usize, snapshot: &[Byte]) {
+        self.mem.copy_from_slice(snapshot);
+        self.history.clear();
+        self.time = time;
+        self.accesses.get_mut().clear();
+    }
+
+    // collect the latest value of every byte written after the given time
+    pub fn get_new_bytes_since(&self, time: usize) -> Vec<(usize, Byte)> {
+        let mut new_bytes = std::collections::BTreeMap::new();
+        let recent_history = self
+            .history
+            .iter()
+            .rev()
+            .take_while(|history| history.time > time)
+            .collect::<Vec<&MemoryHistory>>();
+        for history in recent_history.into_iter().rev() {
+            for (index, _, new_value) in &history.index_old_new_values_pairs {
+                new_bytes.insert(*index, *new_value);
             }
         }
+        new_bytes.into_iter().collect()
+    }
+
+    // the bytes written by the current step
+    pub fn get_recent_new_bytes(&self) -> Vec<(usize, Byte)> {
+        match self.history.back() {
+            Some(history) if history.time == self.time => history
+                .index_old_new_values_pairs
+                .iter()
+                .map(|(index, _, new_value)| (*index, *new_value))
+                .collect::<Vec<(usize, Byte)>>(),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Memory;
+
+    #[test]
+    fn writes_of_a_step_are_grouped() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x100, 0x00, 0x01);
+        mem.advance_time();
+        mem.write_word(0x100, 0x02, 0x0302);
+        mem.write_byte(0x100, 0x04, 0x04);
+        assert_eq!(
+            mem.get_recent_new_bytes(),
+            vec![(0x1002, 0x02), (0x1003, 0x03), (0x1004, 0x04)]
+        );
+        assert_eq!(mem.get_new_bytes_since(0).len(), 3);
+
+        // a step without writes has no new bytes
+        mem.advance_time();
+        assert_eq!(mem.get_recent_new_bytes(), vec![]);
+    }
+
+    #[test]
+    fn rewind_puts_back_old_values() {
+        let mut mem = Memory::new();
+        mem.write_byte(0x100, 0x00, 0x01);
+        mem.advance_time();
+        mem.write_byte(0x100, 0x00, 0x02);
+        mem.advance_time();
+        mem.write_byte(0x100, 0x00, 0x03);
+        mem.write_byte(0x100, 0x01, 0x04);
+
+        assert_eq!(
+            mem.rewind_to(1),
+            vec![(0x1001, 0x90, 0x04), (0x1000, 0x02, 0x03)]
+        );
+        assert_eq!(mem.get_time(), 1);
+        assert_eq!(mem.peek_word(0x100, 0x00), 0x9002);
+        mem.rewind_to(0);
+        assert_eq!(mem.peek_byte(0x100, 0x00), 0x01);
+    }
+
+    #[test]
+    fn history_is_bounded_and_cleared_on_reset() {
+        let mut mem = Memory::new();
+        mem.set_history_depth(3);
+        for i in 0..10 {
+            mem.advance_time();
+            mem.write_byte(0x100, 0x00, i);
+        }
+        assert_eq!(mem.get_oldest_time(), 7);
+        assert_eq!(mem.history.len(), 3);
+        mem.rewind_to(mem.get_oldest_time());
+        assert_eq!(mem.peek_byte(0x100, 0x00), 6);
+
+        mem.reset();
+        assert_eq!(mem.get_time(), 0);
+        assert!(mem.history.is_empty());
+        assert_eq!(mem.get_new_bytes_since(0), vec![]);
     }
 }
@@ -1,4 +1,4 @@
-use crate::{compile_and_compare_ins, compiler::compile_str, test_compile};
+use crate::{compile_and_compare_ins, compiler::tests::get_long_jumps_options};
 use pretty_assertions::assert_eq;
 
 compile_and_compare_ins!(
@@ -41,7 +41,8 @@ compile_and_compare_ins!(
         jb start
         jb 0x00 
         ",
-    get_compiled_bytes()
+    get_compiled_bytes(),
+    get_long_jumps_options()
 );
 
 fn get_compiled_bytes() -> Vec<u8> {
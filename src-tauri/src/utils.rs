@@ -28,6 +28,14 @@ impl Either<u8, u16> {
         }
     }
 
+    // the evaluated 8bit numbers are signed, 0xFF is -1 (or) 0xFFFF in a 16bit operand
+    pub fn get_as_sign_extended_u16(&self) -> u16 {
+        match &self {
+            Either::Left(x) => *x as i8 as u16,
+            Either::Right(x) => *x,
+        }
+    }
+
     pub fn get_as_bytes(&self) -> Vec<u8> {
         match &self {
             Either::Left(x) => vec![*x],
@@ -156,6 +156,7 @@ export function useApp() {
       const result: [CPUData, CompiledBytes[], Array<[number, number]>] =
         await invoke("compile_code", {
           code: editorRef.current?.getValue(),
+          longConditionalJumps: true,
         });
       const regs: any = result[0];
       compiledBytesRef.current = result[1];
@@ -232,6 +233,7 @@ export function useApp() {
     try {
       await invoke("try_compile_code", {
         code: editorRef.current?.getValue(),
+        longConditionalJumps: true,
       });
       clearErrorsOnEditor();
     } catch (e) {
@@ -247,6 +249,7 @@ export function useApp() {
     try {
       await invoke("try_compile_code", {
         code: editorRef.current?.getValue(),
+        longConditionalJumps: true,
       });
       clearErrorsOnEditor();
     } catch (e) {
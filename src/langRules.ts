@@ -17,6 +17,7 @@ export const langRules: languages.IMonarchLanguage = {
     "call",
     "hlt",
     "ret",
+    "retf",
 
     "push",
 